@@ -0,0 +1,45 @@
+// Bloom: the bright parts of the image, blurred at half resolution and added back on top
+
+#import post_process
+
+// values: x threshold, y soft knee as a fraction of the threshold
+@fragment
+fn fs_prefilter(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    // Four bilinear taps average the 4x4 full resolution block under this half resolution texel
+    let offset = params.texel_size;
+    var color = textureSample(t_input, s_linear, in.uv + vec2<f32>(-offset.x, -offset.y)).rgb;
+    color += textureSample(t_input, s_linear, in.uv + vec2<f32>(offset.x, -offset.y)).rgb;
+    color += textureSample(t_input, s_linear, in.uv + vec2<f32>(-offset.x, offset.y)).rgb;
+    color += textureSample(t_input, s_linear, in.uv + vec2<f32>(offset.x, offset.y)).rgb;
+    color *= 0.25;
+
+    // Quadratic ramp from threshold - knee to threshold + knee, so the cutoff doesn't pop
+    let threshold = params.values.x;
+    let knee = threshold * params.values.y + 0.0001;
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    let contribution = max(soft, brightness - threshold) / max(brightness, 0.0001);
+    return finish(color * contribution);
+}
+
+// values: xy blur direction, z radius in texels
+@fragment
+fn fs_blur(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    // 9-tap Gaussian, folded into 5 fetches by sampling between texel pairs
+    let step = params.values.xy * params.texel_size * params.values.z;
+    var color = textureSample(t_input, s_linear, in.uv).rgb * 0.2270270270;
+    color += textureSample(t_input, s_linear, in.uv + step * 1.3846153846).rgb * 0.3162162162;
+    color += textureSample(t_input, s_linear, in.uv - step * 1.3846153846).rgb * 0.3162162162;
+    color += textureSample(t_input, s_linear, in.uv + step * 3.2307692308).rgb * 0.0702702703;
+    color += textureSample(t_input, s_linear, in.uv - step * 3.2307692308).rgb * 0.0702702703;
+    return finish(color);
+}
+
+// values: x intensity. t_aux holds the blurred glow
+@fragment
+fn fs_composite(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_linear, in.uv).rgb;
+    let glow = textureSample(t_aux, s_linear, in.uv).rgb;
+    return finish(color + glow * params.values.x);
+}
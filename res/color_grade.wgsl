@@ -0,0 +1,24 @@
+// Color grading through a 3D lookup table
+
+#import post_process
+
+// values: x strength, blending from the original color to the graded one.
+// t_aux is an N*N x N strip: N slices of increasing blue side by side, red increasing
+// across each slice and green increasing downwards.
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_linear, in.uv).rgb;
+    let size = f32(textureDimensions(t_aux).y);
+
+    // LUTs are authored on sRGB-encoded values
+    let cell = clamp(linear_to_srgb(color), vec3<f32>(0.0), vec3<f32>(1.0)) * (size - 1.0);
+    let slice = floor(cell.b);
+    let next_slice = min(slice + 1.0, size - 1.0);
+    // Sampling at texel centers keeps bilinear filtering within a slice
+    let v = (cell.g + 0.5) / size;
+    let low = textureSample(t_aux, s_linear, vec2<f32>((slice * size + cell.r + 0.5) / (size * size), v)).rgb;
+    let high = textureSample(t_aux, s_linear, vec2<f32>((next_slice * size + cell.r + 0.5) / (size * size), v)).rgb;
+    let graded = srgb_to_linear(mix(low, high, cell.b - slice));
+
+    return finish(mix(color, graded, params.values.x));
+}
@@ -63,6 +63,20 @@ function makeInstances() {
   return instanceData;
 }
 
+// Per-instance values for a "script" instance attribute: 4 floats per instance.
+// Cycles the hue around the color wheel in generation order.
+function rainbowColors(count) {
+  const colors = new Float32Array(4 * count);
+  for (let i = 0; i < count; i++) {
+    const hue = (i / count) * Math.PI * 2;
+    colors[i * 4 + 0] = 0.5 + 0.5 * Math.cos(hue);
+    colors[i * 4 + 1] = 0.5 + 0.5 * Math.cos(hue - (Math.PI * 2) / 3);
+    colors[i * 4 + 2] = 0.5 + 0.5 * Math.cos(hue + (Math.PI * 2) / 3);
+    colors[i * 4 + 3] = 1;
+  }
+  return colors;
+}
+
 // let data = new Float32Array([0.0, 1.0, 2.5, -3.14, 42.0]);
 // data_fn(data);
 
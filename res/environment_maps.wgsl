@@ -0,0 +1,182 @@
+// Offscreen passes that build the environment cubemaps, one face of one mip at a time
+
+struct ProcessParams {
+    face: u32,
+    roughness: f32,
+    // 1 for an equirectangular source, 6 for cubemap faces
+    source_layers: u32,
+    // Face size of the mip being rendered
+    target_size: f32,
+    // Face size of mip 0 of the environment map
+    environment_size: f32,
+    _padding: vec3<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: ProcessParams;
+@group(0) @binding(1)
+var source: texture_2d_array<f32>;
+@group(0) @binding(2)
+var environment_map: texture_cube<f32>;
+@group(0) @binding(3)
+var environment_sampler: sampler;
+
+const PI: f32 = 3.14159265359;
+const PREFILTER_SAMPLES: u32 = 256u;
+// Float16 max is 65504; brighter texels would become infinity
+const MAX_RADIANCE: f32 = 64000.0;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // 0..1 across the face, y running down like framebuffer rows
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+// World direction through `uv` on cube face `face` (+X, -X, +Y, -Y, +Z, -Z)
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x * 2.0 - 1.0;
+    let v = uv.y * 2.0 - 1.0;
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -v, -u); }
+        case 1u: { return vec3<f32>(-1.0, -v, u); }
+        case 2u: { return vec3<f32>(u, 1.0, v); }
+        case 3u: { return vec3<f32>(u, -1.0, -v); }
+        case 4u: { return vec3<f32>(u, -v, 1.0); }
+        default: { return vec3<f32>(-u, -v, -1.0); }
+    }
+}
+
+fn sample_source(direction: vec3<f32>) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(source));
+    var layer = 0;
+    var uv: vec2<f32>;
+    if (params.source_layers == 6u) {
+        // Inverse of face_direction: the major axis picks the face
+        let a = abs(direction);
+        if (a.x >= a.y && a.x >= a.z) {
+            layer = select(1, 0, direction.x > 0.0);
+            uv = vec2<f32>(select(direction.z, -direction.z, direction.x > 0.0), -direction.y) / a.x;
+        } else if (a.y >= a.z) {
+            layer = select(3, 2, direction.y > 0.0);
+            uv = vec2<f32>(direction.x, select(-direction.z, direction.z, direction.y > 0.0)) / a.y;
+        } else {
+            layer = select(5, 4, direction.z > 0.0);
+            uv = vec2<f32>(select(-direction.x, direction.x, direction.z > 0.0), -direction.y) / a.z;
+        }
+        uv = uv * 0.5 + 0.5;
+    } else {
+        let longitude = atan2(direction.z, direction.x);
+        let latitude = acos(clamp(direction.y, -1.0, 1.0));
+        uv = vec2<f32>(longitude / (2.0 * PI) + 0.5, latitude / PI);
+    }
+    // Float32 sources can't be filtered on every backend, so load texels directly
+    let texel = clamp(vec2<i32>(uv * size), vec2<i32>(0), vec2<i32>(size) - 1);
+    return textureLoad(source, texel, layer, 0).rgb;
+}
+
+// Project the source image onto a cube face, averaging a 4x4 grid per texel so small mips don't alias
+@fragment
+fn fs_convert(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = vec3<f32>(0.0);
+    for (var y = 0; y < 4; y = y + 1) {
+        for (var x = 0; x < 4; x = x + 1) {
+            let offset = (vec2<f32>(f32(x), f32(y)) + 0.5) / 4.0 - 0.5;
+            let uv = in.uv + offset / params.target_size;
+            color = color + sample_source(normalize(face_direction(params.face, uv)));
+        }
+    }
+    return vec4<f32>(min(color / 16.0, vec3<f32>(MAX_RADIANCE)), 1.0);
+}
+
+// Cosine-weighted integral of the environment over the hemisphere around each normal
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = normalize(face_direction(params.face, in.uv));
+    let up = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(normal.y) > 0.999);
+    let right = normalize(cross(up, normal));
+    let tangent_up = cross(normal, right);
+    // A mip near the target's resolution already averages what falls between samples
+    let lod = log2(params.environment_size / params.target_size);
+
+    let delta = 0.05;
+    var irradiance = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi = phi + delta) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta = theta + delta) {
+            let local = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let direction = local.x * right + local.y * tangent_up + local.z * normal;
+            let radiance = textureSampleLevel(environment_map, environment_sampler, direction, lod).rgb;
+            irradiance = irradiance + radiance * cos(theta) * sin(theta);
+            count = count + 1.0;
+        }
+    }
+    return vec4<f32>(PI * irradiance / count, 1.0);
+}
+
+// Van der Corput radical inverse, spelled out because ES 3.0 has no bit reversal
+fn radical_inverse(index: u32) -> f32 {
+    var bits = index;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+// Half vector distributed by the GGX lobe around `normal`
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let local = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+    let up = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(normal.y) > 0.999);
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return normalize(tangent * local.x + bitangent * local.y + normal * local.z);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
+}
+
+// Environment convolved with the GGX lobe for this mip's roughness, assuming view = normal.
+// Each sample reads a mip sized to its share of the sphere, which keeps bright spots from sparkling.
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = normalize(face_direction(params.face, in.uv));
+    let roughness = params.roughness;
+    let texel_solid_angle = 4.0 * PI / (6.0 * params.environment_size * params.environment_size);
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < PREFILTER_SAMPLES; i = i + 1u) {
+        let xi = vec2<f32>(f32(i) / f32(PREFILTER_SAMPLES), radical_inverse(i));
+        let half_dir = importance_sample_ggx(xi, normal, roughness);
+        let light_dir = normalize(2.0 * dot(normal, half_dir) * half_dir - normal);
+        let n_dot_l = dot(normal, light_dir);
+        if (n_dot_l > 0.0) {
+            let n_dot_h = max(dot(normal, half_dir), 0.0);
+            // With view = normal, the half vector pdf reduces to D / 4
+            let pdf = distribution_ggx(n_dot_h, roughness) / 4.0 + 0.0001;
+            let sample_solid_angle = 1.0 / (f32(PREFILTER_SAMPLES) * pdf);
+            let lod = select(0.5 * log2(sample_solid_angle / texel_solid_angle), 0.0, roughness == 0.0);
+            color = color + textureSampleLevel(environment_map, environment_sampler, light_dir, max(lod, 0.0)).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+    return vec4<f32>(color / max(weight, 0.0001), 1.0);
+}
@@ -0,0 +1,50 @@
+// FXAA: blurs along edges found from luma contrast between neighbouring texels
+
+#import post_process
+
+// Edges are found on perceptual brightness, which sqrt approximates
+fn fxaa_luma(color: vec3<f32>) -> f32 {
+    return sqrt(luminance(color));
+}
+
+// values: x longest blur span in texels, y reduce multiplier, z reduce minimum
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = params.texel_size;
+    let span_max = params.values.x;
+    let reduce_mul = params.values.y;
+    let reduce_min = params.values.z;
+
+    let color_m = textureSample(t_input, s_linear, in.uv).rgb;
+    let luma_nw = fxaa_luma(textureSample(t_input, s_linear, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+    let luma_ne = fxaa_luma(textureSample(t_input, s_linear, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+    let luma_sw = fxaa_luma(textureSample(t_input, s_linear, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+    let luma_se = fxaa_luma(textureSample(t_input, s_linear, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+    let luma_m = fxaa_luma(color_m);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Blur direction runs along the edge, perpendicular to the luma gradient
+    var dir = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * reduce_mul, reduce_min);
+    let inv_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * inv_dir_min, vec2<f32>(-span_max), vec2<f32>(span_max)) * texel;
+
+    let color_a = 0.5 * (
+        textureSample(t_input, s_linear, in.uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        textureSample(t_input, s_linear, in.uv + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    let color_b = color_a * 0.5 + 0.25 * (
+        textureSample(t_input, s_linear, in.uv - dir * 0.5).rgb +
+        textureSample(t_input, s_linear, in.uv + dir * 0.5).rgb
+    );
+    // The wider blur crossed into another edge if it left the local luma range
+    let luma_b = fxaa_luma(color_b);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        return finish(color_a);
+    }
+    return finish(color_b);
+}
@@ -5,20 +5,8 @@ struct Camera {
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
-struct Light {
-    position: vec4<f32>,
-    color: vec4<f32>,
-}
-
-const MAX_LIGHTS: u32 = 10u;
-
-struct LightData {
-    lights: array<Light, MAX_LIGHTS>,
-    num_lights: u32,
-}
-
-@group(0) @binding(1)
-var<uniform> light_data: LightData;
+// Light struct, clustered light list and accessors (group 0, bindings 1-4)
+#import lights
 
 //
 
@@ -34,9 +22,9 @@ struct VertexOutput {
 @vertex
 fn vs_main(model: VertexInput, @builtin(instance_index) instance_index: u32) -> VertexOutput {
     let scale = 0.25;
-    let light = light_data.lights[instance_index];
+    let light = get_light(instance_index);
     var out: VertexOutput;
-    out.clip_position = camera.view_proj * vec4<f32>(model.position * scale + light.position.xyz, 1.0);
+    out.clip_position = camera.view_proj * vec4<f32>(model.position * scale + light.position, 1.0);
     out.color = light.color.xyz;
     return out;
 }
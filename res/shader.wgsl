@@ -7,25 +7,21 @@ struct CameraUniform {
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
-struct Light {
-    position: vec4<f32>,
-    color: vec4<f32>,
-}
-
-const MAX_LIGHTS: u32 = 10u;
+// Light struct, clustered light list and accessors (group 0, bindings 1-4)
+#import lights
 
-struct LightData {
-    lights: array<Light, MAX_LIGHTS>,
-    num_lights: u32,
-}
+// Shadow map array, comparison sampler and light-space matrices (group 0, bindings 5-7)
+#import shadows
 
-@group(0) @binding(1)
-var<uniform> light_data: LightData;
+// Environment maps for image based lighting (group 0, bindings 8-12)
+#import environment
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
 }
 
 struct InstanceInput {
@@ -36,6 +32,10 @@ struct InstanceInput {
     @location(9) normal_matrix_0: vec3<f32>,
     @location(10) normal_matrix_1: vec3<f32>,
     @location(11) normal_matrix_2: vec3<f32>,
+    // Tint multiplied into the base color
+    @location(12) color: vec4<f32>,
+    // Free for custom shaders
+    @location(13) custom: vec4<f32>,
 }
 
 struct VertexOutput {
@@ -43,6 +43,11 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) world_normal: vec3<f32>,
     @location(2) world_position: vec3<f32>,
+    @location(3) world_tangent: vec3<f32>,
+    @location(4) world_bitangent: vec3<f32>,
+    // Distance along the view direction, for picking the light cluster
+    @location(5) view_depth: f32,
+    @location(6) instance_color: vec4<f32>,
 }
 
 @vertex
@@ -65,55 +70,181 @@ fn vs_main(
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
     out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = normal_matrix * model.tangent;
+    out.world_bitangent = normal_matrix * model.bitangent;
     var world_position: vec4<f32> = model_matrix * vec4<f32>(model.position, 1.0);
     out.world_position = world_position.xyz;
     out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.view_depth = out.clip_position.w;
+    out.instance_color = instance.color;
     return out;
 }
 
 // Fragment shader
 
+// Metallic-roughness material. Each factor is multiplied by its texture; unset
+// texture slots are bound to white (or a flat normal), leaving the factor as is.
 struct MaterialProperties {
-    color: vec4<f32>,
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    ao: f32,
+    // Alpha below this is discarded in mask mode
+    alpha_cutoff: f32,
 }
 
+// Values for fields a material doesn't set; the rest default to zero
+const DEFAULT_BASE_COLOR: vec4<f32> = vec4<f32>(1.0);
+const DEFAULT_ROUGHNESS: f32 = 1.0;
+const DEFAULT_AO: f32 = 1.0;
+const DEFAULT_ALPHA_CUTOFF: f32 = 0.5;
+
+// Set per pipeline from the material's alpha mode
+override ALPHA_MODE: u32 = 0u;
+const ALPHA_OPAQUE: u32 = 0u;
+const ALPHA_MASK: u32 = 1u;
+const ALPHA_BLEND: u32 = 2u;
+
+// Base color (sRGB)
 @group(1) @binding(0)
 var t_diffuse: texture_2d<f32>;
 @group(1) @binding(1)
 var s_diffuse: sampler;
 @group(1) @binding(2)
 var<uniform> material_properties: MaterialProperties;
+// Materials without a normal map get a flat (0.5, 0.5, 1.0) texture here
+@group(1) @binding(3)
+var t_normal: texture_2d<f32>;
+@group(1) @binding(4)
+var s_normal: sampler;
+// Metallic is read from blue and roughness from green, so a packed glTF
+// metallic-roughness texture or separate greyscale maps both work
+@group(1) @binding(5)
+var t_metallic: texture_2d<f32>;
+@group(1) @binding(6)
+var s_metallic: sampler;
+@group(1) @binding(7)
+var t_roughness: texture_2d<f32>;
+@group(1) @binding(8)
+var s_roughness: sampler;
+@group(1) @binding(9)
+var t_emissive: texture_2d<f32>;
+@group(1) @binding(10)
+var s_emissive: sampler;
+// Ambient occlusion is read from red
+@group(1) @binding(11)
+var t_occlusion: texture_2d<f32>;
+@group(1) @binding(12)
+var s_occlusion: sampler;
+
+const PI: f32 = 3.14159265359;
+// Used without an environment map: fraction of each light's color applied everywhere
+const AMBIENT_STRENGTH: f32 = 0.03;
+
+// Trowbridge-Reitz GGX normal distribution
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
+}
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let texture_color: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
-    let object_color: vec4<f32> = texture_color * material_properties.color;
-    let view_dir = normalize(camera.view_pos.xyz - in.world_position);
-
-    var total_light = vec3<f32>(0.0, 0.0, 0.0);
-
-    for (var i = 0u; i < light_data.num_lights; i = i + 1u) {
-        let light = light_data.lights[i];
-        let light_to_frag = light.position.xyz - in.world_position;
-        let dist_sq = dot(light_to_frag, light_to_frag);
-        let attenuation = 1.0 / (1.0 + 0.1 * dist_sq); // Simple inverse square attenuation
+// Smith geometry term with Schlick-GGX for both view and light directions
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = (r * r) / 8.0;
+    let ggx_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let ggx_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return ggx_v * ggx_l;
+}
 
-        let light_dir = normalize(light_to_frag);
-        let half_dir = normalize(view_dir + light_dir);
+// Fades a light smoothly to zero at `range` (0 = unlimited), so cluster culling has no visible edge
+fn range_window(dist_sq: f32, range: f32) -> f32 {
+    if (range <= 0.0) {
+        return 1.0;
+    }
+    let ratio = dist_sq / (range * range);
+    return pow(clamp(1.0 - ratio * ratio, 0.0, 1.0), 2.0);
+}
 
-        let ambient_strength = 0.1;
-        let ambient_color = light.color.xyz * ambient_strength;
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
 
-        let diffuse_strength = max(dot(in.world_normal, light_dir), 0.0);
-        let diffuse_color = light.color.xyz * diffuse_strength;
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base_color = textureSample(t_diffuse, s_diffuse, in.tex_coords) * material_properties.base_color * in.instance_color;
+    if (ALPHA_MODE == ALPHA_MASK && base_color.a < material_properties.alpha_cutoff) {
+        discard;
+    }
+    let metallic = clamp(textureSample(t_metallic, s_metallic, in.tex_coords).b * material_properties.metallic, 0.0, 1.0);
+    // Clamp away from zero: a perfectly smooth GGX lobe is a singularity for point lights
+    let roughness = clamp(textureSample(t_roughness, s_roughness, in.tex_coords).g * material_properties.roughness, 0.04, 1.0);
+    let emissive = textureSample(t_emissive, s_emissive, in.tex_coords).rgb * material_properties.emissive;
+    let ao = textureSample(t_occlusion, s_occlusion, in.tex_coords).r * material_properties.ao;
+    let tangent_normal = textureSample(t_normal, s_normal, in.tex_coords).xyz * 2.0 - 1.0;
+
+    // Re-orthogonalize the interpolated tangent frame before moving the normal into world space
+    let vertex_normal = normalize(in.world_normal);
+    let tangent = normalize(in.world_tangent - vertex_normal * dot(vertex_normal, in.world_tangent));
+    let bitangent = normalize(in.world_bitangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, vertex_normal);
+    let world_normal = normalize(tbn * tangent_normal);
 
-        let specular_strength = pow(max(dot(in.world_normal, half_dir), 0.0), 32.0);
-        let specular_color = specular_strength * light.color.xyz;
+    let view_dir = normalize(camera.view_pos.xyz - in.world_position);
+    let n_dot_v = max(dot(world_normal, view_dir), 0.0001);
+
+    // Dielectrics reflect about 4% at normal incidence; metals tint reflections with base color
+    let f0 = mix(vec3<f32>(0.04), base_color.rgb, metallic);
+
+    var radiance_out = vec3<f32>(0.0, 0.0, 0.0);
+    var ambient_light = vec3<f32>(0.0, 0.0, 0.0);
+
+    // Only the lights whose range reaches this fragment's cluster
+    let cluster = light_cluster_at(in.clip_position.xy, in.view_depth);
+    for (var i = 0u; i < num_cluster_lights(cluster); i = i + 1u) {
+        let light = cluster_light(cluster, i);
+        var light_dir: vec3<f32>;
+        var attenuation = 1.0;
+        var window = 1.0;
+        if (light.kind == LIGHT_DIRECTIONAL) {
+            light_dir = -normalize(light.direction);
+        } else {
+            let light_to_frag = light.position - in.world_position;
+            let dist_sq = dot(light_to_frag, light_to_frag);
+            light_dir = normalize(light_to_frag);
+            window = range_window(dist_sq, light.range);
+            attenuation = window / (1.0 + 0.1 * dist_sq);
+            if (light.kind == LIGHT_SPOT) {
+                let cos_angle = dot(-light_dir, normalize(light.direction));
+                attenuation = attenuation * smoothstep(light.outer_cone_cos, light.inner_cone_cos, cos_angle);
+            }
+        }
+        let shadow = shadow_visibility(light, in.world_position, vertex_normal, light_dir, in.view_depth);
+        let radiance = light.color.rgb * light.intensity * attenuation * shadow;
 
-        // Apply attenuation to diffuse and specular components
-        total_light = total_light + ambient_color + (diffuse_color + specular_color) * attenuation;
+        let half_dir = normalize(view_dir + light_dir);
+        let n_dot_l = max(dot(world_normal, light_dir), 0.0);
+        let n_dot_h = max(dot(world_normal, half_dir), 0.0);
+
+        let fresnel = fresnel_schlick(max(dot(half_dir, view_dir), 0.0), f0);
+        let specular = distribution_ggx(n_dot_h, roughness)
+            * geometry_smith(n_dot_v, n_dot_l, roughness)
+            * fresnel / (4.0 * n_dot_v * n_dot_l + 0.0001);
+
+        // Energy not reflected specularly is diffused, except by metals
+        let k_diffuse = (vec3<f32>(1.0) - fresnel) * (1.0 - metallic);
+        radiance_out = radiance_out + (k_diffuse * base_color.rgb / PI + specular) * radiance * n_dot_l;
+        ambient_light = ambient_light + light.color.rgb * light.intensity * window * AMBIENT_STRENGTH;
     }
 
-    let result = total_light * object_color.xyz;
-    return vec4<f32>(result, object_color.a);
+    var ambient = ambient_light * base_color.rgb;
+    if (environment_enabled()) {
+        ambient = environment_lighting(world_normal, view_dir, base_color.rgb, metallic, roughness, f0);
+    }
+    ambient = ambient * ao;
+    let result = ambient + radiance_out + emissive;
+    let alpha = select(1.0, base_color.a, ALPHA_MODE == ALPHA_BLEND);
+    return vec4<f32>(result, alpha);
 }
@@ -0,0 +1,27 @@
+// Environment cubemap drawn behind everything else
+
+// Environment maps and view rotation (group 0, bindings 8-12)
+#import environment
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) direction: vec3<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // Fullscreen triangle on the far plane
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    let ndc = vec4<f32>(uv * 2.0 - 1.0, 1.0, 1.0);
+    var out: VertexOutput;
+    out.clip_position = ndc;
+    let world = environment.inv_view_proj * ndc;
+    out.direction = world.xyz / world.w;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(environment_map, environment_sampler, normalize(in.direction), 0.0).rgb;
+    return vec4<f32>(color * environment.intensity, 1.0);
+}
@@ -0,0 +1,58 @@
+// Fullscreen pass mapping the HDR scene target into the swapchain
+
+const TONEMAP_NONE: u32 = 0u;
+const TONEMAP_REINHARD: u32 = 1u;
+const TONEMAP_ACES: u32 = 2u;
+
+struct ToneMapParams {
+    exposure: f32,
+    tonemapper: u32,
+    // Set when the swapchain isn't sRGB, so the shader has to encode
+    encode_srgb: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> params: ToneMapParams;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // (-1, -1), (3, -1), (-1, 3): a triangle whose inside covers the whole viewport
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn linear_to_srgb(x: vec3<f32>) -> vec3<f32> {
+    let low = x * 12.92;
+    let high = 1.055 * pow(x, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, x <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+    let hdr = textureLoad(t_scene, vec2<i32>(frag_coord.xy), 0).rgb * params.exposure;
+    var color: vec3<f32>;
+    if (params.tonemapper == TONEMAP_ACES) {
+        color = aces(hdr);
+    } else if (params.tonemapper == TONEMAP_REINHARD) {
+        color = hdr / (1.0 + hdr);
+    } else {
+        color = clamp(hdr, vec3<f32>(0.0), vec3<f32>(1.0));
+    }
+    if (params.encode_srgb != 0u) {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
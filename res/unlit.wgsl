@@ -0,0 +1,84 @@
+// Vertex shader
+
+struct CameraUniform {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+}
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    // Tint multiplied into the base color
+    @location(12) color: vec4<f32>,
+    // Free for custom shaders
+    @location(13) custom: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) instance_color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3
+    );
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.instance_color = instance.color;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+struct MaterialProperties {
+    base_color: vec4<f32>,
+    // Alpha below this is discarded in mask mode
+    alpha_cutoff: f32,
+}
+
+// Values for fields a material doesn't set
+const DEFAULT_BASE_COLOR: vec4<f32> = vec4<f32>(1.0);
+const DEFAULT_ALPHA_CUTOFF: f32 = 0.5;
+
+// Set per pipeline from the material's alpha mode
+override ALPHA_MODE: u32 = 0u;
+const ALPHA_MASK: u32 = 1u;
+const ALPHA_BLEND: u32 = 2u;
+
+@group(1) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(1) @binding(1)
+var s_diffuse: sampler;
+@group(1) @binding(2)
+var<uniform> material_properties: MaterialProperties;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texture_color: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let color = texture_color * material_properties.base_color * in.instance_color;
+    if (ALPHA_MODE == ALPHA_MASK && color.a < material_properties.alpha_cutoff) {
+        discard;
+    }
+    return vec4<f32>(color.rgb, select(1.0, color.a, ALPHA_MODE == ALPHA_BLEND));
+}
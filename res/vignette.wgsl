@@ -0,0 +1,17 @@
+// Vignette: darkens the image towards its edges
+
+#import post_process
+
+// values: x intensity, y radius where darkening ends, z smoothness of the falloff
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_linear, in.uv).rgb;
+    let intensity = params.values.x;
+    let radius = params.values.y;
+    let smoothness = max(params.values.z, 0.001);
+
+    // 0 at the center, 1 at the middle of each edge
+    let edge_distance = length(in.uv - 0.5) * 2.0;
+    let falloff = smoothstep(radius - smoothness, radius, edge_distance);
+    return finish(color * (1.0 - falloff * intensity));
+}
@@ -0,0 +1,438 @@
+use cgmath::{InnerSpace, Matrix4, One, Quaternion, Vector3, VectorSpace};
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, Mul};
+
+/// Most distinct time offsets the instances of one system can be spread over
+pub const MAX_ANIMATION_OFFSETS: u32 = 16;
+
+/// A node's transform relative to its parent, split the way glTF animates it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeTransform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for NodeTransform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl NodeTransform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// `self` moved `t` of the way to `other`, rotating the short way round
+    pub fn blend(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self
+                .rotation
+                .nlerp(shortest_arc(self.rotation, other.rotation), t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+/// Local transform and morph target weights of every node of a skeleton
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pose {
+    pub transforms: Vec<NodeTransform>,
+    /// Weights of the morph targets of each node's mesh, empty for nodes without any
+    pub weights: Vec<Vec<f32>>,
+}
+
+impl Pose {
+    /// `self` moved `t` of the way to `other`, node by node
+    pub fn blend(&self, other: &Self, t: f32) -> Self {
+        Self {
+            transforms: self
+                .transforms
+                .iter()
+                .zip(&other.transforms)
+                .map(|(from, to)| from.blend(to, t))
+                .collect(),
+            weights: self
+                .weights
+                .iter()
+                .zip(&other.weights)
+                .map(|(from, to)| {
+                    // Missing weights are zero
+                    (0..from.len().max(to.len()))
+                        .map(|i| {
+                            let from = from.get(i).copied().unwrap_or_default();
+                            let to = to.get(i).copied().unwrap_or_default();
+                            from + (to - from) * t
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// `to`, or its negation if that's the nearer of the two equivalent rotations to `from`
+pub fn shortest_arc(from: Quaternion<f32>, to: Quaternion<f32>) -> Quaternion<f32> {
+    if from.dot(to) < 0.0 { -to } else { to }
+}
+
+pub struct SkeletonNode {
+    pub name: String,
+    pub parent: Option<usize>,
+    /// Transform when no clip moves the node
+    pub rest: NodeTransform,
+    /// Morph target weights of the node's mesh when no clip animates them
+    pub weights: Vec<f32>,
+}
+
+/// Node hierarchy of a model, every parent ordered before its children
+#[derive(Default)]
+pub struct Skeleton {
+    pub nodes: Vec<SkeletonNode>,
+}
+
+impl Skeleton {
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            transforms: self.nodes.iter().map(|node| node.rest).collect(),
+            weights: self.nodes.iter().map(|node| node.weights.clone()).collect(),
+        }
+    }
+
+    /// Model-space transform of every node for local transforms `pose`
+    pub fn world_matrices(&self, pose: &[NodeTransform]) -> Vec<Matrix4<f32>> {
+        let mut world: Vec<Matrix4<f32>> = Vec::with_capacity(self.nodes.len());
+        for (node, transform) in self.nodes.iter().zip(pose) {
+            let local = transform.matrix();
+            world.push(match node.parent {
+                Some(parent) => world[parent] * local,
+                None => local,
+            });
+        }
+        world
+    }
+}
+
+/// Joints that skinned meshes are bound to, with their bind poses
+pub struct Skin {
+    pub name: String,
+    /// Skeleton node of each joint
+    pub joints: Vec<usize>,
+    /// Takes a bind-pose vertex into each joint's space
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+impl Skin {
+    /// Matrix taking bind-pose vertices to their posed position, for each joint
+    pub fn joint_matrices(&self, world: &[Matrix4<f32>]) -> Vec<Matrix4<f32>> {
+        self.joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(&node, inverse_bind)| world[node] * inverse_bind)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    /// Hermite spline; each key stores an in tangent, the value and an out tangent
+    CubicSpline,
+}
+
+pub enum ChannelValues {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<Quaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+    /// One list of keys per morph target of the node's mesh
+    Weights(Vec<Vec<f32>>),
+}
+
+/// Keyframes animating one property of one node
+pub struct Channel {
+    pub node: usize,
+    pub interpolation: Interpolation,
+    /// Key times in seconds, ascending
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+}
+
+impl Channel {
+    /// Interpolated value of `values` (laid out per `interpolation`) at `time`
+    fn sample<T>(&self, values: &[T], time: f32) -> Option<T>
+    where
+        T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+    {
+        let stride = if self.interpolation == Interpolation::CubicSpline {
+            3
+        } else {
+            1
+        };
+        let value = |key: usize| values.get(key * stride + stride / 2).copied();
+        let last = self.times.len().checked_sub(1)?;
+        let next = self.times.partition_point(|&key_time| key_time <= time);
+        if next == 0 {
+            return value(0);
+        }
+        if next > last {
+            return value(last);
+        }
+
+        let key = next - 1;
+        let span = self.times[next] - self.times[key];
+        let t = if span > 0.0 {
+            (time - self.times[key]) / span
+        } else {
+            0.0
+        };
+        match self.interpolation {
+            Interpolation::Step => value(key),
+            Interpolation::Linear => Some(value(key)? * (1.0 - t) + value(next)? * t),
+            Interpolation::CubicSpline => {
+                let out_tangent = *values.get(key * 3 + 2)?;
+                let in_tangent = *values.get(next * 3)?;
+                let (t2, t3) = (t * t, t * t * t);
+                Some(
+                    value(key)? * (2.0 * t3 - 3.0 * t2 + 1.0)
+                        + out_tangent * ((t3 - 2.0 * t2 + t) * span)
+                        + value(next)? * (-2.0 * t3 + 3.0 * t2)
+                        + in_tangent * ((t3 - t2) * span),
+                )
+            }
+        }
+    }
+}
+
+pub struct AnimationClip {
+    pub name: String,
+    /// Time of the last key of any channel
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    /// Overwrite the nodes this clip animates with their transform and weights at `time`
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in &self.channels {
+            if let ChannelValues::Weights(targets) = &channel.values {
+                let Some(weights) = pose.weights.get_mut(channel.node) else {
+                    continue;
+                };
+                weights.resize(weights.len().max(targets.len()), 0.0);
+                for (weight, values) in weights.iter_mut().zip(targets) {
+                    if let Some(value) = channel.sample(values, time) {
+                        *weight = value;
+                    }
+                }
+                continue;
+            }
+            let Some(transform) = pose.transforms.get_mut(channel.node) else {
+                continue;
+            };
+            match &channel.values {
+                ChannelValues::Translation(values) => {
+                    if let Some(translation) = channel.sample(values, time) {
+                        transform.translation = translation;
+                    }
+                }
+                ChannelValues::Rotation(values) => {
+                    if let Some(rotation) = channel.sample(values, time) {
+                        transform.rotation = rotation.normalize();
+                    }
+                }
+                ChannelValues::Scale(values) => {
+                    if let Some(scale) = channel.sample(values, time) {
+                        transform.scale = scale;
+                    }
+                }
+                ChannelValues::Weights(_) => {}
+            }
+        }
+    }
+
+    /// Clip time for a playback time, wrapped or held at the ends
+    fn clip_time(&self, time: f32, looping: bool) -> f32 {
+        if self.duration <= 0.0 {
+            0.0
+        } else if looping {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration)
+        }
+    }
+}
+
+/// Where the weights of a system's morph targets come from, in place of the model's own
+/// or its animation's. Target `i` of every morphed mesh gets the `i`th weight.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum MorphWeights {
+    #[serde(rename = "fixed")]
+    Fixed { weights: Vec<f32> },
+    /// Weights from a script function called with the target count every frame
+    #[serde(rename = "script")]
+    Script { function: String },
+}
+
+impl MorphWeights {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MorphWeights::Fixed { .. } => "Fixed",
+            MorphWeights::Script { .. } => "Script",
+        }
+    }
+}
+
+/// A clip fading out under the one now playing
+#[derive(Debug, Clone, PartialEq)]
+struct FadingClip {
+    clip: String,
+    time: f32,
+    elapsed: f32,
+    duration: f32,
+}
+
+/// Plays one of a model's clips, cross-fading from the previous one when switched
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationPlayer {
+    clip: String,
+    pub playing: bool,
+    pub looping: bool,
+    /// Playback rate, negative to play backwards
+    pub speed: f32,
+    /// Seconds `play` cross-fades from the previous clip over
+    pub fade: f32,
+    /// Distinct time offsets instances are spread over, at most `MAX_ANIMATION_OFFSETS`
+    pub offset_count: u32,
+    /// Seconds the offsets are spaced evenly over; a looping clip's duration spreads
+    /// them around the whole loop
+    pub offset_spread: f32,
+    #[serde(skip)]
+    time: f32,
+    #[serde(skip)]
+    previous: Option<FadingClip>,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            clip: String::new(),
+            playing: true,
+            looping: true,
+            speed: 1.0,
+            fade: 0.25,
+            offset_count: 1,
+            offset_spread: 0.0,
+            time: 0.0,
+            previous: None,
+        }
+    }
+}
+
+impl AnimationPlayer {
+    pub fn new(clip: &str) -> Self {
+        Self {
+            clip: clip.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn clip(&self) -> &str {
+        &self.clip
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Play `clip` from the start, fading over `fade` seconds from the current one.
+    /// Playing the current clip again just resumes it.
+    pub fn play(&mut self, clip: &str) {
+        self.playing = true;
+        if clip == self.clip {
+            return;
+        }
+        self.previous = (self.fade > 0.0 && !self.clip.is_empty()).then(|| FadingClip {
+            clip: std::mem::take(&mut self.clip),
+            time: self.time,
+            elapsed: 0.0,
+            duration: self.fade,
+        });
+        self.clip = clip.to_string();
+        self.time = 0.0;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn resume(&mut self) {
+        self.playing = true;
+    }
+
+    /// Restart the current clip, dropping any fade
+    pub fn rewind(&mut self) {
+        self.time = 0.0;
+        self.previous = None;
+    }
+
+    pub fn offset_count(&self) -> usize {
+        self.offset_count.clamp(1, MAX_ANIMATION_OFFSETS) as usize
+    }
+
+    /// Time added to the playback time of instances given `offset`
+    pub fn offset_time(&self, offset: usize) -> f32 {
+        self.offset_spread * offset as f32 / self.offset_count() as f32
+    }
+
+    /// Move playback on by `dt` seconds
+    pub fn advance(&mut self, dt: f32) {
+        if !self.playing {
+            return;
+        }
+        self.time += dt * self.speed;
+        if let Some(previous) = &mut self.previous {
+            previous.time += dt * self.speed;
+            previous.elapsed += dt;
+            if previous.elapsed >= previous.duration {
+                self.previous = None;
+            }
+        }
+    }
+
+    /// Pose of `skeleton` at the current time plus `offset` seconds, blended with the
+    /// fading clip. Nodes no clip moves stay at rest.
+    pub fn pose(&self, skeleton: &Skeleton, clips: &[AnimationClip], offset: f32) -> Pose {
+        let find = |name: &str| clips.iter().find(|clip| clip.name == name);
+        let sampled = |clip: &AnimationClip, time: f32| {
+            let mut pose = skeleton.rest_pose();
+            clip.sample(clip.clip_time(time + offset, self.looping), &mut pose);
+            pose
+        };
+
+        let Some(clip) = find(&self.clip) else {
+            return skeleton.rest_pose();
+        };
+        let pose = sampled(clip, self.time);
+        match &self.previous {
+            Some(previous) => {
+                let Some(previous_clip) = find(&previous.clip) else {
+                    return pose;
+                };
+                let weight = (previous.elapsed / previous.duration).clamp(0.0, 1.0);
+                sampled(previous_clip, previous.time).blend(&pose, weight)
+            }
+            None => pose,
+        }
+    }
+}
@@ -1,17 +1,35 @@
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-
-use crate::light::LightManager;
+use std::sync::Arc;
+
+use crate::animation::{AnimationPlayer, MAX_ANIMATION_OFFSETS, MorphWeights};
+use crate::environment::EnvironmentSource;
+use crate::light::{Light, LightKind, LightManager};
+use crate::lod::{LodLevel, MAX_LOD_LEVELS};
+use crate::material_schema::{UniformField, UniformType, UniformValues};
+use crate::model::{AlphaMode, MaterialRegistry, TextureRegistry, slot_color_space};
+use crate::particle_simulation::SimulationParams;
 use crate::particle_system::{
-    GeneratorType, GridParams, ParticleSystem, ParticleSystemManager, SphereParams,
+    BoxParams, GeneratorType, GridParams, HeightmapParams, InstanceAttributes, InstanceValues,
+    JitterParams, LineParams, MeshSurfaceParams, ParticleSystem, ParticleSystemManager,
+    PoissonDiskParams, RandomizeParams, RingParams, SphereParams, SpiralParams, TransformParams,
+    UnionParams,
 };
+use crate::post_process::{PostEffect, PostProcessPass};
+use crate::render_settings::RenderSettings;
+use crate::texture::{AddressMode, ColorSpace, FilterMode, SamplerSettings};
+use crate::tonemapping::Tonemapper;
+use crate::world::EnvironmentData;
 use egui::{Align2, Context};
 
 pub struct UiState {
     pub model_path_input: String,
     pub new_material_name: String,
-    pub new_material_texture: String,
-    pub new_material_color: [f32; 4],
+    pub new_material_shader: String,
+    pub new_material_textures: HashMap<String, String>,
+    pub new_material_uniforms: UniformValues,
+    pub shader_path_input: String,
+    pub environment_path_input: String,
+    pub lut_path_input: String,
 }
 
 impl Default for UiState {
@@ -19,8 +37,12 @@ impl Default for UiState {
         Self {
             model_path_input: String::new(),
             new_material_name: String::new(),
-            new_material_texture: String::new(),
-            new_material_color: [1.0, 1.0, 1.0, 1.0],
+            new_material_shader: crate::defaults::DEFAULT_SHADER_PATH.to_string(),
+            new_material_textures: HashMap::new(),
+            new_material_uniforms: UniformValues::new(),
+            shader_path_input: String::new(),
+            environment_path_input: String::new(),
+            lut_path_input: String::new(),
         }
     }
 }
@@ -29,9 +51,18 @@ pub struct UiActions {
     pub save_requested: bool,
     pub load_requested: bool,
     pub model_to_load: Option<String>,
-    pub material_color_changed: Option<(crate::model::MaterialSource, [f32; 4])>,
-    pub material_to_create: Option<(String, String, [f32; 4])>, // (name, texture_path, color)
-    pub material_texture_changed: Option<(crate::model::MaterialSource, String)>, // (material_source, new_texture_path)
+    pub material_uniform_changed: Option<(crate::model::MaterialSource, String, [f32; 4])>, // (material_source, field, value)
+    pub material_to_create: Option<(String, String, HashMap<String, String>, UniformValues)>, // (name, shader, textures, uniforms)
+    pub material_texture_changed: Option<(crate::model::MaterialSource, String, String)>, // (material_source, slot, new_texture_path)
+    pub material_shader_changed: Option<(crate::model::MaterialSource, String)>, // (material_source, new_shader)
+    pub material_alpha_mode_changed: Option<(crate::model::MaterialSource, AlphaMode)>,
+    pub material_sampler_changed: Option<(
+        crate::model::MaterialSource,
+        String,
+        Option<SamplerSettings>,
+    )>, // (material_source, slot, None to reset)
+    pub shader_to_load: Option<String>,
+    pub environment_changed: Option<Option<EnvironmentData>>, // None inside clears the environment
 }
 
 impl Default for UiActions {
@@ -40,29 +71,52 @@ impl Default for UiActions {
             save_requested: false,
             load_requested: false,
             model_to_load: None,
-            material_color_changed: None,
+            material_uniform_changed: None,
             material_to_create: None,
             material_texture_changed: None,
+            material_shader_changed: None,
+            material_alpha_mode_changed: None,
+            material_sampler_changed: None,
+            shader_to_load: None,
+            environment_changed: None,
         }
     }
 }
 
+/// Scene state the UI reads but doesn't edit
+pub struct UiInputs<'a> {
+    pub environment: Option<&'a EnvironmentData>,
+    pub delta_time_ms: f32,
+    pub device: &'a wgpu::Device,
+    pub models: &'a HashMap<String, Arc<crate::model::Model>>,
+    pub materials: &'a MaterialRegistry,
+    pub textures: &'a TextureRegistry,
+    pub pipeline_cache: &'a crate::pipeline::PipelineCache,
+    pub loading_models_count: usize,
+}
+
 pub fn app_ui(
     ctx: &Context,
     clear_color: &mut wgpu::Color,
+    render_settings: &mut RenderSettings,
     particle_system_manager: &mut ParticleSystemManager,
     light_manager: &mut LightManager,
-    light_buffer: &wgpu::Buffer,
-    delta_time_ms: f32,
-    queue: &wgpu::Queue,
-    device: &wgpu::Device,
-    models: &HashMap<String, Arc<crate::model::Model>>,
-    materials: &HashMap<crate::model::MaterialSource, Arc<crate::model::GpuMaterial>>,
-    textures: &Arc<Mutex<HashMap<String, std::sync::Arc<crate::texture::GpuTexture>>>>,
+    inputs: UiInputs,
     ui_state: &mut UiState,
-    loading_models_count: usize,
 ) -> UiActions {
+    let UiInputs {
+        environment,
+        delta_time_ms,
+        device,
+        models,
+        materials,
+        textures,
+        pipeline_cache,
+        loading_models_count,
+    } = inputs;
     let mut actions = UiActions::default();
+    let mut shader_paths: Vec<String> = pipeline_cache.shader_paths().cloned().collect();
+    shader_paths.sort();
     egui::Window::new("Scene Editor")
         .default_open(true)
         .max_width(400.0)
@@ -98,13 +152,14 @@ pub fn app_ui(
 
             // Check particle systems
             for (_name, system) in particle_system_manager.systems() {
-                if !models.contains_key(system.model_path()) {
-                    missing_models.insert(system.model_path().to_string());
-                } else {
-                    let material_source = system.material_source();
-                    if !materials.contains_key(material_source) {
-                        missing_materials.insert(material_source.display_key());
+                if let Some(model) = models.get(system.model_path()) {
+                    for (_index, material_source) in system.mesh_materials(model) {
+                        if !materials.contains_key(&material_source) {
+                            missing_materials.insert(material_source.display_key());
+                        }
                     }
+                } else {
+                    missing_models.insert(system.model_path().to_string());
                 }
             }
 
@@ -136,162 +191,238 @@ pub fn app_ui(
                 clear_color.a = color[3].clamp(0.0, 1.0) as f64;
             }
 
-            ui.separator();
+            // HDR scene color is scaled by exposure, then mapped to display range
+            egui::ComboBox::from_id_salt("tonemapper")
+                .selected_text(render_settings.tone_map.tonemapper.label())
+                .show_ui(ui, |ui| {
+                    for tonemapper in Tonemapper::ALL {
+                        ui.selectable_value(
+                            &mut render_settings.tone_map.tonemapper,
+                            tonemapper,
+                            tonemapper.label(),
+                        );
+                    }
+                });
+            ui.add(
+                egui::Slider::new(&mut render_settings.tone_map.exposure, 0.01..=16.0)
+                    .logarithmic(true)
+                    .text("Exposure"),
+            );
 
-            // Light Manager
-            ui.collapsing(
-                format!(
-                    "Lights ({}/{})",
-                    light_manager.num_lights(),
-                    light_manager.max_lights()
-                ),
-                |ui| {
-                    let mut needs_gpu_sync = false;
+            let msaa_samples = render_settings.msaa_samples();
+            egui::ComboBox::from_id_salt("msaa_samples")
+                .selected_text(msaa_label(msaa_samples))
+                .show_ui(ui, |ui| {
+                    for count in render_settings.supported_msaa_samples().to_vec() {
+                        ui.selectable_value(
+                            &mut render_settings.requested_msaa_samples,
+                            count,
+                            msaa_label(count),
+                        );
+                    }
+                });
+            ui.checkbox(&mut render_settings.frustum_culling, "Frustum culling");
+
+            ui.separator();
 
-                    // Add light button
-                    if ui.button("➕ Add Light").clicked() {
-                        if let Some(_idx) =
-                            light_manager.add_light([0.0, 3.0, 0.0], [1.0, 1.0, 1.0, 1.0])
+            // Environment map for the skybox and ambient lighting
+            ui.collapsing("🌅 Environment", |ui| {
+                match environment {
+                    Some(data) => {
+                        ui.label(format!("Source: {}", data.source.paths().join(", ")));
+                        let mut intensity = data.intensity;
+                        if ui
+                            .add(
+                                egui::Slider::new(&mut intensity, 0.0..=8.0)
+                                    .logarithmic(true)
+                                    .text("Intensity"),
+                            )
+                            .changed()
                         {
-                            needs_gpu_sync = true;
+                            actions.environment_changed = Some(Some(EnvironmentData {
+                                intensity,
+                                ..data.clone()
+                            }));
+                        }
+                        if ui.button("Clear").clicked() {
+                            actions.environment_changed = Some(None);
                         }
                     }
+                    None => {
+                        ui.label("None (solid background, flat ambient)");
+                    }
+                }
 
-                    ui.separator();
+                ui.label("Enter panorama path (e.g., 'sky.hdr'):");
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut ui_state.environment_path_input);
+
+                    if ui.button("Load").clicked() && !ui_state.environment_path_input.is_empty() {
+                        actions.environment_changed = Some(Some(EnvironmentData {
+                            source: EnvironmentSource::Equirectangular {
+                                path: ui_state.environment_path_input.clone(),
+                            },
+                            intensity: environment.map_or(1.0, |data| data.intensity),
+                        }));
+                        ui_state.environment_path_input.clear();
+                    }
+                });
+            });
 
+            // Post-process chain, run top to bottom after tonemapping
+            let post_process = &mut render_settings.post_process;
+            ui.collapsing(
+                format!("✨ Post Processing ({})", post_process.len()),
+                |ui| {
                     let mut to_remove = None;
+                    let mut to_move_up = None;
+                    for (i, pass) in post_process.iter_mut().enumerate() {
+                        ui.push_id(i, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut pass.enabled, pass.effect.label());
+                                if ui.small_button("⬆").clicked() && i > 0 {
+                                    to_move_up = Some(i);
+                                }
+                                if ui.small_button("🗑").clicked() {
+                                    to_remove = Some(i);
+                                }
+                            });
+                            post_effect_editor(ui, &mut pass.effect, &mut ui_state.lut_path_input);
+                        });
+                        ui.separator();
+                    }
+                    if let Some(i) = to_move_up {
+                        post_process.swap(i - 1, i);
+                    }
+                    if let Some(i) = to_remove {
+                        post_process.remove(i);
+                    }
 
-                    // Iterate through all possible light slots
-                    for i in 0..light_manager.max_lights() {
-                        if let Some(light) = light_manager.get_light(i) {
-                            // Copy light data to avoid borrow checker issues
-                            let mut pos = [light.position[0], light.position[1], light.position[2]];
-                            let mut color = light.color;
-
-                            ui.push_id(i, |ui| {
-                                ui.horizontal(|ui| {
-                                    let header =
-                                        egui::CollapsingHeader::new(format!("Light {}", i))
-                                            .default_open(false);
-
-                                    if header
-                                        .show(ui, |ui| {
-                                            ui.label("Model & Material:");
-
-                                            egui::ComboBox::from_id_source(format!(
-                                                "light_{}_model",
-                                                i
-                                            ))
-                                            .selected_text(light_manager.model_path())
-                                            .show_ui(
-                                                ui,
-                                                |ui| {
-                                                    for model_path in models.keys() {
-                                                        if ui
-                                                            .selectable_label(
-                                                                light_manager.model_path()
-                                                                    == model_path,
-                                                                model_path,
-                                                            )
-                                                            .clicked()
-                                                        {
-                                                            light_manager
-                                                                .set_model_path(model_path.clone());
-                                                        }
-                                                    }
-                                                },
-                                            );
+                    ui.horizontal_wrapped(|ui| {
+                        for effect in PostEffect::defaults() {
+                            if ui.button(format!("➕ {}", effect.label())).clicked() {
+                                post_process.push(PostProcessPass::new(effect));
+                            }
+                        }
+                    });
+                },
+            );
+
+            ui.separator();
+
+            // Light Manager
+            ui.collapsing(format!("Lights ({})", light_manager.num_lights()), |ui| {
+                // Add light button
+                if ui.button("➕ Add Light").clicked() {
+                    light_manager.add_light(Light::point([0.0, 3.0, 0.0], [1.0, 1.0, 1.0, 1.0]));
+                }
+
+                ui.separator();
+
+                let mut to_remove = None;
+
+                // Iterate through all possible light slots
+                for i in 0..light_manager.slot_count() {
+                    if let Some(light) = light_manager.get_light(i) {
+                        // Copy light data to avoid borrow checker issues
+                        let mut light = *light;
+
+                        ui.push_id(i, |ui| {
+                            ui.horizontal(|ui| {
+                                let header = egui::CollapsingHeader::new(format!("Light {}", i))
+                                    .default_open(false);
+
+                                if header
+                                    .show(ui, |ui| {
+                                        ui.label("Model & Material:");
 
-                                            // Material dropdown
-                                            let current_display =
-                                                light_manager.material_source().display_name();
-
-                                            egui::ComboBox::from_id_source(format!(
-                                                "light_{}_material",
-                                                i
-                                            ))
-                                            .selected_text(current_display)
-                                            .show_ui(
-                                                ui,
-                                                |ui| {
-                                                    // Show all available materials
-                                                    for material_source in materials.keys() {
-                                                        let is_selected = light_manager
-                                                            .material_source()
-                                                            == material_source;
-
-                                                        if ui
-                                                            .selectable_label(
-                                                                is_selected,
-                                                                material_source.display_key(),
-                                                            )
-                                                            .clicked()
-                                                        {
-                                                            light_manager.set_material_source(
-                                                                material_source.clone(),
-                                                            );
-                                                        }
+                                        egui::ComboBox::from_id_source(format!(
+                                            "light_{}_model",
+                                            i
+                                        ))
+                                        .selected_text(light_manager.model_path())
+                                        .show_ui(
+                                            ui,
+                                            |ui| {
+                                                for model_path in models.keys() {
+                                                    if ui
+                                                        .selectable_label(
+                                                            light_manager.model_path()
+                                                                == model_path,
+                                                            model_path,
+                                                        )
+                                                        .clicked()
+                                                    {
+                                                        light_manager
+                                                            .set_model_path(model_path.clone());
                                                     }
-                                                },
-                                            );
+                                                }
+                                            },
+                                        );
 
-                                            ui.separator();
-                                            ui.label("Position:");
-                                            let pos_changed = ui
-                                                .add(
-                                                    egui::Slider::new(&mut pos[0], -20.0..=20.0)
-                                                        .text("X"),
-                                                )
-                                                .changed()
-                                                | ui.add(
-                                                    egui::Slider::new(&mut pos[1], -20.0..=20.0)
-                                                        .text("Y"),
-                                                )
-                                                .changed()
-                                                | ui.add(
-                                                    egui::Slider::new(&mut pos[2], -20.0..=20.0)
-                                                        .text("Z"),
-                                                )
-                                                .changed();
-
-                                            ui.label("Color:");
-                                            let color_changed = ui
-                                                .color_edit_button_rgba_unmultiplied(&mut color)
-                                                .changed();
-
-                                            if pos_changed || color_changed {
-                                                needs_gpu_sync = true;
-                                            }
-                                        })
-                                        .body_returned
-                                        .is_some()
-                                    {
-                                        // Delete button next to the header
-                                        if ui.button("🗑").clicked() {
-                                            to_remove = Some(i);
+                                        let mut entire_model = light_manager.entire_model();
+                                        if ui.checkbox(&mut entire_model, "Entire model").changed()
+                                        {
+                                            light_manager.set_entire_model(entire_model);
                                         }
+
+                                        // Material dropdown
+                                        let current_display =
+                                            light_manager.material_source().display_name();
+
+                                        egui::ComboBox::from_id_source(format!(
+                                            "light_{}_material",
+                                            i
+                                        ))
+                                        .selected_text(current_display)
+                                        .show_ui(
+                                            ui,
+                                            |ui| {
+                                                // Show all available materials
+                                                for material_source in materials.keys() {
+                                                    let is_selected = light_manager
+                                                        .material_source()
+                                                        == material_source;
+
+                                                    if ui
+                                                        .selectable_label(
+                                                            is_selected,
+                                                            material_source.display_key(),
+                                                        )
+                                                        .clicked()
+                                                    {
+                                                        light_manager.set_material_source(
+                                                            material_source.clone(),
+                                                        );
+                                                    }
+                                                }
+                                            },
+                                        );
+
+                                        ui.separator();
+                                        light_editor(ui, i, &mut light);
+                                    })
+                                    .body_returned
+                                    .is_some()
+                                {
+                                    // Delete button next to the header
+                                    if ui.button("🗑").clicked() {
+                                        to_remove = Some(i);
                                     }
-                                });
+                                }
                             });
+                        });
 
-                            // Update light after UI interaction
-                            light_manager.update_light(i, pos, color);
-                        }
-                    }
-
-                    // Remove light if delete was clicked
-                    if let Some(idx) = to_remove {
-                        light_manager.remove_light(idx);
-                        needs_gpu_sync = true;
+                        // Update light after UI interaction
+                        light_manager.update_light(i, light);
                     }
+                }
 
-                    // Sync to GPU if anything changed
-                    if needs_gpu_sync {
-                        let light_data = light_manager.sync_to_gpu();
-                        queue.write_buffer(light_buffer, 0, bytemuck::cast_slice(&[light_data]));
-                    }
-                },
-            );
+                // Remove light if delete was clicked
+                if let Some(idx) = to_remove {
+                    light_manager.remove_light(idx);
+                }
+            });
 
             ui.separator();
 
@@ -300,43 +431,16 @@ pub fn app_ui(
                 format!("Particle Systems ({})", particle_system_manager.count()),
                 |ui| {
                     // --- Add Particle System Buttons ---
-                    ui.horizontal(|ui| {
-                        if ui.button("➕ Add Grid").clicked() {
-                            let name = format!("Grid_{}", particle_system_manager.count());
-                            let params = GridParams {
-                                rows: 10,
-                                spacing: 1.0,
-                                center: [0.0, 0.0, 0.0],
-                            };
-
-                            // Get material from the default model's first mesh
-                            let material_source = if let Some(model) =
-                                models.get(crate::defaults::PARTICLE_SYSTEM_MODEL_PATH)
-                            {
-                                model.meshes[0].material_source.clone()
-                            } else {
-                                // Fallback to default material if model not loaded
-                                crate::model::MaterialSource::System("default".to_string())
-                            };
-
-                            let system = ParticleSystem::new(
-                                device,
-                                name.clone(),
-                                crate::defaults::PARTICLE_SYSTEM_MODEL_PATH.to_string(),
-                                0, // mesh_index: use first mesh
-                                material_source,
-                                GeneratorType::Grid(params),
+                    ui.horizontal_wrapped(|ui| {
+                        for generator in GeneratorType::defaults() {
+                            if !ui.button(format!("➕ Add {}", generator.label())).clicked() {
+                                continue;
+                            }
+                            let name = format!(
+                                "{}_{}",
+                                generator.label().replace(' ', ""),
+                                particle_system_manager.count()
                             );
-                            particle_system_manager.add(name, system);
-                        }
-
-                        if ui.button("➕ Add Sphere").clicked() {
-                            let name = format!("Sphere_{}", particle_system_manager.count());
-                            let params = SphereParams {
-                                count: 1000,
-                                radius: 5.0,
-                                center: [0.0, 0.0, 0.0],
-                            };
 
                             // Get material from the default model's first mesh
                             let material_source = if let Some(model) =
@@ -354,7 +458,8 @@ pub fn app_ui(
                                 crate::defaults::PARTICLE_SYSTEM_MODEL_PATH.to_string(),
                                 0, // mesh_index: use first mesh
                                 material_source,
-                                GeneratorType::Sphere(params),
+                                generator,
+                                None,
                             );
                             particle_system_manager.add(name, system);
                         }
@@ -373,7 +478,11 @@ pub fn app_ui(
 
                                 if header
                                     .show(ui, |ui| {
-                                        ui.label(format!("Instances: {}", system.num_instances()));
+                                        ui.label(format!(
+                                            "Instances: {} visible of {}",
+                                            system.num_visible(),
+                                            system.num_instances()
+                                        ));
 
                                         ui.separator();
 
@@ -428,155 +537,67 @@ pub fn app_ui(
                                             },
                                         );
 
+                                        let mut entire_model = system.entire_model();
+                                        if ui.checkbox(&mut entire_model, "Entire model").changed()
+                                        {
+                                            system.set_entire_model(entire_model);
+                                        }
+                                        if entire_model
+                                            && let Some(model) = models.get(system.model_path())
+                                        {
+                                            material_overrides_editor(ui, system, model, materials);
+                                        }
+
                                         ui.separator();
-                                        ui.label("Generator:");
+                                        ui.label("Levels of detail:");
+                                        lod_editor(ui, system, models);
 
-                                        let mut params_changed = false;
+                                        if let Some(model) = models.get(system.model_path())
+                                            && !model.animations.is_empty()
+                                        {
+                                            ui.separator();
+                                            ui.label("Animation:");
+                                            animation_editor(ui, system, model);
+                                        }
 
-                                        match system.generator_mut() {
-                                            crate::particle_system::GeneratorType::Grid(params) => {
-                                                ui.label("Type: Grid");
-                                                ui.separator();
+                                        if let Some(model) = models.get(system.model_path())
+                                            && morph_target_count(model) > 0
+                                        {
+                                            ui.separator();
+                                            ui.label("Morph targets:");
+                                            morph_weights_editor(ui, system, model);
+                                        }
 
-                                                ui.horizontal(|ui| {
-                                                    ui.label("Rows:");
-                                                    if ui
-                                                        .add(egui::Slider::new(
-                                                            &mut params.rows,
-                                                            5..=50,
-                                                        ))
-                                                        .changed()
-                                                    {
-                                                        params_changed = true;
-                                                    }
-                                                });
+                                        ui.separator();
+                                        ui.label("Generator:");
 
-                                                ui.horizontal(|ui| {
-                                                    ui.label("Spacing:");
-                                                    if ui
-                                                        .add(egui::Slider::new(
-                                                            &mut params.spacing,
-                                                            0.5..=10.0,
-                                                        ))
-                                                        .changed()
-                                                    {
-                                                        params_changed = true;
-                                                    }
-                                                });
-
-                                                ui.label("Center:");
-                                                if ui
-                                                    .add(
-                                                        egui::Slider::new(
-                                                            &mut params.center[0],
-                                                            -50.0..=50.0,
-                                                        )
-                                                        .text("X"),
-                                                    )
-                                                    .changed()
-                                                {
-                                                    params_changed = true;
-                                                }
-                                                if ui
-                                                    .add(
-                                                        egui::Slider::new(
-                                                            &mut params.center[1],
-                                                            -50.0..=50.0,
-                                                        )
-                                                        .text("Y"),
-                                                    )
-                                                    .changed()
-                                                {
-                                                    params_changed = true;
-                                                }
-                                                if ui
-                                                    .add(
-                                                        egui::Slider::new(
-                                                            &mut params.center[2],
-                                                            -50.0..=50.0,
-                                                        )
-                                                        .text("Z"),
-                                                    )
-                                                    .changed()
-                                                {
-                                                    params_changed = true;
-                                                }
-                                            }
-                                            crate::particle_system::GeneratorType::Sphere(
-                                                params,
-                                            ) => {
-                                                ui.label("Type: Sphere");
-                                                ui.separator();
-
-                                                ui.horizontal(|ui| {
-                                                    ui.label("Count:");
-                                                    if ui
-                                                        .add(egui::Slider::new(
-                                                            &mut params.count,
-                                                            100..=5000,
-                                                        ))
-                                                        .changed()
-                                                    {
-                                                        params_changed = true;
-                                                    }
-                                                });
+                                        let params_changed =
+                                            generator_editor(ui, system.generator_mut(), models);
 
-                                                ui.horizontal(|ui| {
-                                                    ui.label("Radius:");
-                                                    if ui
-                                                        .add(egui::Slider::new(
-                                                            &mut params.radius,
-                                                            1.0..=20.0,
-                                                        ))
-                                                        .changed()
-                                                    {
-                                                        params_changed = true;
-                                                    }
-                                                });
-
-                                                ui.label("Center:");
-                                                if ui
-                                                    .add(
-                                                        egui::Slider::new(
-                                                            &mut params.center[0],
-                                                            -50.0..=50.0,
-                                                        )
-                                                        .text("X"),
-                                                    )
-                                                    .changed()
-                                                {
-                                                    params_changed = true;
-                                                }
-                                                if ui
-                                                    .add(
-                                                        egui::Slider::new(
-                                                            &mut params.center[1],
-                                                            -50.0..=50.0,
-                                                        )
-                                                        .text("Y"),
-                                                    )
-                                                    .changed()
-                                                {
-                                                    params_changed = true;
-                                                }
-                                                if ui
-                                                    .add(
-                                                        egui::Slider::new(
-                                                            &mut params.center[2],
-                                                            -50.0..=50.0,
-                                                        )
-                                                        .text("Z"),
-                                                    )
-                                                    .changed()
-                                                {
-                                                    params_changed = true;
-                                                }
-                                            }
+                                        if params_changed {
+                                            system.mark_dirty();
                                         }
 
-                                        if params_changed {
+                                        ui.separator();
+                                        ui.label("Instance attributes:");
+                                        if instance_attributes_editor(
+                                            ui,
+                                            name,
+                                            system.attributes_mut(),
+                                        ) {
                                             system.mark_dirty();
                                         }
+
+                                        ui.separator();
+                                        let mut dynamic = system.simulation().is_some();
+                                        if ui.checkbox(&mut dynamic, "Simulate").changed() {
+                                            system.set_simulation(
+                                                dynamic.then(SimulationParams::default),
+                                            );
+                                        }
+                                        if let Some(params) = system.simulation_mut() {
+                                            simulation_editor(ui, params);
+                                        }
                                     })
                                     .body_returned
                                     .is_some()
@@ -618,22 +639,35 @@ pub fn app_ui(
             ui.collapsing(format!("🖼️ Textures"), |ui| {
                 let registry = textures.lock().unwrap();
 
-                // Count usage
-                let mut texture_usage: HashMap<String, Vec<String>> = HashMap::new();
+                // Count usage; slots use the copy in their own color space
+                let mut texture_usage: HashMap<(String, ColorSpace), Vec<String>> = HashMap::new();
                 for (mat_source, material) in materials.iter() {
-                    texture_usage
-                        .entry(material.desc.texture_path.clone())
-                        .or_default()
-                        .push(mat_source.display_key());
+                    for (slot, texture_path) in &material.desc.textures {
+                        texture_usage
+                            .entry((texture_path.clone(), slot_color_space(slot)))
+                            .or_default()
+                            .push(mat_source.display_key());
+                    }
                 }
 
-                for (path, texture) in registry.iter() {
-                    let users = texture_usage.get(path).map(|v| v.len()).unwrap_or(0);
-                    let size_bytes = texture.width * texture.height * 4; // RGBA
-                    let size_kb = size_bytes as f32 / 1024.0;
+                let total_bytes: u64 = registry.values().map(|texture| texture.memory_bytes).sum();
+                ui.label(format!(
+                    "GPU memory: {:.1} MB",
+                    total_bytes as f32 / (1024.0 * 1024.0)
+                ));
+
+                for (key, texture) in registry.iter() {
+                    let users = texture_usage.get(key).map(|v| v.len()).unwrap_or(0);
+                    let size_kb = texture.memory_bytes as f32 / 1024.0;
 
-                    ui.collapsing(&texture.label, |ui| {
+                    let title = format!("{} ({:?})", texture.label, texture.color_space);
+                    ui.collapsing(title, |ui| {
                         ui.label(format!("Size: {}×{}", texture.width, texture.height));
+                        ui.label(format!(
+                            "Format: {:?} ({} mips)",
+                            texture.format,
+                            texture.texture.mip_level_count()
+                        ));
                         ui.label(format!("Memory: {:.1} KB", size_kb));
                         ui.label(format!(
                             "Used by {} material{}",
@@ -641,7 +675,7 @@ pub fn app_ui(
                             if users == 1 { "" } else { "s" }
                         ));
 
-                        if let Some(material_keys) = texture_usage.get(path) {
+                        if let Some(material_keys) = texture_usage.get(key) {
                             ui.label("Materials:");
                             for mat_key in material_keys {
                                 ui.label(format!("  • {}", mat_key));
@@ -655,61 +689,80 @@ pub fn app_ui(
 
             // Materials Inspection & Editing
             ui.collapsing(format!("🎨 Materials ({})", materials.len()), |ui| {
+                // Any texture suits any slot; materials pick the copy in the slot's color space
+                let texture_registry = textures.lock().unwrap();
+                let mut available_textures: Vec<String> = texture_registry
+                    .keys()
+                    .map(|(path, _)| path.clone())
+                    .collect();
+                drop(texture_registry);
+                available_textures.sort();
+                available_textures.dedup();
+
                 // New material creation UI
                 ui.collapsing("➕ New Material", |ui| {
                     ui.label("Material Name:");
                     ui.text_edit_singleline(&mut ui_state.new_material_name);
 
-                    ui.label("Texture:");
-                    let texture_registry = textures.lock().unwrap();
-                    let available_textures: Vec<String> =
-                        texture_registry.keys().cloned().collect();
-                    drop(texture_registry);
-
-                    if available_textures.is_empty() {
-                        ui.colored_label(
-                            egui::Color32::RED,
-                            "No textures loaded. Load a model first.",
-                        );
-                    } else {
-                        egui::ComboBox::from_id_source("new_material_texture")
-                            .selected_text(if ui_state.new_material_texture.is_empty() {
-                                "Select texture..."
-                            } else {
-                                &ui_state.new_material_texture
-                            })
-                            .show_ui(ui, |ui| {
-                                for texture_path in &available_textures {
-                                    if ui
-                                        .selectable_label(
-                                            ui_state.new_material_texture == *texture_path,
-                                            texture_path,
-                                        )
-                                        .clicked()
-                                    {
-                                        ui_state.new_material_texture = texture_path.clone();
-                                    }
-                                }
-                            });
+                    ui.label("Shader:");
+                    if let Some(shader) = shader_combo(
+                        ui,
+                        "new_material_shader",
+                        &ui_state.new_material_shader,
+                        &shader_paths,
+                    ) {
+                        ui_state.new_material_shader = shader;
                     }
 
-                    ui.label("Color:");
-                    ui.color_edit_button_rgba_unmultiplied(&mut ui_state.new_material_color);
+                    // Slots and fields come from the selected shader's schema
+                    if let Some(layout) =
+                        pipeline_cache.material_layout(&ui_state.new_material_shader)
+                    {
+                        let schema = &layout.schema;
+                        for slot in schema.textures.iter().filter(|slot| slot.takes_images()) {
+                            ui.label(format!("Texture ({}):", slot.name));
+                            let current = ui_state
+                                .new_material_textures
+                                .get(&slot.name)
+                                .cloned()
+                                .unwrap_or_default();
+                            if let Some(texture_path) = texture_combo(
+                                ui,
+                                format!("new_material_{}", slot.name),
+                                &current,
+                                &available_textures,
+                            ) {
+                                ui_state
+                                    .new_material_textures
+                                    .insert(slot.name.clone(), texture_path);
+                            }
+                        }
+                        for field in schema.fields() {
+                            let mut value = ui_state
+                                .new_material_uniforms
+                                .get(&field.name)
+                                .copied()
+                                .unwrap_or(field.default);
+                            if uniform_widget(ui, field, &mut value) {
+                                ui_state
+                                    .new_material_uniforms
+                                    .insert(field.name.clone(), value);
+                            }
+                        }
+                    }
 
                     ui.horizontal(|ui| {
                         if ui.button("Create Material").clicked()
                             && !ui_state.new_material_name.is_empty()
-                            && !ui_state.new_material_texture.is_empty()
                         {
                             actions.material_to_create = Some((
                                 ui_state.new_material_name.clone(),
-                                ui_state.new_material_texture.clone(),
-                                ui_state.new_material_color,
+                                ui_state.new_material_shader.clone(),
+                                std::mem::take(&mut ui_state.new_material_textures),
+                                std::mem::take(&mut ui_state.new_material_uniforms),
                             ));
                             // Reset form
                             ui_state.new_material_name.clear();
-                            ui_state.new_material_texture.clear();
-                            ui_state.new_material_color = [1.0, 1.0, 1.0, 1.0];
                         }
                     });
                 });
@@ -735,146 +788,26 @@ pub fn app_ui(
                     }
                 }
 
-                // System Materials
-                if !system_materials.is_empty() {
-                    ui.label(egui::RichText::new("System Materials").strong());
-                    for (key, material) in system_materials {
-                        ui.push_id(key, |ui| {
-                            ui.collapsing(&material.desc.name, |ui| {
-                                ui.label(format!("Key: {}", key));
-                                ui.separator();
-
-                                // Texture selector
-                                ui.label("Texture:");
-                                let texture_registry = textures.lock().unwrap();
-                                let available_textures: Vec<String> =
-                                    texture_registry.keys().cloned().collect();
-                                drop(texture_registry);
-
-                                egui::ComboBox::from_id_source(format!("{}_texture", key))
-                                    .selected_text(&material.desc.texture_path)
-                                    .show_ui(ui, |ui| {
-                                        for texture_path in &available_textures {
-                                            if ui
-                                                .selectable_label(
-                                                    material.desc.texture_path == *texture_path,
-                                                    texture_path,
-                                                )
-                                                .clicked()
-                                            {
-                                                actions.material_texture_changed =
-                                                    Some((key.clone(), texture_path.clone()));
-                                            }
-                                        }
-                                    });
-
-                                ui.separator();
-
-                                // Color picker
-                                ui.label("Tint Color:");
-                                let mut color = material.desc.properties.borrow().color;
-                                if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
-                                    actions.material_color_changed = Some((key.clone(), color));
-                                }
-                            });
-                        });
+                for (title, group) in [
+                    ("System Materials", system_materials),
+                    ("Model Materials", model_materials),
+                    ("Custom Materials", custom_materials),
+                ] {
+                    if group.is_empty() {
+                        continue;
                     }
-                }
-
-                // Model Materials
-                if !model_materials.is_empty() {
-                    ui.label(egui::RichText::new("Model Materials").strong());
-                    for (key, material) in model_materials {
+                    ui.label(egui::RichText::new(title).strong());
+                    for (key, material) in group {
                         ui.push_id(key, |ui| {
                             ui.collapsing(&material.desc.name, |ui| {
-                                ui.label(format!("Key: {}", key));
-
-                                // Display source model
-                                if let crate::model::MaterialSource::Model { model_path, .. } = key
-                                {
-                                    ui.label(format!("From: {}", model_path));
-                                }
-
-                                ui.separator();
-
-                                // Texture selector
-                                ui.label("Texture:");
-                                let texture_registry = textures.lock().unwrap();
-                                let available_textures: Vec<String> =
-                                    texture_registry.keys().cloned().collect();
-                                drop(texture_registry);
-
-                                egui::ComboBox::from_id_source(format!("{}_texture", key))
-                                    .selected_text(&material.desc.texture_path)
-                                    .show_ui(ui, |ui| {
-                                        for texture_path in &available_textures {
-                                            if ui
-                                                .selectable_label(
-                                                    material.desc.texture_path == *texture_path,
-                                                    texture_path,
-                                                )
-                                                .clicked()
-                                            {
-                                                actions.material_texture_changed =
-                                                    Some((key.clone(), texture_path.clone()));
-                                            }
-                                        }
-                                    });
-
-                                ui.separator();
-
-                                // Color picker
-                                ui.label("Tint Color:");
-                                let mut color = material.desc.properties.borrow().color;
-                                if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
-                                    actions.material_color_changed = Some((key.clone(), color));
-                                }
-                            });
-                        });
-                    }
-                }
-
-                // Custom Materials
-                if !custom_materials.is_empty() {
-                    ui.label(egui::RichText::new("Custom Materials").strong());
-                    for (key, material) in custom_materials {
-                        ui.push_id(key, |ui| {
-                            ui.collapsing(&material.desc.name, |ui| {
-                                ui.label(format!("Key: {}", key));
-                                ui.separator();
-
-                                // Texture selector
-                                ui.label("Texture:");
-                                let texture_registry = textures.lock().unwrap();
-                                let available_textures: Vec<String> =
-                                    texture_registry.keys().cloned().collect();
-                                drop(texture_registry);
-
-                                egui::ComboBox::from_id_source(format!("{}_texture", key))
-                                    .selected_text(&material.desc.texture_path)
-                                    .show_ui(ui, |ui| {
-                                        for texture_path in &available_textures {
-                                            if ui
-                                                .selectable_label(
-                                                    material.desc.texture_path == *texture_path,
-                                                    texture_path,
-                                                )
-                                                .clicked()
-                                            {
-                                                actions.material_texture_changed =
-                                                    Some((key.clone(), texture_path.clone()));
-                                            }
-                                        }
-                                    });
-
-                                ui.separator();
-
-                                // Color picker
-                                ui.label("Tint Color:");
-                                let mut color = material.desc.properties.borrow().color;
-                                if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
-                                    actions.material_color_changed = Some((key.clone(), color));
-                                }
+                                material_editor(
+                                    ui,
+                                    key,
+                                    material,
+                                    &available_textures,
+                                    &shader_paths,
+                                    &mut actions,
+                                );
                             });
                         });
                     }
@@ -901,7 +834,7 @@ pub fn app_ui(
 
             // Load Model
             ui.collapsing("📦 Load Model", |ui| {
-                ui.label("Enter model path (e.g., 'teapot.obj'):");
+                ui.label("Enter model path (e.g., 'teapot.obj' or 'scene.glb'):");
 
                 ui.horizontal(|ui| {
                     ui.text_edit_singleline(&mut ui_state.model_path_input);
@@ -921,9 +854,1118 @@ pub fn app_ui(
 
             ui.separator();
 
+            // Load Shader
+            ui.collapsing(format!("🖌 Shaders ({})", shader_paths.len()), |ui| {
+                for shader_path in &shader_paths {
+                    ui.label(format!("• {}", shader_path));
+                }
+                ui.label(format!("Pipelines: {}", pipeline_cache.num_pipelines()));
+
+                ui.separator();
+                ui.label("Enter shader path (e.g., 'unlit.wgsl'):");
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut ui_state.shader_path_input);
+
+                    if ui.button("Load").clicked() && !ui_state.shader_path_input.is_empty() {
+                        actions.shader_to_load = Some(ui_state.shader_path_input.clone());
+                        ui_state.shader_path_input.clear();
+                    }
+                });
+            });
+
+            ui.separator();
+
             ui.label(format!("Delta Time: {:.2} ms", delta_time_ms));
             ui.label(format!("FPS: {:.1}", 1000.0 / delta_time_ms));
+            let (visible, total) = particle_system_manager.systems().fold(
+                (0, 0),
+                |(visible, total), (_name, system)| {
+                    (
+                        visible + system.num_visible(),
+                        total + system.num_instances(),
+                    )
+                },
+            );
+            ui.label(format!("Instances: {} visible of {}", visible, total));
         });
 
     actions
 }
+
+/// Dropdown of loaded shaders. Returns the newly picked shader, if any.
+fn shader_combo(
+    ui: &mut egui::Ui,
+    id: impl std::hash::Hash,
+    current: &str,
+    shader_paths: &[String],
+) -> Option<String> {
+    let mut picked = None;
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(current)
+        .show_ui(ui, |ui| {
+            for shader_path in shader_paths {
+                if ui
+                    .selectable_label(current == shader_path, shader_path)
+                    .clicked()
+                    && current != shader_path
+                {
+                    picked = Some(shader_path.clone());
+                }
+            }
+        });
+    picked
+}
+
+/// Texture dropdown. Returns the newly picked texture, if any.
+fn texture_combo(
+    ui: &mut egui::Ui,
+    id: impl std::hash::Hash,
+    current: &str,
+    available_textures: &[String],
+) -> Option<String> {
+    let mut picked = None;
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(if current.is_empty() {
+            crate::defaults::DEFAULT_TEXTURE_PATH
+        } else {
+            current
+        })
+        .show_ui(ui, |ui| {
+            for texture_path in available_textures {
+                if ui
+                    .selectable_label(current == texture_path, texture_path)
+                    .clicked()
+                    && current != texture_path
+                {
+                    picked = Some(texture_path.clone());
+                }
+            }
+        });
+    picked
+}
+
+fn msaa_label(samples: u32) -> String {
+    if samples > 1 {
+        format!("MSAA {}x", samples)
+    } else {
+        "MSAA off".to_string()
+    }
+}
+
+/// Parameter sliders for one post-process effect
+fn post_effect_editor(ui: &mut egui::Ui, effect: &mut PostEffect, lut_path_input: &mut String) {
+    match effect {
+        PostEffect::Bloom(params) => {
+            ui.add(egui::Slider::new(&mut params.threshold, 0.0..=1.0).text("Threshold"));
+            ui.add(egui::Slider::new(&mut params.soft_knee, 0.0..=1.0).text("Soft knee"));
+            ui.add(egui::Slider::new(&mut params.intensity, 0.0..=4.0).text("Intensity"));
+            ui.add(egui::Slider::new(&mut params.radius, 0.5..=4.0).text("Radius"));
+        }
+        PostEffect::Fxaa(params) => {
+            ui.add(egui::Slider::new(&mut params.span_max, 1.0..=16.0).text("Span max"));
+            ui.add(egui::Slider::new(&mut params.reduce_mul, 0.0..=0.5).text("Reduce mul"));
+            ui.add(
+                egui::Slider::new(&mut params.reduce_min, 0.0..=0.1)
+                    .logarithmic(true)
+                    .text("Reduce min"),
+            );
+        }
+        PostEffect::Vignette(params) => {
+            ui.add(egui::Slider::new(&mut params.intensity, 0.0..=1.0).text("Intensity"));
+            ui.add(egui::Slider::new(&mut params.radius, 0.0..=2.0).text("Radius"));
+            ui.add(egui::Slider::new(&mut params.smoothness, 0.0..=2.0).text("Smoothness"));
+        }
+        PostEffect::ColorGrade(params) => {
+            ui.add(egui::Slider::new(&mut params.strength, 0.0..=1.0).text("Strength"));
+            ui.label(format!(
+                "LUT: {}",
+                params.lut.as_deref().unwrap_or("identity")
+            ));
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(lut_path_input);
+                if ui.button("Set").clicked() && !lut_path_input.is_empty() {
+                    params.lut = Some(std::mem::take(lut_path_input));
+                }
+                if ui.button("Reset").clicked() {
+                    params.lut = None;
+                }
+            });
+        }
+    }
+}
+
+/// Kind, placement, color and falloff controls for one light
+fn light_editor(ui: &mut egui::Ui, index: usize, light: &mut Light) {
+    egui::ComboBox::from_id_salt(format!("light_{}_kind", index))
+        .selected_text(light.kind.label())
+        .show_ui(ui, |ui| {
+            for kind in LightKind::ALL {
+                ui.selectable_value(&mut light.kind, kind, kind.label());
+            }
+        });
+
+    // Directional lights still have a position: it's where their marker is drawn
+    ui.label("Position:");
+    for (axis, value) in ["X", "Y", "Z"].into_iter().zip(light.position.iter_mut()) {
+        ui.add(egui::Slider::new(value, -20.0..=20.0).text(axis));
+    }
+
+    if light.kind != LightKind::Point {
+        ui.label("Direction:");
+        for (axis, value) in ["X", "Y", "Z"].into_iter().zip(light.direction.iter_mut()) {
+            ui.add(egui::Slider::new(value, -1.0..=1.0).text(axis));
+        }
+    }
+
+    ui.label("Color:");
+    ui.color_edit_button_rgba_unmultiplied(&mut light.color);
+    ui.add(egui::Slider::new(&mut light.intensity, 0.0..=20.0).text("Intensity"));
+
+    if light.kind != LightKind::Directional {
+        ui.add(egui::Slider::new(&mut light.range, 0.0..=100.0).text("Range (0 = unlimited)"));
+    }
+
+    if light.kind == LightKind::Spot {
+        ui.add(egui::Slider::new(&mut light.outer_cone_deg, 0.0..=90.0).text("Outer cone°"));
+        ui.add(
+            egui::Slider::new(&mut light.inner_cone_deg, 0.0..=light.outer_cone_deg)
+                .text("Inner cone°"),
+        );
+    }
+
+    ui.checkbox(&mut light.cast_shadows, "Cast shadows");
+    if light.cast_shadows {
+        ui.add(egui::Slider::new(&mut light.shadow_bias, 0.0..=0.5).text("Shadow bias"));
+        ui.add(egui::Slider::new(&mut light.shadow_normal_bias, 0.0..=0.5).text("Normal bias"));
+    }
+}
+
+/// X, Y and Z sliders for one point. Returns true if any changed.
+fn point_editor(
+    ui: &mut egui::Ui,
+    label: &str,
+    point: &mut [f32; 3],
+    range: std::ops::RangeInclusive<f32>,
+) -> bool {
+    ui.label(format!("{}:", label));
+    let mut changed = false;
+    for (axis, value) in ["X", "Y", "Z"].into_iter().zip(point.iter_mut()) {
+        changed |= ui
+            .add(egui::Slider::new(value, range.clone()).text(axis))
+            .changed();
+    }
+    changed
+}
+
+/// The material each mesh of `model` draws with, either its own or an override
+fn material_overrides_editor(
+    ui: &mut egui::Ui,
+    system: &mut ParticleSystem,
+    model: &crate::model::Model,
+    materials: &MaterialRegistry,
+) {
+    for (index, mesh) in model.meshes.iter().enumerate() {
+        let current = system.material_overrides().get(&index).cloned();
+        let selected_text = match &current {
+            Some(material) => material.display_name(),
+            None => format!("{} (model's)", mesh.material_source.display_name()),
+        };
+        ui.horizontal(|ui| {
+            ui.label(format!("{}:", mesh.name));
+            egui::ComboBox::from_id_salt(("material_override", index))
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    if ui
+                        .selectable_label(current.is_none(), "Model's own")
+                        .clicked()
+                    {
+                        system.set_material_override(index, None);
+                    }
+                    for material_source in materials.keys() {
+                        if ui
+                            .selectable_label(
+                                current.as_ref() == Some(material_source),
+                                material_source.display_key(),
+                            )
+                            .clicked()
+                        {
+                            system.set_material_override(index, Some(material_source.clone()));
+                        }
+                    }
+                });
+        });
+    }
+}
+
+/// Coarser meshes a system switches to with distance, kept ordered nearest first
+fn lod_editor(
+    ui: &mut egui::Ui,
+    system: &mut ParticleSystem,
+    models: &HashMap<String, Arc<crate::model::Model>>,
+) {
+    let mut lods = system.lods().to_vec();
+    let mut changed = false;
+    let mut to_remove = None;
+    for index in 0..lods.len() {
+        // Each level switches in between its neighbours
+        let nearest = index
+            .checked_sub(1)
+            .map_or(0.0, |previous| lods[previous].distance);
+        let farthest = lods.get(index + 1).map_or(1000.0, |next| next.distance);
+        let lod = &mut lods[index];
+        ui.push_id(index, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("LOD {}:", index + 1));
+                egui::ComboBox::from_id_salt("model")
+                    .selected_text(&lod.model)
+                    .show_ui(ui, |ui| {
+                        for model_path in models.keys() {
+                            changed |= ui
+                                .selectable_value(&mut lod.model, model_path.clone(), model_path)
+                                .changed();
+                        }
+                    });
+                if ui.button("Remove").clicked() {
+                    to_remove = Some(index);
+                }
+            });
+            if let Some(model) = models.get(&lod.model) {
+                let last_mesh = model.meshes.len().saturating_sub(1);
+                changed |= ui
+                    .add(egui::Slider::new(&mut lod.mesh_index, 0..=last_mesh).text("Mesh"))
+                    .changed();
+            }
+            changed |= ui
+                .add(egui::Slider::new(&mut lod.distance, nearest..=farthest).text("From distance"))
+                .changed();
+        });
+    }
+    if let Some(index) = to_remove {
+        lods.remove(index);
+        changed = true;
+    }
+    if lods.len() + 1 < MAX_LOD_LEVELS && ui.button("➕ Add LOD").clicked() {
+        let distance = lods.last().map_or(20.0, |last| last.distance * 2.0);
+        lods.push(LodLevel {
+            model: system.model_path().to_string(),
+            mesh_index: system.mesh_index(),
+            distance,
+        });
+        changed = true;
+    }
+    if changed {
+        system.set_lods(lods);
+    }
+}
+
+/// Clip playback for the skinned and morphed meshes of a system's model, whose instances
+/// can play it at several time offsets
+fn animation_editor(ui: &mut egui::Ui, system: &mut ParticleSystem, model: &crate::model::Model) {
+    let mut animated = system.animation().is_some();
+    if ui.checkbox(&mut animated, "Animate").changed() {
+        system.set_animation(animated.then(|| AnimationPlayer::new(&model.animations[0].name)));
+    }
+    let Some(player) = system.animation_mut() else {
+        return;
+    };
+
+    egui::ComboBox::from_id_salt("clip")
+        .selected_text(player.clip())
+        .show_ui(ui, |ui| {
+            for clip in &model.animations {
+                if ui
+                    .selectable_label(player.clip() == clip.name, &clip.name)
+                    .clicked()
+                {
+                    player.play(&clip.name);
+                }
+            }
+        });
+    ui.horizontal(|ui| {
+        if player.playing {
+            if ui.button("⏸ Pause").clicked() {
+                player.pause();
+            }
+        } else if ui.button("▶ Play").clicked() {
+            player.resume();
+        }
+        if ui.button("⏮ Rewind").clicked() {
+            player.rewind();
+        }
+        ui.checkbox(&mut player.looping, "Loop");
+        ui.label(format!("{:.2}s", player.time()));
+    });
+    ui.add(egui::Slider::new(&mut player.speed, -2.0..=2.0).text("Speed"));
+    ui.add(egui::Slider::new(&mut player.fade, 0.0..=2.0).text("Cross-fade (s)"));
+
+    // Instances are reshuffled across the offsets when their number changes
+    let offsets_changed = ui
+        .add(
+            egui::Slider::new(&mut player.offset_count, 1..=MAX_ANIMATION_OFFSETS)
+                .text("Time offsets"),
+        )
+        .changed();
+    let duration = model
+        .animations
+        .iter()
+        .find(|clip| clip.name == player.clip())
+        .map_or(0.0, |clip| clip.duration);
+    ui.add(
+        egui::Slider::new(&mut player.offset_spread, 0.0..=duration.max(1.0))
+            .text("Offset spread (s)"),
+    );
+    if offsets_changed {
+        system.mark_dirty();
+    }
+}
+
+/// Most morph targets of any mesh of `model`
+fn morph_target_count(model: &crate::model::Model) -> usize {
+    model
+        .meshes
+        .iter()
+        .filter_map(|mesh| mesh.deformation.as_ref()?.morph.as_ref())
+        .map(|morph| morph.targets.len())
+        .max()
+        .unwrap_or_default()
+}
+
+/// Where a system's morph target weights come from: the model and its animation,
+/// fixed values or a script
+fn morph_weights_editor(
+    ui: &mut egui::Ui,
+    system: &mut ParticleSystem,
+    model: &crate::model::Model,
+) {
+    let target_count = morph_target_count(model);
+    let mut weights = system.morph_weights().cloned();
+    let mut changed = false;
+    let selected = weights.as_ref().map_or("Model", MorphWeights::label);
+    egui::ComboBox::from_id_salt("morph_weights")
+        .selected_text(format!("Weights: {selected}"))
+        .show_ui(ui, |ui| {
+            if ui.selectable_label(weights.is_none(), "Model").clicked() && weights.is_some() {
+                weights = None;
+                changed = true;
+            }
+            for option in [
+                MorphWeights::Fixed {
+                    weights: vec![0.0; target_count],
+                },
+                MorphWeights::Script {
+                    function: String::new(),
+                },
+            ] {
+                let is_selected = weights
+                    .as_ref()
+                    .is_some_and(|weights| weights.label() == option.label());
+                if ui.selectable_label(is_selected, option.label()).clicked() && !is_selected {
+                    weights = Some(option);
+                    changed = true;
+                }
+            }
+        });
+    match &mut weights {
+        Some(MorphWeights::Fixed { weights }) => {
+            weights.resize(target_count, 0.0);
+            for (target, weight) in weights.iter_mut().enumerate() {
+                changed |= ui
+                    .add(egui::Slider::new(weight, 0.0..=1.0).text(format!("Target {target}")))
+                    .changed();
+            }
+        }
+        Some(MorphWeights::Script { function }) => {
+            ui.horizontal(|ui| {
+                ui.label("Function:");
+                // Only on commit, so half-typed names aren't called every frame
+                changed |= ui.text_edit_singleline(function).lost_focus();
+            });
+        }
+        None => {}
+    }
+    if changed {
+        system.set_morph_weights(weights);
+    }
+}
+
+/// Parameters of a generator and any it wraps. Returns true if any changed.
+fn generator_editor(
+    ui: &mut egui::Ui,
+    generator: &mut GeneratorType,
+    models: &HashMap<String, Arc<crate::model::Model>>,
+) -> bool {
+    let mut changed = match generator {
+        GeneratorType::Grid(params) => grid_editor(ui, params),
+        GeneratorType::Sphere(params) => sphere_editor(ui, params),
+        GeneratorType::Line(params) => line_editor(ui, params),
+        GeneratorType::Ring(params) => ring_editor(ui, params),
+        GeneratorType::Box(params) => box_editor(ui, params),
+        GeneratorType::Spiral(params) => spiral_editor(ui, params),
+        GeneratorType::PoissonDisk(params) => poisson_disk_editor(ui, params),
+        GeneratorType::Heightmap(params) => heightmap_editor(ui, params),
+        GeneratorType::MeshSurface(params) => mesh_surface_editor(ui, params, models),
+        GeneratorType::Transform(params) => {
+            let mut changed = transform_editor(ui, params);
+            changed |= child_generator_editor(ui, &mut params.generator, models);
+            changed
+        }
+        GeneratorType::Union(params) => union_editor(ui, params, models),
+        GeneratorType::Jitter(params) => {
+            let mut changed = jitter_editor(ui, params);
+            changed |= child_generator_editor(ui, &mut params.generator, models);
+            changed
+        }
+        GeneratorType::Randomize(params) => {
+            let mut changed = randomize_editor(ui, params);
+            changed |= child_generator_editor(ui, &mut params.generator, models);
+            changed
+        }
+    };
+
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Wrap in:");
+        for operator in GeneratorType::operators(generator.clone()) {
+            if ui.button(operator.label()).clicked() {
+                *generator = operator;
+                changed = true;
+            }
+        }
+        // Operators can be removed again, keeping what they wrap
+        if let Some(child) = generator.children_mut().first()
+            && ui.button("Unwrap").clicked()
+        {
+            let child = (**child).clone();
+            *generator = child;
+            changed = true;
+        }
+    });
+    changed
+}
+
+/// The generator an operator wraps, under its own header
+fn child_generator_editor(
+    ui: &mut egui::Ui,
+    generator: &mut GeneratorType,
+    models: &HashMap<String, Arc<crate::model::Model>>,
+) -> bool {
+    egui::CollapsingHeader::new(format!("Of: {}", generator.label()))
+        .id_salt("child")
+        .default_open(true)
+        .show(ui, |ui| generator_editor(ui, generator, models))
+        .body_returned
+        .unwrap_or(false)
+}
+
+fn grid_editor(ui: &mut egui::Ui, params: &mut GridParams) -> bool {
+    ui.label("Type: Grid");
+    ui.separator();
+    let mut changed = ui
+        .add(egui::Slider::new(&mut params.rows, 5..=50).text("Rows"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut params.spacing, 0.5..=10.0).text("Spacing"))
+        .changed();
+    changed |= point_editor(ui, "Center", &mut params.center, -50.0..=50.0);
+    changed
+}
+
+fn sphere_editor(ui: &mut egui::Ui, params: &mut SphereParams) -> bool {
+    ui.label("Type: Sphere");
+    ui.separator();
+    let mut changed = ui
+        .add(egui::Slider::new(&mut params.count, 100..=5000).text("Count"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut params.radius, 1.0..=20.0).text("Radius"))
+        .changed();
+    changed |= point_editor(ui, "Center", &mut params.center, -50.0..=50.0);
+    changed
+}
+
+fn line_editor(ui: &mut egui::Ui, params: &mut LineParams) -> bool {
+    ui.label("Type: Line");
+    ui.separator();
+    let mut changed = ui
+        .add(egui::Slider::new(&mut params.count, 2..=1000).text("Count"))
+        .changed();
+    changed |= point_editor(ui, "Start", &mut params.start, -50.0..=50.0);
+    changed |= point_editor(ui, "End", &mut params.end, -50.0..=50.0);
+    changed
+}
+
+fn ring_editor(ui: &mut egui::Ui, params: &mut RingParams) -> bool {
+    ui.label("Type: Ring");
+    ui.separator();
+    let mut changed = ui
+        .add(egui::Slider::new(&mut params.count, 1..=1000).text("Count"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut params.radius, 0.5..=50.0).text("Radius"))
+        .changed();
+    changed |= point_editor(ui, "Center", &mut params.center, -50.0..=50.0);
+    changed
+}
+
+fn box_editor(ui: &mut egui::Ui, params: &mut BoxParams) -> bool {
+    ui.label("Type: Box");
+    ui.separator();
+    let mut changed = ui
+        .add(egui::Slider::new(&mut params.count, 1..=10000).text("Count"))
+        .changed();
+    changed |= point_editor(ui, "Size", &mut params.size, 0.0..=100.0);
+    changed |= point_editor(ui, "Center", &mut params.center, -50.0..=50.0);
+    changed |= ui
+        .add(egui::DragValue::new(&mut params.seed).prefix("Seed: "))
+        .changed();
+    changed
+}
+
+fn spiral_editor(ui: &mut egui::Ui, params: &mut SpiralParams) -> bool {
+    ui.label("Type: Spiral");
+    ui.separator();
+    let mut changed = ui
+        .add(egui::Slider::new(&mut params.count, 2..=5000).text("Count"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut params.turns, 0.5..=20.0).text("Turns"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut params.radius, 0.5..=50.0).text("Radius"))
+        .changed();
+    changed |= point_editor(ui, "Center", &mut params.center, -50.0..=50.0);
+    changed
+}
+
+fn poisson_disk_editor(ui: &mut egui::Ui, params: &mut PoissonDiskParams) -> bool {
+    ui.label("Type: Poisson Disk");
+    ui.separator();
+    let mut changed = ui
+        .add(egui::Slider::new(&mut params.min_distance, 0.25..=10.0).text("Min distance"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut params.max_count, 1..=10000).text("Max count"))
+        .changed();
+    for (axis, value) in ["Width", "Depth"].into_iter().zip(params.size.iter_mut()) {
+        changed |= ui
+            .add(egui::Slider::new(value, 1.0..=200.0).text(axis))
+            .changed();
+    }
+    changed |= point_editor(ui, "Center", &mut params.center, -50.0..=50.0);
+    changed |= ui
+        .add(egui::DragValue::new(&mut params.seed).prefix("Seed: "))
+        .changed();
+    changed
+}
+
+/// Resource path edited in a copy until committed, so half-typed paths aren't requested
+fn path_editor(ui: &mut egui::Ui, label: &str, path: &mut String) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let id = ui.id().with(label);
+        let mut edited = ui
+            .data_mut(|data| data.get_temp::<String>(id))
+            .unwrap_or_else(|| path.clone());
+        let response = ui.text_edit_singleline(&mut edited);
+        if response.lost_focus() {
+            ui.data_mut(|data| data.remove::<String>(id));
+            let changed = edited != *path;
+            *path = edited;
+            changed
+        } else {
+            if response.has_focus() {
+                ui.data_mut(|data| data.insert_temp(id, edited));
+            }
+            false
+        }
+    })
+    .inner
+}
+
+fn mesh_surface_editor(
+    ui: &mut egui::Ui,
+    params: &mut MeshSurfaceParams,
+    models: &HashMap<String, Arc<crate::model::Model>>,
+) -> bool {
+    ui.label("Type: Mesh Surface");
+    ui.separator();
+    let mut changed = path_editor(ui, "Model:", &mut params.model);
+    match models.get(&params.model) {
+        Some(model) => {
+            let last_mesh = model.meshes.len().saturating_sub(1);
+            changed |= ui
+                .add(egui::Slider::new(&mut params.mesh_index, 0..=last_mesh).text("Mesh"))
+                .changed();
+        }
+        None => {
+            ui.label("Loading...");
+        }
+    }
+    changed |= ui
+        .add(egui::Slider::new(&mut params.count, 1..=10000).text("Count"))
+        .changed();
+    changed |= ui
+        .add(egui::DragValue::new(&mut params.seed).prefix("Seed: "))
+        .changed();
+    changed |= point_editor(ui, "Center", &mut params.center, -50.0..=50.0);
+    changed
+}
+
+fn heightmap_editor(ui: &mut egui::Ui, params: &mut HeightmapParams) -> bool {
+    ui.label("Type: Heightmap");
+    ui.separator();
+    let mut changed = false;
+    changed |= path_editor(ui, "Image:", &mut params.path);
+    if params.load_failed() {
+        ui.label("Failed to load");
+    } else if params.heightmap().is_none() {
+        ui.label("Loading...");
+    }
+    changed |= ui
+        .add(egui::Slider::new(&mut params.resolution, 2..=200).text("Resolution"))
+        .changed();
+    for (axis, value) in ["Width", "Depth"].into_iter().zip(params.size.iter_mut()) {
+        changed |= ui
+            .add(egui::Slider::new(value, 1.0..=200.0).text(axis))
+            .changed();
+    }
+    changed |= ui
+        .add(egui::Slider::new(&mut params.height, 0.0..=50.0).text("Height"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut params.min_level, 0.0..=1.0).text("Min level"))
+        .changed();
+    changed |= ui
+        .checkbox(&mut params.align_to_surface, "Align to surface")
+        .changed();
+    changed |= point_editor(ui, "Center", &mut params.center, -50.0..=50.0);
+    changed
+}
+
+fn transform_editor(ui: &mut egui::Ui, params: &mut TransformParams) -> bool {
+    ui.label("Type: Transform");
+    ui.separator();
+    let mut changed = point_editor(ui, "Translation", &mut params.translation, -50.0..=50.0);
+    changed |= point_editor(ui, "Rotation", &mut params.rotation, -180.0..=180.0);
+    changed |= point_editor(ui, "Scale", &mut params.scale, 0.1..=10.0);
+    changed
+}
+
+fn union_editor(
+    ui: &mut egui::Ui,
+    params: &mut UnionParams,
+    models: &HashMap<String, Arc<crate::model::Model>>,
+) -> bool {
+    ui.label("Type: Union");
+    ui.separator();
+    let mut changed = false;
+    let mut to_remove = None;
+    for (index, generator) in params.generators.iter_mut().enumerate() {
+        ui.push_id(index, |ui| {
+            egui::CollapsingHeader::new(format!("{}: {}", index, generator.label()))
+                .default_open(true)
+                .show(ui, |ui| {
+                    changed |= generator_editor(ui, generator, models);
+                    if ui.button("Remove").clicked() {
+                        to_remove = Some(index);
+                    }
+                });
+        });
+    }
+    if let Some(index) = to_remove {
+        params.generators.remove(index);
+        changed = true;
+    }
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Add:");
+        for generator in GeneratorType::defaults() {
+            if ui.button(generator.label()).clicked() {
+                params.generators.push(generator);
+                changed = true;
+            }
+        }
+    });
+    changed
+}
+
+fn jitter_editor(ui: &mut egui::Ui, params: &mut JitterParams) -> bool {
+    ui.label("Type: Jitter");
+    ui.separator();
+    let mut changed = point_editor(ui, "Offset", &mut params.offset, 0.0..=10.0);
+    changed |= ui
+        .add(egui::DragValue::new(&mut params.seed).prefix("Seed: "))
+        .changed();
+    changed
+}
+
+fn randomize_editor(ui: &mut egui::Ui, params: &mut RandomizeParams) -> bool {
+    ui.label("Type: Randomize");
+    ui.separator();
+    let mut changed = point_editor(ui, "Rotation", &mut params.rotation, 0.0..=180.0);
+    changed |= ui
+        .add(egui::Slider::new(&mut params.min_scale, 0.1..=5.0).text("Min scale"))
+        .changed();
+    changed |= ui
+        .add(egui::Slider::new(&mut params.max_scale, 0.1..=5.0).text("Max scale"))
+        .changed();
+    changed |= ui
+        .add(egui::DragValue::new(&mut params.seed).prefix("Seed: "))
+        .changed();
+    changed
+}
+
+/// Sources of per-instance color, scale and custom data. Returns true if any changed.
+fn instance_attributes_editor(
+    ui: &mut egui::Ui,
+    id: &str,
+    attributes: &mut InstanceAttributes,
+) -> bool {
+    let mut changed = false;
+    for (label, values, default, components) in [
+        ("Color", &mut attributes.color, [1.0; 4], None),
+        ("Scale", &mut attributes.scale, [1.0; 4], Some(1)),
+        ("Custom", &mut attributes.custom, [0.0; 4], Some(4)),
+    ] {
+        changed |= instance_values_editor(
+            ui,
+            &format!("{id}_{label}"),
+            label,
+            values,
+            default,
+            components,
+        );
+    }
+    changed
+}
+
+/// Kind picker and fields for one attribute's values. `components` is how many of the
+/// four floats mean something, or None to edit them as a color.
+fn instance_values_editor(
+    ui: &mut egui::Ui,
+    id: &str,
+    label: &str,
+    values: &mut Option<InstanceValues>,
+    default: [f32; 4],
+    components: Option<usize>,
+) -> bool {
+    let mut changed = false;
+    let selected = values.as_ref().map_or("None", InstanceValues::label);
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(format!("{label}: {selected}"))
+        .show_ui(ui, |ui| {
+            if ui.selectable_label(values.is_none(), "None").clicked() && values.is_some() {
+                *values = None;
+                changed = true;
+            }
+            for option in InstanceValues::defaults(default) {
+                let is_selected = values
+                    .as_ref()
+                    .is_some_and(|values| values.label() == option.label());
+                if ui.selectable_label(is_selected, option.label()).clicked() && !is_selected {
+                    *values = Some(option);
+                    changed = true;
+                }
+            }
+        });
+
+    let Some(values) = values else {
+        return changed;
+    };
+    ui.push_id(id, |ui| match values {
+        InstanceValues::Constant { value } => {
+            changed |= instance_value_widget(ui, "Value", value, components);
+        }
+        InstanceValues::Gradient { start, end } => {
+            changed |= instance_value_widget(ui, "Start", start, components);
+            changed |= instance_value_widget(ui, "End", end, components);
+        }
+        InstanceValues::Random { min, max, seed } => {
+            changed |= instance_value_widget(ui, "Min", min, components);
+            changed |= instance_value_widget(ui, "Max", max, components);
+            changed |= ui
+                .add(egui::DragValue::new(seed).prefix("Seed: "))
+                .changed();
+        }
+        InstanceValues::Palette { colors, seed } => {
+            let mut to_remove = None;
+            for (index, color) in colors.iter_mut().enumerate() {
+                ui.push_id(index, |ui| {
+                    ui.horizontal(|ui| {
+                        changed |= instance_value_widget(ui, "", color, components);
+                        if ui.button("🗑").clicked() {
+                            to_remove = Some(index);
+                        }
+                    });
+                });
+            }
+            if let Some(index) = to_remove {
+                colors.remove(index);
+                changed = true;
+            }
+            if ui.button("➕ Add").clicked() {
+                colors.push(default);
+                changed = true;
+            }
+            changed |= ui
+                .add(egui::DragValue::new(seed).prefix("Seed: "))
+                .changed();
+        }
+        InstanceValues::Script { function } => {
+            ui.horizontal(|ui| {
+                ui.label("Function:");
+                // Only on commit, so the script isn't called for every keystroke
+                changed |= ui.text_edit_singleline(function).lost_focus();
+            });
+        }
+    });
+    changed
+}
+
+fn instance_value_widget(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut [f32; 4],
+    components: Option<usize>,
+) -> bool {
+    ui.horizontal(|ui| {
+        if !label.is_empty() {
+            ui.label(label);
+        }
+        let Some(components) = components else {
+            return ui.color_edit_button_rgba_unmultiplied(value).changed();
+        };
+        let mut changed = false;
+        for component in value.iter_mut().take(components) {
+            changed |= ui
+                .add(egui::DragValue::new(component).speed(0.01))
+                .changed();
+        }
+        changed
+    })
+    .inner
+}
+
+/// Spawning and force controls for a dynamic particle system
+fn simulation_editor(ui: &mut egui::Ui, params: &mut SimulationParams) {
+    ui.add(
+        egui::Slider::new(&mut params.spawn_rate, 1.0..=5000.0)
+            .logarithmic(true)
+            .text("Spawn rate"),
+    );
+    ui.add(egui::Slider::new(&mut params.lifetime, 0.1..=20.0).text("Lifetime"));
+    ui.add(egui::Slider::new(&mut params.initial_speed, 0.0..=50.0).text("Initial speed"));
+    ui.add(egui::Slider::new(&mut params.spread_deg, 0.0..=180.0).text("Spread°"));
+    ui.add(egui::Slider::new(&mut params.end_scale, 0.0..=2.0).text("End scale"));
+
+    ui.label("Gravity:");
+    for (axis, value) in ["X", "Y", "Z"].into_iter().zip(params.gravity.iter_mut()) {
+        ui.add(egui::Slider::new(value, -20.0..=20.0).text(axis));
+    }
+    ui.add(egui::Slider::new(&mut params.drag, 0.0..=5.0).text("Drag"));
+
+    ui.label("Vortex:");
+    ui.add(egui::Slider::new(&mut params.vortex_strength, -50.0..=50.0).text("Strength"));
+    for (axis, value) in ["X", "Y", "Z"]
+        .into_iter()
+        .zip(params.vortex_center.iter_mut())
+    {
+        ui.add(egui::Slider::new(value, -50.0..=50.0).text(format!("Center {}", axis)));
+    }
+    for (axis, value) in ["X", "Y", "Z"]
+        .into_iter()
+        .zip(params.vortex_axis.iter_mut())
+    {
+        ui.add(egui::Slider::new(value, -1.0..=1.0).text(format!("Axis {}", axis)));
+    }
+
+    ui.label("Noise:");
+    ui.add(egui::Slider::new(&mut params.noise_strength, 0.0..=50.0).text("Strength"));
+    ui.add(egui::Slider::new(&mut params.noise_frequency, 0.01..=5.0).text("Frequency"));
+}
+
+/// Editor widget for one uniform field. Returns true if the value changed.
+fn uniform_widget(ui: &mut egui::Ui, field: &UniformField, value: &mut [f32; 4]) -> bool {
+    let is_color = field.name.contains("color") || field.name.contains("emissive");
+    ui.horizontal(|ui| {
+        ui.label(format!("{}:", field.name));
+        match field.ty {
+            UniformType::Vec4 if is_color => {
+                ui.color_edit_button_rgba_unmultiplied(value).changed()
+            }
+            UniformType::Vec3 if is_color => {
+                let mut rgb = [value[0], value[1], value[2]];
+                let changed = ui.color_edit_button_rgb(&mut rgb).changed();
+                value[..3].copy_from_slice(&rgb);
+                changed
+            }
+            UniformType::I32 | UniformType::U32 => {
+                let mut int = value[0] as i64;
+                let changed = ui.add(egui::DragValue::new(&mut int)).changed();
+                value[0] = if field.ty == UniformType::U32 {
+                    int.max(0) as f32
+                } else {
+                    int as f32
+                };
+                changed
+            }
+            _ => {
+                let mut changed = false;
+                for component in value.iter_mut().take(field.ty.components()) {
+                    changed |= ui
+                        .add(egui::DragValue::new(component).speed(0.01))
+                        .changed();
+                }
+                changed
+            }
+        }
+    })
+    .inner
+}
+
+/// Per-slot sampler override. Returns the new override, `Some(None)` once it is removed.
+fn sampler_editor(
+    ui: &mut egui::Ui,
+    id: String,
+    current: Option<&SamplerSettings>,
+) -> Option<Option<SamplerSettings>> {
+    let mut overridden = current.is_some();
+    let mut settings = current.copied().unwrap_or_default();
+    egui::CollapsingHeader::new("Sampler")
+        .id_salt(&id)
+        .show(ui, |ui| {
+            ui.checkbox(&mut overridden, "Override texture sampler");
+            if !overridden {
+                return;
+            }
+            for (label, mode) in [
+                ("Address U", &mut settings.address_mode_u),
+                ("Address V", &mut settings.address_mode_v),
+            ] {
+                egui::ComboBox::from_id_salt(format!("{id}_{label}"))
+                    .selected_text(format!("{label}: {}", mode.label()))
+                    .show_ui(ui, |ui| {
+                        for option in AddressMode::ALL {
+                            ui.selectable_value(mode, option, option.label());
+                        }
+                    });
+            }
+            for (label, filter) in [
+                ("Mag filter", &mut settings.mag_filter),
+                ("Min filter", &mut settings.min_filter),
+                ("Mip filter", &mut settings.mipmap_filter),
+            ] {
+                egui::ComboBox::from_id_salt(format!("{id}_{label}"))
+                    .selected_text(format!("{label}: {}", filter.label()))
+                    .show_ui(ui, |ui| {
+                        for option in FilterMode::ALL {
+                            ui.selectable_value(filter, option, option.label());
+                        }
+                    });
+            }
+            ui.add(egui::Slider::new(&mut settings.anisotropy, 1..=16).text("Anisotropy"));
+        });
+
+    let new = overridden.then_some(settings);
+    (new.as_ref() != current).then_some(new)
+}
+
+/// Schema-driven editor for one material: shader, one texture per slot, one widget per uniform field
+fn material_editor(
+    ui: &mut egui::Ui,
+    key: &crate::model::MaterialSource,
+    material: &crate::model::GpuMaterial,
+    available_textures: &[String],
+    shader_paths: &[String],
+    actions: &mut UiActions,
+) {
+    ui.label(format!("Key: {}", key));
+
+    // Display source model
+    if let crate::model::MaterialSource::Model { model_path, .. } = key {
+        ui.label(format!("From: {}", model_path));
+    }
+
+    ui.separator();
+
+    // Shader selector
+    ui.label("Shader:");
+    if let Some(shader) = shader_combo(
+        ui,
+        format!("{}_shader", key),
+        &material.desc.shader,
+        shader_paths,
+    ) {
+        actions.material_shader_changed = Some((key.clone(), shader));
+    }
+
+    // Alpha mode: blended materials draw in a separate pass after opaque geometry
+    let mut alpha_mode = material.desc.alpha_mode;
+    egui::ComboBox::from_id_salt(format!("{}_alpha_mode", key))
+        .selected_text(alpha_mode.label())
+        .show_ui(ui, |ui| {
+            for mode in AlphaMode::ALL {
+                ui.selectable_value(&mut alpha_mode, mode, mode.label());
+            }
+        });
+    if alpha_mode != material.desc.alpha_mode {
+        actions.material_alpha_mode_changed = Some((key.clone(), alpha_mode));
+    }
+
+    // Texture selectors
+    for slot in &material.schema.textures {
+        ui.label(format!("Texture ({}):", slot.name));
+        if slot.takes_images() {
+            let current = material
+                .desc
+                .textures
+                .get(&slot.name)
+                .map(String::as_str)
+                .unwrap_or_default();
+            if let Some(texture_path) = texture_combo(
+                ui,
+                format!("{}_{}_texture", key, slot.name),
+                current,
+                available_textures,
+            ) {
+                actions.material_texture_changed =
+                    Some((key.clone(), slot.name.clone(), texture_path));
+            }
+        } else {
+            ui.label(format!(
+                "Blank {:?} {:?}",
+                slot.view_dimension, slot.sample_type
+            ));
+        }
+        if let Some(texture) = material.textures.get(&slot.name) {
+            ui.label(format!(
+                "{}x{}, {:?}",
+                texture.width, texture.height, texture.color_space
+            ));
+        }
+        if let Some(sampler) = sampler_editor(
+            ui,
+            format!("{}_{}_sampler", key, slot.name),
+            material.desc.samplers.get(&slot.name),
+        ) {
+            actions.material_sampler_changed = Some((key.clone(), slot.name.clone(), sampler));
+        }
+    }
+    if material.has_normal_map() {
+        ui.label("Normal mapped (tangent space)");
+    }
+
+    ui.separator();
+
+    // Uniform fields
+    for field in material.schema.fields() {
+        let mut value = material
+            .desc
+            .uniforms
+            .borrow()
+            .get(&field.name)
+            .copied()
+            .unwrap_or(field.default);
+        if uniform_widget(ui, field, &mut value) {
+            actions.material_uniform_changed = Some((key.clone(), field.name.clone(), value));
+        }
+    }
+}
@@ -0,0 +1,2409 @@
+use anyhow::bail;
+
+/// Texels of one 4x4 block, row by row
+type Block = [[u8; 4]; 16];
+
+/// ETC1 intensity modifiers per table: the small and large step, used with either sign
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// Distance between paint colors in ETC2's T and H modes
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// BC6H and BC7 interpolation weights for 2, 3 and 4 bit indices, out of 64
+const BC_WEIGHTS2: [u32; 4] = [0, 21, 43, 64];
+const BC_WEIGHTS3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC_WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// BC6H and BC7 two subset partitions: bit `i` is texel `i`'s subset
+const BC_PARTITIONS2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800,
+    0xffe8, 0xff00, 0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc,
+    0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718,
+    0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// BC7 three subset partitions: bits `2i` and `2i + 1` are texel `i`'s subset
+const BC_PARTITIONS3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+/// Texel whose index drops its top bit in the second subset of each two subset partition
+const BC_ANCHORS2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor texels of the second and third subsets of each three subset partition
+#[rustfmt::skip]
+const BC_ANCHORS3: [[u8; 2]; 64] = [
+    [3, 15], [3, 8], [15, 8], [15, 3], [8, 15], [3, 15], [15, 3], [15, 8],
+    [8, 15], [8, 15], [6, 15], [6, 15], [6, 15], [5, 15], [3, 15], [3, 8],
+    [3, 15], [3, 8], [8, 15], [15, 3], [3, 15], [3, 8], [6, 15], [10, 8],
+    [5, 3], [8, 15], [8, 6], [6, 10], [8, 15], [5, 15], [15, 10], [15, 8],
+    [8, 15], [15, 3], [3, 15], [5, 10], [6, 10], [10, 8], [8, 9], [15, 10],
+    [15, 6], [3, 15], [15, 8], [5, 15], [15, 3], [15, 6], [15, 6], [15, 8],
+    [3, 15], [15, 3], [5, 15], [5, 15], [5, 15], [8, 15], [5, 15], [10, 15],
+    [5, 15], [10, 15], [8, 15], [13, 15], [15, 3], [12, 15], [3, 15], [3, 8],
+];
+
+/// Layout of one BC7 mode
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    /// A P bit per endpoint, or one per subset shared by both its endpoints
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    /// Modes 4 and 5 have a second set of indices, for alpha or (when selected) color
+    secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 4,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 4,
+        alpha_bits: 0,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 6,
+        alpha_bits: 0,
+        endpoint_p_bits: false,
+        shared_p_bits: true,
+        index_bits: 3,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 0,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 0,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 1,
+        color_bits: 5,
+        alpha_bits: 6,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 3,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 8,
+        endpoint_p_bits: false,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 2,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 7,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 4,
+        secondary_index_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 5,
+        endpoint_p_bits: true,
+        shared_p_bits: false,
+        index_bits: 2,
+        secondary_index_bits: 0,
+    },
+];
+
+/// BC6H endpoint channels as its bit layouts name them: r0, g0, b0, r1 and so on, the
+/// first two endpoints for the first subset
+const R0: usize = 0;
+const G0: usize = 1;
+const B0: usize = 2;
+const R1: usize = 3;
+const G1: usize = 4;
+const B1: usize = 5;
+const R2: usize = 6;
+const G2: usize = 7;
+const B2: usize = 8;
+const R3: usize = 9;
+const G3: usize = 10;
+const B3: usize = 11;
+
+/// Layout of one BC6H mode
+struct Bc6hMode {
+    /// The mode's value in its (two or five) mode bits
+    value: u32,
+    /// Whether endpoints after the first are stored as deltas from it
+    transformed: bool,
+    endpoint_bits: u32,
+    /// Bits of each delta (or endpoint, untransformed) per channel
+    delta_bits: [u32; 3],
+    /// Where the endpoint bits come from, in order: channel, lowest bit and bit count
+    fields: &'static [(usize, u32, u32)],
+}
+
+#[rustfmt::skip]
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode {
+        value: 0b00,
+        transformed: true,
+        endpoint_bits: 10,
+        delta_bits: [5, 5, 5],
+        fields: &[
+            (G2, 4, 1), (B2, 4, 1), (B3, 4, 1), (R0, 0, 10), (G0, 0, 10), (B0, 0, 10),
+            (R1, 0, 5), (G3, 4, 1), (G2, 0, 4), (G1, 0, 5), (B3, 0, 1), (G3, 0, 4), (B1, 0, 5),
+            (B3, 1, 1), (B2, 0, 4), (R2, 0, 5), (B3, 2, 1), (R3, 0, 5), (B3, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        value: 0b01,
+        transformed: true,
+        endpoint_bits: 7,
+        delta_bits: [6, 6, 6],
+        fields: &[
+            (G2, 5, 1), (G3, 4, 1), (G3, 5, 1), (R0, 0, 7), (B3, 0, 1), (B3, 1, 1), (B2, 4, 1),
+            (G0, 0, 7), (B2, 5, 1), (B3, 2, 1), (G2, 4, 1), (B0, 0, 7), (B3, 3, 1), (B3, 5, 1),
+            (B3, 4, 1), (R1, 0, 6), (G2, 0, 4), (G1, 0, 6), (G3, 0, 4), (B1, 0, 6), (B2, 0, 4),
+            (R2, 0, 6), (R3, 0, 6),
+        ],
+    },
+    Bc6hMode {
+        value: 0b00010,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [5, 4, 4],
+        fields: &[
+            (R0, 0, 10), (G0, 0, 10), (B0, 0, 10), (R1, 0, 5), (R0, 10, 1), (G2, 0, 4),
+            (G1, 0, 4), (G0, 10, 1), (B3, 0, 1), (G3, 0, 4), (B1, 0, 4), (B0, 10, 1),
+            (B3, 1, 1), (B2, 0, 4), (R2, 0, 5), (B3, 2, 1), (R3, 0, 5), (B3, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        value: 0b00110,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 5, 4],
+        fields: &[
+            (R0, 0, 10), (G0, 0, 10), (B0, 0, 10), (R1, 0, 4), (R0, 10, 1), (G3, 4, 1),
+            (G2, 0, 4), (G1, 0, 5), (G0, 10, 1), (G3, 0, 4), (B1, 0, 4), (B0, 10, 1),
+            (B3, 1, 1), (B2, 0, 4), (R2, 0, 4), (B3, 0, 1), (B3, 2, 1), (R3, 0, 4), (G2, 4, 1),
+            (B3, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        value: 0b01010,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 4, 5],
+        fields: &[
+            (R0, 0, 10), (G0, 0, 10), (B0, 0, 10), (R1, 0, 4), (R0, 10, 1), (B2, 4, 1),
+            (G2, 0, 4), (G1, 0, 4), (G0, 10, 1), (B3, 0, 1), (G3, 0, 4), (B1, 0, 5),
+            (B0, 10, 1), (B2, 0, 4), (R2, 0, 4), (B3, 1, 1), (B3, 2, 1), (R3, 0, 4), (B3, 4, 1),
+            (B3, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        value: 0b01110,
+        transformed: true,
+        endpoint_bits: 9,
+        delta_bits: [5, 5, 5],
+        fields: &[
+            (R0, 0, 9), (B2, 4, 1), (G0, 0, 9), (G2, 4, 1), (B0, 0, 9), (B3, 4, 1), (R1, 0, 5),
+            (G3, 4, 1), (G2, 0, 4), (G1, 0, 5), (B3, 0, 1), (G3, 0, 4), (B1, 0, 5), (B3, 1, 1),
+            (B2, 0, 4), (R2, 0, 5), (B3, 2, 1), (R3, 0, 5), (B3, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        value: 0b10010,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [6, 5, 5],
+        fields: &[
+            (R0, 0, 8), (G3, 4, 1), (B2, 4, 1), (G0, 0, 8), (B3, 2, 1), (G2, 4, 1), (B0, 0, 8),
+            (B3, 3, 1), (B3, 4, 1), (R1, 0, 6), (G2, 0, 4), (G1, 0, 5), (B3, 0, 1), (G3, 0, 4),
+            (B1, 0, 5), (B3, 1, 1), (B2, 0, 4), (R2, 0, 6), (R3, 0, 6),
+        ],
+    },
+    Bc6hMode {
+        value: 0b10110,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 6, 5],
+        fields: &[
+            (R0, 0, 8), (B3, 0, 1), (B2, 4, 1), (G0, 0, 8), (G2, 5, 1), (G2, 4, 1), (B0, 0, 8),
+            (G3, 5, 1), (B3, 4, 1), (R1, 0, 5), (G3, 4, 1), (G2, 0, 4), (G1, 0, 6), (G3, 0, 4),
+            (B1, 0, 5), (B3, 1, 1), (B2, 0, 4), (R2, 0, 5), (B3, 2, 1), (R3, 0, 5), (B3, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        value: 0b11010,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 5, 6],
+        fields: &[
+            (R0, 0, 8), (B3, 1, 1), (B2, 4, 1), (G0, 0, 8), (B2, 5, 1), (G2, 4, 1), (B0, 0, 8),
+            (B3, 5, 1), (B3, 4, 1), (R1, 0, 5), (G3, 4, 1), (G2, 0, 4), (G1, 0, 5), (B3, 0, 1),
+            (G3, 0, 4), (B1, 0, 6), (B2, 0, 4), (R2, 0, 5), (B3, 2, 1), (R3, 0, 5), (B3, 3, 1),
+        ],
+    },
+    Bc6hMode {
+        value: 0b11110,
+        transformed: false,
+        endpoint_bits: 6,
+        delta_bits: [6, 6, 6],
+        fields: &[
+            (R0, 0, 6), (G3, 4, 1), (B3, 0, 1), (B3, 1, 1), (B2, 4, 1), (G0, 0, 6), (G2, 5, 1),
+            (B2, 5, 1), (B3, 2, 1), (G2, 4, 1), (B0, 0, 6), (G3, 5, 1), (B3, 3, 1), (B3, 5, 1),
+            (B3, 4, 1), (R1, 0, 6), (G2, 0, 4), (G1, 0, 6), (G3, 0, 4), (B1, 0, 6), (B2, 0, 4),
+            (R2, 0, 6), (R3, 0, 6),
+        ],
+    },
+    Bc6hMode {
+        value: 0b00011,
+        transformed: false,
+        endpoint_bits: 10,
+        delta_bits: [10, 10, 10],
+        fields: &[
+            (R0, 0, 10), (G0, 0, 10), (B0, 0, 10), (R1, 0, 10), (G1, 0, 10), (B1, 0, 10),
+        ],
+    },
+    Bc6hMode {
+        value: 0b00111,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [9, 9, 9],
+        fields: &[
+            (R0, 0, 10), (G0, 0, 10), (B0, 0, 10), (R1, 0, 9), (R0, 10, 1), (G1, 0, 9),
+            (G0, 10, 1), (B1, 0, 9), (B0, 10, 1),
+        ],
+    },
+    // The high bits of the last two modes' first endpoint are stored top bit first
+    Bc6hMode {
+        value: 0b01011,
+        transformed: true,
+        endpoint_bits: 12,
+        delta_bits: [8, 8, 8],
+        fields: &[
+            (R0, 0, 10), (G0, 0, 10), (B0, 0, 10), (R1, 0, 8), (R0, 11, 1), (R0, 10, 1),
+            (G1, 0, 8), (G0, 11, 1), (G0, 10, 1), (B1, 0, 8), (B0, 11, 1), (B0, 10, 1),
+        ],
+    },
+    Bc6hMode {
+        value: 0b01111,
+        transformed: true,
+        endpoint_bits: 16,
+        delta_bits: [4, 4, 4],
+        fields: &[
+            (R0, 0, 10), (G0, 0, 10), (B0, 0, 10), (R1, 0, 4), (R0, 15, 1), (R0, 14, 1),
+            (R0, 13, 1), (R0, 12, 1), (R0, 11, 1), (R0, 10, 1), (G1, 0, 4), (G0, 15, 1),
+            (G0, 14, 1), (G0, 13, 1), (G0, 12, 1), (G0, 11, 1), (G0, 10, 1), (B1, 0, 4),
+            (B0, 15, 1), (B0, 14, 1), (B0, 13, 1), (B0, 12, 1), (B0, 11, 1), (B0, 10, 1),
+        ],
+    },
+];
+
+/// What ASTC decodes invalid blocks, and HDR ones outside the HDR profile, to
+const ASTC_ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// ASTC's integer sequence ranges, smallest first, as bits and the trit (3) or quint (5)
+/// each value carries on top of them (1 for neither)
+const ASTC_RANGES: [(u32, u32); 21] = [
+    (1, 1),
+    (0, 3),
+    (2, 1),
+    (0, 5),
+    (1, 3),
+    (3, 1),
+    (1, 5),
+    (2, 3),
+    (4, 1),
+    (2, 5),
+    (3, 3),
+    (5, 1),
+    (3, 5),
+    (4, 3),
+    (6, 1),
+    (4, 5),
+    (5, 3),
+    (7, 1),
+    (5, 5),
+    (6, 3),
+    (8, 1),
+];
+
+/// Decode one mip level to tightly packed RGBA8, for devices that can't sample `format`.
+/// One and two channel formats fill the rest the way sampling them would: 0, then alpha 1.
+/// Signed channels are moved from -1..1 to 0..1, the way unsigned normal maps store
+/// them, and BC6H's HDR colors are clamped to 0..1.
+pub fn decode(
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    use wgpu::TextureFormat as Format;
+
+    let texel_bytes = (width * height * 4) as usize;
+    match format.remove_srgb_suffix() {
+        Format::Rgba8Unorm | Format::Bgra8Unorm if data.len() < texel_bytes => {
+            bail!("Level data is truncated")
+        }
+        Format::Rgba8Unorm => Ok(data[..texel_bytes].to_vec()),
+        Format::Bgra8Unorm => Ok(data[..texel_bytes]
+            .chunks_exact(4)
+            .flat_map(|texel| [texel[2], texel[1], texel[0], texel[3]])
+            .collect()),
+        Format::Bc1RgbaUnorm => {
+            decode_blocks(width, height, data, 8, |block| bc1_colors(block, false))
+        }
+        Format::Bc2RgbaUnorm => decode_blocks(width, height, data, 16, |block| {
+            let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+            let mut texels = bc1_colors(&block[8..], true);
+            for (i, texel) in texels.iter_mut().enumerate() {
+                texel[3] = ((alpha >> (4 * i)) & 15) as u8 * 17;
+            }
+            texels
+        }),
+        Format::Bc3RgbaUnorm => decode_blocks(width, height, data, 16, |block| {
+            let alpha = bc4_values(&block[..8]);
+            let mut texels = bc1_colors(&block[8..], true);
+            for (texel, alpha) in texels.iter_mut().zip(alpha) {
+                texel[3] = alpha;
+            }
+            texels
+        }),
+        Format::Bc4RUnorm => decode_blocks(width, height, data, 8, |block| {
+            bc4_values(block).map(|r| [r, 0, 0, 255])
+        }),
+        Format::Bc4RSnorm => decode_blocks(width, height, data, 8, |block| {
+            bc4_signed_values(block).map(|r| [r, 128, 128, 255])
+        }),
+        Format::Bc5RgUnorm => decode_blocks(width, height, data, 16, |block| {
+            let (red, green) = (bc4_values(&block[..8]), bc4_values(&block[8..]));
+            std::array::from_fn(|i| [red[i], green[i], 0, 255])
+        }),
+        Format::Bc5RgSnorm => decode_blocks(width, height, data, 16, |block| {
+            let (red, green) = (
+                bc4_signed_values(&block[..8]),
+                bc4_signed_values(&block[8..]),
+            );
+            std::array::from_fn(|i| [red[i], green[i], 128, 255])
+        }),
+        Format::Bc6hRgbUfloat => {
+            decode_blocks(width, height, data, 16, |block| bc6h_colors(block, false))
+        }
+        Format::Bc6hRgbFloat => {
+            decode_blocks(width, height, data, 16, |block| bc6h_colors(block, true))
+        }
+        Format::Bc7RgbaUnorm => decode_blocks(width, height, data, 16, bc7_colors),
+        Format::Etc2Rgb8Unorm => {
+            decode_blocks(width, height, data, 8, |block| etc2_colors(block, false))
+        }
+        Format::Etc2Rgb8A1Unorm => {
+            decode_blocks(width, height, data, 8, |block| etc2_colors(block, true))
+        }
+        Format::Etc2Rgba8Unorm => decode_blocks(width, height, data, 16, |block| {
+            let alpha = eac_values(&block[..8], false);
+            let mut texels = etc2_colors(&block[8..], false);
+            for (texel, alpha) in texels.iter_mut().zip(alpha) {
+                texel[3] = alpha;
+            }
+            texels
+        }),
+        Format::EacR11Unorm => decode_blocks(width, height, data, 8, |block| {
+            eac_values(block, true).map(|r| [r, 0, 0, 255])
+        }),
+        Format::EacRg11Unorm => decode_blocks(width, height, data, 16, |block| {
+            let (red, green) = (eac_values(&block[..8], true), eac_values(&block[8..], true));
+            std::array::from_fn(|i| [red[i], green[i], 0, 255])
+        }),
+        Format::EacR11Snorm => decode_blocks(width, height, data, 8, |block| {
+            eac_signed_values(block).map(|r| [r, 128, 128, 255])
+        }),
+        Format::EacRg11Snorm => decode_blocks(width, height, data, 16, |block| {
+            let (red, green) = (
+                eac_signed_values(&block[..8]),
+                eac_signed_values(&block[8..]),
+            );
+            std::array::from_fn(|i| [red[i], green[i], 128, 255])
+        }),
+        Format::Astc {
+            channel: wgpu::AstcChannel::Unorm,
+            ..
+        } => {
+            let (block_width, block_height) = format.block_dimensions();
+            let footprint = (block_width as usize, block_height as usize);
+            decode_footprints(width, height, data, footprint, 16, |block| {
+                astc_colors(block, footprint)
+            })
+        }
+        format => bail!("No software decoder for {format:?}"),
+    }
+}
+
+/// Decode 4x4 blocks of `block_size` bytes into an image, cropping partial edge blocks
+fn decode_blocks(
+    width: u32,
+    height: u32,
+    data: &[u8],
+    block_size: usize,
+    decode_block: impl Fn(&[u8]) -> Block,
+) -> anyhow::Result<Vec<u8>> {
+    decode_footprints(width, height, data, (4, 4), block_size, decode_block)
+}
+
+/// Decode blocks of `block_size` bytes, each covering `footprint` texels, into an image
+fn decode_footprints<T: AsRef<[[u8; 4]]>>(
+    width: u32,
+    height: u32,
+    data: &[u8],
+    (block_width, block_height): (usize, usize),
+    block_size: usize,
+    decode_block: impl Fn(&[u8]) -> T,
+) -> anyhow::Result<Vec<u8>> {
+    let (width, height) = (width as usize, height as usize);
+    let columns = width.div_ceil(block_width);
+    let rows = height.div_ceil(block_height);
+    if data.len() < columns * rows * block_size {
+        bail!("Level data is truncated");
+    }
+
+    let mut pixels = vec![0; width * height * 4];
+    for (index, block) in data
+        .chunks_exact(block_size)
+        .take(columns * rows)
+        .enumerate()
+    {
+        let block_x = index % columns * block_width;
+        let block_y = index / columns * block_height;
+        for (i, texel) in decode_block(block).as_ref().iter().enumerate() {
+            let (x, y) = (block_x + i % block_width, block_y + i / block_width);
+            if x < width && y < height {
+                let offset = (y * width + x) * 4;
+                pixels[offset..offset + 4].copy_from_slice(texel);
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let r = (color >> 11) as u8 & 31;
+    let g = (color >> 5) as u8 & 63;
+    let b = color as u8 & 31;
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255]
+}
+
+fn lerp(a: [u8; 4], b: [u8; 4], numerator: u32, denominator: u32) -> [u8; 4] {
+    std::array::from_fn(|c| {
+        ((a[c] as u32 * (denominator - numerator) + b[c] as u32 * numerator) / denominator) as u8
+    })
+}
+
+/// BC1 color block. BC2 and BC3 always use the four color mode; BC1 switches to three
+/// colors and transparent black when the endpoints are in ascending order.
+fn bc1_colors(block: &[u8], four_color: bool) -> Block {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (p0, p1) = (rgb565(c0), rgb565(c1));
+    let palette = if four_color || c0 > c1 {
+        [p0, p1, lerp(p0, p1, 1, 3), lerp(p0, p1, 2, 3)]
+    } else {
+        [p0, p1, lerp(p0, p1, 1, 2), [0; 4]]
+    };
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    std::array::from_fn(|i| palette[(indices >> (2 * i)) as usize & 3])
+}
+
+/// A signed channel value moved from -1..1 to 0..255
+fn signed_to_unorm(value: i32, max: i32) -> u8 {
+    ((value.clamp(-max, max) + max) * 255 / (2 * max)) as u8
+}
+
+/// BC4 single channel block, also BC3's alpha and each channel of BC5
+fn bc4_values(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let palette: [u8; 8] = std::array::from_fn(|i| match i {
+        0 => a0 as u8,
+        1 => a1 as u8,
+        i if a0 > a1 => (((8 - i as u32) * a0 + (i as u32 - 1) * a1) / 7) as u8,
+        6 => 0,
+        7 => 255,
+        i => (((6 - i as u32) * a0 + (i as u32 - 1) * a1) / 5) as u8,
+    });
+    let mut index_bytes = [0; 8];
+    index_bytes[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(index_bytes);
+    std::array::from_fn(|i| palette[(indices >> (3 * i)) as usize & 7])
+}
+
+/// BC4 block of signed values, as in BC4 and BC5 SNORM. -128 is the same -1 as -127.
+fn bc4_signed_values(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (
+        (block[0] as i8).max(-127) as i32,
+        (block[1] as i8).max(-127) as i32,
+    );
+    let palette: [i32; 8] = std::array::from_fn(|i| match i {
+        0 => a0,
+        1 => a1,
+        i if a0 > a1 => ((8 - i as i32) * a0 + (i as i32 - 1) * a1) / 7,
+        6 => -127,
+        7 => 127,
+        i => ((6 - i as i32) * a0 + (i as i32 - 1) * a1) / 5,
+    });
+    let mut index_bytes = [0; 8];
+    index_bytes[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(index_bytes);
+    std::array::from_fn(|i| signed_to_unorm(palette[(indices >> (3 * i)) as usize & 7], 127))
+}
+
+/// `width` bits of a big-endian ETC block, the highest at bit `high`
+fn field(bits: u64, high: u32, width: u32) -> i32 {
+    ((bits >> (high + 1 - width)) & ((1 << width) - 1)) as i32
+}
+
+fn rgb(color: [i32; 3]) -> [u8; 4] {
+    let [r, g, b] = color.map(|channel| channel.clamp(0, 255) as u8);
+    [r, g, b, 255]
+}
+
+fn offset(color: [i32; 3], amount: i32) -> [i32; 3] {
+    color.map(|channel| channel + amount)
+}
+
+/// ETC2 RGB block. Punchthrough blocks (RGB8A1) reuse the differential bit as an opacity
+/// flag, which takes away the individual mode and makes index 2 transparent.
+fn etc2_colors(block: &[u8], punchthrough: bool) -> Block {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let differential = punchthrough || field(bits, 33, 1) == 1;
+    let opaque = !punchthrough || field(bits, 33, 1) == 1;
+    // Pixel indices run down columns and are split into a high and a low bit plane
+    let index_of = |i: usize| {
+        let p = i % 4 * 4 + i / 4;
+        ((bits >> (16 + p)) & 1) << 1 | ((bits >> p) & 1)
+    };
+
+    let base_colors = if differential {
+        let signed = |value: i32| if value >= 4 { value - 8 } else { value };
+        let base = [field(bits, 63, 5), field(bits, 55, 5), field(bits, 47, 5)];
+        let delta = [field(bits, 58, 3), field(bits, 50, 3), field(bits, 42, 3)].map(signed);
+        // A delta that overflows its channel selects one of ETC2's extra modes
+        let overflows =
+            std::array::from_fn::<_, 3, _>(|c| !(0..32).contains(&(base[c] + delta[c])));
+        if overflows[0] || overflows[1] {
+            let paint = if overflows[0] {
+                etc2_t_paint(bits)
+            } else {
+                etc2_h_paint(bits)
+            };
+            return std::array::from_fn(|i| match index_of(i) as usize {
+                2 if !opaque => [0; 4],
+                index => rgb(paint[index]),
+            });
+        }
+        if overflows[2] {
+            return etc2_planar(bits);
+        }
+        let extend = |value: i32| value << 3 | value >> 2;
+        [
+            base.map(extend),
+            std::array::from_fn(|c| extend(base[c] + delta[c])),
+        ]
+    } else {
+        let extend = |high: u32| field(bits, high, 4) * 17;
+        [
+            [extend(63), extend(55), extend(47)],
+            [extend(59), extend(51), extend(43)],
+        ]
+    };
+
+    // Two half blocks side by side, or stacked when flipped, each with its own table
+    let flip = field(bits, 32, 1) == 1;
+    let tables = [field(bits, 39, 3), field(bits, 36, 3)];
+    std::array::from_fn(|i| {
+        let (x, y) = (i % 4, i / 4);
+        let half = if flip { y / 2 } else { x / 2 };
+        let [small, large] = ETC_MODIFIERS[tables[half] as usize];
+        let modifier = match index_of(i) {
+            2 if !opaque => return [0; 4],
+            0 if !opaque => 0,
+            0 => small,
+            1 => large,
+            2 => -small,
+            _ => -large,
+        };
+        rgb(offset(base_colors[half], modifier))
+    })
+}
+
+fn etc2_t_paint(bits: u64) -> [[i32; 3]; 4] {
+    let extend = |value: i32| value * 17;
+    let c1 = [
+        field(bits, 60, 2) << 2 | field(bits, 57, 2),
+        field(bits, 55, 4),
+        field(bits, 51, 4),
+    ]
+    .map(extend);
+    let c2 = [field(bits, 47, 4), field(bits, 43, 4), field(bits, 39, 4)].map(extend);
+    let distance = ETC_DISTANCES[(field(bits, 35, 2) << 1 | field(bits, 32, 1)) as usize];
+    [c1, offset(c2, distance), c2, offset(c2, -distance)]
+}
+
+fn etc2_h_paint(bits: u64) -> [[i32; 3]; 4] {
+    let c1 = [
+        field(bits, 62, 4),
+        field(bits, 58, 3) << 1 | field(bits, 52, 1),
+        field(bits, 51, 1) << 3 | field(bits, 49, 3),
+    ];
+    let c2 = [field(bits, 46, 4), field(bits, 42, 4), field(bits, 38, 4)];
+    // The last distance bit is implied by the order of the two colors
+    let packed = |[r, g, b]: [i32; 3]| r << 8 | g << 4 | b;
+    let order = (packed(c1) >= packed(c2)) as i32;
+    let distance =
+        ETC_DISTANCES[(field(bits, 34, 1) << 2 | field(bits, 32, 1) << 1 | order) as usize];
+    let (c1, c2) = (c1.map(|value| value * 17), c2.map(|value| value * 17));
+    [
+        offset(c1, distance),
+        offset(c1, -distance),
+        offset(c2, distance),
+        offset(c2, -distance),
+    ]
+}
+
+/// Planar mode: a gradient from the origin color towards the horizontal and vertical ones
+fn etc2_planar(bits: u64) -> Block {
+    let extend6 = |value: i32| value << 2 | value >> 4;
+    let extend7 = |value: i32| value << 1 | value >> 6;
+    let origin = [
+        extend6(field(bits, 62, 6)),
+        extend7(field(bits, 56, 1) << 6 | field(bits, 54, 6)),
+        extend6(field(bits, 48, 1) << 5 | field(bits, 44, 2) << 3 | field(bits, 41, 3)),
+    ];
+    let horizontal = [
+        extend6(field(bits, 38, 5) << 1 | field(bits, 32, 1)),
+        extend7(field(bits, 31, 7)),
+        extend6(field(bits, 24, 6)),
+    ];
+    let vertical = [
+        extend6(field(bits, 18, 6)),
+        extend7(field(bits, 12, 7)),
+        extend6(field(bits, 5, 6)),
+    ];
+    std::array::from_fn(|i| {
+        let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+        rgb(std::array::from_fn(|c| {
+            (x * (horizontal[c] - origin[c]) + y * (vertical[c] - origin[c]) + 4 * origin[c] + 2)
+                >> 2
+        }))
+    })
+}
+
+/// EAC block: ETC2's alpha channel, or with `eleven_bit` one channel of R11/RG11
+/// (reduced to 8 bits here)
+fn eac_values(block: &[u8], eleven_bit: bool) -> [u8; 16] {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let base = field(bits, 63, 8);
+    let multiplier = field(bits, 55, 4);
+    let modifiers = EAC_MODIFIERS[field(bits, 51, 4) as usize];
+    std::array::from_fn(|i| {
+        let p = (i % 4 * 4 + i / 4) as u32;
+        let modifier = modifiers[((bits >> (45 - 3 * p)) & 7) as usize];
+        if eleven_bit {
+            let scale = if multiplier == 0 { 1 } else { multiplier * 8 };
+            let value = (base * 8 + 4 + modifier * scale).clamp(0, 2047);
+            ((value * 255 + 1023) / 2047) as u8
+        } else {
+            (base + modifier * multiplier).clamp(0, 255) as u8
+        }
+    })
+}
+
+/// EAC block of signed values, one channel of R11 or RG11 SNORM (reduced to 8 bits here)
+fn eac_signed_values(block: &[u8]) -> [u8; 16] {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let base = (block[0] as i8).max(-127) as i32;
+    let multiplier = field(bits, 55, 4);
+    let modifiers = EAC_MODIFIERS[field(bits, 51, 4) as usize];
+    std::array::from_fn(|i| {
+        let p = (i % 4 * 4 + i / 4) as u32;
+        let modifier = modifiers[((bits >> (45 - 3 * p)) & 7) as usize];
+        let scale = if multiplier == 0 { 1 } else { multiplier * 8 };
+        signed_to_unorm(base * 8 + modifier * scale, 1023)
+    })
+}
+
+/// Reads a 128 bit block from its lowest bit up
+struct BitReader {
+    bits: u128,
+    position: u32,
+}
+
+impl BitReader {
+    fn new(bits: u128) -> Self {
+        Self { bits, position: 0 }
+    }
+
+    /// The next `count` bits, zeros past the end
+    fn read(&mut self, count: u32) -> u32 {
+        let value = self.bits.checked_shr(self.position).unwrap_or(0) & ((1 << count) - 1);
+        self.position += count;
+        value as u32
+    }
+}
+
+fn block_bits(block: &[u8]) -> u128 {
+    u128::from_le_bytes(block[..16].try_into().unwrap())
+}
+
+/// `endpoint` to `other` by `weight` out of 64, as BC6H and BC7 interpolate
+fn bc_interpolate(endpoint: i32, other: i32, weight: u32) -> i32 {
+    ((64 - weight as i32) * endpoint + weight as i32 * other + 32) >> 6
+}
+
+/// Subset of texel `i` in one of `subsets` subsets' partition `partition`
+fn bc_subset(subsets: usize, partition: usize, i: usize) -> usize {
+    match subsets {
+        1 => 0,
+        2 => (BC_PARTITIONS2[partition] >> i) as usize & 1,
+        _ => (BC_PARTITIONS3[partition] >> (2 * i)) as usize & 3,
+    }
+}
+
+/// Whether texel `i` is the anchor of its subset, and so stores its index a bit short
+fn bc_is_anchor(subsets: usize, partition: usize, i: usize) -> bool {
+    match subsets {
+        _ if i == 0 => true,
+        1 => false,
+        2 => i == BC_ANCHORS2[partition] as usize,
+        _ => BC_ANCHORS3[partition].contains(&(i as u8)),
+    }
+}
+
+/// BC7 block. Each of its eight modes trades subsets against endpoint and index precision.
+fn bc7_colors(block: &[u8]) -> Block {
+    // The mode is the number of zero bits before the first set one
+    let Some(mode) = BC7_MODES.get(block[0].trailing_zeros() as usize) else {
+        return [[0; 4]; 16];
+    };
+    let mut bits = BitReader::new(block_bits(block));
+    bits.read(block[0].trailing_zeros() + 1);
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits) == 1;
+
+    // Every endpoint's red, then every endpoint's green and so on
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..4 {
+        let channel_bits = if channel < 3 {
+            mode.color_bits
+        } else {
+            mode.alpha_bits
+        };
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = bits.read(channel_bits);
+        }
+    }
+    let mut p_bits = [0; 6];
+    if mode.endpoint_p_bits {
+        for p in &mut p_bits[..endpoint_count] {
+            *p = bits.read(1);
+        }
+    } else if mode.shared_p_bits {
+        for subset in 0..mode.subsets {
+            let p = bits.read(1);
+            p_bits[subset * 2] = p;
+            p_bits[subset * 2 + 1] = p;
+        }
+    }
+    let has_p = (mode.endpoint_p_bits || mode.shared_p_bits) as u32;
+    for (endpoint, p) in endpoints[..endpoint_count].iter_mut().zip(p_bits) {
+        for (channel, value) in endpoint.iter_mut().enumerate() {
+            let channel_bits = if channel < 3 {
+                mode.color_bits
+            } else {
+                mode.alpha_bits
+            };
+            if channel_bits == 0 {
+                *value = 255;
+                continue;
+            }
+            // The P bit is the lowest, then the top bits fill out the bottom of the byte
+            let precision = channel_bits + has_p;
+            let with_p = *value << has_p | p;
+            *value = with_p << (8 - precision) | with_p >> (2 * precision - 8);
+        }
+    }
+
+    let mut primary = [0; 16];
+    for (i, index) in primary.iter_mut().enumerate() {
+        let anchor = bc_is_anchor(mode.subsets, partition, i) as u32;
+        *index = bits.read(mode.index_bits - anchor) as usize;
+    }
+    let mut secondary = [0; 16];
+    if mode.secondary_index_bits > 0 {
+        for (i, index) in secondary.iter_mut().enumerate() {
+            *index = bits.read(mode.secondary_index_bits - (i == 0) as u32) as usize;
+        }
+    }
+    let weight = |index_bits: u32, index: usize| match index_bits {
+        2 => BC_WEIGHTS2[index],
+        3 => BC_WEIGHTS3[index],
+        _ => BC_WEIGHTS4[index],
+    };
+
+    std::array::from_fn(|i| {
+        let subset = bc_subset(mode.subsets, partition, i);
+        let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+        let (color_weight, alpha_weight) = if mode.secondary_index_bits == 0 {
+            let weight = weight(mode.index_bits, primary[i]);
+            (weight, weight)
+        } else if index_selection {
+            (
+                weight(mode.secondary_index_bits, secondary[i]),
+                weight(mode.index_bits, primary[i]),
+            )
+        } else {
+            (
+                weight(mode.index_bits, primary[i]),
+                weight(mode.secondary_index_bits, secondary[i]),
+            )
+        };
+        let mut texel: [u8; 4] = std::array::from_fn(|c| {
+            let weight = if c < 3 { color_weight } else { alpha_weight };
+            bc_interpolate(e0[c] as i32, e1[c] as i32, weight) as u8
+        });
+        // Modes 4 and 5 can store a color channel in alpha's place, for its precision
+        if rotation > 0 {
+            texel.swap(3, rotation as usize - 1);
+        }
+        texel
+    })
+}
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    value << (32 - bits) >> (32 - bits)
+}
+
+/// A BC6H endpoint channel from `bits` bits to the 16 bit range interpolation runs in
+fn bc6h_unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        match value {
+            _ if bits >= 15 => value,
+            0 => 0,
+            _ if value == (1 << bits) - 1 => 0xffff,
+            _ => ((value << 16) + 0x8000) >> bits,
+        }
+    } else {
+        let magnitude = value.abs();
+        let unquantized = match magnitude {
+            _ if bits >= 16 => magnitude,
+            0 => 0,
+            _ if magnitude >= (1 << (bits - 1)) - 1 => 0x7fff,
+            _ => ((magnitude << 15) + 0x4000) >> (bits - 1),
+        };
+        if value < 0 { -unquantized } else { unquantized }
+    }
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (half >> 10 & 31) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 => f32::INFINITY,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// An interpolated BC6H channel as the half float it stands for
+fn bc6h_half(value: i32, signed: bool) -> f32 {
+    let half = if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | ((-value * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    };
+    half_to_f32(half)
+}
+
+/// BC6H block: HDR RGB in one or two subsets, here clamped to 0..1
+fn bc6h_colors(block: &[u8], signed: bool) -> Block {
+    let mut bits = BitReader::new(block_bits(block));
+    let mut mode_value = bits.read(2);
+    if mode_value > 1 {
+        mode_value |= bits.read(3) << 2;
+    }
+    let Some(mode) = BC6H_MODES.iter().find(|mode| mode.value == mode_value) else {
+        return [[0, 0, 0, 255]; 16];
+    };
+    let mut endpoints = [0i32; 12];
+    for &(channel, low, count) in mode.fields {
+        endpoints[channel] |= (bits.read(count) as i32) << low;
+    }
+    // The four modes ending in 0b11 have one subset, the rest two
+    let subsets = if mode.value & 3 == 3 { 1 } else { 2 };
+    let partition = if subsets == 2 {
+        bits.read(5) as usize
+    } else {
+        0
+    };
+
+    let channel_count = subsets * 6;
+    let mask = (1 << mode.endpoint_bits) - 1;
+    if signed {
+        for value in &mut endpoints[..3] {
+            *value = sign_extend(*value, mode.endpoint_bits);
+        }
+    }
+    for channel in 3..channel_count {
+        let mut value = endpoints[channel];
+        if mode.transformed {
+            let delta = sign_extend(value, mode.delta_bits[channel % 3]);
+            value = (endpoints[channel % 3] + delta) & mask;
+        }
+        if signed {
+            value = sign_extend(value, mode.endpoint_bits);
+        }
+        endpoints[channel] = value;
+    }
+    for value in &mut endpoints[..channel_count] {
+        *value = bc6h_unquantize(*value, mode.endpoint_bits, signed);
+    }
+
+    let index_bits = if subsets == 2 { 3 } else { 4 };
+    std::array::from_fn(|i| i)
+        .map(|i| {
+            let anchor = bc_is_anchor(subsets, partition, i) as u32;
+            (i, bits.read(index_bits - anchor) as usize)
+        })
+        .map(|(i, index)| {
+            let weight = if subsets == 2 {
+                BC_WEIGHTS3[index]
+            } else {
+                BC_WEIGHTS4[index]
+            };
+            let first = bc_subset(subsets, partition, i) * 6;
+            let mut texel = [255; 4];
+            for (c, value) in texel.iter_mut().take(3).enumerate() {
+                let interpolated =
+                    bc_interpolate(endpoints[first + c], endpoints[first + 3 + c], weight);
+                *value = (bc6h_half(interpolated, signed).clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+            texel
+        })
+}
+
+/// Weight grid of an ASTC block
+struct AstcGrid {
+    width: usize,
+    height: usize,
+    /// Index into `ASTC_RANGES`
+    range: usize,
+    dual_plane: bool,
+}
+
+/// The weight grid an ASTC block mode (its lowest 11 bits) describes, None if reserved
+fn astc_grid(mode: u32) -> Option<AstcGrid> {
+    let bit = |n: u32| (mode >> n) & 1;
+    let a = (mode >> 5 & 3) as usize;
+    let mut high_precision = bit(9) == 1;
+    let mut dual_plane = bit(10) == 1;
+    let (range, width, height) = if mode & 3 != 0 {
+        let b = (mode >> 7 & 3) as usize;
+        let (width, height) = match mode >> 2 & 3 {
+            0 => (b + 4, a + 2),
+            1 => (b + 8, a + 2),
+            2 => (a + 2, b + 8),
+            _ if bit(8) == 1 => ((b & 1) + 2, a + 2),
+            _ => (a + 2, (b & 1) + 6),
+        };
+        (bit(4) | (mode & 3) << 1, width, height)
+    } else {
+        let b = (mode >> 9 & 3) as usize;
+        let (width, height) = match mode >> 7 & 3 {
+            0 => (12, a + 2),
+            1 => (a + 2, 12),
+            2 => {
+                high_precision = false;
+                dual_plane = false;
+                (a + 6, b + 6)
+            }
+            _ => match a {
+                0 => (6, 10),
+                1 => (10, 6),
+                _ => return None,
+            },
+        };
+        (bit(4) | (mode >> 2 & 3) << 1, width, height)
+    };
+    if range < 2 {
+        return None;
+    }
+    Some(AstcGrid {
+        width,
+        height,
+        range: range as usize - 2 + 6 * high_precision as usize,
+        dual_plane,
+    })
+}
+
+/// Bits an integer sequence of `count` values in `range` takes
+fn ise_bit_count(count: usize, range: usize) -> usize {
+    let (bits, base) = ASTC_RANGES[range];
+    count * bits as usize
+        + match base {
+            3 => (8 * count).div_ceil(5),
+            5 => (7 * count).div_ceil(3),
+            _ => 0,
+        }
+}
+
+/// Five values' trits from the eight bits they're packed into
+fn astc_trits(t: u32) -> [u32; 5] {
+    let (c, t4, t3) = if t >> 2 & 7 == 7 {
+        ((t >> 5 & 7) << 2 | t & 3, 2, 2)
+    } else if t >> 5 & 3 == 3 {
+        (t & 31, 2, t >> 7 & 1)
+    } else {
+        (t & 31, t >> 7 & 1, t >> 5 & 3)
+    };
+    let bit = |n: u32| c >> n & 1;
+    let (t2, t1, t0) = if c & 3 == 3 {
+        (2, bit(4), bit(3) << 1 | (bit(2) & !bit(3) & 1))
+    } else if c >> 2 & 3 == 3 {
+        (2, 2, c & 3)
+    } else {
+        (bit(4), c >> 2 & 3, bit(1) << 1 | (bit(0) & !bit(1) & 1))
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+/// Three values' quints from the seven bits they're packed into
+fn astc_quints(q: u32) -> [u32; 3] {
+    let bit = |n: u32| q >> n & 1;
+    if q >> 1 & 3 == 3 && q >> 5 & 3 == 0 {
+        let q2 = bit(0) << 2 | (bit(4) & !bit(0) & 1) << 1 | (bit(3) & !bit(0) & 1);
+        return [4, 4, q2];
+    }
+    let (q2, c) = if q >> 1 & 3 == 3 {
+        (4, (q >> 3 & 3) << 3 | (!q >> 5 & 3) << 1 | bit(0))
+    } else {
+        (q >> 5 & 3, q & 31)
+    };
+    let (q1, q0) = if c & 7 == 5 {
+        (4, c >> 3 & 3)
+    } else {
+        (c >> 3 & 3, c & 7)
+    };
+    [q0, q1, q2]
+}
+
+/// `count` values of an integer sequence in `range` read from the bottom of `bits`, each
+/// its trit or quint above its plain bits
+fn ise_decode(bits: u128, count: usize, range: usize) -> Vec<u32> {
+    let (value_bits, base) = ASTC_RANGES[range];
+    // Bits past the sequence belong to something else; a partial last group reads zeros
+    let length = ise_bit_count(count, range) as u32;
+    let mask = u128::MAX.checked_shr(128 - length).unwrap_or(0);
+    let mut reader = BitReader::new(bits & mask);
+    let mut values = Vec::with_capacity(count + 4);
+    while values.len() < count {
+        match base {
+            3 => {
+                let mut low = [0; 5];
+                let mut packed = 0;
+                for (value, (packed_bits, shift)) in
+                    low.iter_mut().zip([(2, 0), (2, 2), (1, 4), (2, 5), (1, 7)])
+                {
+                    *value = reader.read(value_bits);
+                    packed |= reader.read(packed_bits) << shift;
+                }
+                for (trit, low) in astc_trits(packed).into_iter().zip(low) {
+                    values.push(trit << value_bits | low);
+                }
+            }
+            5 => {
+                let mut low = [0; 3];
+                let mut packed = 0;
+                for (value, (packed_bits, shift)) in low.iter_mut().zip([(3, 0), (2, 3), (2, 5)]) {
+                    *value = reader.read(value_bits);
+                    packed |= reader.read(packed_bits) << shift;
+                }
+                for (quint, low) in astc_quints(packed).into_iter().zip(low) {
+                    values.push(quint << value_bits | low);
+                }
+            }
+            _ => values.push(reader.read(value_bits)),
+        }
+    }
+    values.truncate(count);
+    values
+}
+
+/// `value`'s `bits` bits repeated to fill `to` bits
+fn replicate(value: u32, bits: u32, to: u32) -> u32 {
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < to {
+        result = result << bits | value;
+        filled += bits;
+    }
+    result >> (filled - to)
+}
+
+/// The spec's bit patterns for unquantizing: each letter is a bit of `low`, `a` lowest
+fn pattern_bits(pattern: &str, low: u32) -> u32 {
+    pattern.bytes().fold(0, |result, letter| {
+        result << 1
+            | match letter {
+                b'0' => 0,
+                letter => low >> (letter - b'a') & 1,
+            }
+    })
+}
+
+/// A color endpoint value in `range` scaled to 0..255
+fn astc_unquantize_color(value: u32, range: usize) -> u32 {
+    let (bits, base) = ASTC_RANGES[range];
+    if base == 1 {
+        return replicate(value, bits, 8);
+    }
+    let (digit, low) = (value >> bits, value & ((1 << bits) - 1));
+    let (pattern, scale) = match (base, bits) {
+        (3, 1) => ("000000000", 204),
+        (3, 2) => ("b000b0bb0", 93),
+        (3, 3) => ("cb000cbcb", 44),
+        (3, 4) => ("dcb000dcb", 22),
+        (3, 5) => ("edcb000ed", 11),
+        (3, 6) => ("fedcb000f", 5),
+        (5, 1) => ("000000000", 113),
+        (5, 2) => ("b0000bb00", 54),
+        (5, 3) => ("cb0000cbc", 26),
+        (5, 4) => ("dcb0000dc", 13),
+        _ => ("edcb0000e", 6),
+    };
+    let a = if low & 1 == 1 { 0x1ff } else { 0 };
+    let t = (digit * scale + pattern_bits(pattern, low)) ^ a;
+    (a & 0x80) | t >> 2
+}
+
+/// A weight in `range` scaled to 0..64
+fn astc_unquantize_weight(value: u32, range: usize) -> u32 {
+    let (bits, base) = ASTC_RANGES[range];
+    let weight = match (base, bits) {
+        (1, _) => replicate(value, bits, 6),
+        (3, 0) => [0, 32, 63][value as usize],
+        (5, 0) => [0, 16, 32, 47, 63][value as usize],
+        _ => {
+            let (digit, low) = (value >> bits, value & ((1 << bits) - 1));
+            let (pattern, scale) = match (base, bits) {
+                (3, 1) => ("0000000", 50),
+                (3, 2) => ("b000b0b", 23),
+                (3, 3) => ("cb000cb", 11),
+                (5, 1) => ("0000000", 28),
+                _ => ("b0000b0", 13),
+            };
+            let a = if low & 1 == 1 { 0x7f } else { 0 };
+            let t = (digit * scale + pattern_bits(pattern, low)) ^ a;
+            (a & 0x20) | t >> 2
+        }
+    };
+    if weight > 32 { weight + 1 } else { weight }
+}
+
+/// Moves a bit from `b` into the top of `a` and makes `b` a signed offset
+fn bit_transfer_signed(a: &mut i32, b: &mut i32) {
+    *b = *b >> 1 | (*a & 0x80);
+    *a = *a >> 1 & 0x3f;
+    if *a & 0x20 != 0 {
+        *a -= 0x40;
+    }
+}
+
+/// Averages red and green towards blue, which ASTC uses to get more precision from
+/// endpoints stored in the reverse order
+fn blue_contract([r, g, b, a]: [i32; 4]) -> [i32; 4] {
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+/// The two endpoints LDR color endpoint mode `mode` makes from `values`, None for the
+/// HDR modes
+fn astc_endpoints(mode: u32, values: &[u32]) -> Option<[[i32; 4]; 2]> {
+    let mut v: [i32; 8] = std::array::from_fn(|i| values.get(i).copied().unwrap_or(0) as i32);
+    let endpoints = match mode {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xc0);
+            let l1 = (l0 + (v[1] & 0x3f)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let [v0, v1, v2, v3, ..] = &mut v;
+            bit_transfer_signed(v1, v0);
+            bit_transfer_signed(v3, v2);
+            [
+                [*v0, *v0, *v0, *v2],
+                [*v0 + *v1, *v0 + *v1, *v0 + *v1, *v2 + *v3],
+            ]
+        }
+        6 => [
+            [
+                (v[0] * v[3]) >> 8,
+                (v[1] * v[3]) >> 8,
+                (v[2] * v[3]) >> 8,
+                255,
+            ],
+            [v[0], v[1], v[2], 255],
+        ],
+        8 | 12 => {
+            let alpha = if mode == 12 { [v[6], v[7]] } else { [255, 255] };
+            let e0 = [v[0], v[2], v[4], alpha[0]];
+            let e1 = [v[1], v[3], v[5], alpha[1]];
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [e0, e1]
+            } else {
+                [blue_contract(e1), blue_contract(e0)]
+            }
+        }
+        9 | 13 => {
+            for pair in v.chunks_exact_mut(2) {
+                let [base, offset] = pair else { unreachable!() };
+                bit_transfer_signed(offset, base);
+            }
+            let alpha = if mode == 13 {
+                [v[6], v[6] + v[7]]
+            } else {
+                [255, 255]
+            };
+            let e0 = [v[0], v[2], v[4], alpha[0]];
+            let e1 = [v[0] + v[1], v[2] + v[3], v[4] + v[5], alpha[1]];
+            if v[1] + v[3] + v[5] >= 0 {
+                [e0, e1]
+            } else {
+                [blue_contract(e1), blue_contract(e0)]
+            }
+        }
+        10 => [
+            [
+                (v[0] * v[3]) >> 8,
+                (v[1] * v[3]) >> 8,
+                (v[2] * v[3]) >> 8,
+                v[4],
+            ],
+            [v[0], v[1], v[2], v[5]],
+        ],
+        _ => return None,
+    };
+    Some(endpoints.map(|endpoint| endpoint.map(|c| c.clamp(0, 255))))
+}
+
+/// ASTC's hash of a texel to one of `partition_count` partitions, from pattern `seed`
+fn astc_partition(seed: u32, partition_count: usize, x: usize, y: usize, small: bool) -> usize {
+    let (x, y) = if small { (x << 1, y << 1) } else { (x, y) };
+    let seed = seed + (partition_count as u32 - 1) * 1024;
+    let mut rnum = seed;
+    rnum ^= rnum >> 15;
+    rnum = rnum.wrapping_sub(rnum << 17);
+    rnum = rnum.wrapping_add(rnum << 7);
+    rnum = rnum.wrapping_add(rnum << 4);
+    rnum ^= rnum >> 5;
+    rnum = rnum.wrapping_add(rnum << 16);
+    rnum ^= rnum >> 7;
+    rnum ^= rnum >> 3;
+    rnum ^= rnum << 6;
+    rnum ^= rnum >> 17;
+
+    let mut seeds: [u32; 8] = std::array::from_fn(|i| {
+        let s = rnum >> (4 * i) & 15;
+        s * s
+    });
+    let (sh1, sh2) = match (seed & 1 == 1, seed & 2 == 2) {
+        (true, two) => (
+            if two { 4 } else { 5 },
+            if partition_count == 3 { 6 } else { 5 },
+        ),
+        (false, two) => (
+            if partition_count == 3 { 6 } else { 5 },
+            if two { 4 } else { 5 },
+        ),
+    };
+    for (i, s) in seeds.iter_mut().enumerate() {
+        *s >>= if i % 2 == 0 { sh1 } else { sh2 };
+    }
+    let (x, y) = (x as u32, y as u32);
+    let mut sums = [
+        seeds[0] * x + seeds[1] * y + (rnum >> 14),
+        seeds[2] * x + seeds[3] * y + (rnum >> 10),
+        seeds[4] * x + seeds[5] * y + (rnum >> 6),
+        seeds[6] * x + seeds[7] * y + (rnum >> 2),
+    ]
+    .map(|sum| sum & 63);
+    for sum in &mut sums[partition_count..] {
+        *sum = 0;
+    }
+    // The first of the largest
+    (0..4).rev().max_by_key(|&i| sums[i]).unwrap_or(0)
+}
+
+/// ASTC block of `footprint` texels, row by row. Invalid blocks are the error color.
+fn astc_colors(block: &[u8], footprint: (usize, usize)) -> Vec<[u8; 4]> {
+    astc_block(block_bits(block), footprint)
+        .unwrap_or_else(|| vec![ASTC_ERROR_COLOR; footprint.0 * footprint.1])
+}
+
+fn astc_block(bits: u128, (block_width, block_height): (usize, usize)) -> Option<Vec<[u8; 4]>> {
+    let read = |low: usize, count: usize| (bits >> low & ((1 << count) - 1)) as u32;
+    let texel_count = block_width * block_height;
+    // Void extent: one 16 bit per channel color for the whole block, LDR unless flagged
+    if read(0, 9) == 0x1fc {
+        if read(9, 1) == 1 {
+            return None;
+        }
+        let color = std::array::from_fn(|c| (read(64 + 16 * c, 16) >> 8) as u8);
+        return Some(vec![color; texel_count]);
+    }
+
+    let grid = astc_grid(read(0, 11))?;
+    let partition_count = read(11, 2) as usize + 1;
+    let plane_count = 1 + grid.dual_plane as usize;
+    let weight_count = grid.width * grid.height * plane_count;
+    let weight_bits = ise_bit_count(weight_count, grid.range);
+    if grid.width > block_width
+        || grid.height > block_height
+        || weight_count > 64
+        || !(24..=96).contains(&weight_bits)
+        || (partition_count == 4 && grid.dual_plane)
+    {
+        return None;
+    }
+
+    // Color endpoint modes; more than one partition can give each its own, their
+    // high bits kept below the weights
+    let mut below_weights = 128 - weight_bits;
+    let (modes, color_start) = if partition_count == 1 {
+        ([read(13, 4); 4], 17)
+    } else {
+        let shared = read(23, 6);
+        if shared & 3 == 0 {
+            ([shared >> 2; 4], 29)
+        } else {
+            let extra = 3 * partition_count - 4;
+            below_weights -= extra;
+            let encoded = shared | read(below_weights, extra) << 6;
+            let class = (encoded & 3) - 1;
+            let mut modes = [0; 4];
+            for (i, mode) in modes.iter_mut().take(partition_count).enumerate() {
+                let high = encoded >> (2 + i) & 1;
+                let low = encoded >> (2 + partition_count + 2 * i) & 3;
+                *mode = (class + high) << 2 | low;
+            }
+            (modes, 29)
+        }
+    };
+    let plane2_component = grid.dual_plane.then(|| {
+        below_weights -= 2;
+        read(below_weights, 2) as usize
+    });
+
+    let value_count: usize = modes[..partition_count]
+        .iter()
+        .map(|&mode| (mode as usize / 4 + 1) * 2)
+        .sum();
+    if value_count > 18 || below_weights < color_start {
+        return None;
+    }
+    let color_bits = below_weights - color_start;
+    // The largest range that fits, of at least six levels
+    let color_range = (4..ASTC_RANGES.len())
+        .rev()
+        .find(|&range| ise_bit_count(value_count, range) <= color_bits)?;
+    let values: Vec<u32> = ise_decode(bits >> color_start, value_count, color_range)
+        .into_iter()
+        .map(|value| astc_unquantize_color(value, color_range))
+        .collect();
+    let mut endpoints = Vec::with_capacity(partition_count);
+    let mut first = 0;
+    for &mode in &modes[..partition_count] {
+        endpoints.push(astc_endpoints(mode, &values[first..])?);
+        first += (mode as usize / 4 + 1) * 2;
+    }
+
+    // Weights are stored from the top of the block down, plane by plane per grid point
+    let weights: Vec<u32> = ise_decode(bits.reverse_bits(), weight_count, grid.range)
+        .into_iter()
+        .map(|value| astc_unquantize_weight(value, grid.range))
+        .collect();
+    let scale_s = (1024 + block_width as u32 / 2) / (block_width as u32 - 1);
+    let scale_t = (1024 + block_height as u32 / 2) / (block_height as u32 - 1);
+    let infill = |x: usize, y: usize, plane: usize| {
+        let gs = (scale_s * x as u32 * (grid.width as u32 - 1) + 32) >> 6;
+        let gt = (scale_t * y as u32 * (grid.height as u32 - 1) + 32) >> 6;
+        let (js, fs, jt, ft) = ((gs >> 4) as usize, gs & 15, (gt >> 4) as usize, gt & 15);
+        let weight = |s: usize, t: usize| {
+            weights
+                .get((t * grid.width + s) * plane_count + plane)
+                .copied()
+                .unwrap_or(0)
+        };
+        let w11 = (fs * ft + 8) >> 4;
+        (weight(js, jt) * (16 + w11 - fs - ft)
+            + weight(js + 1, jt) * (fs - w11)
+            + weight(js, jt + 1) * (ft - w11)
+            + weight(js + 1, jt + 1) * w11
+            + 8)
+            >> 4
+    };
+
+    let seed = read(13, 10);
+    Some(
+        (0..texel_count)
+            .map(|i| {
+                let (x, y) = (i % block_width, i / block_width);
+                let partition = if partition_count > 1 {
+                    astc_partition(seed, partition_count, x, y, texel_count < 31)
+                } else {
+                    0
+                };
+                let [e0, e1] = endpoints[partition];
+                let (w0, w1) = (
+                    infill(x, y, 0),
+                    if grid.dual_plane { infill(x, y, 1) } else { 0 },
+                );
+                std::array::from_fn(|c| {
+                    let weight = if plane2_component == Some(c) { w1 } else { w0 };
+                    let (c0, c1) = (e0[c] as u32 * 257, e1[c] as u32 * 257);
+                    ((c0 * (64 - weight) + c1 * weight + 32) >> 6 >> 8) as u8
+                })
+            })
+            .collect(),
+    )
+}
+
+// Reference blocks are assembled field by field from the format specifications'
+// bit layouts, and their expected texels worked out from the same documents
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::TextureFormat as Format;
+
+    /// Pack `(bit count, value)` fields into a block, lowest bit first
+    fn pack(fields: &[(u32, u128)]) -> [u8; 16] {
+        let mut bits = 0u128;
+        let mut offset = 0;
+        for &(count, value) in fields {
+            assert!(value >> count == 0, "{value} doesn't fit in {count} bits");
+            bits |= value << offset;
+            offset += count;
+        }
+        assert!(offset <= 128, "fields take {offset} bits");
+        bits.to_le_bytes()
+    }
+
+    /// Pack `(bit count, value)` fields into an ETC block, highest bit first
+    fn pack_etc(fields: &[(u32, u64)]) -> [u8; 8] {
+        let mut bits = 0u64;
+        let mut offset = 64;
+        for &(count, value) in fields {
+            offset -= count;
+            bits |= value << offset;
+        }
+        assert_eq!(offset, 0);
+        bits.to_be_bytes()
+    }
+
+    /// Index fields for a BC6H or BC7 block, anchor texels one bit shorter
+    fn bc_indices(indices: &[u128], bits: u32, anchors: &[usize]) -> Vec<(u32, u128)> {
+        indices
+            .iter()
+            .enumerate()
+            .map(|(i, &index)| (bits - anchors.contains(&i) as u32, index))
+            .collect()
+    }
+
+    fn decode_texels(format: Format, width: u32, height: u32, data: &[u8]) -> Vec<[u8; 4]> {
+        decode(format, width, height, data)
+            .unwrap()
+            .chunks_exact(4)
+            .map(|texel| texel.try_into().unwrap())
+            .collect()
+    }
+
+    /// Texel `i` takes color `indices[i]` from the palette of its subset
+    fn from_palettes<const N: usize>(
+        palettes: &[[[u8; 4]; N]],
+        subsets: [usize; 16],
+        indices: [u128; 16],
+    ) -> Vec<[u8; 4]> {
+        (0..16)
+            .map(|i| palettes[subsets[i]][indices[i] as usize])
+            .collect()
+    }
+
+    #[test]
+    fn footprints_crop_partial_edge_blocks() {
+        // A 7x6 image of 5x4 blocks: two columns and two rows, the right and bottom ones partial
+        let data = [10, 11, 12, 13];
+        let decode_block = |block: &[u8]| -> Vec<[u8; 4]> {
+            (0..20).map(|i| [block[0], i as u8, 0, 255]).collect()
+        };
+        let pixels = decode_footprints(7, 6, &data, (5, 4), 1, decode_block).unwrap();
+        assert_eq!(pixels.len(), 7 * 6 * 4);
+        for (i, texel) in pixels.chunks_exact(4).enumerate() {
+            let (x, y) = (i % 7, i / 7);
+            let block = 10 + (y / 4 * 2 + x / 5) as u8;
+            let texel_in_block = (y % 4 * 5 + x % 5) as u8;
+            assert_eq!(texel, [block, texel_in_block, 0, 255], "pixel ({x}, {y})");
+        }
+
+        assert!(decode_footprints(7, 6, &data[..3], (5, 4), 1, decode_block).is_err());
+    }
+
+    #[test]
+    fn bc7_mode0() {
+        // Partition 0 of the three subset table, anchors at texels 0, 3 and 15
+        let subsets = [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2];
+        let indices = [0, 1, 2, 3, 4, 5, 6, 7, 7, 6, 5, 4, 3, 2, 1, 0];
+        let mut fields = vec![(1, 0b1), (4, 0)];
+        // Four bit endpoints, red then green then blue, then a P bit per endpoint
+        for value in [0, 15, 15, 15, 0, 0, 0, 15, 0, 0, 0, 15, 0, 15, 0, 0, 15, 0] {
+            fields.push((4, value));
+        }
+        for p in [0, 1, 1, 0, 0, 1] {
+            fields.push((1, p));
+        }
+        fields.extend(bc_indices(&indices, 3, &[0, 3, 15]));
+
+        let palettes = [
+            [0, 36, 72, 108, 147, 183, 219, 255].map(|v| [v, v, v, 255]),
+            [
+                [255, 8, 8, 255],
+                [254, 7, 7, 255],
+                [253, 6, 6, 255],
+                [252, 5, 5, 255],
+                [250, 3, 3, 255],
+                [249, 2, 2, 255],
+                [248, 1, 1, 255],
+                [247, 0, 0, 255],
+            ],
+            [
+                [0, 0, 247, 255],
+                [1, 36, 213, 255],
+                [2, 72, 180, 255],
+                [3, 108, 146, 255],
+                [5, 147, 109, 255],
+                [6, 183, 75, 255],
+                [7, 219, 42, 255],
+                [8, 255, 8, 255],
+            ],
+        ];
+        assert_eq!(
+            decode_texels(Format::Bc7RgbaUnorm, 4, 4, &pack(&fields)),
+            from_palettes(&palettes, subsets, indices)
+        );
+    }
+
+    #[test]
+    fn bc7_mode1() {
+        // Partition 17 of the two subset table, whose second anchor is texel 2
+        let subsets = [0, 1, 1, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        let indices = [0, 7, 3, 5, 1, 2, 3, 4, 5, 6, 7, 0, 1, 2, 3, 4];
+        let mut fields = vec![(2, 0b10), (6, 17)];
+        for value in [0, 63, 63, 0, 0, 63, 0, 63, 0, 63, 32, 16] {
+            fields.push((6, value));
+        }
+        // One P bit shared by both endpoints of a subset
+        fields.extend([(1, 1), (1, 0)]);
+        fields.extend(bc_indices(&indices, 3, &[0, 2]));
+
+        let palettes = [
+            [2, 38, 73, 109, 148, 184, 219, 255].map(|v| [v, v, v, 255]),
+            [
+                [253, 0, 129, 255],
+                [217, 36, 120, 255],
+                [182, 71, 111, 255],
+                [146, 107, 102, 255],
+                [107, 146, 91, 255],
+                [71, 182, 82, 255],
+                [36, 217, 73, 255],
+                [0, 253, 64, 255],
+            ],
+        ];
+        assert_eq!(
+            decode_texels(Format::Bc7RgbaUnorm, 4, 4, &pack(&fields)),
+            from_palettes(&palettes, subsets, indices)
+        );
+    }
+
+    #[test]
+    fn bc7_mode2() {
+        // Partition 1 of the three subset table, anchors at texels 0, 3 and 8
+        let subsets = [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1];
+        let indices = [0, 1, 2, 1, 3, 2, 1, 0, 1, 2, 3, 0, 1, 2, 3, 3];
+        let mut fields = vec![(3, 0b100), (6, 1)];
+        for value in [
+            0, 31, 31, 0, 0, 16, 0, 31, 0, 31, 0, 16, 0, 31, 0, 0, 31, 16,
+        ] {
+            fields.push((5, value));
+        }
+        fields.extend(bc_indices(&indices, 2, &[0, 3, 8]));
+
+        let palettes = [
+            [0, 84, 171, 255].map(|v| [v, v, v, 255]),
+            [
+                [255, 0, 0, 255],
+                [171, 84, 0, 255],
+                [84, 171, 0, 255],
+                [0, 255, 0, 255],
+            ],
+            [
+                [0, 0, 255, 255],
+                [43, 43, 215, 255],
+                [89, 89, 172, 255],
+                [132, 132, 132, 255],
+            ],
+        ];
+        assert_eq!(
+            decode_texels(Format::Bc7RgbaUnorm, 4, 4, &pack(&fields)),
+            from_palettes(&palettes, subsets, indices)
+        );
+    }
+
+    #[test]
+    fn bc7_mode3() {
+        // Partition 34 of the two subset table, whose second anchor is texel 6
+        let subsets = [0, 1, 0, 1, 1, 0, 1, 0, 0, 1, 0, 1, 1, 0, 1, 0];
+        let indices = [0, 1, 2, 3, 3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0];
+        let mut fields = vec![(4, 0b1000), (6, 34)];
+        for value in [0, 127, 127, 0, 0, 127, 0, 0, 0, 127, 0, 127] {
+            fields.push((7, value));
+        }
+        for p in [0, 1, 0, 1] {
+            fields.push((1, p));
+        }
+        fields.extend(bc_indices(&indices, 2, &[0, 6]));
+
+        let palettes = [
+            [0, 84, 171, 255].map(|v| [v, v, v, 255]),
+            [
+                [254, 0, 0, 255],
+                [171, 0, 84, 255],
+                [84, 1, 171, 255],
+                [1, 1, 255, 255],
+            ],
+        ];
+        assert_eq!(
+            decode_texels(Format::Bc7RgbaUnorm, 4, 4, &pack(&fields)),
+            from_palettes(&palettes, subsets, indices)
+        );
+    }
+
+    #[test]
+    fn bc7_mode4() {
+        // Rotation 2 swaps green and alpha; index selection 1 gives color the three bit indices
+        let mut fields = vec![(5, 0b10000), (2, 2), (1, 1)];
+        for value in [0, 31, 31, 0, 10, 20] {
+            fields.push((5, value));
+        }
+        fields.extend([(6, 0), (6, 63)]);
+        fields.extend(bc_indices(&[0, 1, 2, 3].repeat(4), 2, &[0]));
+        fields.extend(bc_indices(&[0, 1, 2, 3, 4, 5, 6, 7].repeat(2), 3, &[0]));
+
+        let colors = [
+            [0, 255, 82],
+            [36, 219, 94],
+            [72, 183, 105],
+            [108, 147, 117],
+            [147, 108, 130],
+            [183, 72, 142],
+            [219, 36, 153],
+            [255, 0, 165],
+        ];
+        let alphas = [0, 84, 171, 255];
+        let expected: Vec<_> = (0..16)
+            .map(|i| {
+                let [r, g, b] = colors[i % 8];
+                [r, alphas[i % 4], b, g]
+            })
+            .collect();
+        assert_eq!(
+            decode_texels(Format::Bc7RgbaUnorm, 4, 4, &pack(&fields)),
+            expected
+        );
+    }
+
+    #[test]
+    fn bc7_mode5() {
+        // Rotation 1 swaps red and alpha
+        let mut fields = vec![(6, 0b100000), (2, 1)];
+        for value in [0, 0x7f, 0x40, 0x20, 0x7f, 0] {
+            fields.push((7, value));
+        }
+        fields.extend([(8, 0x10), (8, 0xf0)]);
+        fields.extend(bc_indices(&[0, 1, 2, 3].repeat(4), 2, &[0]));
+        let alpha_indices: Vec<u128> = (0..16).map(|i| i / 4).collect();
+        fields.extend(bc_indices(&alpha_indices, 2, &[0]));
+
+        let colors = [[0, 129, 255], [84, 108, 171], [171, 85, 84], [255, 64, 0]];
+        let alphas = [16, 90, 167, 240];
+        let expected: Vec<_> = (0..16)
+            .map(|i| {
+                let [r, g, b] = colors[i % 4];
+                [alphas[i / 4], g, b, r]
+            })
+            .collect();
+        assert_eq!(
+            decode_texels(Format::Bc7RgbaUnorm, 4, 4, &pack(&fields)),
+            expected
+        );
+    }
+
+    #[test]
+    fn bc7_mode6() {
+        let mut fields = vec![(7, 0b1000000)];
+        for value in [0, 0x7f, 0x7f, 0, 0x20, 0x60, 0x7f, 0x40] {
+            fields.push((7, value));
+        }
+        fields.extend([(1, 0), (1, 1)]);
+        let indices: Vec<u128> = (0..16).collect();
+        fields.extend(bc_indices(&indices, 4, &[0]));
+
+        let expected = vec![
+            [0, 254, 64, 254],
+            [16, 238, 72, 246],
+            [36, 218, 82, 236],
+            [52, 203, 90, 229],
+            [68, 187, 98, 221],
+            [84, 171, 106, 213],
+            [104, 151, 116, 203],
+            [120, 135, 124, 195],
+            [135, 120, 133, 188],
+            [151, 104, 141, 180],
+            [171, 84, 151, 170],
+            [187, 68, 159, 162],
+            [203, 52, 167, 154],
+            [219, 37, 175, 147],
+            [239, 17, 185, 137],
+            [255, 1, 193, 129],
+        ];
+        assert_eq!(
+            decode_texels(Format::Bc7RgbaUnorm, 4, 4, &pack(&fields)),
+            expected
+        );
+    }
+
+    #[test]
+    fn bc7_mode7() {
+        // Partition 13 of the two subset table: top half and bottom half
+        let subsets = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1];
+        let indices = [0, 1, 2, 3, 3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0];
+        let mut fields = vec![(8, 0b10000000), (6, 13)];
+        for value in [31, 0, 0, 31, 0, 31, 0, 31, 0, 0, 31, 31, 31, 0, 16, 31] {
+            fields.push((5, value));
+        }
+        for p in [1, 0, 0, 1] {
+            fields.push((1, p));
+        }
+        fields.extend(bc_indices(&indices, 2, &[0, 15]));
+
+        let palettes = [
+            [
+                [255, 4, 4, 255],
+                [171, 85, 3, 171],
+                [84, 170, 1, 84],
+                [0, 251, 0, 0],
+            ],
+            [
+                [0, 0, 251, 130],
+                [84, 84, 252, 171],
+                [171, 171, 254, 214],
+                [255, 255, 255, 255],
+            ],
+        ];
+        assert_eq!(
+            decode_texels(Format::Bc7RgbaUnorm, 4, 4, &pack(&fields)),
+            from_palettes(&palettes, subsets, indices)
+        );
+    }
+
+    #[test]
+    fn bc6h_unsigned_untransformed() {
+        // Mode 0b00011: one subset of ten bit endpoints
+        let mut fields = vec![(5, 0b00011)];
+        for value in [0, 495, 462, 495, 1023, 0] {
+            fields.push((10, value));
+        }
+        let indices: Vec<u128> = (0..16).collect();
+        fields.extend(bc_indices(&indices, 4, &[0]));
+
+        let expected = vec![
+            [0, 255, 128, 255],
+            [0, 255, 72, 255],
+            [0, 255, 33, 255],
+            [0, 255, 18, 255],
+            [0, 255, 10, 255],
+            [0, 255, 6, 255],
+            [1, 255, 3, 255],
+            [1, 255, 1, 255],
+            [2, 255, 1, 255],
+            [4, 255, 0, 255],
+            [9, 255, 0, 255],
+            [16, 255, 0, 255],
+            [31, 255, 0, 255],
+            [60, 255, 0, 255],
+            [135, 255, 0, 255],
+            [255, 255, 0, 255],
+        ];
+        assert_eq!(
+            decode_texels(Format::Bc6hRgbUfloat, 4, 4, &pack(&fields)),
+            expected
+        );
+    }
+
+    #[test]
+    fn bc6h_unsigned_reversed_high_bits() {
+        // Mode 0b01111: sixteen bit base endpoint whose top six bits are stored reversed,
+        // and four bit deltas of -8, 7 and 3
+        let base: [u128; 3] = [31711, 15000, 27000];
+        let mut fields = vec![(5, 0b01111)];
+        for value in base {
+            fields.push((10, value & 0x3ff));
+        }
+        for (value, delta) in base.into_iter().zip([0b1000, 7, 3]) {
+            fields.push((4, delta));
+            for bit in (10..16).rev() {
+                fields.push((1, value >> bit & 1));
+            }
+        }
+        let indices: Vec<u128> = (0..16).collect();
+        fields.extend(bc_indices(&indices, 4, &[0]));
+
+        assert_eq!(
+            decode_texels(Format::Bc6hRgbUfloat, 4, 4, &pack(&fields)),
+            vec![[255, 1, 56, 255]; 16]
+        );
+    }
+
+    #[test]
+    fn bc6h_signed_two_subsets() {
+        // Mode 0b00: ten bit base endpoint w, five bit deltas x, y and z, partition 0
+        let (w, x, y, z): ([u128; 3], [u128; 3], [u128; 3], [u128; 3]) = (
+            [240, 230, 0x3fb],     // 240, 230, -5
+            [7, 0b10000, 15],      // 7, -16, 15
+            [0b11000, 3, 0b11111], // -8, 3, -1
+            [7, 15, 0b10000],      // 7, 15, -16
+        );
+        let bit = |value: u128, bit: u32| (1, value >> bit & 1);
+        let low = |value: u128, count: u32| (count, value & ((1 << count) - 1));
+        let mut fields = vec![
+            (2, 0b00),
+            bit(y[1], 4),
+            bit(y[2], 4),
+            bit(z[2], 4),
+            (10, w[0]),
+            (10, w[1]),
+            (10, w[2]),
+            (5, x[0]),
+            bit(z[1], 4),
+            low(y[1], 4),
+            (5, x[1]),
+            bit(z[2], 0),
+            low(z[1], 4),
+            (5, x[2]),
+            bit(z[2], 1),
+            low(y[2], 4),
+            (5, y[0]),
+            bit(z[2], 2),
+            (5, z[0]),
+            bit(z[2], 3),
+            (5, 0),
+        ];
+        let subsets = [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1];
+        let indices = [0, 1, 2, 3, 4, 5, 6, 7, 7, 6, 5, 4, 3, 2, 1, 0];
+        fields.extend(bc_indices(&indices, 3, &[0, 15]));
+
+        // Blue is negative or too small to reach one step of eight bits
+        let palettes = [
+            [
+                [199, 125, 0, 255],
+                [207, 116, 0, 255],
+                [214, 107, 0, 255],
+                [222, 99, 0, 255],
+                [230, 89, 0, 255],
+                [238, 80, 0, 255],
+                [245, 72, 0, 255],
+                [253, 63, 0, 255],
+            ],
+            [
+                [137, 145, 0, 255],
+                [154, 158, 0, 255],
+                [170, 171, 0, 255],
+                [186, 184, 0, 255],
+                [204, 199, 0, 255],
+                [221, 212, 0, 255],
+                [237, 225, 0, 255],
+                [253, 238, 0, 255],
+            ],
+        ];
+        assert_eq!(
+            decode_texels(Format::Bc6hRgbFloat, 4, 4, &pack(&fields)),
+            from_palettes(&palettes, subsets, indices)
+        );
+    }
+
+    /// Pixel index planes of an ETC block, most significant bits first
+    fn etc_indices(index: impl Fn(usize, usize) -> u64) -> [(u32, u64); 2] {
+        let (mut msb, mut lsb) = (0, 0);
+        for x in 0..4 {
+            for y in 0..4 {
+                let p = x * 4 + y;
+                msb |= (index(x, y) >> 1) << p;
+                lsb |= (index(x, y) & 1) << p;
+            }
+        }
+        [(16, msb), (16, lsb)]
+    }
+
+    fn etc_expected(color: impl Fn(usize, usize) -> [u8; 4]) -> Vec<[u8; 4]> {
+        (0..16).map(|i| color(i % 4, i / 4)).collect()
+    }
+
+    #[test]
+    fn etc2_t_mode() {
+        // Red 2 plus a delta of -3 overflows: base colors (9, 4, 12) and (8, 2, 15), distance 3
+        let mut fields = vec![
+            (3, 0),
+            (2, 0b10),
+            (1, 1),
+            (2, 0b01),
+            (4, 4),
+            (4, 12),
+            (4, 8),
+            (4, 2),
+            (4, 15),
+            (2, 0b01),
+            (1, 1),
+            (1, 1),
+        ];
+        fields.extend(etc_indices(|x, _| x as u64));
+
+        let paint = [
+            [153, 68, 204, 255],
+            [152, 50, 255, 255],
+            [136, 34, 255, 255],
+            [120, 18, 239, 255],
+        ];
+        assert_eq!(
+            decode_texels(Format::Etc2Rgb8Unorm, 4, 4, &pack_etc(&fields)),
+            etc_expected(|x, _| paint[x])
+        );
+    }
+
+    #[test]
+    fn etc2_h_mode() {
+        // Green 30 plus a delta of 2 overflows: base colors (10, 7, 5) and (3, 12, 9); the
+        // first is larger, so the distance index is 0b101
+        let mut fields = vec![
+            (1, 0),
+            (4, 10),
+            (3, 0b011),
+            (3, 0b111),
+            (1, 1),
+            (1, 0),
+            (1, 0),
+            (3, 0b101),
+            (4, 3),
+            (4, 12),
+            (4, 9),
+            (1, 1),
+            (1, 1),
+            (1, 0),
+        ];
+        fields.extend(etc_indices(|_, y| y as u64));
+
+        let paint = [
+            [202, 151, 117, 255],
+            [138, 87, 53, 255],
+            [83, 236, 185, 255],
+            [19, 172, 121, 255],
+        ];
+        assert_eq!(
+            decode_texels(Format::Etc2Rgb8Unorm, 4, 4, &pack_etc(&fields)),
+            etc_expected(|_, y| paint[y])
+        );
+    }
+
+    #[test]
+    fn etc2_planar_mode() {
+        // Blue 31 plus a delta of 3 overflows. Origin (32, 16, 30), horizontal (63, 0, 63),
+        // vertical (0, 127, 21)
+        let fields = [
+            (1, 0),
+            (6, 32),
+            (1, 0),
+            (1, 0),
+            (6, 16),
+            (1, 0),
+            (3, 0b111),
+            (2, 0b11),
+            (1, 0),
+            (3, 0b110),
+            (5, 0b11111),
+            (1, 1),
+            (1, 1),
+            (7, 0),
+            (6, 63),
+            (6, 0),
+            (7, 127),
+            (6, 21),
+        ];
+
+        let rows = [
+            [
+                [130, 32, 121],
+                [161, 24, 155],
+                [193, 16, 188],
+                [224, 8, 222],
+            ],
+            [
+                [98, 88, 112],
+                [129, 80, 146],
+                [160, 72, 179],
+                [191, 64, 213],
+            ],
+            [
+                [65, 144, 103],
+                [96, 136, 137],
+                [128, 128, 170],
+                [159, 120, 204],
+            ],
+            [
+                [33, 199, 94],
+                [64, 191, 128],
+                [95, 183, 161],
+                [126, 175, 195],
+            ],
+        ];
+        assert_eq!(
+            decode_texels(Format::Etc2Rgb8Unorm, 4, 4, &pack_etc(&fields)),
+            etc_expected(|x, y| {
+                let [r, g, b] = rows[y][x];
+                [r, g, b, 255]
+            })
+        );
+    }
+
+    #[test]
+    fn etc2_punchthrough() {
+        // Differential mode with the opaque bit clear: index 0 is the base color and index 2
+        // transparent. Left half (16, 8, 24) with table 0, right half (16, 10, 25) with table 7
+        let mut fields = vec![
+            (5, 16),
+            (3, 0),
+            (5, 8),
+            (3, 2),
+            (5, 24),
+            (3, 1),
+            (3, 0),
+            (3, 7),
+            (1, 0),
+            (1, 0),
+        ];
+        fields.extend(etc_indices(|_, y| y as u64));
+
+        let left = [
+            [132, 66, 198, 255],
+            [140, 74, 206, 255],
+            [0; 4],
+            [124, 58, 190, 255],
+        ];
+        let right = [
+            [132, 82, 206, 255],
+            [255, 255, 255, 255],
+            [0; 4],
+            [0, 0, 23, 255],
+        ];
+        assert_eq!(
+            decode_texels(Format::Etc2Rgb8A1Unorm, 4, 4, &pack_etc(&fields)),
+            etc_expected(|x, y| if x < 2 { left[y] } else { right[y] })
+        );
+    }
+
+    /// An ASTC block from its low fields and its weight stream, which is stored from the
+    /// top bit down
+    fn astc(low: &[(u32, u128)], weights: &[(u32, u128)]) -> [u8; 16] {
+        let low = u128::from_le_bytes(pack(low));
+        let weights = u128::from_le_bytes(pack(weights)).reverse_bits();
+        assert_eq!(low & weights, 0, "fields overlap the weights");
+        (low | weights).to_le_bytes()
+    }
+
+    fn astc_format(block: wgpu::AstcBlock) -> Format {
+        Format::Astc {
+            block,
+            channel: wgpu::AstcChannel::Unorm,
+        }
+    }
+
+    /// Block mode of a 4x4 grid of two bit weights: B = 0, A = 2, range 0b100
+    const ASTC_GRID_4X4_RANGE_3: u128 = 0b000_0100_0010;
+
+    #[test]
+    fn astc_4x4_one_partition() {
+        // Direct RGBA endpoints (mode 12), weight by column
+        let mut low = vec![(11, ASTC_GRID_4X4_RANGE_3), (2, 0), (4, 12)];
+        for value in [0x00, 0xff, 0x80, 0x40, 0x20, 0xe0, 0xff, 0x10] {
+            low.push((8, value));
+        }
+        let weights: Vec<_> = (0..16).map(|i| (2, i % 4)).collect();
+
+        let columns = [
+            [0, 128, 32, 255],
+            [84, 107, 95, 177],
+            [171, 85, 161, 94],
+            [255, 64, 224, 16],
+        ];
+        assert_eq!(
+            decode_texels(
+                astc_format(wgpu::AstcBlock::B4x4),
+                4,
+                4,
+                &astc(&low, &weights)
+            ),
+            (0..16).map(|i| columns[i % 4]).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn astc_4x4_trit_weights_blue_contract() {
+        // Trit weights (range 0b011) on a 4x4 grid, direct RGB endpoints (mode 8) whose second
+        // endpoint sums lower than the first, which swaps and blue contracts them
+        let low = [
+            (11, 0b000_0101_0001),
+            (2, 0),
+            (4, 8),
+            (8, 200),
+            (8, 10),
+            (8, 100),
+            (8, 50),
+            (8, 150),
+            (8, 30),
+        ];
+        // Trit blocks decoding to [1, 2, 1, 2, 1], [0, 0, 0, 2, 2], [2, 2, 2, 0, 2] and [2]
+        let weights = [(8, 0xd9), (8, 0x1c), (8, 0x6e), (2, 0b10)];
+        let trits = [1, 2, 1, 2, 1, 0, 0, 0, 2, 2, 2, 2, 2, 0, 2, 2];
+
+        let colors = [[20, 40, 30, 255], [97, 82, 90, 255], [175, 125, 150, 255]];
+        assert_eq!(
+            decode_texels(
+                astc_format(wgpu::AstcBlock::B4x4),
+                4,
+                4,
+                &astc(&low, &weights)
+            ),
+            trits.map(|trit| colors[trit]).to_vec()
+        );
+    }
+
+    #[test]
+    fn astc_8x8_one_partition_dual_plane() {
+        // A 4x4 grid of two planes, infilled across 8x8 texels. Luminance and alpha endpoints
+        // (mode 4); alpha is the second plane, weighted by row while the first is by column
+        let low = [
+            (11, 0b100_0100_0010),
+            (2, 0),
+            (4, 4),
+            (8, 0),
+            (8, 255),
+            (8, 255),
+            (8, 0),
+            (13, 0),
+            (2, 3),
+        ];
+        let weights: Vec<_> = (0..16).flat_map(|i| [(2, i % 4), (2, i / 4)]).collect();
+
+        let luminance = [0, 36, 72, 112, 143, 183, 219, 255];
+        let alpha = [255, 219, 183, 143, 112, 72, 36, 0];
+        let expected: Vec<_> = (0..64)
+            .map(|i| {
+                let l = luminance[i % 8];
+                [l, l, l, alpha[i / 8]]
+            })
+            .collect();
+        assert_eq!(
+            decode_texels(
+                astc_format(wgpu::AstcBlock::B8x8),
+                8,
+                8,
+                &astc(&low, &weights)
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn astc_4x4_two_partitions() {
+        // Partition seed 0x155, zero weights. The first partition takes luminance endpoints
+        // (mode 0) and the second luminance and alpha (mode 4), from class 0 with the second
+        // partition's high bit set
+        let low = [
+            (11, ASTC_GRID_4X4_RANGE_3),
+            (2, 1),
+            (10, 0x155),
+            (6, 0b00_1001),
+            (8, 0x30),
+            (8, 0),
+            (8, 0xff),
+            (8, 0),
+            (8, 0x80),
+            (8, 0),
+        ];
+        let weights = [(32, 0)];
+
+        let partitions = [[1, 0, 0, 0], [1, 0, 0, 0], [0, 0, 0, 1], [0, 0, 0, 1]];
+        let colors = [[0x30, 0x30, 0x30, 255], [255, 255, 255, 0x80]];
+        assert_eq!(
+            decode_texels(
+                astc_format(wgpu::AstcBlock::B4x4),
+                4,
+                4,
+                &astc(&low, &weights)
+            ),
+            (0..16)
+                .map(|i| colors[partitions[i / 4][i % 4]])
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn astc_8x8_three_partitions() {
+        // Partition seed 0x1f, luminance endpoints (mode 0) for all three, zero weights
+        let low = [
+            (11, ASTC_GRID_4X4_RANGE_3),
+            (2, 2),
+            (10, 0x1f),
+            (6, 0),
+            (8, 0x00),
+            (8, 0),
+            (8, 0x80),
+            (8, 0),
+            (8, 0xff),
+            (8, 0),
+        ];
+        let weights = [(32, 0)];
+
+        let partitions = [
+            [0, 0, 1, 1, 1, 0, 2, 2],
+            [1, 0, 1, 1, 1, 0, 2, 2],
+            [1, 0, 1, 1, 0, 0, 2, 2],
+            [1, 0, 1, 2, 0, 0, 2, 2],
+            [1, 0, 2, 1, 0, 0, 2, 2],
+            [1, 0, 2, 1, 0, 0, 2, 2],
+            [1, 0, 1, 0, 0, 0, 2, 2],
+            [2, 2, 1, 0, 0, 0, 2, 2],
+        ];
+        let luminance = [0x00, 0x80, 0xff];
+        assert_eq!(
+            decode_texels(
+                astc_format(wgpu::AstcBlock::B8x8),
+                8,
+                8,
+                &astc(&low, &weights)
+            ),
+            (0..64)
+                .map(|i| {
+                    let l = luminance[partitions[i / 8][i % 8]];
+                    [l, l, l, 255]
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use crate::block_decode;
+use anyhow::{Context, anyhow, bail};
+use std::io::Read;
+
+const KTX2_MAGIC: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+/// ASTC block sizes in the order Vulkan (and so KTX2) numbers them
+const ASTC_BLOCKS: [wgpu::AstcBlock; 14] = [
+    wgpu::AstcBlock::B4x4,
+    wgpu::AstcBlock::B5x4,
+    wgpu::AstcBlock::B5x5,
+    wgpu::AstcBlock::B6x5,
+    wgpu::AstcBlock::B6x6,
+    wgpu::AstcBlock::B8x5,
+    wgpu::AstcBlock::B8x6,
+    wgpu::AstcBlock::B8x8,
+    wgpu::AstcBlock::B10x5,
+    wgpu::AstcBlock::B10x6,
+    wgpu::AstcBlock::B10x8,
+    wgpu::AstcBlock::B10x10,
+    wgpu::AstcBlock::B12x10,
+    wgpu::AstcBlock::B12x12,
+];
+
+/// A 2D texture read from a KTX2 or DDS container, with the mip chain it was authored with.
+/// `format` is never an sRGB variant: whether texels are gamma-decoded is up to the
+/// material slot the texture is bound to, as for any other texture.
+pub struct CompressedImage {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Level 0 first, each padded to whole blocks
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    pub fn is_container(bytes: &[u8]) -> bool {
+        bytes.starts_with(&KTX2_MAGIC) || bytes.starts_with(DDS_MAGIC)
+    }
+
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        let image = if bytes.starts_with(&KTX2_MAGIC) {
+            Self::parse_ktx2(bytes)?
+        } else {
+            Self::parse_dds(bytes)?
+        };
+        for (level, data) in image.levels.iter().enumerate() {
+            let expected = image.level_size(level as u32);
+            if data.len() < expected {
+                bail!(
+                    "Mip level {level} has {} bytes, expected {expected}",
+                    data.len()
+                );
+            }
+        }
+        Ok(image)
+    }
+
+    /// Bytes in one mip level, rounded up to whole blocks
+    fn level_size(&self, level: u32) -> usize {
+        let (block_width, block_height) = self.format.block_dimensions();
+        let block_size = self.format.block_copy_size(None).unwrap_or_default();
+        let columns = (self.width >> level).max(1).div_ceil(block_width);
+        let rows = (self.height >> level).max(1).div_ceil(block_height);
+        (columns * rows * block_size) as usize
+    }
+
+    fn parse_ktx2(bytes: &[u8]) -> anyhow::Result<Self> {
+        let reader = ktx2::Reader::new(bytes).context("Invalid KTX2 file")?;
+        let header = reader.header();
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            bail!("Only 2D KTX2 textures are supported");
+        }
+        let format = header
+            .format
+            .ok_or_else(|| {
+                anyhow!("KTX2 files without a format (Basis Universal) are not supported")
+            })
+            .and_then(ktx2_format)?;
+
+        let levels = reader
+            .levels()
+            .map(|level| match header.supercompression_scheme {
+                None => Ok(level.data.to_vec()),
+                Some(ktx2::SupercompressionScheme::Zstandard) => {
+                    let mut data = Vec::with_capacity(level.uncompressed_byte_length as usize);
+                    ruzstd::decoding::StreamingDecoder::new(level.data)
+                        .map_err(|e| anyhow!("Invalid zstd level: {e}"))?
+                        .read_to_end(&mut data)?;
+                    Ok(data)
+                }
+                Some(scheme) => bail!("KTX2 supercompression {scheme:?} is not supported"),
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            format,
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            levels,
+        })
+    }
+
+    fn parse_dds(bytes: &[u8]) -> anyhow::Result<Self> {
+        let dds = ddsfile::Dds::read(bytes).context("Invalid DDS file")?;
+        if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+            bail!("Only 2D DDS textures are supported");
+        }
+        let format = match (dds.get_dxgi_format(), dds.get_d3d_format()) {
+            (Some(format), _) => dxgi_format(format)?,
+            (None, Some(format)) => d3d_format(format)?,
+            (None, None) => bail!("DDS file has an unknown pixel format"),
+        };
+
+        // Layer 0 holds every mip level back to back
+        let mut image = Self {
+            format,
+            width: dds.get_width(),
+            height: dds.get_height(),
+            levels: Vec::new(),
+        };
+        let mut data = dds.get_data(0).context("DDS file is truncated")?;
+        for level in 0..dds.get_num_mipmap_levels().max(1) {
+            let size = image.level_size(level).min(data.len());
+            let (level_data, rest) = data.split_at(size);
+            image.levels.push(level_data.to_vec());
+            data = rest;
+        }
+        Ok(image)
+    }
+
+    /// Decode every level to RGBA8, for devices without the format
+    pub fn decode_rgba8(&self) -> anyhow::Result<Vec<image::RgbaImage>> {
+        self.levels
+            .iter()
+            .enumerate()
+            .map(|(level, data)| {
+                let width = (self.width >> level).max(1);
+                let height = (self.height >> level).max(1);
+                let pixels = block_decode::decode(self.format, width, height, data)?;
+                image::RgbaImage::from_raw(width, height, pixels)
+                    .ok_or_else(|| anyhow!("Decoded level {level} has the wrong size"))
+            })
+            .collect()
+    }
+}
+
+fn ktx2_format(format: ktx2::Format) -> anyhow::Result<wgpu::TextureFormat> {
+    use ktx2::Format as Vk;
+    use wgpu::TextureFormat as Wgpu;
+
+    Ok(match format {
+        Vk::R8G8B8A8_UNORM | Vk::R8G8B8A8_SRGB => Wgpu::Rgba8Unorm,
+        Vk::B8G8R8A8_UNORM | Vk::B8G8R8A8_SRGB => Wgpu::Bgra8Unorm,
+        Vk::BC1_RGB_UNORM_BLOCK
+        | Vk::BC1_RGB_SRGB_BLOCK
+        | Vk::BC1_RGBA_UNORM_BLOCK
+        | Vk::BC1_RGBA_SRGB_BLOCK => Wgpu::Bc1RgbaUnorm,
+        Vk::BC2_UNORM_BLOCK | Vk::BC2_SRGB_BLOCK => Wgpu::Bc2RgbaUnorm,
+        Vk::BC3_UNORM_BLOCK | Vk::BC3_SRGB_BLOCK => Wgpu::Bc3RgbaUnorm,
+        Vk::BC4_UNORM_BLOCK => Wgpu::Bc4RUnorm,
+        Vk::BC4_SNORM_BLOCK => Wgpu::Bc4RSnorm,
+        Vk::BC5_UNORM_BLOCK => Wgpu::Bc5RgUnorm,
+        Vk::BC5_SNORM_BLOCK => Wgpu::Bc5RgSnorm,
+        Vk::BC6H_UFLOAT_BLOCK => Wgpu::Bc6hRgbUfloat,
+        Vk::BC6H_SFLOAT_BLOCK => Wgpu::Bc6hRgbFloat,
+        Vk::BC7_UNORM_BLOCK | Vk::BC7_SRGB_BLOCK => Wgpu::Bc7RgbaUnorm,
+        Vk::ETC2_R8G8B8_UNORM_BLOCK | Vk::ETC2_R8G8B8_SRGB_BLOCK => Wgpu::Etc2Rgb8Unorm,
+        Vk::ETC2_R8G8B8A1_UNORM_BLOCK | Vk::ETC2_R8G8B8A1_SRGB_BLOCK => Wgpu::Etc2Rgb8A1Unorm,
+        Vk::ETC2_R8G8B8A8_UNORM_BLOCK | Vk::ETC2_R8G8B8A8_SRGB_BLOCK => Wgpu::Etc2Rgba8Unorm,
+        Vk::EAC_R11_UNORM_BLOCK => Wgpu::EacR11Unorm,
+        Vk::EAC_R11_SNORM_BLOCK => Wgpu::EacR11Snorm,
+        Vk::EAC_R11G11_UNORM_BLOCK => Wgpu::EacRg11Unorm,
+        Vk::EAC_R11G11_SNORM_BLOCK => Wgpu::EacRg11Snorm,
+        // UNORM and SRGB alternate through every block size
+        format if (157..=184).contains(&format.value()) => Wgpu::Astc {
+            block: ASTC_BLOCKS[(format.value() - 157) as usize / 2],
+            channel: wgpu::AstcChannel::Unorm,
+        },
+        format => bail!("KTX2 format {format:?} is not supported"),
+    })
+}
+
+fn dxgi_format(format: ddsfile::DxgiFormat) -> anyhow::Result<wgpu::TextureFormat> {
+    use ddsfile::DxgiFormat as Dxgi;
+    use wgpu::TextureFormat as Wgpu;
+
+    Ok(match format {
+        Dxgi::R8G8B8A8_UNorm | Dxgi::R8G8B8A8_UNorm_sRGB => Wgpu::Rgba8Unorm,
+        Dxgi::B8G8R8A8_UNorm | Dxgi::B8G8R8A8_UNorm_sRGB => Wgpu::Bgra8Unorm,
+        Dxgi::BC1_UNorm | Dxgi::BC1_UNorm_sRGB => Wgpu::Bc1RgbaUnorm,
+        Dxgi::BC2_UNorm | Dxgi::BC2_UNorm_sRGB => Wgpu::Bc2RgbaUnorm,
+        Dxgi::BC3_UNorm | Dxgi::BC3_UNorm_sRGB => Wgpu::Bc3RgbaUnorm,
+        Dxgi::BC4_UNorm => Wgpu::Bc4RUnorm,
+        Dxgi::BC4_SNorm => Wgpu::Bc4RSnorm,
+        Dxgi::BC5_UNorm => Wgpu::Bc5RgUnorm,
+        Dxgi::BC5_SNorm => Wgpu::Bc5RgSnorm,
+        Dxgi::BC6H_UF16 => Wgpu::Bc6hRgbUfloat,
+        Dxgi::BC6H_SF16 => Wgpu::Bc6hRgbFloat,
+        Dxgi::BC7_UNorm | Dxgi::BC7_UNorm_sRGB => Wgpu::Bc7RgbaUnorm,
+        format => bail!("DDS format {format:?} is not supported"),
+    })
+}
+
+fn d3d_format(format: ddsfile::D3DFormat) -> anyhow::Result<wgpu::TextureFormat> {
+    use ddsfile::D3DFormat as D3d;
+    use wgpu::TextureFormat as Wgpu;
+
+    Ok(match format {
+        D3d::A8B8G8R8 => Wgpu::Rgba8Unorm,
+        D3d::A8R8G8B8 => Wgpu::Bgra8Unorm,
+        D3d::DXT1 => Wgpu::Bc1RgbaUnorm,
+        D3d::DXT3 => Wgpu::Bc2RgbaUnorm,
+        D3d::DXT5 => Wgpu::Bc3RgbaUnorm,
+        format => bail!("DDS format {format:?} is not supported"),
+    })
+}
@@ -0,0 +1,108 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3, Vector4};
+
+/// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// Smallest box around `points`, or an empty box at the origin if there are none
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Self {
+                min: Point3::origin(),
+                max: Point3::origin(),
+            };
+        };
+        points.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |bounds, point| Self {
+                min: Point3::new(
+                    bounds.min.x.min(point.x),
+                    bounds.min.y.min(point.y),
+                    bounds.min.z.min(point.z),
+                ),
+                max: Point3::new(
+                    bounds.max.x.max(point.x),
+                    bounds.max.y.max(point.y),
+                    bounds.max.z.max(point.z),
+                ),
+            },
+        )
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    /// Smallest box around both
+    pub fn union(self, other: Self) -> Self {
+        Self::from_points([self.min, self.max, other.min, other.max])
+    }
+
+    /// Box around this one once `transform`ed, grown to stay axis-aligned
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        // The center moves as a point; the half extents project onto each world axis
+        let center = transform.transform_point(self.center());
+        let half = (self.max - self.min) * 0.5;
+        let extent = Vector3::new(
+            transform.x.x.abs() * half.x
+                + transform.y.x.abs() * half.y
+                + transform.z.x.abs() * half.z,
+            transform.x.y.abs() * half.x
+                + transform.y.y.abs() * half.y
+                + transform.z.y.abs() * half.z,
+            transform.x.z.abs() * half.x
+                + transform.y.z.abs() * half.y
+                + transform.z.z.abs() * half.z,
+        );
+        Self {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+}
+
+/// The six planes bounding a camera's view volume, with normals facing inwards
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Planes of a view-projection matrix with wgpu's 0 to 1 depth range
+    pub fn from_view_proj(view_proj: &Matrix4<f32>) -> Self {
+        let row = |i: usize| {
+            Vector4::new(
+                view_proj.x[i],
+                view_proj.y[i],
+                view_proj.z[i],
+                view_proj.w[i],
+            )
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z],
+        }
+    }
+
+    /// Whether any part of `bounds` may be visible. Conservative: boxes near a frustum
+    /// corner can pass without being on screen.
+    pub fn intersects(&self, bounds: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner farthest along the plane's normal
+            let farthest = |normal: f32, min: f32, max: f32| if normal >= 0.0 { max } else { min };
+            let corner = Vector3::new(
+                farthest(plane.x, bounds.min.x, bounds.max.x),
+                farthest(plane.y, bounds.min.y, bounds.max.y),
+                farthest(plane.z, bounds.min.z, bounds.max.z),
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
@@ -18,3 +18,21 @@ pub const PARTICLE_SYSTEM_MODEL_PATH: &str = "cube.obj";
 
 /// Default material key for particle systems
 pub const PARTICLE_SYSTEM_MATERIAL_KEY: &str = "default";
+
+/// Grayscale image new heightmap generators start with
+pub const HEIGHTMAP_PATH: &str = "heightmap.png";
+
+/// Model new mesh surface scatter generators cover
+pub const SURFACE_SCATTER_MODEL_PATH: &str = "teapot.obj";
+
+/// Default shader for materials that don't name one
+pub const DEFAULT_SHADER_PATH: &str = "shader.wgsl";
+
+/// Shaders compiled at startup so materials can switch between them immediately
+pub const BUILTIN_SHADERS: &[&str] = &["shader.wgsl", "unlit.wgsl"];
+
+/// Texture bound to material slots that have nothing assigned
+pub const DEFAULT_TEXTURE_PATH: &str = "white.png";
+
+/// Registry key of the generated flat normal map bound to unset normal slots
+pub const FLAT_NORMAL_TEXTURE_PATH: &str = "flat-normal";
@@ -0,0 +1,436 @@
+use crate::culling::Aabb;
+use crate::model::{MeshDeformation, ModelVertex, SkinVertex};
+use crate::particle_simulation::SimulationBackend;
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3, Zero,
+};
+use wgpu::util::DeviceExt;
+
+const DEFORM_SHADER: &str = include_str!("shaders/deform.wgsl");
+
+/// Matches `@workgroup_size` in deform.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Matches `DeformParams` in deform.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DeformUniform {
+    vertex_count: u32,
+    joint_count: u32,
+    offset_count: u32,
+    target_count: u32,
+}
+
+/// An undeformed vertex followed by its joints and weights, as deform.wgsl reads them
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SourceVertex {
+    vertex: ModelVertex,
+    joints: [u32; 4],
+    weights: [f32; 4],
+}
+
+/// The deformation compute pipeline shared by every deformed mesh, when the backend
+/// has one
+pub struct Deformer {
+    compute: Option<(wgpu::BindGroupLayout, wgpu::ComputePipeline)>,
+}
+
+impl Deformer {
+    pub fn new(device: &wgpu::Device, backend: SimulationBackend) -> Self {
+        if backend == SimulationBackend::Cpu {
+            return Self { compute: None };
+        }
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Deformation Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Deformation Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Deformation Shader"),
+            source: wgpu::ShaderSource::Wgsl(DEFORM_SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Deformation Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Self {
+            compute: Some((layout, pipeline)),
+        }
+    }
+}
+
+/// Buffers the deformation shader poses a mesh with
+struct DeformBuffers {
+    uniform_buffer: wgpu::Buffer,
+    /// Every time offset's joint matrices, then every time offset's target weights
+    pose_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// A skinned or morphed mesh posed once per animation time offset, drawn in place of
+/// its undeformed vertices. Posed by the compute shader when there is one, otherwise on
+/// the CPU.
+pub struct DeformedMesh {
+    vertex_count: usize,
+    /// One for meshes without a skin, which are posed by the identity
+    joint_count: usize,
+    target_count: usize,
+    offset_count: usize,
+    /// `offset_count` posed copies of the mesh's vertices, one after another
+    vertex_buffer: wgpu::Buffer,
+    compute: Option<DeformBuffers>,
+    /// Model-space bounds of every posed copy
+    bounds: Aabb,
+}
+
+impl DeformedMesh {
+    pub fn new(
+        device: &wgpu::Device,
+        deformer: &Deformer,
+        name: &str,
+        deformation: &MeshDeformation,
+        joint_count: usize,
+        offset_count: usize,
+    ) -> Self {
+        let vertex_count = deformation.bind_vertices.len();
+        let joint_count = joint_count.max(1);
+        let target_count = deformation
+            .morph
+            .as_ref()
+            .map_or(0, |morph| morph.targets.len());
+        let offset_count = offset_count.max(1);
+
+        let mut usage = wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST;
+        if deformer.compute.is_some() {
+            usage |= wgpu::BufferUsages::STORAGE;
+        }
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{:?} Deformed Vertex Buffer", name)),
+            size: (offset_count * vertex_count.max(1) * std::mem::size_of::<ModelVertex>())
+                as wgpu::BufferAddress,
+            usage,
+            mapped_at_creation: false,
+        });
+
+        let compute = deformer.compute.as_ref().map(|(layout, _)| {
+            // Without a skin every vertex follows the single identity joint
+            let rigid = SkinVertex {
+                joints: [0; 4],
+                weights: [1.0, 0.0, 0.0, 0.0],
+            };
+            let source: Vec<SourceVertex> = deformation
+                .bind_vertices
+                .iter()
+                .enumerate()
+                .map(|(i, vertex)| {
+                    let influence = deformation
+                        .skin
+                        .as_ref()
+                        .and_then(|skin| skin.influences.get(i))
+                        .unwrap_or(&rigid);
+                    SourceVertex {
+                        vertex: *vertex,
+                        joints: influence.joints,
+                        weights: influence.weights,
+                    }
+                })
+                .collect();
+            // The vertices are followed by the position and normal offsets of every
+            // vertex, target by target
+            let mut contents: Vec<f32> = bytemuck::cast_slice(&source).to_vec();
+            for target in deformation.morph.iter().flat_map(|morph| &morph.targets) {
+                contents.extend((0..vertex_count).flat_map(|i| {
+                    let [px, py, pz] = target.positions.get(i).copied().unwrap_or_default();
+                    let [nx, ny, nz] = target.normals.get(i).copied().unwrap_or_default();
+                    [px, py, pz, nx, ny, nz]
+                }));
+            }
+            let source_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{:?} Deformation Source", name)),
+                contents: bytemuck::cast_slice(&contents),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+            let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("{:?} Deformation Params", name)),
+                size: std::mem::size_of::<DeformUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let pose_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("{:?} Deformation Pose", name)),
+                size: (offset_count
+                    * (joint_count * 16 + target_count)
+                    * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("{:?} Deformation Bind Group", name)),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: source_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: pose_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: vertex_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+            DeformBuffers {
+                uniform_buffer,
+                pose_buffer,
+                bind_group,
+            }
+        });
+
+        Self {
+            vertex_count,
+            joint_count,
+            target_count,
+            offset_count,
+            vertex_buffer,
+            compute,
+            bounds: deformation.bind_bounds,
+        }
+    }
+
+    pub fn matches(&self, joint_count: usize, offset_count: usize) -> bool {
+        self.joint_count == joint_count.max(1) && self.offset_count == offset_count.max(1)
+    }
+
+    /// Pose each copy with its time offset's joint matrices and morph target weights,
+    /// recording any compute work into `encoder`
+    pub fn pose(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        deformer: &Deformer,
+        deformation: &MeshDeformation,
+        joint_matrices: &[Vec<Matrix4<f32>>],
+        weights: &[Vec<f32>],
+    ) {
+        let for_offset = |offset: usize| {
+            (
+                joint_matrices.get(offset).map_or(&[][..], Vec::as_slice),
+                weights.get(offset).map_or(&[][..], Vec::as_slice),
+            )
+        };
+        self.bounds = (0..self.offset_count)
+            .filter_map(|offset| {
+                let (matrices, weights) = for_offset(offset);
+                posed_bounds(deformation, matrices, weights)
+            })
+            .reduce(Aabb::union)
+            .unwrap_or(self.bounds);
+
+        match (&self.compute, &deformer.compute) {
+            (Some(buffers), Some((_, pipeline))) => {
+                let mut joints: Vec<[[f32; 4]; 4]> =
+                    Vec::with_capacity(self.offset_count * self.joint_count);
+                let mut target_weights: Vec<f32> =
+                    Vec::with_capacity(self.offset_count * self.target_count);
+                for offset in 0..self.offset_count {
+                    let (matrices, weights) = for_offset(offset);
+                    joints.extend((0..self.joint_count).map(|joint| -> [[f32; 4]; 4] {
+                        matrices
+                            .get(joint)
+                            .copied()
+                            .unwrap_or(Matrix4::identity())
+                            .into()
+                    }));
+                    target_weights.extend(
+                        (0..self.target_count)
+                            .map(|target| weights.get(target).copied().unwrap_or_default()),
+                    );
+                }
+                let mut pose: Vec<f32> = bytemuck::cast_slice(&joints).to_vec();
+                pose.extend(target_weights);
+                queue.write_buffer(&buffers.pose_buffer, 0, bytemuck::cast_slice(&pose));
+                let uniform = DeformUniform {
+                    vertex_count: self.vertex_count as u32,
+                    joint_count: self.joint_count as u32,
+                    offset_count: self.offset_count as u32,
+                    target_count: self.target_count as u32,
+                };
+                queue.write_buffer(&buffers.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Deformation Pass"),
+                    timestamp_writes: None,
+                });
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &buffers.bind_group, &[]);
+                pass.dispatch_workgroups(
+                    (self.vertex_count as u32).div_ceil(WORKGROUP_SIZE),
+                    self.offset_count as u32,
+                    1,
+                );
+            }
+            _ => {
+                let vertices: Vec<ModelVertex> = (0..self.offset_count)
+                    .flat_map(|offset| {
+                        let (matrices, weights) = for_offset(offset);
+                        deform_vertices(deformation, matrices, weights)
+                    })
+                    .collect();
+                queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+            }
+        }
+    }
+
+    /// The copy posed for time offset `offset`, for binding as the vertex buffer
+    pub fn vertices(&self, offset: usize) -> wgpu::BufferSlice<'_> {
+        let size = (self.vertex_count * std::mem::size_of::<ModelVertex>()) as wgpu::BufferAddress;
+        let start = offset.min(self.offset_count - 1) as wgpu::BufferAddress * size;
+        self.vertex_buffer.slice(start..start + size.max(1))
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+/// The undeformed vertices of `deformation` with its morph targets applied at
+/// `weights`, then moved by their joints' `joint_matrices`. Without a skin or joint
+/// matrices they stay where the targets put them.
+pub fn deform_vertices(
+    deformation: &MeshDeformation,
+    joint_matrices: &[Matrix4<f32>],
+    weights: &[f32],
+) -> Vec<ModelVertex> {
+    let direction = |linear: &Matrix3<f32>, direction: Vector3<f32>| -> [f32; 3] {
+        let moved = linear * direction;
+        if moved.magnitude2() == 0.0 {
+            moved.into()
+        } else {
+            moved.normalize().into()
+        }
+    };
+    let targets = deformation
+        .morph
+        .iter()
+        .flat_map(|morph| morph.targets.iter().zip(weights))
+        .filter(|(_, weight)| **weight != 0.0);
+    let skin = deformation
+        .skin
+        .as_ref()
+        .zip(joint_matrices.len().checked_sub(1));
+
+    deformation
+        .bind_vertices
+        .iter()
+        .enumerate()
+        .map(|(i, vertex)| {
+            let mut position = Vector3::from(vertex.position);
+            let mut normal = Vector3::from(vertex.normal);
+            for (target, &weight) in targets.clone() {
+                if let Some(offset) = target.positions.get(i) {
+                    position += Vector3::from(*offset) * weight;
+                }
+                if let Some(offset) = target.normals.get(i) {
+                    normal += Vector3::from(*offset) * weight;
+                }
+            }
+
+            let matrix = match skin {
+                Some((skin, last_joint)) => {
+                    let mut matrix = Matrix4::zero();
+                    if let Some(influence) = skin.influences.get(i) {
+                        for (&joint, &weight) in influence.joints.iter().zip(&influence.weights) {
+                            matrix += joint_matrices[(joint as usize).min(last_joint)] * weight;
+                        }
+                    }
+                    matrix
+                }
+                None => Matrix4::identity(),
+            };
+            let linear = Matrix3::from_cols(
+                matrix.x.truncate(),
+                matrix.y.truncate(),
+                matrix.z.truncate(),
+            );
+            ModelVertex {
+                position: matrix.transform_point(Point3::from_vec(position)).into(),
+                tex_coords: vertex.tex_coords,
+                normal: direction(&linear, normal),
+                tangent: direction(&linear, Vector3::from(vertex.tangent)),
+                bitangent: direction(&linear, Vector3::from(vertex.bitangent)),
+            }
+        })
+        .collect()
+}
+
+/// Bounds of `deformation` posed with `joint_matrices` and target `weights`. The
+/// targets can move a vertex anywhere within the sum of their weighted offset boxes,
+/// and a blend of joints stays within the boxes of all of them.
+pub fn posed_bounds(
+    deformation: &MeshDeformation,
+    joint_matrices: &[Matrix4<f32>],
+    weights: &[f32],
+) -> Option<Aabb> {
+    let extent = deformation
+        .morph
+        .as_ref()
+        .map(|morph| morph.delta_extent(weights));
+    let grow = |bounds: Aabb| match extent {
+        Some(extent) => Aabb {
+            min: bounds.min + extent.min.to_vec(),
+            max: bounds.max + extent.max.to_vec(),
+        },
+        None => bounds,
+    };
+    match &deformation.skin {
+        Some(skin) if !joint_matrices.is_empty() => skin
+            .joint_bounds
+            .iter()
+            .zip(joint_matrices)
+            .filter_map(|(bounds, matrix)| Some(grow(*bounds.as_ref()?).transformed(matrix)))
+            .reduce(Aabb::union),
+        _ => Some(grow(deformation.bind_bounds)),
+    }
+}
@@ -139,13 +139,13 @@ impl ScriptEngine for ScriptEngineDesktop {
 
         let function_call = format!("{}({})", function_name, json_data);
 
-        log::info!("Before {}", function_call);
+        log::debug!("Before {}", function_call);
         let source = Source::from_bytes(&function_call);
         let result = self
             .context
             .eval(source)
             .map_err(|e| format!("Function call failed: {}", e))?;
-        log::info!("After {}", function_call);
+        log::debug!("After {}", function_call);
 
         // Extract typed array object
         let js_typed_array = result
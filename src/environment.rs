@@ -0,0 +1,624 @@
+use crate::camera::{Camera, Projection};
+use crate::resources;
+use crate::texture::GpuTexture;
+use anyhow::anyhow;
+use cgmath::{Matrix4, SquareMatrix, Vector4};
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+const ENVIRONMENT_SHADER: &str = include_str!("shaders/environment.wgsl");
+
+/// Face size of the cubemap the skybox samples; its mips feed the prefilter pass
+const ENVIRONMENT_SIZE: u32 = 512;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+/// Roughness 0 to 1 across these mips of the prefiltered map
+const PREFILTERED_MIPS: u32 = 5;
+
+/// Image an environment is built from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EnvironmentSource {
+    /// One latitude-longitude panorama, usually a Radiance `.hdr`
+    Equirectangular { path: String },
+    /// Six square face images in +X, -X, +Y, -Y, +Z, -Z order
+    Cubemap { faces: [String; 6] },
+}
+
+impl EnvironmentSource {
+    pub fn paths(&self) -> Vec<&str> {
+        match self {
+            EnvironmentSource::Equirectangular { path } => vec![path.as_str()],
+            EnvironmentSource::Cubemap { faces } => faces.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// Decoded source pixels as linear RGBA floats, one layer per image
+pub struct EnvironmentImage {
+    width: u32,
+    height: u32,
+    layers: u32,
+    data: Vec<f32>,
+}
+
+impl EnvironmentImage {
+    pub async fn load(source: &EnvironmentSource) -> anyhow::Result<Self> {
+        let mut size = None;
+        let mut data = Vec::new();
+        let paths = source.paths();
+        for path in &paths {
+            let bytes = resources::load_binary(path).await?;
+            let (width, height, pixels) =
+                decode_linear(&bytes).map_err(|e| anyhow!("Failed to decode '{}': {}", path, e))?;
+            if size.is_some_and(|size| size != (width, height)) {
+                return Err(anyhow!(
+                    "Cubemap face '{}' differs in size from the others",
+                    path
+                ));
+            }
+            if paths.len() == 6 && width != height {
+                return Err(anyhow!("Cubemap face '{}' is not square", path));
+            }
+            size = Some((width, height));
+            data.extend(pixels);
+        }
+        let (width, height) = size.ok_or_else(|| anyhow!("Environment has no images"))?;
+        Ok(Self {
+            width,
+            height,
+            layers: paths.len() as u32,
+            data,
+        })
+    }
+}
+
+/// Decode any supported image to linear RGBA floats. Float formats (Radiance HDR) are
+/// already linear; 8-bit images are assumed to be sRGB.
+fn decode_linear(bytes: &[u8]) -> anyhow::Result<(u32, u32, Vec<f32>)> {
+    let image = image::load_from_memory(bytes)?;
+    let is_float = matches!(
+        image,
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+    );
+    let rgba = image.to_rgba32f();
+    let (width, height) = rgba.dimensions();
+    let mut data = rgba.into_raw();
+    if !is_float {
+        for pixel in data.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel = if *channel <= 0.04045 {
+                    *channel / 12.92
+                } else {
+                    ((*channel + 0.055) / 1.055).powf(2.4)
+                };
+            }
+        }
+    }
+    Ok((width, height, data))
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    intensity: f32,
+    prefiltered_mips: f32,
+    enabled: u32,
+    _padding: u32,
+}
+
+/// Parameters for one face of one mip in the processing passes
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ProcessParams {
+    face: u32,
+    roughness: f32,
+    source_layers: u32,
+    target_size: f32,
+    environment_size: f32,
+    _padding: [f32; 3],
+}
+
+struct EnvironmentMaps {
+    environment: wgpu::TextureView,
+    irradiance: wgpu::TextureView,
+    prefiltered: wgpu::TextureView,
+}
+
+/// A cubemap environment drawn as the skybox and convolved for image based lighting.
+///
+/// `set_image` projects the source onto a cubemap, then renders an irradiance map for
+/// diffuse light and a roughness-prefiltered mip chain for specular. Without an image
+/// the maps are black and shaders fall back to the flat ambient term.
+pub struct Environment {
+    format: wgpu::TextureFormat,
+    enabled: bool,
+    maps: EnvironmentMaps,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    convert_pipeline: wgpu::RenderPipeline,
+    irradiance_pipeline: wgpu::RenderPipeline,
+    prefilter_pipeline: wgpu::RenderPipeline,
+    skybox_layout: wgpu::PipelineLayout,
+    skybox_shader: wgpu::ShaderModule,
+    skybox_pipeline: wgpu::RenderPipeline,
+}
+
+impl Environment {
+    /// WGSL for `#import environment`
+    pub fn shader_source() -> String {
+        ENVIRONMENT_SHADER.to_string()
+    }
+
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 5] {
+        let visibility = wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT;
+        let cube = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::Cube,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        [
+            cube(8),
+            cube(9),
+            cube(10),
+            wgpu::BindGroupLayoutEntry {
+                binding: 11,
+                visibility,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 12,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    /// `format` is the scene color format, `processing_shader` the WGSL for the
+    /// convolution passes (`environment_maps.wgsl`) and `skybox_shader` the preprocessed
+    /// skybox WGSL, which uses the per-frame layout
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        per_frame_layout: &wgpu::BindGroupLayout,
+        processing_shader: &str,
+        skybox_shader: &str,
+    ) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Environment Uniform Buffer"),
+            size: std::mem::size_of::<EnvironmentUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let processing_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Environment Processing Shader"),
+            source: wgpu::ShaderSource::Wgsl(processing_shader.into()),
+        });
+        let processing_pipeline = |entry_point: &str| {
+            // Layout comes from the resources each entry point uses
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: None,
+                vertex: wgpu::VertexState {
+                    module: &processing_module,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &processing_module,
+                    entry_point: Some(entry_point),
+                    targets: &[Some(format.into())],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+        let convert_pipeline = processing_pipeline("fs_convert");
+        let irradiance_pipeline = processing_pipeline("fs_irradiance");
+        let prefilter_pipeline = processing_pipeline("fs_prefilter");
+
+        let skybox_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Skybox Pipeline Layout"),
+            bind_group_layouts: &[per_frame_layout],
+            push_constant_ranges: &[],
+        });
+        let skybox_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Skybox Shader"),
+            source: wgpu::ShaderSource::Wgsl(skybox_shader.into()),
+        });
+        let skybox_pipeline = Self::create_skybox_pipeline(
+            device,
+            &skybox_layout,
+            &skybox_shader,
+            format,
+            sample_count,
+        );
+
+        Self {
+            format,
+            enabled: false,
+            maps: Self::fallback_maps(device, format),
+            sampler,
+            uniform_buffer,
+            convert_pipeline,
+            irradiance_pipeline,
+            prefilter_pipeline,
+            skybox_layout,
+            skybox_shader,
+            skybox_pipeline,
+        }
+    }
+
+    fn create_skybox_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Skybox Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Drawn at the far plane after opaque geometry, only where nothing else was
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: GpuTexture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.skybox_pipeline = Self::create_skybox_pipeline(
+            device,
+            &self.skybox_layout,
+            &self.skybox_shader,
+            self.format,
+            sample_count,
+        );
+    }
+
+    fn create_cube(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        size: u32,
+        mips: u32,
+        label: &str,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: mips,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        })
+    }
+
+    /// Black 1x1 cubes bound while there's no environment
+    fn fallback_maps(device: &wgpu::Device, format: wgpu::TextureFormat) -> EnvironmentMaps {
+        let view = Self::cube_view(&Self::create_cube(
+            device,
+            format,
+            1,
+            1,
+            "Empty Environment",
+        ));
+        EnvironmentMaps {
+            environment: view.clone(),
+            irradiance: view.clone(),
+            prefiltered: view,
+        }
+    }
+
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 5] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::TextureView(&self.maps.environment),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: wgpu::BindingResource::TextureView(&self.maps.irradiance),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: wgpu::BindingResource::TextureView(&self.maps.prefiltered),
+            },
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 12,
+                resource: self.uniform_buffer.as_entire_binding(),
+            },
+        ]
+    }
+
+    /// Go back to the solid background and flat ambient light.
+    /// The per-frame bind group must be rebuilt afterwards.
+    pub fn clear(&mut self, device: &wgpu::Device) {
+        self.enabled = false;
+        self.maps = Self::fallback_maps(device, self.format);
+    }
+
+    /// Build the skybox, irradiance and prefiltered maps from `image`.
+    /// The per-frame bind group must be rebuilt afterwards.
+    pub fn set_image(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &EnvironmentImage,
+    ) {
+        let source = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Environment Source"),
+                size: wgpu::Extent3d {
+                    width: image.width,
+                    height: image.height,
+                    depth_or_array_layers: image.layers,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&image.data),
+        );
+        let source_view = source.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let environment_mips = ENVIRONMENT_SIZE.ilog2() + 1;
+        let environment = Self::create_cube(
+            device,
+            self.format,
+            ENVIRONMENT_SIZE,
+            environment_mips,
+            "Environment Map",
+        );
+        let irradiance =
+            Self::create_cube(device, self.format, IRRADIANCE_SIZE, 1, "Irradiance Map");
+        let prefiltered = Self::create_cube(
+            device,
+            self.format,
+            PREFILTERED_SIZE,
+            PREFILTERED_MIPS,
+            "Prefiltered Environment Map",
+        );
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment Encoder"),
+        });
+        let params = |face: u32, roughness: f32, target_size: u32| ProcessParams {
+            face,
+            roughness,
+            source_layers: image.layers,
+            target_size: target_size as f32,
+            environment_size: ENVIRONMENT_SIZE as f32,
+            _padding: [0.0; 3],
+        };
+
+        // Every mip is projected from the source directly so the prefilter pass has a mip chain
+        let source_entries = [wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&source_view),
+        }];
+        for mip in 0..environment_mips {
+            let size = ENVIRONMENT_SIZE >> mip;
+            for face in 0..6 {
+                self.render_face(
+                    device,
+                    &mut encoder,
+                    &self.convert_pipeline,
+                    (&environment, face, mip),
+                    params(face, 0.0, size),
+                    &source_entries,
+                );
+            }
+        }
+
+        let environment_view = Self::cube_view(&environment);
+        let environment_entries = [
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&environment_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ];
+        for face in 0..6 {
+            self.render_face(
+                device,
+                &mut encoder,
+                &self.irradiance_pipeline,
+                (&irradiance, face, 0),
+                params(face, 0.0, IRRADIANCE_SIZE),
+                &environment_entries,
+            );
+        }
+        for mip in 0..PREFILTERED_MIPS {
+            let roughness = mip as f32 / (PREFILTERED_MIPS - 1) as f32;
+            for face in 0..6 {
+                self.render_face(
+                    device,
+                    &mut encoder,
+                    &self.prefilter_pipeline,
+                    (&prefiltered, face, mip),
+                    params(face, roughness, PREFILTERED_SIZE >> mip),
+                    &environment_entries,
+                );
+            }
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.maps = EnvironmentMaps {
+            environment: environment_view,
+            irradiance: Self::cube_view(&irradiance),
+            prefiltered: Self::cube_view(&prefiltered),
+        };
+        self.enabled = true;
+    }
+
+    /// Run one processing pass into `(texture, face, mip)`. `inputs` are the bind group
+    /// entries the pipeline reads besides its params.
+    fn render_face(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        target: (&wgpu::Texture, u32, u32),
+        params: ProcessParams,
+        inputs: &[wgpu::BindGroupEntry],
+    ) {
+        let (texture, face, mip) = target;
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment Pass Params"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: params_buffer.as_entire_binding(),
+        }];
+        entries.extend_from_slice(inputs);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment Pass Bind Group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: face,
+            array_layer_count: Some(1),
+            base_mip_level: mip,
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Environment Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Upload the skybox view rotation and the lighting intensity for this frame
+    pub fn update(
+        &self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        projection: &Projection,
+        intensity: f32,
+    ) {
+        // The sky is infinitely far away, so only the camera's rotation matters
+        let mut view = camera.calc_matrix();
+        view.w = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let inv_view_proj = (projection.calc_matrix() * view)
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        let uniform = EnvironmentUniform {
+            inv_view_proj: inv_view_proj.into(),
+            intensity,
+            prefiltered_mips: PREFILTERED_MIPS as f32,
+            enabled: self.enabled as u32,
+            _padding: 0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn draw_skybox<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        per_frame_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        render_pass.set_pipeline(&self.skybox_pipeline);
+        render_pass.set_bind_group(0, per_frame_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,774 @@
+use crate::animation::{
+    AnimationClip, Channel, ChannelValues, Interpolation, NodeTransform, Skeleton, SkeletonNode,
+    Skin, shortest_arc,
+};
+use crate::model::{
+    AlphaMode, DIFFUSE_SLOT, EMISSIVE_SLOT, FallbackTextures, GpuMaterial, METALLIC_SLOT,
+    MaterialDesc, MaterialSource, Mesh, MeshMorph, MeshSkin, Model, ModelVertex, MorphTarget,
+    NORMAL_SLOT, OCCLUSION_SLOT, ROUGHNESS_SLOT, SkinVertex, TextureRegistry, load_texture,
+    model_name, slot_color_space,
+};
+use crate::{
+    material_schema::{MaterialLayout, UniformValues},
+    resources::load_binary,
+    texture::{AddressMode, ColorSpace, FilterMode, GpuTexture, SamplerSettings},
+};
+use anyhow::{Context, anyhow, bail};
+use base64::Engine;
+use cgmath::{
+    InnerSpace, Matrix, Matrix3, Matrix4, Point3, Quaternion, SquareMatrix, Transform, Vector3,
+};
+use gltf::animation::util::ReadOutputs;
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
+/// What every stage of loading one glTF file reads from
+struct GltfContext<'a> {
+    file_name: &'a str,
+    buffers: Vec<Vec<u8>>,
+    device: &'a wgpu::Device,
+    queue: &'a wgpu::Queue,
+    layout: &'a MaterialLayout,
+    fallbacks: &'a FallbackTextures,
+    texture_registry: &'a TextureRegistry,
+}
+
+/// Load a .gltf (with external or data URI buffers and images) or a binary .glb.
+/// Each triangle primitive becomes a mesh with its node's world transform baked in,
+/// except skinned ones, which stay in bind pose for their joints to move. Morph
+/// targets are kept with their mesh, weighted by the node's or mesh's weights.
+pub async fn load_gltf(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &MaterialLayout,
+    fallbacks: &FallbackTextures,
+    texture_registry: &TextureRegistry,
+) -> anyhow::Result<(Model, HashMap<MaterialSource, GpuMaterial>)> {
+    let bytes = load_binary(file_name).await?;
+    let gltf::Gltf { document, mut blob } =
+        gltf::Gltf::from_slice(&bytes).with_context(|| format!("Invalid glTF file {file_name}"))?;
+
+    let mut buffers = Vec::new();
+    for buffer in document.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => blob
+                .take()
+                .ok_or_else(|| anyhow!("{file_name} has no binary chunk"))?,
+            gltf::buffer::Source::Uri(uri) => load_uri(file_name, uri).await?,
+        };
+        if data.len() < buffer.length() {
+            bail!(
+                "Buffer {} of {file_name} is shorter than its declared length",
+                buffer.index()
+            );
+        }
+        buffers.push(data);
+    }
+
+    let context = GltfContext {
+        file_name,
+        buffers,
+        device,
+        queue,
+        layout,
+        fallbacks,
+        texture_registry,
+    };
+
+    let mut materials_map = HashMap::new();
+    let mut material_sources = Vec::new();
+    for material in document.materials() {
+        let index = material.index().unwrap_or_default();
+        let mut name = material
+            .name()
+            .map_or_else(|| format!("Material {index}"), str::to_string);
+        let mut material_source = MaterialSource::Model {
+            model_path: file_name.to_string(),
+            material_name: name.clone(),
+        };
+        // Names aren't required to be unique; later duplicates get their index
+        if materials_map.contains_key(&material_source) {
+            name = format!("{name} ({index})");
+            material_source = MaterialSource::Model {
+                model_path: file_name.to_string(),
+                material_name: name.clone(),
+            };
+        }
+
+        let gpu_material = load_material(&context, &material, name).await?;
+        materials_map.insert(material_source.clone(), gpu_material);
+        material_sources.push(material_source);
+    }
+
+    // Primitives without a material use the default one
+    if materials_map.is_empty() {
+        material_sources.push(MaterialSource::System("default".to_string()));
+    }
+
+    let (skeleton, skeleton_nodes) = skeleton(&document);
+    let skins = document
+        .skins()
+        .map(|skin| load_skin(&context.buffers, &skin, &skeleton_nodes))
+        .collect();
+    let animations = load_animations(&document, &context.buffers, &skeleton_nodes);
+
+    let mut meshes = Vec::new();
+    for PlacedMesh {
+        mesh,
+        transform,
+        skin,
+        node,
+    } in placed_meshes(&document)
+    {
+        let mesh_name = mesh.name().unwrap_or(file_name);
+        let weights: Vec<f32> = node
+            .as_ref()
+            .and_then(gltf::Node::weights)
+            .or_else(|| mesh.weights())
+            .map(<[f32]>::to_vec)
+            .unwrap_or_default();
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                log::warn!(
+                    "Skipping {:?} primitive of mesh '{mesh_name}' in {file_name}; only triangles are supported",
+                    primitive.mode()
+                );
+                continue;
+            }
+            let material_source = primitive
+                .material()
+                .index()
+                .and_then(|index| material_sources.get(index).cloned())
+                .unwrap_or_else(|| MaterialSource::System("default".to_string()));
+            // Skinned vertices are posed by their joints alone, so the node's transform
+            // doesn't apply
+            let influences = skin
+                .as_ref()
+                .and_then(|_| primitive_influences(&context.buffers, &primitive));
+            let transform = if influences.is_some() {
+                Matrix4::identity()
+            } else {
+                transform
+            };
+            let Some((vertices, indices)) =
+                primitive_geometry(&context.buffers, &primitive, transform)
+            else {
+                log::warn!(
+                    "Skipping primitive of mesh '{mesh_name}' in {file_name} without positions"
+                );
+                continue;
+            };
+            let mesh_skin = skin
+                .as_ref()
+                .zip(influences)
+                .map(|(skin, influences)| MeshSkin::new(skin.index(), influences, &vertices));
+            let targets = primitive_morph_targets(&context.buffers, &primitive, transform);
+            let morph = (!targets.is_empty()).then(|| {
+                let mut weights = weights.clone();
+                weights.resize(targets.len(), 0.0);
+                let node = node.as_ref().and_then(|node| skeleton_nodes[node.index()]);
+                MeshMorph::new(targets, weights, node)
+            });
+            meshes.push(Mesh::new_deformed(
+                device,
+                mesh_name,
+                vertices,
+                indices,
+                material_source,
+                mesh_skin,
+                morph,
+            ));
+        }
+    }
+
+    Ok((
+        Model {
+            name: model_name(file_name).to_string(),
+            meshes,
+            material_keys: material_sources,
+            skeleton,
+            skins,
+            animations,
+        },
+        materials_map,
+    ))
+}
+
+/// A mesh placed in the scene by a node
+struct PlacedMesh<'a> {
+    mesh: gltf::Mesh<'a>,
+    /// World transform of the node
+    transform: Matrix4<f32>,
+    skin: Option<gltf::Skin<'a>>,
+    /// None for meshes no scene places
+    node: Option<gltf::Node<'a>>,
+}
+
+/// Every mesh the default scene (or the first, or failing that the bare mesh list)
+/// places, in document order
+fn placed_meshes(document: &gltf::Document) -> Vec<PlacedMesh<'_>> {
+    let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    else {
+        return document
+            .meshes()
+            .map(|mesh| PlacedMesh {
+                mesh,
+                transform: Matrix4::identity(),
+                skin: None,
+                node: None,
+            })
+            .collect();
+    };
+
+    let mut placed = Vec::new();
+    let mut stack: Vec<_> = scene
+        .nodes()
+        .map(|node| (node, Matrix4::identity()))
+        .collect();
+    stack.reverse();
+    while let Some((node, parent)) = stack.pop() {
+        let transform = parent * Matrix4::from(node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            placed.push(PlacedMesh {
+                mesh,
+                transform,
+                skin: node.skin(),
+                node: Some(node.clone()),
+            });
+        }
+        let children: Vec<_> = node.children().collect();
+        stack.extend(children.into_iter().rev().map(|child| (child, transform)));
+    }
+    placed
+}
+
+/// Every node of the document ordered parents first, and the index each glTF node
+/// ended up at
+fn skeleton(document: &gltf::Document) -> (Skeleton, Vec<Option<usize>>) {
+    let nodes: Vec<_> = document.nodes().collect();
+    let mut parents = vec![None; nodes.len()];
+    for node in &nodes {
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+        }
+    }
+
+    let mut remap = vec![None; nodes.len()];
+    let mut skeleton = Skeleton::default();
+    let mut stack: Vec<usize> = (0..nodes.len())
+        .rev()
+        .filter(|&index| parents[index].is_none())
+        .collect();
+    while let Some(index) = stack.pop() {
+        // Only reachable twice in a malformed file with cycles
+        if remap[index].is_some() {
+            continue;
+        }
+        let node = &nodes[index];
+        let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+        remap[index] = Some(skeleton.nodes.len());
+        skeleton.nodes.push(SkeletonNode {
+            name: node
+                .name()
+                .map_or_else(|| format!("Node {index}"), str::to_string),
+            parent: parents[index].and_then(|parent| remap[parent]),
+            rest: NodeTransform {
+                translation: translation.into(),
+                rotation: Quaternion::new(w, x, y, z),
+                scale: scale.into(),
+            },
+            weights: node
+                .weights()
+                .or_else(|| node.mesh().and_then(|mesh| mesh.weights()))
+                .map(<[f32]>::to_vec)
+                .unwrap_or_default(),
+        });
+        let children: Vec<usize> = node.children().map(|child| child.index()).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    (skeleton, remap)
+}
+
+fn load_skin(buffers: &[Vec<u8>], skin: &gltf::Skin, skeleton_nodes: &[Option<usize>]) -> Skin {
+    let joints: Vec<usize> = skin
+        .joints()
+        .map(|joint| skeleton_nodes[joint.index()].unwrap_or_default())
+        .collect();
+    // Missing matrices are identity, meaning the joints are already in the bind pose
+    let mut inverse_bind_matrices: Vec<Matrix4<f32>> = skin
+        .reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice))
+        .read_inverse_bind_matrices()
+        .map(|matrices| matrices.map(Matrix4::from).collect())
+        .unwrap_or_default();
+    inverse_bind_matrices.resize(joints.len(), Matrix4::identity());
+    Skin {
+        name: skin
+            .name()
+            .map_or_else(|| format!("Skin {}", skin.index()), str::to_string),
+        joints,
+        inverse_bind_matrices,
+    }
+}
+
+fn load_animations(
+    document: &gltf::Document,
+    buffers: &[Vec<u8>],
+    skeleton_nodes: &[Option<usize>],
+) -> Vec<AnimationClip> {
+    let mut clips: Vec<AnimationClip> = Vec::new();
+    for animation in document.animations() {
+        let mut channels = Vec::new();
+        for channel in animation.channels() {
+            let Some(node) = skeleton_nodes[channel.target().node().index()] else {
+                continue;
+            };
+            let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let Some(times) = reader.read_inputs() else {
+                continue;
+            };
+            let times: Vec<f32> = times.collect();
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+            };
+            let values = match reader.read_outputs() {
+                Some(ReadOutputs::Translations(values)) => {
+                    ChannelValues::Translation(values.map(Vector3::from).collect())
+                }
+                Some(ReadOutputs::Rotations(values)) => {
+                    let mut rotations: Vec<Quaternion<f32>> = values
+                        .into_f32()
+                        .map(|[x, y, z, w]| Quaternion::new(w, x, y, z))
+                        .collect();
+                    // Keys are blended linearly, so each should be on the same side as the
+                    // last. Spline tangents aren't rotations, so are left alone.
+                    if interpolation != Interpolation::CubicSpline {
+                        for i in 1..rotations.len() {
+                            rotations[i] = shortest_arc(rotations[i - 1], rotations[i]);
+                        }
+                    }
+                    ChannelValues::Rotation(rotations)
+                }
+                Some(ReadOutputs::Scales(values)) => {
+                    ChannelValues::Scale(values.map(Vector3::from).collect())
+                }
+                Some(ReadOutputs::MorphTargetWeights(values)) => {
+                    // Each key (or spline in tangent, value and out tangent) holds one
+                    // weight per target
+                    let values: Vec<f32> = values.into_f32().collect();
+                    let keys = times.len()
+                        * if interpolation == Interpolation::CubicSpline {
+                            3
+                        } else {
+                            1
+                        };
+                    if keys == 0 {
+                        continue;
+                    }
+                    let target_count = values.len() / keys;
+                    ChannelValues::Weights(
+                        (0..target_count)
+                            .map(|target| {
+                                values
+                                    .iter()
+                                    .skip(target)
+                                    .step_by(target_count)
+                                    .copied()
+                                    .collect()
+                            })
+                            .collect(),
+                    )
+                }
+                None => continue,
+            };
+            channels.push(Channel {
+                node,
+                interpolation,
+                times,
+                values,
+            });
+        }
+
+        let index = animation.index();
+        let mut name = animation
+            .name()
+            .map_or_else(|| format!("Animation {index}"), str::to_string);
+        // Clips are played by name, so each needs its own
+        if clips.iter().any(|clip| clip.name == name) {
+            name = format!("{name} ({index})");
+        }
+        clips.push(AnimationClip {
+            name,
+            duration: channels
+                .iter()
+                .filter_map(|channel| channel.times.last().copied())
+                .fold(0.0, f32::max),
+            channels,
+        });
+    }
+    clips
+}
+
+/// Joints and weights of the first set of a primitive's skinning attributes, with
+/// the weights normalized, or None if it has none
+fn primitive_influences(
+    buffers: &[Vec<u8>],
+    primitive: &gltf::Primitive,
+) -> Option<Vec<SkinVertex>> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    let joints = reader.read_joints(0)?.into_u16();
+    let weights = reader.read_weights(0)?.into_f32();
+    Some(
+        joints
+            .zip(weights)
+            .map(|(joints, weights)| {
+                let total: f32 = weights.iter().sum();
+                SkinVertex {
+                    joints: joints.map(u32::from),
+                    weights: if total > 0.0 {
+                        weights.map(|weight| weight / total)
+                    } else {
+                        [1.0, 0.0, 0.0, 0.0]
+                    },
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Vertices (in model space) and indices of a triangle primitive, or None without
+/// positions. Missing indices are generated, missing normals are rebuilt from faces.
+fn primitive_geometry(
+    buffers: &[Vec<u8>],
+    primitive: &gltf::Primitive,
+    transform: Matrix4<f32>,
+) -> Option<(Vec<ModelVertex>, Vec<u32>)> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    let positions: Vec<[f32; 3]> = reader.read_positions()?.collect();
+    let mut indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    let normals: Vec<[f32; 3]> = match reader.read_normals() {
+        Some(normals) => normals.collect(),
+        None => face_normals(&positions, &indices),
+    };
+    let tex_coords: Vec<[f32; 2]> = reader
+        .read_tex_coords(0)
+        .map(|tex_coords| tex_coords.into_f32().collect())
+        .unwrap_or_default();
+
+    let (linear, normal_matrix) = linear_matrices(transform);
+    // A mirroring transform turns triangles inside out
+    if linear.determinant() < 0.0 {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    let vertices = positions
+        .iter()
+        .enumerate()
+        .map(|(i, position)| {
+            let normal = normals
+                .get(i)
+                .map(|normal| normal_matrix * Vector3::from(*normal))
+                .filter(|normal| normal.magnitude2() > 0.0)
+                .map_or([0.0; 3], |normal| normal.normalize().into());
+            ModelVertex {
+                position: transform.transform_point(Point3::from(*position)).into(),
+                // glTF UVs already have their origin at the top left, as wgpu expects
+                tex_coords: tex_coords.get(i).copied().unwrap_or_default(),
+                normal,
+                // Filled in by Mesh::new
+                tangent: [0.0; 3],
+                bitangent: [0.0; 3],
+            }
+        })
+        .collect();
+    Some((vertices, indices))
+}
+
+/// The linear part of `transform`, and its inverse transpose for moving normals
+fn linear_matrices(transform: Matrix4<f32>) -> (Matrix3<f32>, Matrix3<f32>) {
+    let linear = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    let normal_matrix = linear
+        .invert()
+        .map_or(linear, |inverse| inverse.transpose());
+    (linear, normal_matrix)
+}
+
+/// Position and normal offsets of each of a primitive's morph targets, moved by
+/// `transform` as its vertices are
+fn primitive_morph_targets(
+    buffers: &[Vec<u8>],
+    primitive: &gltf::Primitive,
+    transform: Matrix4<f32>,
+) -> Vec<MorphTarget> {
+    let (linear, normal_matrix) = linear_matrices(transform);
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    reader
+        .read_morph_targets()
+        .map(|(positions, normals, _tangents)| MorphTarget {
+            positions: positions
+                .into_iter()
+                .flatten()
+                .map(|offset| (linear * Vector3::from(offset)).into())
+                .collect(),
+            normals: normals
+                .into_iter()
+                .flatten()
+                .map(|offset| (normal_matrix * Vector3::from(offset)).into())
+                .collect(),
+        })
+        .collect()
+}
+
+/// Smooth normals accumulated from the area-weighted normals of each vertex's faces
+fn face_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+        if [a, b, c].iter().any(|&i| i >= positions.len()) {
+            continue;
+        }
+        let [pa, pb, pc] = [a, b, c].map(|i| Vector3::from(positions[i]));
+        let normal = (pb - pa).cross(pc - pa);
+        for i in [a, b, c] {
+            normals[i] += normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.magnitude2() > 0.0 {
+                normal.normalize().into()
+            } else {
+                [0.0; 3]
+            }
+        })
+        .collect()
+}
+
+async fn load_material(
+    context: &GltfContext<'_>,
+    material: &gltf::Material<'_>,
+    name: String,
+) -> anyhow::Result<GpuMaterial> {
+    let pbr = material.pbr_metallic_roughness();
+    let metallic_roughness = pbr.metallic_roughness_texture().map(|info| info.texture());
+
+    let mut texture_paths = HashMap::new();
+    let mut textures = HashMap::new();
+    let mut samplers = HashMap::new();
+    // Metallic is read from blue and roughness from green, so glTF's packed texture
+    // serves both slots
+    for (slot, texture) in [
+        (
+            DIFFUSE_SLOT,
+            pbr.base_color_texture().map(|info| info.texture()),
+        ),
+        (
+            NORMAL_SLOT,
+            material.normal_texture().map(|info| info.texture()),
+        ),
+        (METALLIC_SLOT, metallic_roughness.clone()),
+        (ROUGHNESS_SLOT, metallic_roughness),
+        (
+            OCCLUSION_SLOT,
+            material.occlusion_texture().map(|info| info.texture()),
+        ),
+        (
+            EMISSIVE_SLOT,
+            material.emissive_texture().map(|info| info.texture()),
+        ),
+    ] {
+        let Some(texture) = texture else {
+            continue;
+        };
+        if !context.layout.schema.has_texture_slot(slot) {
+            continue;
+        }
+        let color_space = slot_color_space(slot);
+        let (path, gpu_texture) = load_image(context, &texture.source(), color_space).await?;
+        texture_paths.insert(slot.to_string(), path);
+        textures.insert(slot.to_string(), gpu_texture);
+        samplers.insert(slot.to_string(), sampler_settings(&texture.sampler()));
+    }
+
+    let [r, g, b] = material.emissive_factor();
+    let mut uniforms = UniformValues::new();
+    uniforms.insert("base_color".to_string(), pbr.base_color_factor());
+    uniforms.insert("emissive".to_string(), [r, g, b, 0.0]);
+    uniforms.insert(
+        "metallic".to_string(),
+        [pbr.metallic_factor(), 0.0, 0.0, 0.0],
+    );
+    uniforms.insert(
+        "roughness".to_string(),
+        [pbr.roughness_factor(), 0.0, 0.0, 0.0],
+    );
+    let alpha_mode = match material.alpha_mode() {
+        gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+        gltf::material::AlphaMode::Mask => {
+            let cutoff = material.alpha_cutoff().unwrap_or(0.5);
+            uniforms.insert("alpha_cutoff".to_string(), [cutoff, 0.0, 0.0, 0.0]);
+            AlphaMode::Mask
+        }
+        gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+    };
+
+    let desc = MaterialDesc {
+        name,
+        shader: crate::defaults::DEFAULT_SHADER_PATH.to_string(),
+        textures: texture_paths,
+        uniforms: RefCell::new(uniforms),
+        alpha_mode,
+        samplers,
+    };
+    Ok(GpuMaterial::new(
+        context.device,
+        desc,
+        context.layout,
+        textures,
+        context.fallbacks,
+    ))
+}
+
+/// An image's registry path and texture. External files are registered under their
+/// path like any texture; embedded ones under "<model file>#image<index>".
+async fn load_image(
+    context: &GltfContext<'_>,
+    image: &gltf::Image<'_>,
+    color_space: ColorSpace,
+) -> anyhow::Result<(String, Arc<GpuTexture>)> {
+    if let gltf::image::Source::Uri { uri, .. } = image.source()
+        && !uri.starts_with("data:")
+    {
+        let path = resolve_uri(context.file_name, uri);
+        let texture = load_texture(
+            &path,
+            color_space,
+            context.device,
+            context.queue,
+            context.texture_registry,
+        )
+        .await?;
+        return Ok((path, texture));
+    }
+
+    let embedded_path = format!("{}#image{}", context.file_name, image.index());
+    let key = (embedded_path.clone(), color_space);
+    if let Some(existing) = context.texture_registry.lock().unwrap().get(&key) {
+        return Ok((embedded_path, Arc::clone(existing)));
+    }
+    let bytes = match image.source() {
+        gltf::image::Source::Uri { uri, .. } => decode_data_uri(uri)?,
+        gltf::image::Source::View { view, .. } => {
+            let buffer = &context.buffers[view.buffer().index()];
+            buffer
+                .get(view.offset()..view.offset() + view.length())
+                .ok_or_else(|| anyhow!("Image {} overruns its buffer", image.index()))?
+                .to_vec()
+        }
+    };
+
+    let texture = Arc::new(GpuTexture::from_bytes(
+        context.device,
+        context.queue,
+        &bytes,
+        &embedded_path,
+        color_space,
+    )?);
+    context
+        .texture_registry
+        .lock()
+        .unwrap()
+        .insert(key, Arc::clone(&texture));
+    Ok((embedded_path, texture))
+}
+
+fn sampler_settings(sampler: &gltf::texture::Sampler) -> SamplerSettings {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => AddressMode::Repeat,
+    };
+    let (min_filter, mipmap_filter) = match sampler.min_filter() {
+        Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => {
+            (FilterMode::Nearest, FilterMode::Nearest)
+        }
+        Some(MinFilter::NearestMipmapLinear) => (FilterMode::Nearest, FilterMode::Linear),
+        Some(MinFilter::LinearMipmapNearest) => (FilterMode::Linear, FilterMode::Nearest),
+        Some(MinFilter::Linear | MinFilter::LinearMipmapLinear) | None => {
+            (FilterMode::Linear, FilterMode::Linear)
+        }
+    };
+    SamplerSettings {
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => FilterMode::Nearest,
+            Some(MagFilter::Linear) | None => FilterMode::Linear,
+        },
+        min_filter,
+        mipmap_filter,
+        ..SamplerSettings::default()
+    }
+}
+
+/// Contents of a buffer URI: inline data, or a file beside the model
+async fn load_uri(file_name: &str, uri: &str) -> anyhow::Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        decode_data_uri(uri)
+    } else {
+        load_binary(&resolve_uri(file_name, uri)).await
+    }
+}
+
+fn decode_data_uri(uri: &str) -> anyhow::Result<Vec<u8>> {
+    let (_, data) = uri
+        .split_once(";base64,")
+        .ok_or_else(|| anyhow!("Only base64 data URIs are supported"))?;
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .context("Invalid base64 in data URI")
+}
+
+/// Resource path of a relative URI, which is percent-encoded and relative to the
+/// model's own directory
+fn resolve_uri(file_name: &str, uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| uri.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8_lossy(&decoded);
+    match file_name.rsplit_once('/') {
+        Some((directory, _)) => format!("{directory}/{path}"),
+        None => path.into_owned(),
+    }
+}
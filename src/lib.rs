@@ -1,18 +1,34 @@
+mod animation;
 mod app_ui;
+mod block_decode;
 mod camera;
+mod compressed_texture;
+mod culling;
 mod defaults;
+mod deform;
 mod egui;
 #[cfg(not(target_arch = "wasm32"))]
 mod engine_desktop;
 #[cfg(target_arch = "wasm32")]
 mod engine_web;
+mod environment;
+mod gltf_model;
 mod light;
+mod light_clusters;
+mod lod;
+mod material_schema;
 mod model;
+mod particle_simulation;
 mod particle_system;
+mod pipeline;
+mod post_process;
+mod render_settings;
 mod resources;
 mod scripting;
+mod shadows;
 mod state;
 mod texture;
+mod tonemapping;
 pub mod world;
 
 use crate::state::State;
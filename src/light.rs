@@ -1,57 +1,166 @@
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+/// How a light emits: from a point, along a direction, or in a cone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum LightKind {
+    #[default]
+    Point,
+    /// Infinitely far away; only `direction` matters
+    Directional,
+    /// Point light restricted to a cone around `direction`
+    Spot,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 3] = [LightKind::Point, LightKind::Directional, LightKind::Spot];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LightKind::Point => "Point",
+            LightKind::Directional => "Directional",
+            LightKind::Spot => "Spot",
+        }
+    }
+
+    /// Matches the LIGHT_* constants in the shaders
+    fn gpu_id(&self) -> u32 {
+        match self {
+            LightKind::Point => 0,
+            LightKind::Directional => 1,
+            LightKind::Spot => 2,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Light {
-    pub position: [f32; 4],
+    pub kind: LightKind,
+    pub position: [f32; 3],
+    /// Direction the light travels in (directional and spot lights)
+    pub direction: [f32; 3],
     pub color: [f32; 4],
+    pub intensity: f32,
+    /// Distance at which point and spot lights fade out completely; 0 means unlimited
+    pub range: f32,
+    /// Full-intensity half angle of a spot cone, in degrees
+    pub inner_cone_deg: f32,
+    /// Half angle where a spot cone has faded to nothing, in degrees
+    pub outer_cone_deg: f32,
+    pub cast_shadows: bool,
+    /// Offset towards the light before the shadow lookup, in world units
+    pub shadow_bias: f32,
+    /// Offset along the surface normal before the shadow lookup, in world units
+    pub shadow_normal_bias: f32,
 }
 
-const MAX_LIGHTS: usize = 10;
+impl Light {
+    pub fn point(position: [f32; 3], color: [f32; 4]) -> Self {
+        Self {
+            position,
+            color,
+            ..Default::default()
+        }
+    }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct LightArrayGpu {
-    lights: [Light; MAX_LIGHTS],
-    num_lights: u32,
-    _padding: [u32; 3],
+    /// Normalized `direction`, falling back to straight down if it's degenerate
+    pub fn unit_direction(&self) -> cgmath::Vector3<f32> {
+        use cgmath::InnerSpace;
+        let direction = cgmath::Vector3::from(self.direction);
+        if direction.magnitude2() > f32::EPSILON {
+            direction.normalize()
+        } else {
+            -cgmath::Vector3::unit_y()
+        }
+    }
 }
 
 impl Default for Light {
     fn default() -> Self {
         Self {
-            position: [0.0; 4],
+            kind: LightKind::Point,
+            position: [0.0; 3],
+            direction: [0.0, -1.0, 0.0],
             color: [0.0; 4],
+            intensity: 1.0,
+            range: 0.0,
+            inner_cone_deg: 20.0,
+            outer_cone_deg: 30.0,
+            cast_shadows: false,
+            shadow_bias: 0.02,
+            shadow_normal_bias: 0.03,
         }
     }
 }
 
+/// GPU layout of a light, matching `Light` in the shaders
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightGpu {
+    position: [f32; 3],
+    kind: u32,
+    color: [f32; 4],
+    direction: [f32; 3],
+    range: f32,
+    intensity: f32,
+    inner_cone_cos: f32,
+    outer_cone_cos: f32,
+    /// First shadow map layer, or -1 if the light has no shadow map this frame
+    shadow_layer: i32,
+    shadow_bias: f32,
+    shadow_normal_bias: f32,
+    _padding: [f32; 2],
+}
+
+impl LightGpu {
+    pub fn new(light: &Light, shadow_layer: Option<u32>) -> Self {
+        let outer_cone_cos = light.outer_cone_deg.clamp(0.0, 90.0).to_radians().cos();
+        // Keep the cone edges apart so the shader's smoothstep stays well defined
+        let inner_cone_cos = light
+            .inner_cone_deg
+            .max(0.0)
+            .to_radians()
+            .cos()
+            .max(outer_cone_cos + 1e-4);
+        Self {
+            position: light.position,
+            kind: light.kind.gpu_id(),
+            color: light.color,
+            direction: light.unit_direction().into(),
+            range: light.range.max(0.0),
+            intensity: light.intensity,
+            inner_cone_cos,
+            outer_cone_cos,
+            shadow_layer: shadow_layer.map_or(-1, |layer| layer as i32),
+            shadow_bias: light.shadow_bias,
+            shadow_normal_bias: light.shadow_normal_bias,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+/// Lights addressed by stable slot index; removed slots are reused by later additions
 pub struct LightManager {
-    lights: [Light; MAX_LIGHTS],
-    active_mask: u32,
-    dirty: bool,
+    lights: Vec<Option<Light>>,
     model_path: String,
     mesh_index: usize,
+    /// Draw every mesh of the model instead of just `mesh_index`
+    entire_model: bool,
     material_source: crate::model::MaterialSource,
 }
 
 impl LightManager {
     pub fn new(material_source: crate::model::MaterialSource) -> Self {
         Self {
-            lights: [Light::default(); MAX_LIGHTS],
-            active_mask: 0,
-            dirty: false,
+            lights: Vec::new(),
             model_path: crate::defaults::LIGHT_MODEL_PATH.to_string(),
             mesh_index: 0,
+            entire_model: false,
             material_source,
         }
     }
 
-    pub fn with_lights(
-        material_source: crate::model::MaterialSource,
-        lights: &[([f32; 3], [f32; 4])],
-    ) -> Self {
+    pub fn with_lights(material_source: crate::model::MaterialSource, lights: &[Light]) -> Self {
         let mut manager = Self::new(material_source);
-        for (pos, color) in lights {
-            manager.add_light(*pos, *color);
+        for light in lights {
+            manager.add_light(*light);
         }
         manager
     }
@@ -72,6 +181,14 @@ impl LightManager {
         self.mesh_index = index;
     }
 
+    pub fn entire_model(&self) -> bool {
+        self.entire_model
+    }
+
+    pub fn set_entire_model(&mut self, entire_model: bool) {
+        self.entire_model = entire_model;
+    }
+
     pub fn material_source(&self) -> &crate::model::MaterialSource {
         &self.material_source
     }
@@ -80,79 +197,47 @@ impl LightManager {
         self.material_source = material;
     }
 
-    pub fn add_light(&mut self, pos: [f32; 3], color: [f32; 4]) -> Option<usize> {
-        for i in 0..MAX_LIGHTS {
-            if self.active_mask & (1 << i) == 0 {
-                self.lights[i] = Light {
-                    position: [pos[0], pos[1], pos[2], 1.0],
-                    color,
-                };
-                self.active_mask |= 1 << i;
-                self.dirty = true;
-                return Some(i);
-            }
+    pub fn add_light(&mut self, light: Light) -> usize {
+        if let Some(index) = self.lights.iter().position(Option::is_none) {
+            self.lights[index] = Some(light);
+            index
+        } else {
+            self.lights.push(Some(light));
+            self.lights.len() - 1
         }
-        None
     }
 
     pub fn remove_light(&mut self, index: usize) {
-        if index < MAX_LIGHTS {
-            self.active_mask &= !(1 << index);
-            self.dirty = true;
+        if let Some(slot) = self.lights.get_mut(index) {
+            *slot = None;
         }
-    }
-
-    pub fn update_light(&mut self, index: usize, pos: [f32; 3], color: [f32; 4]) {
-        if self.is_active(index) {
-            self.lights[index].position = [pos[0], pos[1], pos[2], 1.0];
-            self.lights[index].color = color;
-            self.dirty = true;
+        // Trim trailing empty slots so the slot count doesn't only ever grow
+        while matches!(self.lights.last(), Some(None)) {
+            self.lights.pop();
         }
     }
 
-    pub fn get_light(&self, index: usize) -> Option<&Light> {
-        if self.is_active(index) {
-            Some(&self.lights[index])
-        } else {
-            None
+    pub fn update_light(&mut self, index: usize, light: Light) {
+        if let Some(Some(existing)) = self.lights.get_mut(index) {
+            *existing = light;
         }
     }
 
-    pub fn sync_to_gpu(&self) -> LightArrayGpu {
-        let mut gpu_lights = [Light::default(); MAX_LIGHTS];
-        let mut write_idx = 0;
-
-        for i in 0..MAX_LIGHTS {
-            if self.is_active(i) {
-                gpu_lights[write_idx] = self.lights[i];
-                write_idx += 1;
-            }
-        }
-
-        LightArrayGpu {
-            lights: gpu_lights,
-            num_lights: write_idx as u32,
-            _padding: [0; 3],
-        }
+    pub fn get_light(&self, index: usize) -> Option<&Light> {
+        self.lights.get(index).and_then(Option::as_ref)
     }
 
-    pub fn is_active(&self, index: usize) -> bool {
-        index < MAX_LIGHTS && (self.active_mask & (1 << index)) != 0
+    /// Active lights in slot order, packed as they are laid out on the GPU
+    pub fn active_lights(&self) -> Vec<Light> {
+        self.lights.iter().flatten().copied().collect()
     }
 
     pub fn num_lights(&self) -> u32 {
-        self.active_mask.count_ones()
-    }
-
-    pub fn max_lights(&self) -> usize {
-        MAX_LIGHTS
-    }
-
-    pub fn is_dirty(&self) -> bool {
-        self.dirty
+        self.lights.iter().flatten().count() as u32
     }
 
-    pub fn clear_dirty(&mut self) {
-        self.dirty = false;
+    /// Upper bound (exclusive) on the slot indices currently in use
+    pub fn slot_count(&self) -> usize {
+        self.lights.len()
     }
 }
@@ -0,0 +1,452 @@
+use crate::camera::{Camera, Projection};
+use crate::light::{Light, LightGpu, LightKind};
+use cgmath::{EuclideanSpace, Point3, Transform};
+
+/// Clusters across the screen and along (logarithmic) view depth
+const CLUSTERS_X: u32 = 16;
+const CLUSTERS_Y: u32 = 9;
+const CLUSTERS_Z: u32 = 24;
+const NUM_CLUSTERS: usize = (CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z) as usize;
+
+/// Texels per row of the data textures; matches LIGHT_DATA_WIDTH in lights_texture.wgsl
+const LIGHT_DATA_WIDTH: u32 = 1024;
+/// Texels per light in the light data texture (a LightGpu is five vec4s)
+const TEXELS_PER_LIGHT: u32 = 5;
+
+const LIGHTS_WGSL: &str = include_str!("shaders/lights.wgsl");
+const LIGHTS_STORAGE_WGSL: &str = include_str!("shaders/lights_storage.wgsl");
+const LIGHTS_TEXTURE_WGSL: &str = include_str!("shaders/lights_texture.wgsl");
+
+/// How the light list reaches shaders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightStorage {
+    /// Storage buffers, wherever vertex and fragment shaders can read them
+    Buffers,
+    /// Integer data textures read with textureLoad (WebGL2)
+    Textures,
+}
+
+impl LightStorage {
+    pub fn for_adapter(adapter: &wgpu::Adapter) -> Self {
+        let flags = adapter.get_downlevel_capabilities().flags;
+        if flags
+            .contains(wgpu::DownlevelFlags::VERTEX_STORAGE | wgpu::DownlevelFlags::FRAGMENT_STORAGE)
+            && adapter.limits().max_storage_buffers_per_shader_stage >= 3
+        {
+            LightStorage::Buffers
+        } else {
+            LightStorage::Textures
+        }
+    }
+
+    /// Raise `limits` to what this storage mode binds
+    pub fn apply_limits(&self, limits: &mut wgpu::Limits, adapter: &wgpu::Adapter) {
+        if *self == LightStorage::Buffers {
+            let adapter_limits = adapter.limits();
+            limits.max_storage_buffers_per_shader_stage = 3;
+            limits.max_storage_buffer_binding_size = adapter_limits
+                .max_storage_buffer_binding_size
+                .min(wgpu::Limits::default().max_storage_buffer_binding_size);
+        }
+    }
+
+    /// WGSL spliced in for `#import lights`
+    pub fn shader_source(&self) -> String {
+        let bindings = match self {
+            LightStorage::Buffers => LIGHTS_STORAGE_WGSL,
+            LightStorage::Textures => LIGHTS_TEXTURE_WGSL,
+        };
+        format!("{}\n{}", LIGHTS_WGSL, bindings)
+    }
+
+    /// Layout entries for group 0 bindings 1-4
+    pub fn layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        let visibility = wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT;
+        let data_binding = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: match self {
+                LightStorage::Buffers => wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                LightStorage::Textures => wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Uint,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+            },
+            count: None,
+        };
+
+        vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            data_binding(2),
+            data_binding(3),
+            data_binding(4),
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightClusterParams {
+    grid: [u32; 4],
+    screen_size: [f32; 2],
+    z_scale: f32,
+    z_bias: f32,
+    num_lights: u32,
+    _padding: [u32; 3],
+}
+
+/// A growable GPU array: a storage buffer or a data texture, depending on `LightStorage`
+enum LightData {
+    Buffer(wgpu::Buffer),
+    Texture(wgpu::Texture, wgpu::TextureView),
+}
+
+struct LightDataArray {
+    label: &'static str,
+    /// Bytes per element (storage buffers) or per texel (data textures)
+    element_size: u32,
+    format: wgpu::TextureFormat,
+    capacity: u32,
+    data: LightData,
+}
+
+impl LightDataArray {
+    fn new(
+        device: &wgpu::Device,
+        storage: LightStorage,
+        label: &'static str,
+        element_size: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let capacity = LIGHT_DATA_WIDTH;
+        Self {
+            label,
+            element_size,
+            format,
+            capacity,
+            data: Self::allocate(device, storage, label, element_size, format, capacity),
+        }
+    }
+
+    fn allocate(
+        device: &wgpu::Device,
+        storage: LightStorage,
+        label: &str,
+        element_size: u32,
+        format: wgpu::TextureFormat,
+        capacity: u32,
+    ) -> LightData {
+        match storage {
+            LightStorage::Buffers => {
+                LightData::Buffer(device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size: (capacity * element_size) as u64,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }))
+            }
+            LightStorage::Textures => {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: LIGHT_DATA_WIDTH,
+                        height: capacity / LIGHT_DATA_WIDTH,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                LightData::Texture(texture, view)
+            }
+        }
+    }
+
+    /// Upload `bytes`, reallocating if they don't fit. Returns true if reallocated.
+    fn write(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        storage: LightStorage,
+        bytes: &[u8],
+    ) -> bool {
+        let elements = (bytes.len() as u32).div_ceil(self.element_size);
+        let mut reallocated = false;
+        if elements > self.capacity {
+            // Whole texture rows, so buffers and textures grow the same way
+            self.capacity = elements.next_power_of_two().max(LIGHT_DATA_WIDTH);
+            self.data = Self::allocate(
+                device,
+                storage,
+                self.label,
+                self.element_size,
+                self.format,
+                self.capacity,
+            );
+            reallocated = true;
+        }
+
+        match &self.data {
+            LightData::Buffer(buffer) => queue.write_buffer(buffer, 0, bytes),
+            LightData::Texture(texture, _) => {
+                // Pad to whole rows; the rest of the last row is never read
+                let rows = elements.div_ceil(LIGHT_DATA_WIDTH).max(1);
+                let row_bytes = (LIGHT_DATA_WIDTH * self.element_size) as usize;
+                let mut padded = bytes.to_vec();
+                padded.resize(rows as usize * row_bytes, 0);
+                queue.write_texture(
+                    wgpu::TexelCopyTextureInfo {
+                        texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d::ZERO,
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    &padded,
+                    wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(row_bytes as u32),
+                        rows_per_image: Some(rows),
+                    },
+                    wgpu::Extent3d {
+                        width: LIGHT_DATA_WIDTH,
+                        height: rows,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+        reallocated
+    }
+
+    fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        match &self.data {
+            LightData::Buffer(buffer) => buffer.as_entire_binding(),
+            LightData::Texture(_, view) => wgpu::BindingResource::TextureView(view),
+        }
+    }
+}
+
+/// The view lights are clustered for
+pub struct ClusterView<'a> {
+    pub camera: &'a Camera,
+    pub projection: &'a Projection,
+    /// Framebuffer size in pixels
+    pub screen_size: (u32, u32),
+}
+
+/// Lights bucketed into view-space clusters, so each fragment only loops over the
+/// lights whose range reaches it. Rebuilt on the CPU every frame.
+pub struct LightClusters {
+    storage: LightStorage,
+    params_buffer: wgpu::Buffer,
+    lights: LightDataArray,
+    clusters: LightDataArray,
+    indices: LightDataArray,
+}
+
+impl LightClusters {
+    pub fn new(device: &wgpu::Device, storage: LightStorage) -> Self {
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light_cluster_params"),
+            size: std::mem::size_of::<LightClusterParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // In texture mode a light spans five Rgba32Uint texels; in buffer mode it's one element
+        let light_element_size = match storage {
+            LightStorage::Buffers => std::mem::size_of::<LightGpu>() as u32,
+            LightStorage::Textures => std::mem::size_of::<LightGpu>() as u32 / TEXELS_PER_LIGHT,
+        };
+
+        Self {
+            storage,
+            params_buffer,
+            lights: LightDataArray::new(
+                device,
+                storage,
+                "light_data",
+                light_element_size,
+                wgpu::TextureFormat::Rgba32Uint,
+            ),
+            clusters: LightDataArray::new(
+                device,
+                storage,
+                "light_clusters",
+                8,
+                wgpu::TextureFormat::Rg32Uint,
+            ),
+            indices: LightDataArray::new(
+                device,
+                storage,
+                "light_indices",
+                4,
+                wgpu::TextureFormat::R32Uint,
+            ),
+        }
+    }
+
+    /// Bind group entries for group 0 bindings 1-4
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 4] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: self.params_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: self.lights.binding_resource(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: self.clusters.binding_resource(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: self.indices.binding_resource(),
+            },
+        ]
+    }
+
+    /// Assign `lights` to clusters for the current view and upload everything.
+    /// `shadow_layers` holds each light's first shadow map layer, if it has one.
+    /// Returns true if a GPU resource was reallocated and the bind group must be rebuilt.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        lights: &[Light],
+        shadow_layers: &[Option<u32>],
+        cluster_view: &ClusterView,
+    ) -> bool {
+        let ClusterView {
+            camera,
+            projection,
+            screen_size,
+        } = *cluster_view;
+        let view = camera.calc_matrix();
+        let near = projection.znear;
+        let far = projection.zfar;
+        let log_depth_ratio = (far / near).ln();
+        let slice_depth = |slice: u32| near * (far / near).powf(slice as f32 / CLUSTERS_Z as f32);
+        let slice_at = |depth: f32| {
+            ((depth.max(near).ln() - near.ln()) / log_depth_ratio * CLUSTERS_Z as f32)
+                .clamp(0.0, (CLUSTERS_Z - 1) as f32) as u32
+        };
+        let tan_half_y = (projection.fovy.0 * 0.5).tan();
+        let tan_half_x = tan_half_y * projection.aspect;
+
+        let mut global = Vec::new();
+        let mut cluster_lists: Vec<Vec<u32>> = vec![Vec::new(); NUM_CLUSTERS];
+
+        for (i, light) in lights.iter().enumerate() {
+            if light.kind == LightKind::Directional || light.range <= 0.0 {
+                global.push(i as u32);
+                continue;
+            }
+
+            // View space looks down -z; depth is distance in front of the camera
+            let center = view.transform_point(Point3::from(light.position)).to_vec();
+            let radius = light.range;
+            let depth = -center.z;
+            if depth + radius < near || depth - radius > far {
+                continue;
+            }
+
+            for slice in slice_at(depth - radius)..=slice_at(depth + radius) {
+                let (d0, d1) = (slice_depth(slice), slice_depth(slice + 1));
+                for tile_y in 0..CLUSTERS_Y {
+                    // Tile rows run top to bottom like framebuffer coordinates
+                    let ndc_y0 = 1.0 - 2.0 * (tile_y + 1) as f32 / CLUSTERS_Y as f32;
+                    let ndc_y1 = 1.0 - 2.0 * tile_y as f32 / CLUSTERS_Y as f32;
+                    let (min_y, max_y) = extent(ndc_y0 * tan_half_y, ndc_y1 * tan_half_y, d0, d1);
+                    for tile_x in 0..CLUSTERS_X {
+                        let ndc_x0 = -1.0 + 2.0 * tile_x as f32 / CLUSTERS_X as f32;
+                        let ndc_x1 = -1.0 + 2.0 * (tile_x + 1) as f32 / CLUSTERS_X as f32;
+                        let (min_x, max_x) =
+                            extent(ndc_x0 * tan_half_x, ndc_x1 * tan_half_x, d0, d1);
+
+                        // Squared distance from the sphere center to the cluster's AABB
+                        let dx = (min_x - center.x).max(0.0).max(center.x - max_x);
+                        let dy = (min_y - center.y).max(0.0).max(center.y - max_y);
+                        let dz = (-d1 - center.z).max(0.0).max(center.z + d0);
+                        if dx * dx + dy * dy + dz * dz <= radius * radius {
+                            let cluster =
+                                tile_x + tile_y * CLUSTERS_X + slice * CLUSTERS_X * CLUSTERS_Y;
+                            cluster_lists[cluster as usize].push(i as u32);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut indices = global.clone();
+        let mut clusters = Vec::with_capacity(NUM_CLUSTERS);
+        for list in &cluster_lists {
+            clusters.push([indices.len() as u32, list.len() as u32]);
+            indices.extend_from_slice(list);
+        }
+        // Never upload an empty array
+        if indices.is_empty() {
+            indices.push(0);
+        }
+
+        let gpu_lights: Vec<LightGpu> = if lights.is_empty() {
+            vec![LightGpu::new(&Light::default(), None)]
+        } else {
+            lights
+                .iter()
+                .enumerate()
+                .map(|(i, light)| LightGpu::new(light, shadow_layers.get(i).copied().flatten()))
+                .collect()
+        };
+
+        let params = LightClusterParams {
+            grid: [CLUSTERS_X, CLUSTERS_Y, CLUSTERS_Z, global.len() as u32],
+            screen_size: [screen_size.0.max(1) as f32, screen_size.1.max(1) as f32],
+            z_scale: CLUSTERS_Z as f32 / log_depth_ratio,
+            z_bias: -(CLUSTERS_Z as f32) * near.ln() / log_depth_ratio,
+            num_lights: lights.len() as u32,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let storage = self.storage;
+        let mut reallocated =
+            self.lights
+                .write(device, queue, storage, bytemuck::cast_slice(&gpu_lights));
+        reallocated |= self
+            .clusters
+            .write(device, queue, storage, bytemuck::cast_slice(&clusters));
+        reallocated |= self
+            .indices
+            .write(device, queue, storage, bytemuck::cast_slice(&indices));
+        reallocated
+    }
+}
+
+/// View-space extent along one axis of a tile spanning `[slope0, slope1]` (coordinate
+/// per unit depth) between depths `d0` and `d1`
+fn extent(slope0: f32, slope1: f32, d0: f32, d1: f32) -> (f32, f32) {
+    let candidates = [slope0 * d0, slope0 * d1, slope1 * d0, slope1 * d1];
+    let min = candidates.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = candidates.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    (min, max)
+}
@@ -0,0 +1,341 @@
+use crate::particle_simulation::SimulationBackend;
+use crate::particle_system::InstanceRaw;
+use cgmath::{MetricSpace, Point3};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use wgpu::util::{DeviceExt, DrawIndexedIndirectArgs};
+
+const BUCKETING_SHADER: &str = include_str!("shaders/lod_bucketing.wgsl");
+
+/// Most levels a system can draw, counting its own mesh. Matches `distances` in
+/// lod_bucketing.wgsl.
+pub const MAX_LOD_LEVELS: usize = 8;
+/// Matches `@workgroup_size` in lod_bucketing.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+/// Bytes of one level's indirect draw
+const DRAW_ARGS_SIZE: usize = std::mem::size_of::<DrawIndexedIndirectArgs>();
+
+/// A coarser mesh drawn in place of a particle system's own for instances at least
+/// `distance` from the eye
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LodLevel {
+    pub model: String,
+    #[serde(default)]
+    pub mesh_index: usize,
+    pub distance: f32,
+}
+
+/// Level drawn for an instance at `position`: 0 for the system's own mesh, otherwise
+/// one past the farthest of `levels` whose switch distance it has reached
+pub fn lod_for(levels: &[LodLevel], eye: Point3<f32>, position: Point3<f32>) -> usize {
+    let distance2 = eye.distance2(position);
+    levels
+        .iter()
+        .rposition(|level| distance2 >= level.distance * level.distance)
+        .map_or(0, |index| index + 1)
+}
+
+/// Which of `offset_count` animation time offsets instance `index` of `instance_count`
+/// plays at. Instances are given offsets in equal runs. Matches lod_bucketing.wgsl.
+pub fn animation_offset_for(index: usize, instance_count: usize, offset_count: usize) -> usize {
+    (index as u64 * offset_count as u64 / instance_count.max(1) as u64) as usize
+}
+
+/// The run of instances `animation_offset_for` gives `offset`
+pub fn animation_offset_range(
+    offset: usize,
+    instance_count: usize,
+    offset_count: usize,
+) -> Range<usize> {
+    let start = |offset: usize| {
+        (offset as u64 * instance_count as u64).div_ceil(offset_count.max(1) as u64) as usize
+    };
+    start(offset)..start(offset + 1)
+}
+
+/// Matches `LodParams` in lod_bucketing.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LodUniform {
+    eye: [f32; 3],
+    instance_count: u32,
+    bucket_capacity: u32,
+    level_count: u32,
+    offset_count: u32,
+    part_count: u32,
+    distances: [f32; MAX_LOD_LEVELS],
+}
+
+/// How a system's instances are split into buckets: one per LOD level and animation
+/// time offset, level-major
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketLayout {
+    /// Instances in the buffer being bucketed
+    pub capacity: usize,
+    pub level_count: usize,
+    pub offset_count: usize,
+}
+
+impl BucketLayout {
+    fn clamped(self) -> Self {
+        Self {
+            capacity: self.capacity.max(1),
+            level_count: self.level_count.clamp(1, MAX_LOD_LEVELS),
+            offset_count: self.offset_count.max(1),
+        }
+    }
+
+    pub fn bucket_count(&self) -> usize {
+        self.level_count * self.offset_count
+    }
+
+    /// Most instances one bucket can receive: every instance of one time offset
+    fn bucket_capacity(&self) -> usize {
+        self.capacity.div_ceil(self.offset_count)
+    }
+}
+
+/// The bucketing compute pipeline shared by every system, when the backend has one
+pub struct LodBucketer {
+    compute: Option<(wgpu::BindGroupLayout, wgpu::ComputePipeline)>,
+}
+
+impl LodBucketer {
+    pub fn new(device: &wgpu::Device, backend: SimulationBackend) -> Self {
+        if backend == SimulationBackend::Cpu {
+            return Self { compute: None };
+        }
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("LOD Bucketing Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, false),
+                storage(3, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("LOD Bucketing Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("LOD Bucketing Shader"),
+            source: wgpu::ShaderSource::Wgsl(BUCKETING_SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("LOD Bucketing Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Self {
+            compute: Some((layout, pipeline)),
+        }
+    }
+
+    pub fn has_compute(&self) -> bool {
+        self.compute.is_some()
+    }
+}
+
+/// Per-bucket copies of a GPU-resident instance buffer, filled by the bucketing shader
+/// and drawn indirectly
+pub struct LodBuckets {
+    layout: BucketLayout,
+    /// Meshes drawn with the same instances, each needing its own index counts
+    part_count: usize,
+    uniform_buffer: wgpu::Buffer,
+    /// One region of `bucket_capacity` instances per bucket
+    bucket_buffer: wgpu::Buffer,
+    /// One `DrawIndexedIndirectArgs` per bucket of each part. The shader counts every
+    /// instance into each part's.
+    draw_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl LodBuckets {
+    /// Buckets for the instances read from `instance_buffer`, which must allow storage
+    /// use, drawn with `part_count` meshes. Panics without a compute pipeline.
+    pub fn new(
+        device: &wgpu::Device,
+        bucketer: &LodBucketer,
+        name: &str,
+        layout: BucketLayout,
+        part_count: usize,
+        instance_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let (bind_group_layout, _) = bucketer
+            .compute
+            .as_ref()
+            .expect("LOD buckets need the compute backend");
+        let layout = layout.clamped();
+        let part_count = part_count.max(1);
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("Particle System '{}' LOD Params", name)),
+            size: std::mem::size_of::<LodUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bucket_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("Particle System '{}' LOD Buckets", name)),
+            size: (layout.bucket_count()
+                * layout.bucket_capacity()
+                * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let draw_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("Particle System '{}' LOD Draws", name)),
+            contents: &vec![0; part_count * layout.bucket_count() * DRAW_ARGS_SIZE],
+            usage: wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("Particle System '{}' LOD Bind Group", name)),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instance_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: bucket_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: draw_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            layout,
+            part_count,
+            uniform_buffer,
+            bucket_buffer,
+            draw_buffer,
+            bind_group,
+        }
+    }
+
+    pub fn matches(&self, layout: BucketLayout, part_count: usize) -> bool {
+        self.layout == layout.clamped() && self.part_count == part_count.max(1)
+    }
+
+    /// Record the bucketing of every instance into `encoder`. `index_counts` holds the
+    /// index count of each part's mesh at each level.
+    pub fn bucket(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        bucketer: &LodBucketer,
+        levels: &[LodLevel],
+        eye: Point3<f32>,
+        index_counts: &[Vec<u32>],
+    ) {
+        let Some((_, pipeline)) = &bucketer.compute else {
+            return;
+        };
+
+        let mut distances = [0.0; MAX_LOD_LEVELS];
+        for (distance, level) in distances.iter_mut().zip(levels) {
+            *distance = level.distance * level.distance;
+        }
+        let uniform = LodUniform {
+            eye: eye.into(),
+            instance_count: self.layout.capacity as u32,
+            bucket_capacity: self.layout.bucket_capacity() as u32,
+            level_count: self.layout.level_count as u32,
+            offset_count: self.layout.offset_count as u32,
+            part_count: self.part_count as u32,
+            distances,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        // Counts start from zero each frame; the shader adds every instance it places
+        let bucket_count = self.layout.bucket_count();
+        let offset_count = self.layout.offset_count;
+        let draws: Vec<u8> = (0..self.part_count)
+            .flat_map(|part| (0..bucket_count).map(move |bucket| (part, bucket)))
+            .flat_map(|(part, bucket)| {
+                DrawIndexedIndirectArgs {
+                    index_count: index_counts
+                        .get(part)
+                        .and_then(|counts| counts.get(bucket / offset_count))
+                        .copied()
+                        .unwrap_or(0),
+                    instance_count: 0,
+                    first_index: 0,
+                    base_vertex: 0,
+                    first_instance: 0,
+                }
+                .as_bytes()
+                .to_vec()
+            })
+            .collect();
+        queue.write_buffer(&self.draw_buffer, 0, &draws);
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("LOD Bucketing Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.dispatch_workgroups(uniform.instance_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+
+    fn draw_offset(&self, part: usize, bucket: usize) -> wgpu::BufferAddress {
+        ((part.min(self.part_count - 1) * self.layout.bucket_count() + bucket) * DRAW_ARGS_SIZE)
+            as wgpu::BufferAddress
+    }
+
+    pub fn layout(&self) -> BucketLayout {
+        self.layout
+    }
+
+    /// Instances of `bucket`'s region, for binding as the instance vertex buffer
+    pub fn bucket_instances(&self, bucket: usize) -> wgpu::BufferSlice<'_> {
+        let size = (self.layout.bucket_capacity() * std::mem::size_of::<InstanceRaw>())
+            as wgpu::BufferAddress;
+        let start = bucket as wgpu::BufferAddress * size;
+        self.bucket_buffer.slice(start..start + size)
+    }
+
+    /// Buffer and offset of `part`'s indirect draw for `bucket`
+    pub fn bucket_draw(&self, part: usize, bucket: usize) -> (&wgpu::Buffer, wgpu::BufferAddress) {
+        (&self.draw_buffer, self.draw_offset(part, bucket))
+    }
+}
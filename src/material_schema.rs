@@ -0,0 +1,416 @@
+use anyhow::{Context, anyhow};
+use std::collections::HashMap;
+use std::sync::Arc;
+use wgpu::naga;
+
+/// Bind group index that holds per-material resources in every material shader
+pub const MATERIAL_GROUP: u32 = 1;
+
+/// A typed module constant `DEFAULT_ROUGHNESS` gives the uniform field `roughness` the
+/// value materials that don't set it get. Fields without one default to zero.
+const DEFAULT_PREFIX: &str = "DEFAULT_";
+
+/// A texture binding in the material group, keyed by slot name.
+/// `t_diffuse` becomes the slot "diffuse"; a sampler named `s_diffuse` is paired with it.
+#[derive(Debug, Clone)]
+pub struct TextureSlot {
+    pub name: String,
+    pub binding: u32,
+    pub view_dimension: wgpu::TextureViewDimension,
+    pub sample_type: wgpu::TextureSampleType,
+}
+
+impl TextureSlot {
+    /// Whether the 2D color textures in the texture registry can be bound here. Other
+    /// slots are only ever filled with a blank texture of their own kind.
+    pub fn takes_images(&self) -> bool {
+        self.view_dimension == wgpu::TextureViewDimension::D2
+            && matches!(self.sample_type, wgpu::TextureSampleType::Float { .. })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SamplerSlot {
+    pub name: String,
+    pub binding: u32,
+    /// Comparison samplers as declared; otherwise non-filtering when the paired texture
+    /// can't be filtered (integer or depth textures)
+    pub binding_type: wgpu::SamplerBindingType,
+    /// Texture slot whose sampler is bound here, if the names pair up
+    pub texture_slot: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UniformType {
+    F32,
+    I32,
+    U32,
+    Vec2,
+    Vec3,
+    Vec4,
+    /// Matrices, arrays and nested structs - laid out but not editable
+    Opaque,
+}
+
+impl UniformType {
+    /// Number of scalar components stored for this field
+    pub fn components(&self) -> usize {
+        match self {
+            UniformType::F32 | UniformType::I32 | UniformType::U32 => 1,
+            UniformType::Vec2 => 2,
+            UniformType::Vec3 => 3,
+            UniformType::Vec4 => 4,
+            UniformType::Opaque => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UniformField {
+    pub name: String,
+    pub offset: u32,
+    pub ty: UniformType,
+    /// Value used when a material doesn't set the field, from the shader's `DEFAULT_` constant
+    pub default: [f32; 4],
+}
+
+#[derive(Debug, Clone)]
+pub struct UniformBlock {
+    pub name: String,
+    pub binding: u32,
+    pub size: u32,
+    pub fields: Vec<UniformField>,
+}
+
+/// Values for uniform fields, keyed by field name.
+/// Every value is stored as 4 floats; scalars use the first component.
+pub type UniformValues = HashMap<String, [f32; 4]>;
+
+/// What a shader expects in its material bind group, reflected from its WGSL source
+#[derive(Debug, Clone, Default)]
+pub struct MaterialSchema {
+    pub textures: Vec<TextureSlot>,
+    pub samplers: Vec<SamplerSlot>,
+    pub uniforms: Vec<UniformBlock>,
+    /// Names of the pipeline-overridable constants the shader declares
+    pub overrides: Vec<String>,
+}
+
+impl MaterialSchema {
+    /// Parse and validate WGSL, then collect every resource bound in the material group
+    pub fn reflect(source: &str) -> anyhow::Result<Self> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| anyhow!("{}", e.emit_to_string(source)))?;
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|e| anyhow!("{}", e.emit_to_string(source)))?;
+        Self::from_module(&module)
+    }
+
+    pub fn from_module(module: &naga::Module) -> anyhow::Result<Self> {
+        let mut schema = Self::default();
+
+        for (_, var) in module.global_variables.iter() {
+            let Some(binding) = &var.binding else {
+                continue;
+            };
+            if binding.group != MATERIAL_GROUP {
+                continue;
+            }
+            let var_name = var.name.clone().unwrap_or_default();
+
+            match &module.types[var.ty].inner {
+                naga::TypeInner::Image {
+                    dim,
+                    arrayed,
+                    class,
+                } => {
+                    let view_dimension = match (dim, arrayed) {
+                        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+                        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+                        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+                    };
+                    let sample_type = match class {
+                        naga::ImageClass::Sampled { kind, .. } => match kind {
+                            naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                            naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                            _ => wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        naga::ImageClass::Depth { .. } => wgpu::TextureSampleType::Depth,
+                        naga::ImageClass::Storage { .. } => {
+                            return Err(anyhow!(
+                                "storage texture '{}' is not supported in material group",
+                                var_name
+                            ));
+                        }
+                    };
+                    schema.textures.push(TextureSlot {
+                        name: slot_name(&var_name, "t_"),
+                        binding: binding.binding,
+                        view_dimension,
+                        sample_type,
+                    });
+                }
+                naga::TypeInner::Sampler { comparison } => {
+                    schema.samplers.push(SamplerSlot {
+                        name: var_name.clone(),
+                        binding: binding.binding,
+                        binding_type: if *comparison {
+                            wgpu::SamplerBindingType::Comparison
+                        } else {
+                            wgpu::SamplerBindingType::Filtering
+                        },
+                        texture_slot: None,
+                    });
+                }
+                naga::TypeInner::Struct { members, span }
+                    if var.space == naga::AddressSpace::Uniform =>
+                {
+                    let fields = members
+                        .iter()
+                        .map(|member| {
+                            let name = member.name.clone().unwrap_or_default();
+                            let default = default_constant(module, &name).unwrap_or([0.0; 4]);
+                            UniformField {
+                                name,
+                                offset: member.offset,
+                                ty: uniform_type(&module.types[member.ty].inner),
+                                default,
+                            }
+                        })
+                        .collect();
+                    schema.uniforms.push(UniformBlock {
+                        name: var_name,
+                        binding: binding.binding,
+                        size: *span,
+                        fields,
+                    });
+                }
+                other => {
+                    return Err(anyhow!(
+                        "unsupported material binding '{}': {:?}",
+                        var_name,
+                        other
+                    ));
+                }
+            }
+        }
+
+        // Pair `s_foo` with `t_foo`, otherwise fall back to the first texture slot
+        let first_slot = schema.textures.first().map(|slot| slot.name.clone());
+        for sampler in &mut schema.samplers {
+            let name = slot_name(&sampler.name, "s_");
+            sampler.texture_slot = if schema.textures.iter().any(|slot| slot.name == name) {
+                Some(name)
+            } else {
+                first_slot.clone()
+            };
+            let filterable = schema
+                .textures
+                .iter()
+                .find(|slot| Some(&slot.name) == sampler.texture_slot.as_ref())
+                .is_none_or(|slot| {
+                    matches!(
+                        slot.sample_type,
+                        wgpu::TextureSampleType::Float { filterable: true }
+                    )
+                });
+            if sampler.binding_type == wgpu::SamplerBindingType::Filtering && !filterable {
+                sampler.binding_type = wgpu::SamplerBindingType::NonFiltering;
+            }
+        }
+
+        schema.textures.sort_by_key(|slot| slot.binding);
+        schema.samplers.sort_by_key(|slot| slot.binding);
+        schema.uniforms.sort_by_key(|block| block.binding);
+        schema.overrides = module
+            .overrides
+            .iter()
+            .filter_map(|(_, constant)| constant.name.clone())
+            .collect();
+
+        Ok(schema)
+    }
+
+    pub fn create_bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+    ) -> wgpu::BindGroupLayout {
+        let visibility = wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT;
+        let mut entries = Vec::new();
+
+        for slot in &self.textures {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: slot.binding,
+                visibility,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: slot.view_dimension,
+                    sample_type: slot.sample_type,
+                },
+                count: None,
+            });
+        }
+        for sampler in &self.samplers {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: sampler.binding,
+                visibility,
+                ty: wgpu::BindingType::Sampler(sampler.binding_type),
+                count: None,
+            });
+        }
+        for block in &self.uniforms {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: block.binding,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(block.size as u64),
+                },
+                count: None,
+            });
+        }
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &entries,
+        })
+    }
+
+    /// Editable uniform fields across all blocks, in declaration order
+    pub fn fields(&self) -> impl Iterator<Item = &UniformField> {
+        self.uniforms
+            .iter()
+            .flat_map(|block| block.fields.iter())
+            .filter(|field| field.ty != UniformType::Opaque)
+    }
+
+    pub fn has_texture_slot(&self, name: &str) -> bool {
+        self.textures.iter().any(|slot| slot.name == name)
+    }
+
+    /// Lay out `values` into the byte representation of `block`
+    pub fn pack(block: &UniformBlock, values: &UniformValues) -> Vec<u8> {
+        let mut bytes = vec![0u8; block.size as usize];
+        for field in &block.fields {
+            let value = values.get(&field.name).copied().unwrap_or(field.default);
+            let offset = field.offset as usize;
+            for (i, component) in value.iter().take(field.ty.components()).enumerate() {
+                let word = match field.ty {
+                    UniformType::I32 => (*component as i32).to_ne_bytes(),
+                    UniformType::U32 => (*component as u32).to_ne_bytes(),
+                    _ => component.to_ne_bytes(),
+                };
+                bytes[offset + i * 4..offset + i * 4 + 4].copy_from_slice(&word);
+            }
+        }
+        bytes
+    }
+}
+
+/// A shader's reflected material schema plus the bind group layout built from it.
+/// Cheap to clone so it can be handed to background model loads.
+#[derive(Clone)]
+pub struct MaterialLayout {
+    pub schema: Arc<MaterialSchema>,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl MaterialLayout {
+    pub fn new(device: &wgpu::Device, shader: &str, source: &str) -> anyhow::Result<Self> {
+        let schema = MaterialSchema::reflect(source)
+            .with_context(|| format!("Failed to reflect shader '{}'", shader))?;
+        let bind_group_layout =
+            schema.create_bind_group_layout(device, &format!("{} material layout", shader));
+        Ok(Self {
+            schema: Arc::new(schema),
+            bind_group_layout,
+        })
+    }
+}
+
+fn slot_name(var_name: &str, prefix: &str) -> String {
+    var_name
+        .strip_prefix(prefix)
+        .unwrap_or(var_name)
+        .to_string()
+}
+
+/// The `DEFAULT_` constant for uniform field `field`, if the shader declares one
+fn default_constant(module: &naga::Module, field: &str) -> Option<[f32; 4]> {
+    let name = format!("{}{}", DEFAULT_PREFIX, field.to_uppercase());
+    let (_, constant) = module
+        .constants
+        .iter()
+        .find(|(_, constant)| constant.name.as_deref() == Some(name.as_str()))?;
+    let mut components = Vec::new();
+    constant_components(module, constant.init, &mut components)?;
+    let mut value = [0.0; 4];
+    for (component, scalar) in value.iter_mut().zip(components) {
+        *component = scalar;
+    }
+    Some(value)
+}
+
+/// Flatten a constant expression's scalars, None for anything but numbers and vectors
+fn constant_components(
+    module: &naga::Module,
+    expression: naga::Handle<naga::Expression>,
+    components: &mut Vec<f32>,
+) -> Option<()> {
+    match &module.global_expressions[expression] {
+        naga::Expression::Literal(literal) => components.push(match *literal {
+            naga::Literal::F32(value) => value,
+            naga::Literal::I32(value) => value as f32,
+            naga::Literal::U32(value) => value as f32,
+            _ => return None,
+        }),
+        naga::Expression::ZeroValue(ty) => {
+            components.extend(std::iter::repeat_n(
+                0.0,
+                uniform_type(&module.types[*ty].inner).components(),
+            ));
+        }
+        naga::Expression::Splat { size, value } => {
+            for _ in 0..*size as usize {
+                constant_components(module, *value, components)?;
+            }
+        }
+        naga::Expression::Compose {
+            components: parts, ..
+        } => {
+            for part in parts {
+                constant_components(module, *part, components)?;
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+fn uniform_type(inner: &naga::TypeInner) -> UniformType {
+    match inner {
+        naga::TypeInner::Scalar(scalar) => match scalar.kind {
+            naga::ScalarKind::Float => UniformType::F32,
+            naga::ScalarKind::Sint => UniformType::I32,
+            naga::ScalarKind::Uint => UniformType::U32,
+            _ => UniformType::Opaque,
+        },
+        naga::TypeInner::Vector { size, scalar } if scalar.kind == naga::ScalarKind::Float => {
+            match size {
+                naga::VectorSize::Bi => UniformType::Vec2,
+                naga::VectorSize::Tri => UniformType::Vec3,
+                naga::VectorSize::Quad => UniformType::Vec4,
+            }
+        }
+        _ => UniformType::Opaque,
+    }
+}
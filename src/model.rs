@@ -1,12 +1,18 @@
 use crate::{
+    animation::{AnimationClip, Skeleton, Skin},
+    culling::Aabb,
+    material_schema::{MaterialLayout, MaterialSchema, UniformValues},
     resources::{load_binary, load_string},
-    texture::GpuTexture,
+    texture::{ColorSpace, GpuTexture, SamplerSettings},
 };
+use serde::{Deserialize, Serialize};
 use std::{
     cell::RefCell,
+    collections::HashMap,
     io::{BufReader, Cursor},
     ops::Range,
-    sync::Arc,
+    rc::Rc,
+    sync::{Arc, Mutex},
 };
 use wgpu::util::DeviceExt;
 
@@ -72,6 +78,8 @@ pub struct ModelVertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
+    pub tangent: [f32; 3],
+    pub bitangent: [f32; 3],
 }
 
 impl Vertex for ModelVertex {
@@ -98,6 +106,16 @@ impl Vertex for ModelVertex {
                     shader_location: 2,
                     format: VertexFormat::Float32x3,
                 },
+                VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 8]>() as BufferAddress,
+                    shader_location: 3,
+                    format: VertexFormat::Float32x3,
+                },
+                VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 11]>() as BufferAddress,
+                    shader_location: 4,
+                    format: VertexFormat::Float32x3,
+                },
             ],
         }
     }
@@ -107,18 +125,139 @@ pub struct Model {
     pub name: String,
     pub meshes: Vec<Mesh>,
     pub material_keys: Vec<MaterialSource>,
+    /// Node hierarchy skins and clips refer to; empty for formats without one
+    pub skeleton: Skeleton,
+    pub skins: Vec<Skin>,
+    pub animations: Vec<AnimationClip>,
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct MaterialProperties {
-    pub color: [f32; 4],
+/// Textures by path and the color space they're sampled in. A path used by both color
+/// and data slots is registered once per color space.
+pub type TextureRegistry = Arc<Mutex<HashMap<(String, ColorSpace), Arc<GpuTexture>>>>;
+
+/// Materials by source. Shared with `Rc`, as their uniforms are edited in place
+/// through a `RefCell`.
+pub type MaterialRegistry = HashMap<MaterialSource, Rc<GpuMaterial>>;
+
+/// Textures bound to slots a material leaves unset
+#[derive(Clone)]
+pub struct FallbackTextures {
+    pub white: Arc<GpuTexture>,
+    /// The default texture for data slots
+    pub linear_white: Arc<GpuTexture>,
+    /// Straight-up tangent-space normal, so unmapped materials shade with the vertex normal
+    pub flat_normal: Arc<GpuTexture>,
 }
 
-impl Default for MaterialProperties {
-    fn default() -> Self {
-        Self {
-            color: [1.0, 1.0, 1.0, 1.0], // Default to white (no tint)
+impl FallbackTextures {
+    /// Load the default texture in both color spaces and generate the flat normal map,
+    /// registering all three
+    pub async fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture_registry: &TextureRegistry,
+    ) -> anyhow::Result<Self> {
+        let white = load_texture(
+            crate::defaults::DEFAULT_TEXTURE_PATH,
+            ColorSpace::Srgb,
+            device,
+            queue,
+            texture_registry,
+        )
+        .await?;
+        let linear_white = load_texture(
+            crate::defaults::DEFAULT_TEXTURE_PATH,
+            ColorSpace::Linear,
+            device,
+            queue,
+            texture_registry,
+        )
+        .await?;
+
+        let flat_normal_image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            1,
+            1,
+            image::Rgba([128, 128, 255, 255]),
+        ));
+        let flat_normal = Arc::new(GpuTexture::from_image(
+            device,
+            queue,
+            &flat_normal_image,
+            Some(crate::defaults::FLAT_NORMAL_TEXTURE_PATH),
+            ColorSpace::Linear,
+        )?);
+        texture_registry.lock().unwrap().insert(
+            (
+                crate::defaults::FLAT_NORMAL_TEXTURE_PATH.to_string(),
+                ColorSpace::Linear,
+            ),
+            Arc::clone(&flat_normal),
+        );
+
+        Ok(Self {
+            white,
+            linear_white,
+            flat_normal,
+        })
+    }
+
+    pub fn for_slot(&self, slot: &str) -> &Arc<GpuTexture> {
+        if slot == NORMAL_SLOT {
+            return &self.flat_normal;
+        }
+        match slot_color_space(slot) {
+            ColorSpace::Srgb => &self.white,
+            ColorSpace::Linear => &self.linear_white,
+        }
+    }
+}
+
+/// Texture slot names the model loaders fill in when the shader declares them
+pub const DIFFUSE_SLOT: &str = "diffuse";
+pub const NORMAL_SLOT: &str = "normal";
+pub const METALLIC_SLOT: &str = "metallic";
+pub const ROUGHNESS_SLOT: &str = "roughness";
+pub const EMISSIVE_SLOT: &str = "emissive";
+pub const OCCLUSION_SLOT: &str = "occlusion";
+
+/// How textures bound to `slot` are sampled. The known data slots are linear; any other
+/// slot, including those of custom shaders, is taken to hold colors.
+pub fn slot_color_space(slot: &str) -> ColorSpace {
+    match slot {
+        NORMAL_SLOT | METALLIC_SLOT | ROUGHNESS_SLOT | OCCLUSION_SLOT => ColorSpace::Linear,
+        _ => ColorSpace::Srgb,
+    }
+}
+
+/// How a material's alpha is used
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AlphaMode {
+    /// Alpha is ignored
+    #[default]
+    Opaque,
+    /// Fragments with alpha below the material's `alpha_cutoff` are discarded
+    Mask,
+    /// Blended over what's behind, drawn after opaque geometry from back to front
+    Blend,
+}
+
+impl AlphaMode {
+    pub const ALL: [AlphaMode; 3] = [AlphaMode::Opaque, AlphaMode::Mask, AlphaMode::Blend];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AlphaMode::Opaque => "Opaque",
+            AlphaMode::Mask => "Mask",
+            AlphaMode::Blend => "Blend",
+        }
+    }
+
+    /// Value of the ALPHA_MODE override, matching the ALPHA_* constants in shader.wgsl
+    pub fn gpu_id(&self) -> u32 {
+        match self {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask => 1,
+            AlphaMode::Blend => 2,
         }
     }
 }
@@ -128,19 +267,329 @@ impl Default for MaterialProperties {
 #[derive(Debug, Clone)]
 pub struct MaterialDesc {
     pub name: String,
-    pub texture_path: String,
-    pub properties: RefCell<MaterialProperties>,
+    /// Shader this material renders with (e.g. "shader.wgsl")
+    pub shader: String,
+    /// Texture slot name (from the shader's schema) -> texture registry path
+    pub textures: HashMap<String, String>,
+    /// Uniform field name (from the shader's schema) -> value
+    pub uniforms: RefCell<UniformValues>,
+    pub alpha_mode: AlphaMode,
+    /// Texture slot name -> sampler replacing the texture's own
+    pub samplers: HashMap<String, SamplerSettings>,
 }
 
-/// GPU realization of a material
+/// GPU realization of a material, laid out according to its shader's schema
 #[allow(dead_code)]
 pub struct GpuMaterial {
     pub desc: MaterialDesc,
-    pub diffuse_texture: Arc<GpuTexture>,
-    pub properties_buffer: wgpu::Buffer,
+    pub schema: Arc<MaterialSchema>,
+    /// Bound texture per slot, with fallback textures filling unset slots
+    pub textures: HashMap<String, Arc<GpuTexture>>,
+    /// One buffer per uniform block in the schema, in binding order
+    pub uniform_buffers: Vec<wgpu::Buffer>,
     pub bind_group: wgpu::BindGroup,
 }
 
+impl GpuMaterial {
+    pub fn new(
+        device: &wgpu::Device,
+        desc: MaterialDesc,
+        layout: &MaterialLayout,
+        mut textures: HashMap<String, Arc<GpuTexture>>,
+        fallbacks: &FallbackTextures,
+    ) -> Self {
+        let schema = Arc::clone(&layout.schema);
+
+        for slot in &schema.textures {
+            if !slot.takes_images() {
+                let label = format!("{}_{}_blank", desc.name, slot.name);
+                let blank =
+                    GpuTexture::blank(device, slot.view_dimension, slot.sample_type, &label);
+                textures.insert(slot.name.clone(), Arc::new(blank));
+                continue;
+            }
+            textures
+                .entry(slot.name.clone())
+                .or_insert_with(|| Arc::clone(fallbacks.for_slot(&slot.name)));
+        }
+
+        let uniform_buffers: Vec<wgpu::Buffer> = schema
+            .uniforms
+            .iter()
+            .map(|block| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{}_{}", desc.name, block.name)),
+                    contents: &MaterialSchema::pack(block, &desc.uniforms.borrow()),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                })
+            })
+            .collect();
+
+        let sampler_overrides: HashMap<String, wgpu::Sampler> = desc
+            .samplers
+            .iter()
+            .map(|(slot, settings)| {
+                let label = format!("{}_{}_sampler", desc.name, slot);
+                (slot.clone(), settings.create_sampler(device, Some(&label)))
+            })
+            .collect();
+        // Integer and depth textures are bound with non-filtering samplers, which mustn't filter
+        let unfiltered_samplers: HashMap<u32, wgpu::Sampler> = schema
+            .samplers
+            .iter()
+            .filter(|sampler| sampler.binding_type == wgpu::SamplerBindingType::NonFiltering)
+            .map(|sampler| {
+                let settings = sampler
+                    .texture_slot
+                    .as_ref()
+                    .and_then(|slot| desc.samplers.get(slot))
+                    .copied()
+                    .unwrap_or_default();
+                let label = format!("{}_{}_sampler", desc.name, sampler.name);
+                let unfiltered = settings.unfiltered().create_sampler(device, Some(&label));
+                (sampler.binding, unfiltered)
+            })
+            .collect();
+
+        let mut entries = Vec::new();
+        for slot in &schema.textures {
+            entries.push(wgpu::BindGroupEntry {
+                binding: slot.binding,
+                resource: wgpu::BindingResource::TextureView(&textures[&slot.name].view),
+            });
+        }
+        for sampler in &schema.samplers {
+            let resource = unfiltered_samplers
+                .get(&sampler.binding)
+                .unwrap_or_else(|| match &sampler.texture_slot {
+                    Some(slot) => sampler_overrides
+                        .get(slot)
+                        .unwrap_or(&textures[slot].sampler),
+                    None => &fallbacks.white.sampler,
+                });
+            entries.push(wgpu::BindGroupEntry {
+                binding: sampler.binding,
+                resource: wgpu::BindingResource::Sampler(resource),
+            });
+        }
+        for (block, buffer) in schema.uniforms.iter().zip(&uniform_buffers) {
+            entries.push(wgpu::BindGroupEntry {
+                binding: block.binding,
+                resource: buffer.as_entire_binding(),
+            });
+        }
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{}_bind_group", desc.name)),
+            layout: &layout.bind_group_layout,
+            entries: &entries,
+        });
+
+        Self {
+            desc,
+            schema,
+            textures,
+            uniform_buffers,
+            bind_group,
+        }
+    }
+
+    /// Whether a real normal map is bound rather than the flat fallback
+    pub fn has_normal_map(&self) -> bool {
+        self.schema.has_texture_slot(NORMAL_SLOT) && self.desc.textures.contains_key(NORMAL_SLOT)
+    }
+
+    /// Re-upload uniform values after `desc.uniforms` has been edited
+    pub fn write_uniforms(&self, queue: &wgpu::Queue) {
+        let values = self.desc.uniforms.borrow();
+        for (block, buffer) in self.schema.uniforms.iter().zip(&self.uniform_buffers) {
+            queue.write_buffer(buffer, 0, &MaterialSchema::pack(block, &values));
+        }
+    }
+}
+
+/// Fetch a texture in `color_space` from the registry, loading and registering it on
+/// first use
+pub async fn load_texture(
+    path: &str,
+    color_space: ColorSpace,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture_registry: &TextureRegistry,
+) -> anyhow::Result<Arc<GpuTexture>> {
+    let key = (path.to_string(), color_space);
+    if let Some(existing) = texture_registry.lock().unwrap().get(&key) {
+        return Ok(Arc::clone(existing));
+    }
+
+    let bytes = load_binary(path).await?;
+    let texture = Arc::new(GpuTexture::from_bytes(
+        device,
+        queue,
+        &bytes,
+        path,
+        color_space,
+    )?);
+    texture_registry
+        .lock()
+        .unwrap()
+        .insert(key, Arc::clone(&texture));
+    Ok(texture)
+}
+
+/// A registered texture in `color_space`, copied from the one registered in the other
+/// color space (and registered in turn) if that's all there is. None if `path` was never
+/// loaded.
+pub fn registered_texture(
+    path: &str,
+    color_space: ColorSpace,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture_registry: &TextureRegistry,
+) -> Option<Arc<GpuTexture>> {
+    let mut registry = texture_registry.lock().unwrap();
+    let key = (path.to_string(), color_space);
+    if let Some(existing) = registry.get(&key) {
+        return Some(Arc::clone(existing));
+    }
+    let other = match color_space {
+        ColorSpace::Srgb => ColorSpace::Linear,
+        ColorSpace::Linear => ColorSpace::Srgb,
+    };
+    let source = registry.get(&(path.to_string(), other))?;
+    let texture = Arc::new(source.with_color_space(device, queue, color_space));
+    registry.insert(key, Arc::clone(&texture));
+    Some(texture)
+}
+
+/// CPU copy of a mesh's triangles, for placing instances on its surface
+#[derive(Debug)]
+pub struct MeshSurface {
+    pub positions: Vec<[f32; 3]>,
+    /// Zero where the model has no normals
+    pub normals: Vec<[f32; 3]>,
+    /// Three per triangle
+    pub indices: Vec<u32>,
+}
+
+/// A vertex's joints (indices into its skin's joints) and their weights, summing to one
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinVertex {
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+}
+
+/// Joint influences of a mesh bound to a skin
+#[derive(Debug)]
+pub struct MeshSkin {
+    /// Index into its model's skins
+    pub skin: usize,
+    /// One per vertex
+    pub influences: Vec<SkinVertex>,
+    /// Bind-pose bounds of the vertices each joint moves, None where it moves none.
+    /// A posed mesh lies within these boxes moved by their joints.
+    pub joint_bounds: Vec<Option<Aabb>>,
+}
+
+impl MeshSkin {
+    /// Influences of `skin` on `vertices`, one per vertex
+    pub fn new(skin: usize, influences: Vec<SkinVertex>, vertices: &[ModelVertex]) -> Self {
+        let mut joint_points: Vec<Vec<cgmath::Point3<f32>>> = Vec::new();
+        for (vertex, influence) in vertices.iter().zip(&influences) {
+            for (&joint, &weight) in influence.joints.iter().zip(&influence.weights) {
+                if weight <= 0.0 {
+                    continue;
+                }
+                let joint = joint as usize;
+                if joint >= joint_points.len() {
+                    joint_points.resize_with(joint + 1, Vec::new);
+                }
+                joint_points[joint].push(cgmath::Point3::from(vertex.position));
+            }
+        }
+        Self {
+            skin,
+            influences,
+            joint_bounds: joint_points
+                .into_iter()
+                .map(|points| (!points.is_empty()).then(|| Aabb::from_points(points)))
+                .collect(),
+        }
+    }
+}
+
+/// One blend shape: offsets added to each vertex scaled by the target's weight
+#[derive(Debug, Default)]
+pub struct MorphTarget {
+    /// One per vertex, or none
+    pub positions: Vec<[f32; 3]>,
+    /// One per vertex, or none
+    pub normals: Vec<[f32; 3]>,
+}
+
+/// Blend shapes of a mesh
+#[derive(Debug)]
+pub struct MeshMorph {
+    pub targets: Vec<MorphTarget>,
+    /// Weights of each target when nothing sets them
+    pub weights: Vec<f32>,
+    /// Skeleton node whose posed weights drive the targets, if any
+    pub node: Option<usize>,
+    /// Bounds of each target's position offsets
+    pub delta_bounds: Vec<Aabb>,
+}
+
+impl MeshMorph {
+    pub fn new(targets: Vec<MorphTarget>, weights: Vec<f32>, node: Option<usize>) -> Self {
+        let delta_bounds = targets
+            .iter()
+            .map(|target| {
+                Aabb::from_points(
+                    target
+                        .positions
+                        .iter()
+                        .copied()
+                        .chain([[0.0; 3]])
+                        .map(cgmath::Point3::from),
+                )
+            })
+            .collect();
+        Self {
+            targets,
+            weights,
+            node,
+            delta_bounds,
+        }
+    }
+
+    /// Box every vertex's combined offset lies within for target `weights`
+    pub fn delta_extent(&self, weights: &[f32]) -> Aabb {
+        let mut extent = Aabb {
+            min: cgmath::Point3::new(0.0, 0.0, 0.0),
+            max: cgmath::Point3::new(0.0, 0.0, 0.0),
+        };
+        for (bounds, &weight) in self.delta_bounds.iter().zip(weights) {
+            let (low, high) = (bounds.min * weight, bounds.max * weight);
+            for axis in 0..3 {
+                extent.min[axis] += low[axis].min(high[axis]);
+                extent.max[axis] += low[axis].max(high[axis]);
+            }
+        }
+        extent
+    }
+}
+
+/// What posing a skinned or morphed mesh starts from
+#[derive(Debug)]
+pub struct MeshDeformation {
+    /// The mesh's undeformed vertices: in the bind pose, with no targets applied
+    pub bind_vertices: Vec<ModelVertex>,
+    pub bind_bounds: Aabb,
+    pub skin: Option<MeshSkin>,
+    pub morph: Option<MeshMorph>,
+}
+
 pub struct Mesh {
     pub name: String,
     pub vertex_buffer: wgpu::Buffer,
@@ -148,18 +597,131 @@ pub struct Mesh {
     pub num_elements: u32,
     pub vertex_count: u32,
     pub material_source: MaterialSource,
+    /// Object-space bounds of the vertices
+    pub bounds: Aabb,
+    pub surface: Arc<MeshSurface>,
+    /// Skin and blend shapes, for meshes posed before drawing
+    pub deformation: Option<Arc<MeshDeformation>>,
 }
 
+impl Mesh {
+    /// Upload an indexed triangle list, filling in its tangents, bounds and surface
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        vertices: Vec<ModelVertex>,
+        indices: Vec<u32>,
+        material_source: MaterialSource,
+    ) -> Self {
+        Self::new_deformed(device, name, vertices, indices, material_source, None, None)
+    }
+
+    /// Like `new`, for a mesh posed by a skin, blend shapes or both before drawing
+    pub fn new_deformed(
+        device: &wgpu::Device,
+        name: &str,
+        mut vertices: Vec<ModelVertex>,
+        indices: Vec<u32>,
+        material_source: MaterialSource,
+        skin: Option<MeshSkin>,
+        morph: Option<MeshMorph>,
+    ) -> Self {
+        compute_tangents(&mut vertices, &indices);
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Vertex Buffer", name)),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Index Buffer", name)),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let bounds = Aabb::from_points(
+            vertices
+                .iter()
+                .map(|vertex| cgmath::Point3::from(vertex.position)),
+        );
+        Self {
+            name: name.to_string(),
+            vertex_buffer,
+            index_buffer,
+            num_elements: indices.len() as u32,
+            vertex_count: vertices.len() as u32,
+            material_source,
+            bounds,
+            surface: Arc::new(MeshSurface {
+                positions: vertices.iter().map(|vertex| vertex.position).collect(),
+                normals: vertices.iter().map(|vertex| vertex.normal).collect(),
+                indices,
+            }),
+            deformation: (skin.is_some() || morph.is_some()).then(|| {
+                Arc::new(MeshDeformation {
+                    bind_bounds: bounds,
+                    bind_vertices: vertices,
+                    skin,
+                    morph,
+                })
+            }),
+        }
+    }
+}
+
+/// Load an OBJ (with its MTL materials) or a glTF/GLB model, chosen by extension
 pub async fn load_model(
     file_name: &str,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-    layout: &wgpu::BindGroupLayout,
-    texture_registry: &Arc<std::sync::Mutex<std::collections::HashMap<String, Arc<GpuTexture>>>>,
-) -> anyhow::Result<(
-    Model,
-    std::collections::HashMap<MaterialSource, GpuMaterial>,
-)> {
+    layout: &MaterialLayout,
+    fallbacks: &FallbackTextures,
+    texture_registry: &TextureRegistry,
+) -> anyhow::Result<(Model, HashMap<MaterialSource, GpuMaterial>)> {
+    let extension = file_name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("gltf" | "glb") => {
+            crate::gltf_model::load_gltf(
+                file_name,
+                device,
+                queue,
+                layout,
+                fallbacks,
+                texture_registry,
+            )
+            .await
+        }
+        _ => {
+            load_obj(
+                file_name,
+                device,
+                queue,
+                layout,
+                fallbacks,
+                texture_registry,
+            )
+            .await
+        }
+    }
+}
+
+/// Model name for a file path: the file name without its extension
+/// (e.g. "models/teapot.obj" -> "teapot")
+pub fn model_name(file_name: &str) -> &str {
+    let file = file_name.rsplit('/').next().unwrap_or(file_name);
+    file.rsplit_once('.').map_or(file, |(stem, _)| stem)
+}
+
+async fn load_obj(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &MaterialLayout,
+    fallbacks: &FallbackTextures,
+    texture_registry: &TextureRegistry,
+) -> anyhow::Result<(Model, HashMap<MaterialSource, GpuMaterial>)> {
     let obj_text = load_string(file_name).await?;
     let obj_cursor = Cursor::new(obj_text);
     let mut obj_reader = BufReader::new(obj_cursor);
@@ -178,14 +740,7 @@ pub async fn load_model(
     )
     .await?;
 
-    // Extract model name from file path (e.g., "teapot.obj" -> "teapot")
-    let model_name = file_name
-        .split('/')
-        .last()
-        .unwrap_or(file_name)
-        .trim_end_matches(".obj");
-
-    let mut materials_map = std::collections::HashMap::new();
+    let mut materials_map = HashMap::new();
     let mut material_sources = Vec::new();
 
     for mat in obj_materials? {
@@ -193,65 +748,70 @@ pub async fn load_model(
             model_path: file_name.to_string(),
             material_name: mat.name.clone(),
         };
-        let diffuse_texture_filename = &mat.diffuse_texture;
+        // MTL maps onto the default shader's slots; the PBR extension
+        // (Pr/Pm/Ke and their map_ variants) arrives as unknown params
+        let param = |key: &str| mat.unknown_param.get(key).map(String::as_str);
+        let normal_texture = if mat.normal_texture.is_empty() {
+            param("norm").unwrap_or_default()
+        } else {
+            mat.normal_texture.as_str()
+        };
 
-        // Check if texture already exists in registry, otherwise load it
-        let diffuse_texture = {
-            let mut registry = texture_registry.lock().unwrap();
-            if let Some(existing_texture) = registry.get(diffuse_texture_filename) {
-                Arc::clone(existing_texture)
-            } else {
-                let diffuse_texture_bytes = load_binary(&diffuse_texture_filename).await?;
-                let texture = Arc::new(GpuTexture::from_bytes(
-                    device,
-                    queue,
-                    &diffuse_texture_bytes,
-                    diffuse_texture_filename,
-                )?);
-                registry.insert(diffuse_texture_filename.clone(), Arc::clone(&texture));
-                texture
+        let mut texture_paths = HashMap::new();
+        let mut textures = HashMap::new();
+        for (slot, path) in [
+            (DIFFUSE_SLOT, mat.diffuse_texture.as_str()),
+            (NORMAL_SLOT, normal_texture),
+            (METALLIC_SLOT, param("map_Pm").unwrap_or_default()),
+            (ROUGHNESS_SLOT, param("map_Pr").unwrap_or_default()),
+            (EMISSIVE_SLOT, param("map_Ke").unwrap_or_default()),
+        ] {
+            if path.is_empty() || !layout.schema.has_texture_slot(slot) {
+                continue;
             }
+            let color_space = slot_color_space(slot);
+            let texture = load_texture(path, color_space, device, queue, texture_registry).await?;
+            texture_paths.insert(slot.to_string(), path.to_string());
+            textures.insert(slot.to_string(), texture);
+        }
+
+        // Opacity is `d`, or 1 - `Tr` in files that use the transparency form instead
+        let alpha = if mat.dissolve < 1.0 {
+            mat.dissolve
+        } else {
+            param("Tr")
+                .and_then(parse_mtl_floats)
+                .map_or(1.0, |transparency| 1.0 - transparency[0])
         };
+        let mut uniforms = UniformValues::new();
+        let [r, g, b] = mat.diffuse;
+        uniforms.insert("base_color".to_string(), [r, g, b, alpha]);
+        if let Some(emissive) = param("Ke").and_then(parse_mtl_floats) {
+            uniforms.insert("emissive".to_string(), emissive);
+        }
+        if let Some(metallic) = param("Pm").and_then(parse_mtl_floats) {
+            uniforms.insert("metallic".to_string(), metallic);
+        }
+        if let Some(roughness) = param("Pr").and_then(parse_mtl_floats) {
+            uniforms.insert("roughness".to_string(), roughness);
+        }
 
         let desc = MaterialDesc {
             name: mat.name.clone(),
-            texture_path: diffuse_texture_filename.clone(),
-            properties: RefCell::new(MaterialProperties::default()),
+            shader: crate::defaults::DEFAULT_SHADER_PATH.to_string(),
+            textures: texture_paths,
+            uniforms: RefCell::new(uniforms),
+            alpha_mode: if alpha < 1.0 {
+                AlphaMode::Blend
+            } else {
+                AlphaMode::Opaque
+            },
+            samplers: HashMap::new(),
         };
 
-        let properties_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some(&format!("{}_properties", mat.name)),
-            contents: bytemuck::cast_slice(&[*desc.properties.borrow()]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some(mat.name.as_str()),
-            layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: properties_buffer.as_entire_binding(),
-                },
-            ],
-        });
-
         materials_map.insert(
             material_source.clone(),
-            GpuMaterial {
-                desc,
-                diffuse_texture,
-                properties_buffer,
-                bind_group,
-            },
+            GpuMaterial::new(device, desc, layout, textures, fallbacks),
         );
         material_sources.push(material_source);
     }
@@ -291,21 +851,12 @@ pub async fn load_model(
                         ],
                         tex_coords,
                         normal,
+                        // Filled in by compute_tangents below
+                        tangent: [0.0; 3],
+                        bitangent: [0.0; 3],
                     }
                 })
                 .collect::<Vec<_>>();
-
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&model.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
-
             let material_source = match model.mesh.material_id {
                 Some(material_index) => material_sources
                     .get(material_index)
@@ -314,35 +865,125 @@ pub async fn load_model(
                 None => MaterialSource::System("default".to_string()),
             };
 
-            Mesh {
-                name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: model.mesh.indices.len() as u32,
-                vertex_count: vertices.len() as u32,
+            Mesh::new(
+                device,
+                file_name,
+                vertices,
+                model.mesh.indices,
                 material_source,
-            }
+            )
         })
         .collect::<Vec<_>>();
 
     Ok((
         Model {
-            name: model_name.to_string(),
+            name: model_name(file_name).to_string(),
             meshes,
             material_keys: material_sources,
+            skeleton: Skeleton::default(),
+            skins: Vec::new(),
+            animations: Vec::new(),
         },
         materials_map,
     ))
 }
 
+/// Parse up to four whitespace-separated floats from an MTL parameter, zero-filling the rest
+fn parse_mtl_floats(value: &str) -> Option<[f32; 4]> {
+    let mut result = [0.0; 4];
+    let mut count = 0;
+    for (slot, word) in result.iter_mut().zip(value.split_whitespace()) {
+        *slot = word.parse().ok()?;
+        count += 1;
+    }
+    (count > 0).then_some(result)
+}
+
+/// Per-vertex tangent frames for normal mapping, MikkTSpace-style: triangle tangents
+/// are accumulated on shared vertices, then Gram-Schmidt orthogonalized against the
+/// vertex normal with the bitangent rebuilt from the accumulated handedness.
+fn compute_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+    use cgmath::{InnerSpace, Vector2, Vector3, Zero};
+
+    let mut tangents = vec![Vector3::<f32>::zero(); vertices.len()];
+    let mut bitangents = vec![Vector3::<f32>::zero(); vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        let pos = |i: usize| Vector3::from(vertices[i].position);
+        // tex_coords have v flipped for wgpu; normal maps expect +v to point up the image
+        let uv = |i: usize| Vector2::new(vertices[i].tex_coords[0], -vertices[i].tex_coords[1]);
+
+        let edge1 = pos(i1) - pos(i0);
+        let edge2 = pos(i2) - pos(i0);
+        let delta_uv1 = uv(i1) - uv(i0);
+        let delta_uv2 = uv(i2) - uv(i0);
+
+        let det = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / det;
+        let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) * r;
+        let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) * r;
+
+        for i in [i0, i1, i2] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let normal = Vector3::from(vertex.normal);
+        let mut tangent = tangents[i] - normal * normal.dot(tangents[i]);
+        if tangent.magnitude2() < f32::EPSILON {
+            // No usable UVs: any vector perpendicular to the normal will do
+            let axis = if normal.x.abs() < 0.9 {
+                Vector3::unit_x()
+            } else {
+                Vector3::unit_y()
+            };
+            tangent = axis - normal * normal.dot(axis);
+        }
+        let tangent = tangent.normalize();
+        let handedness = if normal.cross(tangent).dot(bitangents[i]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        let bitangent = normal.cross(tangent) * handedness;
+
+        vertex.tangent = tangent.into();
+        vertex.bitangent = bitangent.into();
+    }
+}
+
+/// Instanced mesh draws. `vertices` is the mesh's own vertex buffer, or a posed copy of
+/// a skinned mesh's.
 pub trait DrawModel<'a> {
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'a Mesh,
+        vertices: wgpu::BufferSlice<'a>,
         material: &'a GpuMaterial,
         instances: Range<u32>,
         per_frame_bind_group: &'a wgpu::BindGroup,
     );
+    /// Instanced draw whose instance count comes from `DrawIndexedIndirectArgs` at
+    /// `offset` in `indirect_buffer`, for counts only the GPU knows
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'a Mesh,
+        vertices: wgpu::BufferSlice<'a>,
+        material: &'a GpuMaterial,
+        indirect_buffer: &'a wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        per_frame_bind_group: &'a wgpu::BindGroup,
+    );
 }
 
 impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
@@ -352,16 +993,33 @@ where
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'b Mesh,
+        vertices: wgpu::BufferSlice<'b>,
         material: &'b GpuMaterial,
         instances: Range<u32>,
         per_frame_bind_group: &'b wgpu::BindGroup,
     ) {
-        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_vertex_buffer(0, vertices);
         self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         self.set_bind_group(0, per_frame_bind_group, &[]);
         self.set_bind_group(1, &material.bind_group, &[]);
         self.draw_indexed(0..mesh.num_elements, 0, instances);
     }
+
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'b Mesh,
+        vertices: wgpu::BufferSlice<'b>,
+        material: &'b GpuMaterial,
+        indirect_buffer: &'b wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        per_frame_bind_group: &'b wgpu::BindGroup,
+    ) {
+        self.set_vertex_buffer(0, vertices);
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_bind_group(0, per_frame_bind_group, &[]);
+        self.set_bind_group(1, &material.bind_group, &[]);
+        self.draw_indexed_indirect(indirect_buffer, offset);
+    }
 }
 
 pub trait DrawLight<'a> {
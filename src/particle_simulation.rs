@@ -0,0 +1,549 @@
+use crate::particle_system::InstanceRaw;
+use cgmath::{InnerSpace, Vector3};
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+const SIMULATION_SHADER: &str = include_str!("shaders/particle_simulation.wgsl");
+
+/// Upper bound on live particles per system, whatever the spawn rate and lifetime
+pub const MAX_PARTICLES: usize = 65_536;
+/// Matches `@workgroup_size` in particle_simulation.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+/// Longest step taken at once, so a stalled frame doesn't fling particles away
+const MAX_STEP: f32 = 0.1;
+/// Floats per `InstanceRaw`
+const INSTANCE_FLOATS: usize = std::mem::size_of::<InstanceRaw>() / 4;
+
+/// Where dynamic particle systems are simulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulationBackend {
+    /// A compute shader writes the instance buffer directly
+    Compute,
+    /// Simulated on the CPU and uploaded every frame, for WebGL2
+    Cpu,
+}
+
+impl SimulationBackend {
+    pub fn for_adapter(adapter: &wgpu::Adapter) -> Self {
+        let flags = adapter.get_downlevel_capabilities().flags;
+        let limits = adapter.limits();
+        // Instances the GPU writes are drawn indirectly once bucketed by LOD level
+        if flags.contains(
+            wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION,
+        ) && limits.max_storage_buffers_per_shader_stage >= 3
+            && limits.max_compute_invocations_per_workgroup >= WORKGROUP_SIZE
+        {
+            SimulationBackend::Compute
+        } else {
+            SimulationBackend::Cpu
+        }
+    }
+
+    /// Raise `limits` to what the simulation shader binds and dispatches
+    pub fn apply_limits(&self, limits: &mut wgpu::Limits, adapter: &wgpu::Adapter) {
+        if *self == SimulationBackend::Compute {
+            let adapter_limits = adapter.limits();
+            let compute_limits = wgpu::Limits::downlevel_defaults();
+            limits.max_storage_buffers_per_shader_stage =
+                limits.max_storage_buffers_per_shader_stage.max(3);
+            limits.max_storage_buffer_binding_size = adapter_limits
+                .max_storage_buffer_binding_size
+                .min(wgpu::Limits::default().max_storage_buffer_binding_size);
+            limits.max_compute_invocations_per_workgroup =
+                compute_limits.max_compute_invocations_per_workgroup;
+            limits.max_compute_workgroup_size_x = compute_limits.max_compute_workgroup_size_x;
+            limits.max_compute_workgroup_size_y = compute_limits.max_compute_workgroup_size_y;
+            limits.max_compute_workgroup_size_z = compute_limits.max_compute_workgroup_size_z;
+            limits.max_compute_workgroups_per_dimension =
+                compute_limits.max_compute_workgroups_per_dimension;
+        }
+    }
+}
+
+/// How a dynamic particle system spawns and moves its particles. Particles leave the
+/// system's generated instances, which act as emitters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationParams {
+    /// Particles per second
+    pub spawn_rate: f32,
+    /// Seconds each particle lives
+    pub lifetime: f32,
+    pub initial_speed: f32,
+    /// Half angle of the cone around each emitter's local +Y that particles leave in
+    pub spread_deg: f32,
+    /// Scale at the end of a particle's life, relative to its emitter's
+    pub end_scale: f32,
+    pub gravity: [f32; 3],
+    /// Fraction of velocity lost per second
+    pub drag: f32,
+    pub vortex_center: [f32; 3],
+    pub vortex_axis: [f32; 3],
+    /// Acceleration around the vortex axis
+    pub vortex_strength: f32,
+    /// Acceleration from a smooth noise field
+    pub noise_strength: f32,
+    /// Noise cells per world unit
+    pub noise_frequency: f32,
+}
+
+impl Default for SimulationParams {
+    fn default() -> Self {
+        Self {
+            spawn_rate: 200.0,
+            lifetime: 4.0,
+            initial_speed: 5.0,
+            spread_deg: 20.0,
+            end_scale: 0.2,
+            gravity: [0.0, -9.81, 0.0],
+            drag: 0.1,
+            vortex_center: [0.0, 0.0, 0.0],
+            vortex_axis: [0.0, 1.0, 0.0],
+            vortex_strength: 0.0,
+            noise_strength: 0.0,
+            noise_frequency: 0.5,
+        }
+    }
+}
+
+impl SimulationParams {
+    /// Particle slots needed to keep every particle alive for its whole lifetime
+    pub fn capacity(&self) -> usize {
+        let count = (self.spawn_rate.max(0.0) * self.lifetime.max(0.0)).ceil();
+        (count as usize).clamp(1, MAX_PARTICLES)
+    }
+
+    fn uniform(
+        &self,
+        capacity: usize,
+        emitter_count: usize,
+        time: f32,
+        dt: f32,
+    ) -> SimulationUniform {
+        let axis = Vector3::from(self.vortex_axis);
+        let axis = if axis.magnitude2() > 0.0 {
+            axis.normalize()
+        } else {
+            Vector3::unit_y()
+        };
+        SimulationUniform {
+            gravity: self.gravity,
+            drag: self.drag.max(0.0),
+            vortex_center: self.vortex_center,
+            vortex_strength: self.vortex_strength,
+            vortex_axis: axis.into(),
+            noise_strength: self.noise_strength,
+            noise_frequency: self.noise_frequency,
+            spawn_rate: self.spawn_rate.max(f32::EPSILON),
+            lifetime: self.lifetime.max(f32::EPSILON),
+            initial_speed: self.initial_speed,
+            cos_spread: self.spread_deg.clamp(0.0, 180.0).to_radians().cos(),
+            end_scale: self.end_scale,
+            time,
+            dt,
+            capacity: capacity as u32,
+            emitter_count: emitter_count as u32,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// Matches `SimulationParams` in particle_simulation.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SimulationUniform {
+    gravity: [f32; 3],
+    drag: f32,
+    vortex_center: [f32; 3],
+    vortex_strength: f32,
+    vortex_axis: [f32; 3],
+    noise_strength: f32,
+    noise_frequency: f32,
+    spawn_rate: f32,
+    lifetime: f32,
+    initial_speed: f32,
+    cos_spread: f32,
+    end_scale: f32,
+    time: f32,
+    dt: f32,
+    capacity: u32,
+    emitter_count: u32,
+    _padding: [u32; 2],
+}
+
+/// Matches `Particle` in particle_simulation.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Particle {
+    position: [f32; 3],
+    /// Negative until first spawned
+    age: f32,
+    velocity: [f32; 3],
+    emitter: u32,
+    seed: u32,
+    _padding: [u32; 3],
+}
+
+impl Particle {
+    fn unspawned(index: u32) -> Self {
+        Self {
+            position: [0.0; 3],
+            age: -1.0,
+            velocity: [0.0; 3],
+            emitter: 0,
+            seed: pcg_hash(index),
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// The compute pipeline shared by every dynamic system, when the backend has one
+pub struct ParticleSimulator {
+    backend: SimulationBackend,
+    compute: Option<(wgpu::BindGroupLayout, wgpu::ComputePipeline)>,
+}
+
+impl ParticleSimulator {
+    pub fn new(device: &wgpu::Device, backend: SimulationBackend) -> Self {
+        if backend == SimulationBackend::Cpu {
+            return Self {
+                backend,
+                compute: None,
+            };
+        }
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle Simulation Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, false),
+                storage(2, true),
+                storage(3, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Simulation Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Simulation Shader"),
+            source: wgpu::ShaderSource::Wgsl(SIMULATION_SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Particle Simulation Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Self {
+            backend,
+            compute: Some((layout, pipeline)),
+        }
+    }
+
+    pub fn backend(&self) -> SimulationBackend {
+        self.backend
+    }
+}
+
+/// GPU copies of a simulation's state, bound alongside the instance buffer it writes
+struct GpuSimulation {
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Running state of one dynamic particle system
+pub struct Simulation {
+    capacity: usize,
+    /// Seconds since the first particle spawned, wrapped to keep precision
+    time: f32,
+    emitters: Vec<InstanceRaw>,
+    /// Particle state for the CPU backend
+    particles: Vec<Particle>,
+    gpu: Option<GpuSimulation>,
+}
+
+impl Simulation {
+    /// Start an empty simulation of `capacity` particles. With the compute backend,
+    /// `instance_buffer` must hold `capacity` instances and allow storage use.
+    pub fn new(
+        device: &wgpu::Device,
+        simulator: &ParticleSimulator,
+        name: &str,
+        capacity: usize,
+        emitters: Vec<InstanceRaw>,
+        instance_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let particles: Vec<Particle> = (0..capacity as u32).map(Particle::unspawned).collect();
+
+        let gpu = simulator.compute.as_ref().map(|(layout, _)| {
+            let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("Particle System '{}' Simulation Params", name)),
+                size: std::mem::size_of::<SimulationUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let particle_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("Particle System '{}' Particles", name)),
+                contents: bytemuck::cast_slice(&particles),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+            // Storage bindings can't be empty, so an emitterless system keeps one zeroed
+            let emitter_contents = if emitters.is_empty() {
+                vec![bytemuck::Zeroable::zeroed()]
+            } else {
+                emitters.clone()
+            };
+            let emitter_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("Particle System '{}' Emitters", name)),
+                contents: bytemuck::cast_slice(&emitter_contents),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("Particle System '{}' Simulation Bind Group", name)),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: particle_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: emitter_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: instance_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+            GpuSimulation {
+                uniform_buffer,
+                bind_group,
+            }
+        });
+
+        Self {
+            capacity,
+            time: 0.0,
+            emitters,
+            // The GPU keeps its own copy
+            particles: if gpu.is_some() { Vec::new() } else { particles },
+            gpu,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn on_gpu(&self) -> bool {
+        self.gpu.is_some()
+    }
+
+    /// Advance by `dt` seconds. The compute backend records a dispatch into `encoder`;
+    /// the CPU backend returns the instances to upload instead.
+    pub fn step(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        simulator: &ParticleSimulator,
+        params: &SimulationParams,
+        dt: f32,
+    ) -> Option<Vec<InstanceRaw>> {
+        if self.emitters.is_empty() {
+            return None;
+        }
+        let dt = dt.clamp(0.0, MAX_STEP);
+        self.time += dt;
+
+        // Every slot is born by the end of the first period, and ages only depend on the
+        // time modulo the period, so whole periods past the second can be dropped
+        let uniform = params.uniform(self.capacity, self.emitters.len(), self.time, dt);
+        let period = uniform.capacity as f32 / uniform.spawn_rate;
+        if self.time >= 2.0 * period {
+            self.time = period + (self.time - period) % period;
+        }
+        let uniform = SimulationUniform {
+            time: self.time,
+            ..uniform
+        };
+
+        match (&self.gpu, &simulator.compute) {
+            (Some(gpu), Some((_, pipeline))) => {
+                queue.write_buffer(&gpu.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Particle Simulation Pass"),
+                    timestamp_writes: None,
+                });
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &gpu.bind_group, &[]);
+                pass.dispatch_workgroups((self.capacity as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+                None
+            }
+            _ => Some(self.step_cpu(&uniform)),
+        }
+    }
+
+    /// `cs_main` for every particle
+    fn step_cpu(&mut self, params: &SimulationUniform) -> Vec<InstanceRaw> {
+        let emitters: Vec<[f32; INSTANCE_FLOATS]> =
+            self.emitters.iter().map(|e| bytemuck::cast(*e)).collect();
+        let period = params.capacity as f32 / params.spawn_rate;
+
+        self.particles
+            .iter_mut()
+            .enumerate()
+            .map(|(index, particle)| {
+                let local_time = params.time - index as f32 / params.spawn_rate;
+                let mut scale = 0.0;
+                if local_time >= 0.0 {
+                    let age = local_time - (local_time / period).floor() * period;
+                    if particle.age < 0.0 || age < particle.age {
+                        *particle = spawn(particle, params, &emitters);
+                    } else {
+                        integrate(particle, params);
+                    }
+                    particle.age = age;
+                    if age < params.lifetime {
+                        scale = 1.0 + (params.end_scale - 1.0) * (age / params.lifetime);
+                    }
+                }
+
+                let emitter = &emitters[particle.emitter as usize];
+                let mut instance = [0.0; INSTANCE_FLOATS];
+                for i in 0..12 {
+                    instance[i] = emitter[i] * scale;
+                }
+                instance[12..15].copy_from_slice(&particle.position);
+                instance[15] = 1.0;
+                instance[16..].copy_from_slice(&emitter[16..]);
+                bytemuck::cast(instance)
+            })
+            .collect()
+    }
+}
+
+pub fn pcg_hash(value: u32) -> u32 {
+    let state = value.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+fn random(seed: &mut u32) -> f32 {
+    *seed = pcg_hash(*seed);
+    *seed as f32 / 4294967296.0
+}
+
+fn lattice(x: i32, y: i32, z: i32) -> f32 {
+    pcg_hash(x as u32 ^ pcg_hash(y as u32 ^ pcg_hash(z as u32))) as f32 / 4294967296.0
+}
+
+/// Smoothly interpolated random values on the integer lattice, in 0 to 1
+fn value_noise(p: Vector3<f32>) -> f32 {
+    let cell = p.map(f32::floor);
+    let f = p - cell;
+    let u = f.map(|f| f * f * (3.0 - 2.0 * f));
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+    let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let x00 = mix(lattice(x, y, z), lattice(x + 1, y, z), u.x);
+    let x10 = mix(lattice(x, y + 1, z), lattice(x + 1, y + 1, z), u.x);
+    let x01 = mix(lattice(x, y, z + 1), lattice(x + 1, y, z + 1), u.x);
+    let x11 = mix(lattice(x, y + 1, z + 1), lattice(x + 1, y + 1, z + 1), u.x);
+    mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z)
+}
+
+/// Three decorrelated noise channels, each in -1 to 1
+fn noise_vector(p: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(
+        value_noise(p),
+        value_noise(p + Vector3::new(31.4, 17.1, 5.9)),
+        value_noise(p + Vector3::new(-47.2, 9.3, 23.7)),
+    ) * 2.0
+        - Vector3::new(1.0, 1.0, 1.0)
+}
+
+fn emitter_column(emitter: &[f32; INSTANCE_FLOATS], column: usize) -> Vector3<f32> {
+    Vector3::new(
+        emitter[column * 4],
+        emitter[column * 4 + 1],
+        emitter[column * 4 + 2],
+    )
+}
+
+/// Restart at a random emitter, moving within the spread cone around its local +Y
+fn spawn(
+    particle: &Particle,
+    params: &SimulationUniform,
+    emitters: &[[f32; INSTANCE_FLOATS]],
+) -> Particle {
+    let mut seed = particle.seed;
+    let index =
+        ((random(&mut seed) * params.emitter_count as f32) as u32).min(params.emitter_count - 1);
+    let emitter = &emitters[index as usize];
+
+    let cos_theta = 1.0 - random(&mut seed) * (1.0 - params.cos_spread);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = random(&mut seed) * std::f32::consts::TAU;
+    let direction = emitter_column(emitter, 0) * (sin_theta * phi.cos())
+        + emitter_column(emitter, 1) * cos_theta
+        + emitter_column(emitter, 2) * (sin_theta * phi.sin());
+    let velocity = if direction.magnitude2() > 0.0 {
+        direction.normalize() * params.initial_speed
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    };
+
+    Particle {
+        position: emitter_column(emitter, 3).into(),
+        age: 0.0,
+        velocity: velocity.into(),
+        emitter: index,
+        seed,
+        _padding: [0; 3],
+    }
+}
+
+fn integrate(particle: &mut Particle, params: &SimulationUniform) {
+    let position = Vector3::from(particle.position);
+    let axis = Vector3::from(params.vortex_axis);
+    let mut acceleration = Vector3::from(params.gravity);
+    let offset = position - Vector3::from(params.vortex_center);
+    let radial = offset - axis * offset.dot(axis);
+    if radial.magnitude2() > 1e-8 {
+        acceleration += axis.cross(radial).normalize() * params.vortex_strength;
+    }
+    acceleration += noise_vector(position * params.noise_frequency) * params.noise_strength;
+
+    let velocity = (Vector3::from(particle.velocity) + acceleration * params.dt)
+        * (1.0 - params.drag * params.dt).max(0.0);
+    particle.velocity = velocity.into();
+    particle.position = (position + velocity * params.dt).into();
+}
@@ -1,6 +1,22 @@
-use cgmath::{InnerSpace, Matrix3, Matrix4, Quaternion, Rotation3, Vector3};
+use crate::animation::{AnimationPlayer, MorphWeights, Pose};
+use crate::culling::{Aabb, Frustum};
+use crate::deform::{DeformedMesh, Deformer};
+use crate::lod::{
+    BucketLayout, LodBucketer, LodBuckets, LodLevel, MAX_LOD_LEVELS, animation_offset_for,
+    animation_offset_range, lod_for,
+};
+use crate::model::MeshSurface;
+use crate::particle_simulation::{
+    ParticleSimulator, Simulation, SimulationBackend, SimulationParams, pcg_hash,
+};
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, MetricSpace, Point3, Quaternion,
+    Rotation3, SquareMatrix, Vector3,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
 use wgpu::util::DeviceExt;
 
 /// Set to ~50 to only upload changes to GPU every 'ms
@@ -11,9 +27,33 @@ const DEBOUNCE_MS: u64 = 0;
 pub struct InstanceRaw {
     model: [[f32; 4]; 4],
     normal: [[f32; 3]; 3],
+    /// Tint multiplied into the material's base color
+    color: [f32; 4],
+    /// Passed through untouched for custom shaders
+    custom: [f32; 4],
 }
 
 impl InstanceRaw {
+    /// An untinted instance with no custom data
+    pub fn new(model: Matrix4<f32>, normal: Matrix3<f32>) -> Self {
+        Self {
+            model: model.into(),
+            normal: normal.into(),
+            color: [1.0; 4],
+            custom: [0.0; 4],
+        }
+    }
+
+    /// World-space origin of the instance
+    pub fn position(&self) -> Point3<f32> {
+        let [x, y, z, _] = self.model[3];
+        Point3::new(x, y, z)
+    }
+
+    pub fn model_matrix(&self) -> Matrix4<f32> {
+        self.model.into()
+    }
+
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use wgpu::{
             BufferAddress, VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode,
@@ -59,11 +99,191 @@ impl InstanceRaw {
                     shader_location: 11,
                     format: VertexFormat::Float32x3,
                 },
+                // Color tint
+                VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 25]>() as BufferAddress,
+                    shader_location: 12,
+                    format: VertexFormat::Float32x4,
+                },
+                // Custom data
+                VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 29]>() as BufferAddress,
+                    shader_location: 13,
+                    format: VertexFormat::Float32x4,
+                },
             ],
         }
     }
 }
 
+// ============================================================================
+// PER-INSTANCE ATTRIBUTES
+// ============================================================================
+
+/// Calls a script function with the instance count, returning the Float32Array it made
+pub type ScriptValues<'a> = dyn FnMut(&str, usize) -> Result<Vec<f32>, String> + 'a;
+
+/// Where each instance's value of a per-instance attribute comes from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum InstanceValues {
+    #[serde(rename = "constant")]
+    Constant { value: [f32; 4] },
+    /// Blends from `start` to `end` in the order instances are generated
+    #[serde(rename = "gradient")]
+    Gradient { start: [f32; 4], end: [f32; 4] },
+    /// Each component picked uniformly between `min` and `max`
+    #[serde(rename = "random")]
+    Random {
+        min: [f32; 4],
+        max: [f32; 4],
+        seed: u32,
+    },
+    /// One of `colors` at random per instance
+    #[serde(rename = "palette")]
+    Palette { colors: Vec<[f32; 4]>, seed: u32 },
+    /// Four floats per instance from a script function called with the instance count
+    #[serde(rename = "script")]
+    Script { function: String },
+}
+
+impl InstanceValues {
+    /// One of each kind, starting from `value`
+    pub fn defaults(value: [f32; 4]) -> [InstanceValues; 5] {
+        [
+            InstanceValues::Constant { value },
+            InstanceValues::Gradient {
+                start: value,
+                end: value,
+            },
+            InstanceValues::Random {
+                min: value,
+                max: value,
+                seed: 0,
+            },
+            InstanceValues::Palette {
+                colors: vec![value],
+                seed: 0,
+            },
+            InstanceValues::Script {
+                function: String::new(),
+            },
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            InstanceValues::Constant { .. } => "Constant",
+            InstanceValues::Gradient { .. } => "Gradient",
+            InstanceValues::Random { .. } => "Random",
+            InstanceValues::Palette { .. } => "Palette",
+            InstanceValues::Script { .. } => "Script",
+        }
+    }
+
+    pub fn values(
+        &self,
+        count: usize,
+        scripts: &mut ScriptValues,
+    ) -> Result<Vec<[f32; 4]>, String> {
+        let random = |seed: u32, i: usize, component: u32| {
+            let hash = pcg_hash(seed ^ pcg_hash(i as u32 ^ pcg_hash(component)));
+            hash as f32 / 4294967296.0
+        };
+        Ok(match self {
+            InstanceValues::Constant { value } => vec![*value; count],
+            InstanceValues::Gradient { start, end } => (0..count)
+                .map(|i| {
+                    let t = i as f32 / count.saturating_sub(1).max(1) as f32;
+                    std::array::from_fn(|c| start[c] + (end[c] - start[c]) * t)
+                })
+                .collect(),
+            InstanceValues::Random { min, max, seed } => (0..count)
+                .map(|i| {
+                    std::array::from_fn(|c| min[c] + (max[c] - min[c]) * random(*seed, i, c as u32))
+                })
+                .collect(),
+            InstanceValues::Palette { colors, seed } => {
+                if colors.is_empty() {
+                    return Err("Palette has no colors".to_string());
+                }
+                (0..count)
+                    .map(|i| {
+                        let pick = (random(*seed, i, 0) * colors.len() as f32) as usize;
+                        colors[pick.min(colors.len() - 1)]
+                    })
+                    .collect()
+            }
+            InstanceValues::Script { function } => {
+                let floats = scripts(function, count)?;
+                if floats.len() < count * 4 {
+                    return Err(format!(
+                        "{}() returned {} floats, expected 4 for each of {} instances",
+                        function,
+                        floats.len(),
+                        count
+                    ));
+                }
+                floats
+                    .chunks_exact(4)
+                    .take(count)
+                    .map(|chunk| [chunk[0], chunk[1], chunk[2], chunk[3]])
+                    .collect()
+            }
+        })
+    }
+}
+
+/// Optional per-instance looks, filled in after the generator has placed the instances
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InstanceAttributes {
+    /// Tint multiplied into the material's base color; white without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<InstanceValues>,
+    /// Uniform scale from the first component; 1 without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<InstanceValues>,
+    /// Free for custom shaders, at instance location 13; zero without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom: Option<InstanceValues>,
+}
+
+impl InstanceAttributes {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill in `instances`. Attributes whose values can't be made keep their defaults.
+    pub fn apply(&self, instances: &mut [InstanceRaw], scripts: &mut ScriptValues) {
+        let count = instances.len();
+        let mut resolve = |name: &str, values: &Option<InstanceValues>| {
+            values
+                .as_ref()?
+                .values(count, scripts)
+                .inspect_err(|e| log::error!("Instance {} values: {}", name, e))
+                .ok()
+        };
+
+        if let Some(colors) = resolve("color", &self.color) {
+            for (instance, color) in instances.iter_mut().zip(colors) {
+                instance.color = color;
+            }
+        }
+        if let Some(scales) = resolve("scale", &self.scale) {
+            // A uniform scale leaves the normal matrix as it is
+            for (instance, scale) in instances.iter_mut().zip(scales) {
+                instance.model = (instance.model_matrix() * Matrix4::from_scale(scale[0])).into();
+            }
+        }
+        if let Some(custom) = resolve("custom", &self.custom) {
+            for (instance, custom) in instances.iter_mut().zip(custom) {
+                instance.custom = custom;
+            }
+        }
+    }
+}
+
 // ============================================================================
 // INSTANCE GENERATOR TRAIT
 // ============================================================================
@@ -113,10 +333,7 @@ impl InstanceGenerator for GridParams {
                     Matrix4::from_translation(world_position) * Matrix4::from(rotation);
                 let normal_matrix = Matrix3::from(rotation);
 
-                instances.push(InstanceRaw {
-                    model: model_matrix.into(),
-                    normal: normal_matrix.into(),
-                });
+                instances.push(InstanceRaw::new(model_matrix, normal_matrix));
 
                 if instances.len() >= count {
                     break;
@@ -177,12 +394,668 @@ impl InstanceGenerator for SphereParams {
             let model_matrix = Matrix4::from_translation(world_position) * Matrix4::from(rotation);
             let normal_matrix = Matrix3::from(rotation);
 
-            instances.push(InstanceRaw {
-                model: model_matrix.into(),
-                normal: normal_matrix.into(),
-            });
+            instances.push(InstanceRaw::new(model_matrix, normal_matrix));
+        }
+
+        instances
+    }
+}
+
+/// Sequence of pseudo-random numbers in 0 to 1, the same for the same seed
+struct SeededRandom(u32);
+
+impl SeededRandom {
+    fn new(seed: u32) -> Self {
+        Self(pcg_hash(seed))
+    }
+
+    fn next(&mut self) -> f32 {
+        self.0 = pcg_hash(self.0);
+        self.0 as f32 / 4294967296.0
+    }
+}
+
+/// Rotation about +Y taking +Z to point along `x`, `z`
+fn facing(x: f32, z: f32) -> Quaternion<f32> {
+    Quaternion::from_angle_y(cgmath::Rad(x.atan2(z)))
+}
+
+fn instance_at(position: Vector3<f32>, rotation: Quaternion<f32>) -> InstanceRaw {
+    InstanceRaw::new(
+        Matrix4::from_translation(position) * Matrix4::from(rotation),
+        Matrix3::from(rotation),
+    )
+}
+
+/// Evenly spaced from `start` to `end`, facing along the line
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LineParams {
+    pub count: usize,
+    pub start: [f32; 3],
+    pub end: [f32; 3],
+}
+
+impl InstanceGenerator for LineParams {
+    fn instance_count(&self) -> usize {
+        self.count
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        let start = Vector3::from(self.start);
+        let direction = Vector3::from(self.end) - start;
+        let rotation = if direction.magnitude2() > 0.001 {
+            Quaternion::from_arc(Vector3::unit_z(), direction.normalize(), None)
+        } else {
+            Quaternion::new(1.0, 0.0, 0.0, 0.0)
+        };
+
+        (0..self.count)
+            .map(|i| {
+                let t = i as f32 / self.count.saturating_sub(1).max(1) as f32;
+                instance_at(start + direction * t, rotation)
+            })
+            .collect()
+    }
+}
+
+/// Evenly spaced around a circle in the XZ plane, facing outwards
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RingParams {
+    pub count: usize,
+    pub radius: f32,
+    pub center: [f32; 3],
+}
+
+impl InstanceGenerator for RingParams {
+    fn instance_count(&self) -> usize {
+        self.count
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        let center = Vector3::from(self.center);
+        (0..self.count)
+            .map(|i| {
+                let angle = std::f32::consts::TAU * i as f32 / self.count as f32;
+                let (sin, cos) = angle.sin_cos();
+                instance_at(
+                    center + Vector3::new(cos, 0.0, sin) * self.radius,
+                    facing(cos, sin),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Uniformly random positions inside a box; the same seed gives the same layout
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BoxParams {
+    pub count: usize,
+    pub size: [f32; 3],
+    pub center: [f32; 3],
+    #[serde(default)]
+    pub seed: u32,
+}
+
+impl InstanceGenerator for BoxParams {
+    fn instance_count(&self) -> usize {
+        self.count
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        let center = Vector3::from(self.center);
+        let mut random = SeededRandom::new(self.seed);
+        (0..self.count)
+            .map(|_| {
+                let offset = Vector3::new(
+                    (random.next() - 0.5) * self.size[0],
+                    (random.next() - 0.5) * self.size[1],
+                    (random.next() - 0.5) * self.size[2],
+                );
+                instance_at(center + offset, Quaternion::new(1.0, 0.0, 0.0, 0.0))
+            })
+            .collect()
+    }
+}
+
+/// Archimedean spiral in the XZ plane: the radius grows evenly with the angle, out to
+/// `radius` after `turns` turns. Instances face along the curve.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpiralParams {
+    pub count: usize,
+    pub turns: f32,
+    pub radius: f32,
+    pub center: [f32; 3],
+}
+
+impl InstanceGenerator for SpiralParams {
+    fn instance_count(&self) -> usize {
+        self.count
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        let center = Vector3::from(self.center);
+        let max_angle = self.turns * std::f32::consts::TAU;
+        let growth = if max_angle > 0.0 {
+            self.radius / max_angle
+        } else {
+            0.0
+        };
+
+        (0..self.count)
+            .map(|i| {
+                let angle = max_angle * i as f32 / self.count.saturating_sub(1).max(1) as f32;
+                let (sin, cos) = angle.sin_cos();
+                let radius = growth * angle;
+                // Derivative of (r cos, r sin) with r = growth * angle, over growth
+                let tangent_x = cos - angle * sin;
+                let tangent_z = sin + angle * cos;
+                instance_at(
+                    center + Vector3::new(cos, 0.0, sin) * radius,
+                    facing(tangent_x, tangent_z),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Random points on a rectangle in the XZ plane, no two closer than `min_distance`
+/// (Bridson's algorithm). Stops at `max_count`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PoissonDiskParams {
+    pub min_distance: f32,
+    pub size: [f32; 2],
+    pub center: [f32; 3],
+    pub max_count: usize,
+    #[serde(default)]
+    pub seed: u32,
+}
+
+impl PoissonDiskParams {
+    /// Candidates tried around each point before it's retired
+    const ATTEMPTS: usize = 30;
+    /// Grid cells a layout is kept to (within a few times); smaller minimum distances
+    /// are raised to fit
+    const MAX_CELLS: f32 = 1_048_576.0;
+
+    fn points(&self) -> Vec<[f32; 2]> {
+        let [width, depth] = self.size;
+        let area = width * depth;
+        if !(self.min_distance > 0.0 && self.min_distance.is_finite())
+            || width <= 0.0
+            || depth <= 0.0
+            || !area.is_finite()
+            || self.max_count == 0
+        {
+            return Vec::new();
         }
 
+        // Cells small enough to hold at most one point each, but not so small that
+        // the grid grows past MAX_CELLS, however tiny the spacing or narrow the area
+        let smallest_cell = (area / Self::MAX_CELLS)
+            .sqrt()
+            .max(width.max(depth) / Self::MAX_CELLS);
+        let cell = (self.min_distance / std::f32::consts::SQRT_2).max(smallest_cell);
+        let min_distance = cell * std::f32::consts::SQRT_2;
+        let columns = (width / cell).ceil() as usize;
+        let rows = (depth / cell).ceil() as usize;
+        let cell_of = |p: [f32; 2]| {
+            (
+                ((p[0] / cell) as usize).min(columns - 1),
+                ((p[1] / cell) as usize).min(rows - 1),
+            )
+        };
+        let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+        let mut random = SeededRandom::new(self.seed);
+
+        let first = [random.next() * width, random.next() * depth];
+        let (x, z) = cell_of(first);
+        grid[z * columns + x] = Some(0);
+        let mut points = vec![first];
+        let mut active = vec![0];
+
+        while !active.is_empty() && points.len() < self.max_count {
+            let slot = (random.next() * active.len() as f32) as usize % active.len();
+            let origin = points[active[slot]];
+            let mut placed = false;
+
+            for _ in 0..Self::ATTEMPTS {
+                // Uniform in the annulus between the minimum distance and twice that
+                let angle = random.next() * std::f32::consts::TAU;
+                let distance = min_distance * (1.0 + random.next());
+                let candidate = [
+                    origin[0] + angle.cos() * distance,
+                    origin[1] + angle.sin() * distance,
+                ];
+                if !(0.0..width).contains(&candidate[0]) || !(0.0..depth).contains(&candidate[1]) {
+                    continue;
+                }
+
+                let (x, z) = cell_of(candidate);
+                let too_close = (z.saturating_sub(2)..(z + 3).min(rows)).any(|nz| {
+                    (x.saturating_sub(2)..(x + 3).min(columns)).any(|nx| {
+                        grid[nz * columns + nx].is_some_and(|other| {
+                            let dx = points[other][0] - candidate[0];
+                            let dz = points[other][1] - candidate[1];
+                            dx * dx + dz * dz < min_distance * min_distance
+                        })
+                    })
+                });
+                if !too_close {
+                    grid[z * columns + x] = Some(points.len());
+                    active.push(points.len());
+                    points.push(candidate);
+                    placed = true;
+                    break;
+                }
+            }
+
+            if !placed {
+                active.swap_remove(slot);
+            }
+        }
+        points
+    }
+}
+
+impl InstanceGenerator for PoissonDiskParams {
+    /// At most this many; how many fit depends on the spacing
+    fn instance_count(&self) -> usize {
+        self.max_count
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        let corner =
+            Vector3::from(self.center) - Vector3::new(self.size[0], 0.0, self.size[1]) * 0.5;
+        self.points()
+            .into_iter()
+            .map(|[x, z]| {
+                instance_at(
+                    corner + Vector3::new(x, 0.0, z),
+                    Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                )
+            })
+            .collect()
+    }
+}
+
+/// A grayscale image loaded for a heightmap generator
+#[derive(Clone, Debug)]
+pub struct Heightmap {
+    path: String,
+    image: Arc<image::ImageBuffer<image::Luma<u16>, Vec<u16>>>,
+}
+
+impl Heightmap {
+    /// Fails on an image with no texels, which has no level to sample
+    pub fn new(path: String, image: &image::DynamicImage) -> anyhow::Result<Self> {
+        if image.width() == 0 || image.height() == 0 {
+            anyhow::bail!("image has no texels");
+        }
+        Ok(Self {
+            path,
+            image: Arc::new(image.to_luma16()),
+        })
+    }
+
+    /// Bilinearly filtered level at `u`, `v` in 0 to 1, itself in 0 to 1
+    fn level(&self, u: f32, v: f32) -> f32 {
+        let (width, height) = self.image.dimensions();
+        let x = u.clamp(0.0, 1.0) * (width - 1) as f32;
+        let y = v.clamp(0.0, 1.0) * (height - 1) as f32;
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let texel = |x, y| self.image.get_pixel(x, y)[0] as f32 / u16::MAX as f32;
+        let (fx, fy) = (x.fract(), y.fract());
+        let top = texel(x0, y0) + (texel(x1, y0) - texel(x0, y0)) * fx;
+        let bottom = texel(x0, y1) + (texel(x1, y1) - texel(x0, y1)) * fx;
+        top + (bottom - top) * fy
+    }
+}
+
+/// A grid of instances over a grayscale image from res/, raised by its brightness.
+/// Nothing is generated until the image has loaded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HeightmapParams {
+    pub path: String,
+    /// Samples along each side
+    pub resolution: usize,
+    /// Extent along X and Z
+    pub size: [f32; 2],
+    /// Height of white above `center`
+    pub height: f32,
+    pub center: [f32; 3],
+    /// Samples darker than this level, in 0 to 1, stay empty
+    #[serde(default)]
+    pub min_level: f32,
+    /// Tilt instances to the slope instead of keeping them upright
+    #[serde(default)]
+    pub align_to_surface: bool,
+    #[serde(skip)]
+    pub loaded: Option<Heightmap>,
+    /// Path whose load failed. It isn't requested again until `path` is edited.
+    #[serde(skip)]
+    pub failed: Option<String>,
+}
+
+impl HeightmapParams {
+    /// The loaded image, if it's the one `path` names
+    pub fn heightmap(&self) -> Option<&Heightmap> {
+        self.loaded.as_ref().filter(|map| map.path == self.path)
+    }
+
+    /// Whether loading the image `path` names failed
+    pub fn load_failed(&self) -> bool {
+        self.failed.as_ref() == Some(&self.path)
+    }
+}
+
+impl InstanceGenerator for HeightmapParams {
+    /// At most this many; dark samples are skipped
+    fn instance_count(&self) -> usize {
+        self.resolution * self.resolution
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        let Some(map) = self.heightmap() else {
+            return Vec::new();
+        };
+        let corner =
+            Vector3::from(self.center) - Vector3::new(self.size[0], 0.0, self.size[1]) * 0.5;
+        let step = 1.0 / self.resolution.saturating_sub(1).max(1) as f32;
+        // Slope from central differences about a texel apart
+        let (width, height) = map.image.dimensions();
+        let (du, dv) = (1.0 / width as f32, 1.0 / height as f32);
+
+        let mut instances = Vec::with_capacity(self.instance_count());
+        for row in 0..self.resolution {
+            for column in 0..self.resolution {
+                let (u, v) = (column as f32 * step, row as f32 * step);
+                let level = map.level(u, v);
+                if level < self.min_level {
+                    continue;
+                }
+
+                let rotation = if self.align_to_surface {
+                    let slope_x = (map.level(u + du, v) - map.level(u - du, v)) * self.height
+                        / (2.0 * du * self.size[0]);
+                    let slope_z = (map.level(u, v + dv) - map.level(u, v - dv)) * self.height
+                        / (2.0 * dv * self.size[1]);
+                    let normal = Vector3::new(-slope_x, 1.0, -slope_z).normalize();
+                    Quaternion::from_arc(Vector3::unit_y(), normal, None)
+                } else {
+                    Quaternion::new(1.0, 0.0, 0.0, 0.0)
+                };
+                let position =
+                    corner + Vector3::new(u * self.size[0], level * self.height, v * self.size[1]);
+                instances.push(instance_at(position, rotation));
+            }
+        }
+        instances
+    }
+}
+
+/// A mesh attached to the surface scatter generator that names it
+#[derive(Clone, Debug)]
+pub struct LoadedSurface {
+    model: String,
+    mesh_index: usize,
+    surface: Arc<MeshSurface>,
+}
+
+/// Random points on another model's mesh, spread evenly by triangle area, each with
+/// its +Y along the surface normal. Nothing is generated until the model has loaded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MeshSurfaceParams {
+    pub model: String,
+    #[serde(default)]
+    pub mesh_index: usize,
+    pub count: usize,
+    pub center: [f32; 3],
+    #[serde(default)]
+    pub seed: u32,
+    #[serde(skip)]
+    pub loaded: Option<LoadedSurface>,
+}
+
+impl MeshSurfaceParams {
+    /// The attached mesh, if it's the one `model` and `mesh_index` name
+    pub fn surface(&self) -> Option<&MeshSurface> {
+        self.loaded
+            .as_ref()
+            .filter(|loaded| loaded.model == self.model && loaded.mesh_index == self.mesh_index)
+            .map(|loaded| loaded.surface.as_ref())
+    }
+
+    pub fn attach(&mut self, surface: Arc<MeshSurface>) {
+        self.loaded = Some(LoadedSurface {
+            model: self.model.clone(),
+            mesh_index: self.mesh_index,
+            surface,
+        });
+    }
+}
+
+impl InstanceGenerator for MeshSurfaceParams {
+    fn instance_count(&self) -> usize {
+        self.count
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        let Some(surface) = self.surface() else {
+            return Vec::new();
+        };
+        let position = |i: u32| Vector3::from(surface.positions[i as usize]);
+        let triangles: Vec<[u32; 3]> = surface
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+
+        // Running total of triangle areas, to pick triangles in proportion to their area
+        let mut total_area = 0.0;
+        let cumulative_areas: Vec<f32> = triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                total_area += (position(b) - position(a))
+                    .cross(position(c) - position(a))
+                    .magnitude()
+                    * 0.5;
+                total_area
+            })
+            .collect();
+        if total_area <= 0.0 {
+            return Vec::new();
+        }
+
+        let center = Vector3::from(self.center);
+        let mut random = SeededRandom::new(self.seed);
+        (0..self.count)
+            .map(|_| {
+                let target = random.next() * total_area;
+                let index = cumulative_areas
+                    .partition_point(|&area| area <= target)
+                    .min(triangles.len() - 1);
+                let [a, b, c] = triangles[index];
+
+                // Uniform over the triangle
+                let r1 = random.next().sqrt();
+                let r2 = random.next();
+                let weights = [1.0 - r1, r1 * (1.0 - r2), r1 * r2];
+                let point =
+                    position(a) * weights[0] + position(b) * weights[1] + position(c) * weights[2];
+
+                // Smooth normals where the model has them, the face's otherwise
+                let normal = [a, b, c]
+                    .iter()
+                    .zip(weights)
+                    .map(|(&i, weight)| Vector3::from(surface.normals[i as usize]) * weight)
+                    .fold(Vector3::new(0.0, 0.0, 0.0), |sum, normal| sum + normal);
+                let normal = if normal.magnitude2() > 1e-8 {
+                    normal.normalize()
+                } else {
+                    let face = (position(b) - position(a)).cross(position(c) - position(a));
+                    if face.magnitude2() > 0.0 {
+                        face.normalize()
+                    } else {
+                        Vector3::unit_y()
+                    }
+                };
+
+                instance_at(
+                    center + point,
+                    Quaternion::from_arc(Vector3::unit_y(), normal, None),
+                )
+            })
+            .collect()
+    }
+}
+
+// ============================================================================
+// GENERATOR OPERATORS
+// ============================================================================
+
+impl InstanceRaw {
+    /// Move the instance by `transform`, applied after its own placement
+    fn transform(&mut self, transform: Matrix4<f32>) {
+        let linear = Matrix3::from_cols(
+            transform.x.truncate(),
+            transform.y.truncate(),
+            transform.z.truncate(),
+        );
+        // Normals take the inverse transpose so non-uniform scales keep them perpendicular
+        let normal_transform = linear
+            .invert()
+            .map(|inverse| inverse.transpose())
+            .unwrap_or(linear);
+        let normal: Matrix3<f32> = self.normal.into();
+        self.model = (transform * self.model_matrix()).into();
+        self.normal = (normal_transform * normal).into();
+    }
+}
+
+/// Another generator's layout, scaled, then rotated, then translated
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransformParams {
+    pub translation: [f32; 3],
+    /// Euler angles in degrees, about X, Y then Z
+    pub rotation: [f32; 3],
+    pub scale: [f32; 3],
+    pub generator: Box<GeneratorType>,
+}
+
+impl TransformParams {
+    fn matrix(&self) -> Matrix4<f32> {
+        let [x, y, z] = self.rotation;
+        let rotation = Quaternion::from(cgmath::Euler::new(
+            cgmath::Deg(x),
+            cgmath::Deg(y),
+            cgmath::Deg(z),
+        ));
+        let [sx, sy, sz] = self.scale;
+        Matrix4::from_translation(self.translation.into())
+            * Matrix4::from(rotation)
+            * Matrix4::from_nonuniform_scale(sx, sy, sz)
+    }
+}
+
+impl InstanceGenerator for TransformParams {
+    fn instance_count(&self) -> usize {
+        self.generator.instance_count()
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        let matrix = self.matrix();
+        let mut instances = self.generator.generate();
+        for instance in &mut instances {
+            instance.transform(matrix);
+        }
+        instances
+    }
+}
+
+/// Every instance of each generator, one after another
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnionParams {
+    pub generators: Vec<GeneratorType>,
+}
+
+impl InstanceGenerator for UnionParams {
+    fn instance_count(&self) -> usize {
+        self.generators
+            .iter()
+            .map(GeneratorType::instance_count)
+            .sum()
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        self.generators
+            .iter()
+            .flat_map(GeneratorType::generate)
+            .collect()
+    }
+}
+
+/// Another generator's instances, each moved up to `offset` along each axis
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JitterParams {
+    pub offset: [f32; 3],
+    #[serde(default)]
+    pub seed: u32,
+    pub generator: Box<GeneratorType>,
+}
+
+impl InstanceGenerator for JitterParams {
+    fn instance_count(&self) -> usize {
+        self.generator.instance_count()
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        let mut random = SeededRandom::new(self.seed);
+        let mut instances = self.generator.generate();
+        for instance in &mut instances {
+            let offset: [f32; 3] = self
+                .offset
+                .map(|offset| (random.next() * 2.0 - 1.0) * offset);
+            instance.transform(Matrix4::from_translation(offset.into()));
+        }
+        instances
+    }
+}
+
+/// Another generator's instances, each turned about its own origin by up to
+/// `rotation` degrees per axis and scaled by a factor between `min_scale` and `max_scale`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RandomizeParams {
+    pub rotation: [f32; 3],
+    pub min_scale: f32,
+    pub max_scale: f32,
+    #[serde(default)]
+    pub seed: u32,
+    pub generator: Box<GeneratorType>,
+}
+
+impl InstanceGenerator for RandomizeParams {
+    fn instance_count(&self) -> usize {
+        self.generator.instance_count()
+    }
+
+    fn generate(&self) -> Vec<InstanceRaw> {
+        let mut random = SeededRandom::new(self.seed);
+        let mut instances = self.generator.generate();
+        for instance in &mut instances {
+            let [x, y, z] = self
+                .rotation
+                .map(|rotation| cgmath::Deg((random.next() * 2.0 - 1.0) * rotation));
+            let rotation = Quaternion::from(cgmath::Euler::new(x, y, z));
+            let scale = self.min_scale + random.next() * (self.max_scale - self.min_scale);
+
+            // Applied before the instance's own placement, so it spins in place
+            let normal: Matrix3<f32> = instance.normal.into();
+            instance.model =
+                (instance.model_matrix() * Matrix4::from(rotation) * Matrix4::from_scale(scale))
+                    .into();
+            instance.normal = (normal * Matrix3::from(rotation)).into();
+        }
         instances
     }
 }
@@ -194,13 +1067,175 @@ pub enum GeneratorType {
     Grid(GridParams),
     #[serde(rename = "sphere")]
     Sphere(SphereParams),
+    #[serde(rename = "line")]
+    Line(LineParams),
+    #[serde(rename = "ring")]
+    Ring(RingParams),
+    #[serde(rename = "box")]
+    Box(BoxParams),
+    #[serde(rename = "spiral")]
+    Spiral(SpiralParams),
+    #[serde(rename = "poisson_disk")]
+    PoissonDisk(PoissonDiskParams),
+    #[serde(rename = "heightmap")]
+    Heightmap(HeightmapParams),
+    #[serde(rename = "mesh_surface")]
+    MeshSurface(MeshSurfaceParams),
+    #[serde(rename = "transform")]
+    Transform(TransformParams),
+    #[serde(rename = "union")]
+    Union(UnionParams),
+    #[serde(rename = "jitter")]
+    Jitter(JitterParams),
+    #[serde(rename = "randomize")]
+    Randomize(RandomizeParams),
 }
 
 impl GeneratorType {
+    /// One of each generator with starting parameters, for adding new systems
+    pub fn defaults() -> [GeneratorType; 9] {
+        let center = [0.0, 0.0, 0.0];
+        [
+            GeneratorType::Grid(GridParams {
+                rows: 10,
+                spacing: 1.0,
+                center,
+            }),
+            GeneratorType::Sphere(SphereParams {
+                count: 1000,
+                radius: 5.0,
+                center,
+            }),
+            GeneratorType::Line(LineParams {
+                count: 20,
+                start: [-10.0, 0.0, 0.0],
+                end: [10.0, 0.0, 0.0],
+            }),
+            GeneratorType::Ring(RingParams {
+                count: 24,
+                radius: 5.0,
+                center,
+            }),
+            GeneratorType::Box(BoxParams {
+                count: 500,
+                size: [20.0, 10.0, 20.0],
+                center,
+                seed: 0,
+            }),
+            GeneratorType::Spiral(SpiralParams {
+                count: 200,
+                turns: 3.0,
+                radius: 10.0,
+                center,
+            }),
+            GeneratorType::PoissonDisk(PoissonDiskParams {
+                min_distance: 2.0,
+                size: [40.0, 40.0],
+                center,
+                max_count: 1000,
+                seed: 0,
+            }),
+            GeneratorType::Heightmap(HeightmapParams {
+                path: crate::defaults::HEIGHTMAP_PATH.to_string(),
+                resolution: 32,
+                size: [40.0, 40.0],
+                height: 8.0,
+                center,
+                min_level: 0.0,
+                align_to_surface: false,
+                loaded: None,
+                failed: None,
+            }),
+            GeneratorType::MeshSurface(MeshSurfaceParams {
+                model: crate::defaults::SURFACE_SCATTER_MODEL_PATH.to_string(),
+                mesh_index: 0,
+                count: 500,
+                center,
+                seed: 0,
+                loaded: None,
+            }),
+        ]
+    }
+
+    /// One of each operator with starting parameters, wrapping `generator`
+    pub fn operators(generator: GeneratorType) -> [GeneratorType; 4] {
+        [
+            GeneratorType::Transform(TransformParams {
+                translation: [0.0; 3],
+                rotation: [0.0, 45.0, 0.0],
+                scale: [1.0; 3],
+                generator: Box::new(generator.clone()),
+            }),
+            GeneratorType::Union(UnionParams {
+                generators: vec![generator.clone()],
+            }),
+            GeneratorType::Jitter(JitterParams {
+                offset: [0.5; 3],
+                seed: 0,
+                generator: Box::new(generator.clone()),
+            }),
+            GeneratorType::Randomize(RandomizeParams {
+                rotation: [0.0, 180.0, 0.0],
+                min_scale: 0.5,
+                max_scale: 1.5,
+                seed: 0,
+                generator: Box::new(generator),
+            }),
+        ]
+    }
+
+    /// The generators an operator wraps; empty for the others
+    pub fn children_mut(&mut self) -> Vec<&mut GeneratorType> {
+        match self {
+            GeneratorType::Transform(TransformParams { generator, .. })
+            | GeneratorType::Jitter(JitterParams { generator, .. })
+            | GeneratorType::Randomize(RandomizeParams { generator, .. }) => vec![generator],
+            GeneratorType::Union(params) => params.generators.iter_mut().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// This generator and everything under it
+    fn for_each_mut(&mut self, visit: &mut impl FnMut(&mut GeneratorType)) {
+        visit(self);
+        for child in self.children_mut() {
+            child.for_each_mut(visit);
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GeneratorType::Grid(_) => "Grid",
+            GeneratorType::Sphere(_) => "Sphere",
+            GeneratorType::Line(_) => "Line",
+            GeneratorType::Ring(_) => "Ring",
+            GeneratorType::Box(_) => "Box",
+            GeneratorType::Spiral(_) => "Spiral",
+            GeneratorType::PoissonDisk(_) => "Poisson Disk",
+            GeneratorType::Heightmap(_) => "Heightmap",
+            GeneratorType::MeshSurface(_) => "Mesh Surface",
+            GeneratorType::Transform(_) => "Transform",
+            GeneratorType::Union(_) => "Union",
+            GeneratorType::Jitter(_) => "Jitter",
+            GeneratorType::Randomize(_) => "Randomize",
+        }
+    }
+
     pub fn generate(&self) -> Vec<InstanceRaw> {
         match self {
             GeneratorType::Grid(params) => params.generate(),
             GeneratorType::Sphere(params) => params.generate(),
+            GeneratorType::Line(params) => params.generate(),
+            GeneratorType::Ring(params) => params.generate(),
+            GeneratorType::Box(params) => params.generate(),
+            GeneratorType::Spiral(params) => params.generate(),
+            GeneratorType::PoissonDisk(params) => params.generate(),
+            GeneratorType::Heightmap(params) => params.generate(),
+            GeneratorType::MeshSurface(params) => params.generate(),
+            GeneratorType::Transform(params) => params.generate(),
+            GeneratorType::Union(params) => params.generate(),
+            GeneratorType::Jitter(params) => params.generate(),
+            GeneratorType::Randomize(params) => params.generate(),
         }
     }
 
@@ -208,6 +1243,17 @@ impl GeneratorType {
         match self {
             GeneratorType::Grid(params) => params.instance_count(),
             GeneratorType::Sphere(params) => params.instance_count(),
+            GeneratorType::Line(params) => params.instance_count(),
+            GeneratorType::Ring(params) => params.instance_count(),
+            GeneratorType::Box(params) => params.instance_count(),
+            GeneratorType::Spiral(params) => params.instance_count(),
+            GeneratorType::PoissonDisk(params) => params.instance_count(),
+            GeneratorType::Heightmap(params) => params.instance_count(),
+            GeneratorType::MeshSurface(params) => params.instance_count(),
+            GeneratorType::Transform(params) => params.instance_count(),
+            GeneratorType::Union(params) => params.instance_count(),
+            GeneratorType::Jitter(params) => params.instance_count(),
+            GeneratorType::Randomize(params) => params.instance_count(),
         }
     }
 }
@@ -224,13 +1270,76 @@ pub enum ParticleSystemDesc {
 // UNIFIED PARTICLE SYSTEM
 // ============================================================================
 
+/// How many instances a `LodDraw` draws
+pub enum InstanceCount<'a> {
+    Direct(u32),
+    /// Read from a `DrawIndexedIndirectArgs` at this offset, written on the GPU
+    Indirect(&'a wgpu::Buffer, wgpu::BufferAddress),
+}
+
+/// Instances to draw with one LOD level's mesh
+pub struct LodDraw<'a> {
+    /// 0 for the system's own mesh, otherwise one past the index into its LODs
+    pub level: usize,
+    /// Animation time offset the instances play at, selecting the posed vertices
+    pub offset: usize,
+    pub instances: wgpu::BufferSlice<'a>,
+    pub count: InstanceCount<'a>,
+}
+
 pub struct ParticleSystem {
     name: String,
     model_path: String,
     mesh_index: usize,
     material_source: crate::model::MaterialSource,
+    /// Draw every mesh of the model with its own material, instead of just `mesh_index`
+    entire_model: bool,
+    /// Materials used in place of a mesh's own when drawing the entire model, by mesh index
+    material_overrides: BTreeMap<usize, crate::model::MaterialSource>,
     generator: GeneratorType,
+    attributes: InstanceAttributes,
+    /// The generator's instances with `attributes` applied. Dynamic systems spawn
+    /// particles from these.
+    emitters: Vec<InstanceRaw>,
+    /// Makes the system dynamic: particles spawn from the generated instances
+    simulation: Option<SimulationParams>,
+    /// Created on the first `simulate` and whenever the particle count changes
+    simulation_state: Option<Simulation>,
     instance_buffer: wgpu::Buffer,
+    /// CPU copy of the uploaded instances, kept for depth sorting. For systems simulated
+    /// on the GPU these are the emitters, as the particles never leave the GPU.
+    instances: Vec<InstanceRaw>,
+    /// Eye position the instance buffer was last sorted for
+    sorted_for: Option<Point3<f32>>,
+    /// World-space bounds of each instance, in `instances` order
+    instance_bounds: Vec<Aabb>,
+    /// Mesh bounds `instance_bounds` was computed from
+    bounds_for: Option<Aabb>,
+    /// Coarser meshes for distant instances, nearest first
+    lods: Vec<LodLevel>,
+    /// Instances that passed the last frustum test, packed for drawing level by level
+    visible_buffer: wgpu::Buffer,
+    /// Indices into `instances` of what `visible_buffer` holds
+    visible: Vec<u32>,
+    /// Range of `visible` each bucket draws: one per LOD level and animation time
+    /// offset, level-major with the system's own mesh first
+    bucket_ranges: Vec<Range<u32>>,
+    /// Per-bucket copies of a GPU-simulated system's instances, when it has LODs
+    lod_buckets: Option<LodBuckets>,
+    /// Plays the model's animation on its skinned and morphed meshes
+    animation: Option<AnimationPlayer>,
+    /// Weights for the model's morph targets in place of its own or the animation's
+    morph_weights: Option<MorphWeights>,
+    /// What a `MorphWeights::Script` function returned this frame
+    script_weights: Vec<f32>,
+    /// Last error from a `MorphWeights::Script` function, logged once until it changes
+    script_error: Option<String>,
+    /// Posed copies of the deformed meshes drawn, by mesh index in the system's model
+    deformed_meshes: HashMap<usize, DeformedMesh>,
+    /// Model `deformed_meshes` were posed from
+    deformed_model: String,
+    /// `instances` changed since `visible_buffer` was last packed
+    visible_stale: bool,
     buffer_capacity: usize,
     current_instance_count: usize,
     needs_rebuild: bool,
@@ -245,6 +1354,7 @@ impl ParticleSystem {
         mesh_index: usize,
         material_source: crate::model::MaterialSource,
         generator: GeneratorType,
+        simulation: Option<SimulationParams>,
     ) -> Self {
         let instances = generator.generate();
         let instance_count = instances.len();
@@ -255,13 +1365,37 @@ impl ParticleSystem {
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         });
 
+        let visible_buffer = Self::create_visible_buffer(device, &name, instance_count);
+
         Self {
             name,
             model_path,
             mesh_index,
             material_source,
+            entire_model: false,
+            material_overrides: BTreeMap::new(),
             generator,
+            attributes: InstanceAttributes::default(),
+            emitters: instances.clone(),
+            simulation,
+            simulation_state: None,
             instance_buffer,
+            instances,
+            sorted_for: None,
+            instance_bounds: Vec::new(),
+            bounds_for: None,
+            lods: Vec::new(),
+            visible_buffer,
+            visible: Vec::new(),
+            bucket_ranges: Vec::new(),
+            lod_buckets: None,
+            animation: None,
+            morph_weights: None,
+            script_weights: Vec::new(),
+            script_error: None,
+            deformed_meshes: HashMap::new(),
+            deformed_model: String::new(),
+            visible_stale: true,
             buffer_capacity: instance_count,
             current_instance_count: instance_count,
             needs_rebuild: false,
@@ -297,6 +1431,64 @@ impl ParticleSystem {
         self.material_source = material;
     }
 
+    pub fn entire_model(&self) -> bool {
+        self.entire_model
+    }
+
+    pub fn set_entire_model(&mut self, entire_model: bool) {
+        self.entire_model = entire_model;
+    }
+
+    pub fn material_overrides(&self) -> &BTreeMap<usize, crate::model::MaterialSource> {
+        &self.material_overrides
+    }
+
+    pub fn set_material_overrides(
+        &mut self,
+        overrides: BTreeMap<usize, crate::model::MaterialSource>,
+    ) {
+        self.material_overrides = overrides;
+    }
+
+    /// Draw mesh `index` with `material` when drawing the entire model, or with its own
+    /// material again with None
+    pub fn set_material_override(
+        &mut self,
+        index: usize,
+        material: Option<crate::model::MaterialSource>,
+    ) {
+        match material {
+            Some(material) => self.material_overrides.insert(index, material),
+            None => self.material_overrides.remove(&index),
+        };
+    }
+
+    /// Indices of the meshes of `model` this system draws, each with its material
+    pub fn mesh_materials(
+        &self,
+        model: &crate::model::Model,
+    ) -> Vec<(usize, crate::model::MaterialSource)> {
+        if !self.entire_model {
+            return if self.mesh_index < model.meshes.len() {
+                vec![(self.mesh_index, self.material_source.clone())]
+            } else {
+                Vec::new()
+            };
+        }
+        model
+            .meshes
+            .iter()
+            .enumerate()
+            .map(|(index, mesh)| {
+                let material = self
+                    .material_overrides
+                    .get(&index)
+                    .unwrap_or(&mesh.material_source);
+                (index, material.clone())
+            })
+            .collect()
+    }
+
     pub fn generator(&self) -> &GeneratorType {
         &self.generator
     }
@@ -310,20 +1502,210 @@ impl ParticleSystem {
         self.mark_dirty();
     }
 
+    pub fn attributes(&self) -> &InstanceAttributes {
+        &self.attributes
+    }
+
+    pub fn attributes_mut(&mut self) -> &mut InstanceAttributes {
+        &mut self.attributes
+    }
+
+    pub fn set_attributes(&mut self, attributes: InstanceAttributes) {
+        self.attributes = attributes;
+        self.mark_dirty();
+    }
+
+    pub fn simulation(&self) -> Option<&SimulationParams> {
+        self.simulation.as_ref()
+    }
+
+    pub fn simulation_mut(&mut self) -> Option<&mut SimulationParams> {
+        self.simulation.as_mut()
+    }
+
+    /// Make the system dynamic, or static again with `None`
+    pub fn set_simulation(&mut self, simulation: Option<SimulationParams>) {
+        self.simulation = simulation;
+        self.mark_dirty();
+    }
+
+    pub fn lods(&self) -> &[LodLevel] {
+        &self.lods
+    }
+
+    /// Replace the LOD levels, keeping at most `MAX_LOD_LEVELS` counting the system's
+    /// own mesh, ordered nearest first
+    pub fn set_lods(&mut self, mut lods: Vec<LodLevel>) {
+        lods.truncate(MAX_LOD_LEVELS - 1);
+        lods.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        self.lods = lods;
+    }
+
+    pub fn animation(&self) -> Option<&AnimationPlayer> {
+        self.animation.as_ref()
+    }
+
+    pub fn animation_mut(&mut self) -> Option<&mut AnimationPlayer> {
+        self.animation.as_mut()
+    }
+
+    /// Animate the model's skinned and morphed meshes, or leave them undeformed with `None`
+    pub fn set_animation(&mut self, animation: Option<AnimationPlayer>) {
+        self.animation = animation;
+        self.mark_dirty();
+    }
+
+    pub fn morph_weights(&self) -> Option<&MorphWeights> {
+        self.morph_weights.as_ref()
+    }
+
+    /// Set the weights of the model's morph targets, or leave them to the model and its
+    /// animation with `None`
+    pub fn set_morph_weights(&mut self, weights: Option<MorphWeights>) {
+        self.morph_weights = weights;
+        self.script_weights.clear();
+        self.script_error = None;
+    }
+
+    /// Distinct animation time offsets the instances are spread over
+    pub fn offset_count(&self) -> usize {
+        self.animation
+            .as_ref()
+            .map_or(1, AnimationPlayer::offset_count)
+    }
+
+    /// Whether the instance buffer is written by the compute shader, so the CPU has no
+    /// copy of it to sort or cull
+    fn simulated_on_gpu(&self) -> bool {
+        self.simulation_state
+            .as_ref()
+            .is_some_and(Simulation::on_gpu)
+    }
+
     pub fn num_instances(&self) -> u32 {
         self.current_instance_count as u32
     }
 
-    pub fn instance_buffer(&self) -> &wgpu::Buffer {
-        &self.instance_buffer
+    /// Instances that survived the last `cull`, packed from the start of `visible_buffer`
+    pub fn num_visible(&self) -> u32 {
+        if self.simulated_on_gpu() {
+            return self.num_instances();
+        }
+        self.visible.len() as u32
+    }
+
+    /// What to draw with each level's mesh for `part`, the position of a mesh among those
+    /// the system draws, farthest level first
+    pub fn lod_draws(&self, part: usize) -> Vec<LodDraw<'_>> {
+        let offset_count = self.offset_count();
+        if self.simulated_on_gpu() {
+            return match &self.lod_buckets {
+                Some(buckets) => (0..buckets.layout().bucket_count())
+                    .rev()
+                    .map(|bucket| {
+                        let (buffer, offset) = buckets.bucket_draw(part, bucket);
+                        LodDraw {
+                            level: bucket / buckets.layout().offset_count,
+                            offset: bucket % buckets.layout().offset_count,
+                            instances: buckets.bucket_instances(bucket),
+                            count: InstanceCount::Indirect(buffer, offset),
+                        }
+                    })
+                    .collect(),
+                // Not bucketed, so everything draws at full detail. Each time offset's
+                // particles are already a run of the instance buffer.
+                None => (0..offset_count)
+                    .filter_map(|offset| {
+                        let (instances, count) = self.offset_instances(offset)?;
+                        Some(LodDraw {
+                            level: 0,
+                            offset,
+                            instances,
+                            count: InstanceCount::Direct(count),
+                        })
+                    })
+                    .collect(),
+            };
+        }
+
+        let stride = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        self.bucket_ranges
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, range)| !range.is_empty())
+            .map(|(bucket, range)| LodDraw {
+                level: bucket / offset_count,
+                offset: bucket % offset_count,
+                instances: self.visible_buffer.slice(
+                    range.start as wgpu::BufferAddress * stride
+                        ..range.end as wgpu::BufferAddress * stride,
+                ),
+                count: InstanceCount::Direct(range.len() as u32),
+            })
+            .collect()
+    }
+
+    /// The run of the instance buffer playing at animation time offset `offset`, and
+    /// its length, culled or not. `None` when no instance plays at it.
+    pub fn offset_instances(&self, offset: usize) -> Option<(wgpu::BufferSlice<'_>, u32)> {
+        let stride = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        let range =
+            animation_offset_range(offset, self.num_instances() as usize, self.offset_count());
+        if range.is_empty() {
+            return None;
+        }
+        let slice = self.instance_buffer.slice(
+            range.start as wgpu::BufferAddress * stride..range.end as wgpu::BufferAddress * stride,
+        );
+        Some((slice, range.len() as u32))
+    }
+
+    fn create_visible_buffer(device: &wgpu::Device, name: &str, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("Particle System '{}' Visible Instances", name)),
+            size: (capacity.max(1) * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
     }
 
     pub fn needs_rebuild(&self) -> bool {
         self.needs_rebuild && self.last_edit_time.elapsed().as_millis() >= DEBOUNCE_MS as u128
     }
 
-    pub fn rebuild(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        let instances = self.generator.generate();
+    /// Regenerate the instances, asking `scripts` for any scripted attribute values
+    pub fn rebuild(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scripts: &mut ScriptValues,
+    ) {
+        let mut instances = self.generator.generate();
+        self.attributes.apply(&mut instances, scripts);
+        // Time offsets go to runs of instances, so scatter neighbours across the runs
+        if self.offset_count() > 1 {
+            let mut keyed: Vec<(u32, InstanceRaw)> = instances
+                .into_iter()
+                .enumerate()
+                .map(|(i, instance)| (pcg_hash(i as u32), instance))
+                .collect();
+            keyed.sort_by_key(|(key, _)| *key);
+            instances = keyed.into_iter().map(|(_, instance)| instance).collect();
+        }
+        self.emitters = instances.clone();
+
+        // Dynamic systems restart from the new emitters on their next step
+        if self.simulation.is_some() {
+            self.simulation_state = None;
+            self.needs_rebuild = false;
+            return;
+        }
+        if self.simulation_state.take().is_some() {
+            // The buffer was sized for particles rather than instances
+            self.buffer_capacity = usize::MAX;
+        }
+
         let new_count = instances.len();
 
         if new_count != self.buffer_capacity {
@@ -333,16 +1715,400 @@ impl ParticleSystem {
                 contents: bytemuck::cast_slice(&instances),
                 usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             });
+            self.visible_buffer = Self::create_visible_buffer(device, &self.name, new_count);
             self.buffer_capacity = new_count;
         } else {
             // Same size - just update contents with queue.write_buffer
             queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
         }
 
+        self.instances = instances;
+        self.sorted_for = None;
+        self.bounds_for = None;
+        self.visible_stale = true;
         self.current_instance_count = new_count;
         self.needs_rebuild = false;
     }
 
+    /// Average instance position, used to order blended systems against each other
+    pub fn center(&self) -> Point3<f32> {
+        if self.instances.is_empty() {
+            return Point3::origin();
+        }
+        let positions: Vec<Point3<f32>> =
+            self.instances.iter().map(InstanceRaw::position).collect();
+        Point3::centroid(&positions)
+    }
+
+    /// Reorder the instance buffer farthest first from `eye`, for alpha blending. Each
+    /// animation time offset's run is sorted on its own so instances keep their offset.
+    /// Skipped when the eye hasn't moved since the last sort.
+    pub fn sort_back_to_front(&mut self, queue: &wgpu::Queue, eye: Point3<f32>) {
+        if self.sorted_for == Some(eye) || self.simulated_on_gpu() {
+            return;
+        }
+        let offset_count = self.offset_count();
+        let instance_count = self.instances.len();
+        for offset in 0..offset_count {
+            let range = animation_offset_range(offset, instance_count, offset_count);
+            self.instances[range].sort_by(|a, b| {
+                let distance_a = a.position().distance2(eye);
+                let distance_b = b.position().distance2(eye);
+                distance_b.total_cmp(&distance_a)
+            });
+        }
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+        self.sorted_for = Some(eye);
+        self.bounds_for = None;
+        self.visible_stale = true;
+    }
+
+    /// Pack the instances whose bounds touch `frustum` into the visible buffer, grouped
+    /// by the LOD level their distance from `eye` selects and then by animation time
+    /// offset, otherwise keeping their order. Every instance is visible without a
+    /// frustum. Only uploads when the visible set or the instances changed.
+    pub fn cull(
+        &mut self,
+        queue: &wgpu::Queue,
+        mesh_bounds: &Aabb,
+        frustum: Option<&Frustum>,
+        eye: Point3<f32>,
+    ) {
+        if self.simulated_on_gpu() {
+            return;
+        }
+        if self.bounds_for != Some(*mesh_bounds) {
+            self.instance_bounds = self
+                .instances
+                .iter()
+                .map(|instance| mesh_bounds.transformed(&instance.model_matrix()))
+                .collect();
+            self.bounds_for = Some(*mesh_bounds);
+        }
+
+        let offset_count = self.offset_count();
+        let instance_count = self.instances.len();
+        let mut buckets = vec![Vec::new(); (self.lods.len() + 1) * offset_count];
+        for i in 0..instance_count as u32 {
+            if frustum.is_none_or(|frustum| frustum.intersects(&self.instance_bounds[i as usize])) {
+                let level = lod_for(&self.lods, eye, self.instances[i as usize].position());
+                let offset = animation_offset_for(i as usize, instance_count, offset_count);
+                buckets[level * offset_count + offset].push(i);
+            }
+        }
+        let mut bucket_ranges = Vec::with_capacity(buckets.len());
+        let mut visible = Vec::with_capacity(instance_count);
+        for bucket in buckets {
+            let start = visible.len() as u32;
+            visible.extend(bucket);
+            bucket_ranges.push(start..visible.len() as u32);
+        }
+        if visible == self.visible && bucket_ranges == self.bucket_ranges && !self.visible_stale {
+            return;
+        }
+        self.bucket_ranges = bucket_ranges;
+
+        let packed: Vec<InstanceRaw> = visible
+            .iter()
+            .map(|&i| self.instances[i as usize])
+            .collect();
+        queue.write_buffer(&self.visible_buffer, 0, bytemuck::cast_slice(&packed));
+        self.visible = visible;
+        self.visible_stale = false;
+    }
+
+    /// Sort a GPU-simulated system's instances into per-level buckets with the compute
+    /// shader, one per animation time offset, recording the work into `encoder`.
+    /// `index_counts` holds the index count of each part's mesh at each level. Does
+    /// nothing for other systems, or without LODs.
+    pub fn bucket_lods(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        bucketer: &LodBucketer,
+        eye: Point3<f32>,
+        index_counts: &[Vec<u32>],
+    ) {
+        if !self.simulated_on_gpu() || self.lods.is_empty() || !bucketer.has_compute() {
+            self.lod_buckets = None;
+            return;
+        }
+        let layout = BucketLayout {
+            capacity: self.buffer_capacity,
+            level_count: self.lods.len() + 1,
+            offset_count: self.offset_count(),
+        };
+        if self
+            .lod_buckets
+            .as_ref()
+            .is_none_or(|buckets| !buckets.matches(layout, index_counts.len()))
+        {
+            self.lod_buckets = Some(LodBuckets::new(
+                device,
+                bucketer,
+                &self.name,
+                layout,
+                index_counts.len(),
+                &self.instance_buffer,
+            ));
+        }
+        if let Some(buckets) = &self.lod_buckets {
+            buckets.bucket(queue, encoder, bucketer, &self.lods, eye, index_counts);
+        }
+    }
+
+    /// Advance a dynamic system by `dt` seconds, recording any compute work into
+    /// `encoder`. Does nothing for static systems.
+    pub fn simulate(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        simulator: &ParticleSimulator,
+        dt: f32,
+    ) {
+        let Some(params) = &self.simulation else {
+            return;
+        };
+
+        let capacity = params.capacity();
+        if self
+            .simulation_state
+            .as_ref()
+            .is_none_or(|state| state.capacity() != capacity)
+        {
+            let mut usage = wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST;
+            if simulator.backend() == SimulationBackend::Compute {
+                usage |= wgpu::BufferUsages::STORAGE;
+            }
+            self.instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("Particle System '{}' Instance Buffer", self.name)),
+                size: (capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+                usage,
+                mapped_at_creation: false,
+            });
+            self.visible_buffer = Self::create_visible_buffer(device, &self.name, capacity);
+            // Bound to the old instance buffer
+            self.lod_buckets = None;
+            self.simulation_state = Some(Simulation::new(
+                device,
+                simulator,
+                &self.name,
+                capacity,
+                self.emitters.clone(),
+                &self.instance_buffer,
+            ));
+            self.instances = self.emitters.clone();
+            self.buffer_capacity = capacity;
+            self.current_instance_count = capacity;
+            self.visible.clear();
+        }
+
+        let Some(state) = &mut self.simulation_state else {
+            return;
+        };
+        if let Some(instances) = state.step(queue, encoder, simulator, params, dt) {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+            self.instances = instances;
+            self.sorted_for = None;
+            self.bounds_for = None;
+            self.visible_stale = true;
+        }
+    }
+
+    /// Move the animation on by `dt` seconds
+    pub fn advance_animation(&mut self, dt: f32) {
+        if let Some(player) = &mut self.animation {
+            player.advance(dt);
+        }
+    }
+
+    /// Ask `scripts` for this frame's weights when they come from a
+    /// `MorphWeights::Script` function, called with the most morph targets of any mesh
+    /// among `mesh_indices` of `model`, the system's model
+    pub fn evaluate_morph_weights(
+        &mut self,
+        model: &crate::model::Model,
+        mesh_indices: &[usize],
+        scripts: &mut ScriptValues,
+    ) {
+        let Some(MorphWeights::Script { function }) = &self.morph_weights else {
+            return;
+        };
+        let target_count = mesh_indices
+            .iter()
+            .filter_map(|&index| {
+                model
+                    .meshes
+                    .get(index)?
+                    .deformation
+                    .as_ref()?
+                    .morph
+                    .as_ref()
+            })
+            .map(|morph| morph.targets.len())
+            .max()
+            .unwrap_or_default();
+        match scripts(function, target_count) {
+            Ok(weights) => {
+                self.script_weights = weights;
+                self.script_error = None;
+            }
+            Err(e) => {
+                if self.script_error.as_ref() != Some(&e) {
+                    log::error!("Morph weights: {}", e);
+                }
+                self.script_weights.clear();
+                self.script_error = Some(e);
+            }
+        }
+    }
+
+    /// Pose the skinned and morphed meshes among `mesh_indices` of `model`, the system's
+    /// model, once per animation time offset, recording any compute work into `encoder`.
+    /// Without an animation or morph weights they draw undeformed.
+    pub fn animate(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        deformer: &Deformer,
+        model: &crate::model::Model,
+        mesh_indices: &[usize],
+    ) {
+        if self.deformed_model != self.model_path {
+            self.deformed_meshes.clear();
+            self.deformed_model = self.model_path.clone();
+        }
+        let deformations: Vec<(usize, &crate::model::MeshDeformation)> = mesh_indices
+            .iter()
+            .filter_map(|&index| Some((index, model.meshes.get(index)?.deformation.as_deref()?)))
+            .filter(|(_, deformation)| !deformation.bind_vertices.is_empty())
+            .collect();
+        if self.animation.is_none() && self.morph_weights.is_none() {
+            self.deformed_meshes.clear();
+            return;
+        }
+        self.deformed_meshes
+            .retain(|index, _| deformations.iter().any(|(deformed, _)| deformed == index));
+        if deformations.is_empty() {
+            return;
+        }
+
+        let offset_count = self.offset_count();
+        let poses: Vec<Pose> = (0..offset_count)
+            .map(|offset| match &self.animation {
+                Some(player) => player.pose(
+                    &model.skeleton,
+                    &model.animations,
+                    player.offset_time(offset),
+                ),
+                None => model.skeleton.rest_pose(),
+            })
+            .collect();
+        let world: Vec<Vec<Matrix4<f32>>> = poses
+            .iter()
+            .map(|pose| model.skeleton.world_matrices(&pose.transforms))
+            .collect();
+        let weights_override: Option<&[f32]> = match &self.morph_weights {
+            Some(MorphWeights::Fixed { weights }) => Some(weights),
+            Some(MorphWeights::Script { .. }) => Some(&self.script_weights),
+            None => None,
+        };
+        for (index, deformation) in deformations {
+            let skin = deformation
+                .skin
+                .as_ref()
+                .and_then(|mesh_skin| model.skins.get(mesh_skin.skin));
+            let joint_count = skin.map_or(1, |skin| skin.joints.len());
+            let name = &model.meshes[index].name;
+            let posed = self
+                .deformed_meshes
+                .entry(index)
+                .and_modify(|posed| {
+                    if !posed.matches(joint_count, offset_count) {
+                        *posed = DeformedMesh::new(
+                            device,
+                            deformer,
+                            name,
+                            deformation,
+                            joint_count,
+                            offset_count,
+                        );
+                    }
+                })
+                .or_insert_with(|| {
+                    DeformedMesh::new(
+                        device,
+                        deformer,
+                        name,
+                        deformation,
+                        joint_count,
+                        offset_count,
+                    )
+                });
+            let joint_matrices: Vec<Vec<Matrix4<f32>>> = match skin {
+                Some(skin) => world
+                    .iter()
+                    .map(|world| skin.joint_matrices(world))
+                    .collect(),
+                None => Vec::new(),
+            };
+            // Set weights win over the node's posed ones, which win over the mesh's own
+            let weights: Vec<Vec<f32>> = match &deformation.morph {
+                Some(morph) => poses
+                    .iter()
+                    .map(|pose| {
+                        weights_override
+                            .or_else(|| {
+                                morph
+                                    .node
+                                    .and_then(|node| pose.weights.get(node))
+                                    .map(Vec::as_slice)
+                                    .filter(|weights| !weights.is_empty())
+                            })
+                            .unwrap_or(&morph.weights)
+                            .to_vec()
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            posed.pose(
+                queue,
+                encoder,
+                deformer,
+                deformation,
+                &joint_matrices,
+                &weights,
+            );
+        }
+    }
+
+    /// Vertices of mesh `mesh_index` of the system's model posed for animation time
+    /// offset `offset`, when it is skinned or morphed and being posed
+    pub fn deformed_vertices(
+        &self,
+        mesh_index: usize,
+        offset: usize,
+    ) -> Option<wgpu::BufferSlice<'_>> {
+        self.deformed_meshes
+            .get(&mesh_index)
+            .map(|posed| posed.vertices(offset))
+    }
+
+    /// Bounds of mesh `mesh_index` of the system's model across every posed copy, when
+    /// it is skinned or morphed and being posed
+    pub fn deformed_bounds(&self, mesh_index: usize) -> Option<Aabb> {
+        self.deformed_meshes
+            .get(&mesh_index)
+            .map(DeformedMesh::bounds)
+    }
+
     pub fn mark_dirty(&mut self) {
         self.needs_rebuild = true;
         self.last_edit_time = web_time::Instant::now();
@@ -355,12 +2121,105 @@ impl ParticleSystem {
 
 pub struct ParticleSystemManager {
     systems: HashMap<String, ParticleSystem>,
+    /// Images for heightmap generators by path; None while loading
+    heightmaps: HashMap<String, Option<Heightmap>>,
+    /// Models surface scatter generators have asked for, so each is only requested once
+    requested_surface_models: HashSet<String>,
 }
 
 impl ParticleSystemManager {
     pub fn new() -> Self {
         Self {
             systems: HashMap::new(),
+            heightmaps: HashMap::new(),
+            requested_surface_models: HashSet::new(),
+        }
+    }
+
+    /// Heightmap paths that systems use but haven't been requested yet. Systems whose
+    /// heightmap has already loaded get it attached and regenerate.
+    pub fn heightmaps_to_load(&mut self) -> Vec<String> {
+        let mut paths = Vec::new();
+        for system in self.systems.values_mut() {
+            let mut attached = false;
+            system.generator.for_each_mut(&mut |generator| {
+                let GeneratorType::Heightmap(params) = generator else {
+                    return;
+                };
+                if params.heightmap().is_some() || params.load_failed() {
+                    return;
+                }
+                params.failed = None;
+                match self.heightmaps.get(&params.path) {
+                    Some(Some(heightmap)) => {
+                        params.loaded = Some(heightmap.clone());
+                        attached = true;
+                    }
+                    Some(None) => {}
+                    None => {
+                        self.heightmaps.insert(params.path.clone(), None);
+                        paths.push(params.path.clone());
+                    }
+                }
+            });
+            if attached {
+                system.mark_dirty();
+            }
+        }
+        paths
+    }
+
+    /// Attach loaded meshes to the surface scatter systems that use them, so they
+    /// regenerate. Returns the models still to be requested.
+    pub fn attach_surfaces(
+        &mut self,
+        models: &HashMap<String, Arc<crate::model::Model>>,
+    ) -> Vec<String> {
+        let mut paths = Vec::new();
+        for system in self.systems.values_mut() {
+            let mut attached = false;
+            system.generator.for_each_mut(&mut |generator| {
+                let GeneratorType::MeshSurface(params) = generator else {
+                    return;
+                };
+                if params.surface().is_some() {
+                    return;
+                }
+                let Some(model) = models.get(&params.model) else {
+                    if self.requested_surface_models.insert(params.model.clone()) {
+                        paths.push(params.model.clone());
+                    }
+                    return;
+                };
+                if let Some(mesh) = model.meshes.get(params.mesh_index) {
+                    params.attach(Arc::clone(&mesh.surface));
+                    attached = true;
+                }
+            });
+            if attached {
+                system.mark_dirty();
+            }
+        }
+        paths
+    }
+
+    pub fn add_heightmap(&mut self, heightmap: Heightmap) {
+        self.heightmaps
+            .insert(heightmap.path.clone(), Some(heightmap));
+    }
+
+    /// Forget the load of `path`, so generators naming it ask again once their path
+    /// is edited
+    pub fn heightmap_failed(&mut self, path: &str) {
+        self.heightmaps.remove(path);
+        for system in self.systems.values_mut() {
+            system.generator.for_each_mut(&mut |generator| {
+                if let GeneratorType::Heightmap(params) = generator
+                    && params.path == path
+                {
+                    params.failed = Some(path.to_string());
+                }
+            });
         }
     }
 
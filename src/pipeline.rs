@@ -0,0 +1,363 @@
+use crate::material_schema::MaterialLayout;
+use crate::model::{AlphaMode, ModelVertex, Vertex};
+use crate::particle_system::InstanceRaw;
+use crate::texture::GpuTexture;
+use std::collections::HashMap;
+
+/// Pipeline-overridable constant material shaders can declare to learn their alpha mode
+const ALPHA_MODE_OVERRIDE: &str = "ALPHA_MODE";
+
+/// Vertex buffer arrangement a pipeline was built for.
+/// Shaders are free to be paired with any layout whose locations they consume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VertexLayout {
+    /// `ModelVertex` in slot 0, `InstanceRaw` in slot 1
+    ModelInstanced,
+}
+
+impl VertexLayout {
+    pub fn buffers(&self) -> Vec<wgpu::VertexBufferLayout<'static>> {
+        match self {
+            VertexLayout::ModelInstanced => vec![ModelVertex::desc(), InstanceRaw::desc()],
+        }
+    }
+}
+
+/// Identifies a render pipeline: one per (shader, vertex layout, alpha mode)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub shader: String,
+    pub vertex_layout: VertexLayout,
+    pub alpha_mode: AlphaMode,
+}
+
+impl PipelineKey {
+    pub fn new(shader: &str, vertex_layout: VertexLayout, alpha_mode: AlphaMode) -> Self {
+        Self {
+            shader: shader.to_string(),
+            vertex_layout,
+            alpha_mode,
+        }
+    }
+}
+
+/// An opaque pipeline drawing a shader's `vs_main` and `fs_main` into one color target
+pub struct RenderPipelineDesc<'a> {
+    pub label: &'a str,
+    pub layout: &'a wgpu::PipelineLayout,
+    pub shader: &'a wgpu::ShaderModule,
+    pub vertex_layouts: &'a [wgpu::VertexBufferLayout<'a>],
+    pub color_format: wgpu::TextureFormat,
+    /// Depth tested and written with `Less` when set
+    pub depth_format: Option<wgpu::TextureFormat>,
+    pub sample_count: u32,
+}
+
+pub fn create_render_pipeline(
+    device: &wgpu::Device,
+    desc: &RenderPipelineDesc,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(desc.label),
+        layout: Some(desc.layout),
+        vertex: wgpu::VertexState {
+            module: desc.shader,
+            entry_point: Some("vs_main"),
+            buffers: desc.vertex_layouts,
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: desc.shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: desc.color_format,
+                blend: Some(wgpu::BlendState {
+                    alpha: wgpu::BlendComponent::REPLACE,
+                    color: wgpu::BlendComponent::REPLACE,
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: desc.depth_format.map(|format| wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: desc.sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// MSAA sample counts (out of 1, 2, 4 and 8) usable for a scene rendered in
+/// `color_format` with a `GpuTexture::DEPTH_FORMAT` depth buffer
+pub fn supported_sample_counts(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    color_format: wgpu::TextureFormat,
+) -> Vec<u32> {
+    // Without adapter specific format features only the counts WebGPU guarantees are allowed
+    let format_features = |format: wgpu::TextureFormat| {
+        if device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        {
+            adapter.get_texture_format_features(format)
+        } else {
+            format.guaranteed_format_features(device.features())
+        }
+    };
+    let color = format_features(color_format);
+    let depth = format_features(GpuTexture::DEPTH_FORMAT);
+    [1, 2, 4, 8]
+        .into_iter()
+        .filter(|&count| {
+            count == 1
+                || (color.flags.sample_count_supported(count)
+                    && color
+                        .flags
+                        .contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+                    && depth.flags.sample_count_supported(count))
+        })
+        .collect()
+}
+
+/// A compiled material shader and the layouts reflected from it
+pub struct ShaderEntry {
+    pub module: wgpu::ShaderModule,
+    pub material_layout: MaterialLayout,
+    pub pipeline_layout: wgpu::PipelineLayout,
+}
+
+/// Compiled shader modules and the render pipelines built from them.
+///
+/// Shaders are registered once their source has been loaded; pipelines are
+/// created lazily the first time a material asks for a (shader, layout) pair.
+/// Lines of the form `#import name` are replaced with the registered import first.
+pub struct PipelineCache {
+    per_frame_layout: wgpu::BindGroupLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+    imports: HashMap<String, String>,
+    shaders: HashMap<String, ShaderEntry>,
+    pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+}
+
+impl PipelineCache {
+    pub fn new(
+        per_frame_layout: wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        Self {
+            per_frame_layout,
+            color_format,
+            sample_count,
+            imports: HashMap::new(),
+            shaders: HashMap::new(),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Change the MSAA sample count of the targets pipelines render into.
+    /// Existing pipelines are dropped and rebuilt on next use.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        if sample_count != self.sample_count {
+            self.sample_count = sample_count;
+            self.pipelines.clear();
+        }
+    }
+
+    /// Register WGSL that shaders can pull in with `#import name`
+    pub fn add_import(&mut self, name: &str, source: String) {
+        self.imports.insert(name.to_string(), source);
+    }
+
+    /// Expand `#import` lines. Unknown imports are an error.
+    pub fn preprocess(&self, source: &str) -> anyhow::Result<String> {
+        let mut output = String::with_capacity(source.len());
+        for line in source.lines() {
+            if let Some(name) = line.trim().strip_prefix("#import") {
+                let name = name.trim();
+                let import = self
+                    .imports
+                    .get(name)
+                    .ok_or_else(|| anyhow::anyhow!("unknown shader import '{}'", name))?;
+                output.push_str(import);
+            } else {
+                output.push_str(line);
+            }
+            output.push('\n');
+        }
+        Ok(output)
+    }
+
+    /// Reflect and compile WGSL source and register it under `path`.
+    /// Replaces any previous module and drops pipelines built from it.
+    pub fn insert_shader(
+        &mut self,
+        device: &wgpu::Device,
+        path: &str,
+        source: &str,
+    ) -> anyhow::Result<()> {
+        let source = self.preprocess(source)?;
+        // Reflection also validates, so a broken shader is rejected here instead of panicking in wgpu
+        let material_layout = MaterialLayout::new(device, path, &source)?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(path),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{} Pipeline Layout", path)),
+            bind_group_layouts: &[&self.per_frame_layout, &material_layout.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        self.pipelines.retain(|key, _| key.shader != path);
+        self.shaders.insert(
+            path.to_string(),
+            ShaderEntry {
+                module,
+                material_layout,
+                pipeline_layout,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn has_shader(&self, path: &str) -> bool {
+        self.shaders.contains_key(path)
+    }
+
+    pub fn shader_paths(&self) -> impl Iterator<Item = &String> {
+        self.shaders.keys()
+    }
+
+    /// Material schema and bind group layout for a loaded shader
+    pub fn material_layout(&self, path: &str) -> Option<&MaterialLayout> {
+        self.shaders.get(path).map(|entry| &entry.material_layout)
+    }
+
+    /// Build the pipeline for `key` if its shader is loaded and it doesn't exist yet.
+    /// Returns false if the shader hasn't been registered.
+    pub fn prepare(&mut self, device: &wgpu::Device, key: &PipelineKey) -> bool {
+        if self.pipelines.contains_key(key) {
+            return true;
+        }
+        let Some(shader) = self.shaders.get(&key.shader) else {
+            return false;
+        };
+
+        log::info!(
+            "Creating pipeline for '{}' ({:?}, {:?})",
+            key.shader,
+            key.vertex_layout,
+            key.alpha_mode
+        );
+        let pipeline = self.create_material_pipeline(device, shader, key);
+        self.pipelines.insert(key.clone(), pipeline);
+        true
+    }
+
+    /// Like `create_render_pipeline`, with blending and depth writes set by the alpha mode.
+    /// Shaders that declare `override ALPHA_MODE: u32` are told the mode through it.
+    fn create_material_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shader: &ShaderEntry,
+        key: &PipelineKey,
+    ) -> wgpu::RenderPipeline {
+        let blended = key.alpha_mode == AlphaMode::Blend;
+        let constants: Vec<(&str, f64)> = if shader
+            .material_layout
+            .schema
+            .overrides
+            .iter()
+            .any(|name| name == ALPHA_MODE_OVERRIDE)
+        {
+            vec![(ALPHA_MODE_OVERRIDE, key.alpha_mode.gpu_id() as f64)]
+        } else {
+            Vec::new()
+        };
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants: &constants,
+            ..Default::default()
+        };
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("{} Pipeline", key.shader)),
+            layout: Some(&shader.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader.module,
+                entry_point: Some("vs_main"),
+                buffers: &key.vertex_layout.buffers(),
+                compilation_options: compilation_options.clone(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader.module,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.color_format,
+                    blend: Some(if blended {
+                        wgpu::BlendState::ALPHA_BLENDING
+                    } else {
+                        wgpu::BlendState::REPLACE
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options,
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            // Blended surfaces are tested against opaque depth but don't occlude each other
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: GpuTexture::DEPTH_FORMAT,
+                depth_write_enabled: !blended,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: self.sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    pub fn get(&self, key: &PipelineKey) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines.get(key)
+    }
+
+    pub fn num_pipelines(&self) -> usize {
+        self.pipelines.len()
+    }
+}
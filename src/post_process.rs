@@ -0,0 +1,753 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+const POST_PROCESS_SHADER: &str = include_str!("shaders/post_process.wgsl");
+
+/// Format of the intermediate targets. Effects run after tonemapping, so 8 bits with sRGB
+/// encoding is enough, and it's renderable and filterable everywhere including WebGL2.
+pub const POST_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Uniform buffer offsets must be aligned to this for dynamic offsets
+const PARAMS_STRIDE: u64 = 256;
+/// Edge length of the identity LUT used until a color grade's LUT has loaded
+const IDENTITY_LUT_SIZE: u32 = 16;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BloomParams {
+    /// Brightness above which pixels glow
+    pub threshold: f32,
+    /// Width of the ramp around the threshold, as a fraction of it
+    pub soft_knee: f32,
+    pub intensity: f32,
+    /// Blur spread in half resolution texels
+    pub radius: f32,
+}
+
+impl Default for BloomParams {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            soft_knee: 0.5,
+            intensity: 0.6,
+            radius: 1.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FxaaParams {
+    /// Longest blur along an edge, in texels
+    pub span_max: f32,
+    pub reduce_mul: f32,
+    pub reduce_min: f32,
+}
+
+impl Default for FxaaParams {
+    fn default() -> Self {
+        Self {
+            span_max: 8.0,
+            reduce_mul: 1.0 / 8.0,
+            reduce_min: 1.0 / 128.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VignetteParams {
+    pub intensity: f32,
+    /// Distance from the center where darkening is complete; 1 is the middle of an edge
+    pub radius: f32,
+    pub smoothness: f32,
+}
+
+impl Default for VignetteParams {
+    fn default() -> Self {
+        Self {
+            intensity: 0.4,
+            radius: 1.3,
+            smoothness: 0.8,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorGradeParams {
+    /// N*N x N strip image; None grades through the identity table
+    pub lut: Option<String>,
+    /// Blend from the original color (0) to the graded one (1)
+    pub strength: f32,
+}
+
+impl Default for ColorGradeParams {
+    fn default() -> Self {
+        Self {
+            lut: None,
+            strength: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum PostEffect {
+    #[serde(rename = "bloom")]
+    Bloom(BloomParams),
+    #[serde(rename = "fxaa")]
+    Fxaa(FxaaParams),
+    #[serde(rename = "vignette")]
+    Vignette(VignetteParams),
+    #[serde(rename = "color_grade")]
+    ColorGrade(ColorGradeParams),
+}
+
+impl PostEffect {
+    /// One of each effect with default parameters
+    pub fn defaults() -> [PostEffect; 4] {
+        [
+            PostEffect::Bloom(BloomParams::default()),
+            PostEffect::Fxaa(FxaaParams::default()),
+            PostEffect::Vignette(VignetteParams::default()),
+            PostEffect::ColorGrade(ColorGradeParams::default()),
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PostEffect::Bloom(_) => "Bloom",
+            PostEffect::Fxaa(_) => "FXAA",
+            PostEffect::Vignette(_) => "Vignette",
+            PostEffect::ColorGrade(_) => "Color Grade",
+        }
+    }
+}
+
+/// One entry of the post-process chain
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PostProcessPass {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub effect: PostEffect,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl PostProcessPass {
+    pub fn new(effect: PostEffect) -> Self {
+        Self {
+            enabled: true,
+            effect,
+        }
+    }
+}
+
+/// Sources of the effect shaders, already run through the import preprocessor
+pub struct PostProcessShaders {
+    pub bloom: String,
+    pub fxaa: String,
+    pub vignette: String,
+    pub color_grade: String,
+}
+
+/// A fragment entry point of one of the effect shaders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Stage {
+    BloomPrefilter,
+    BloomBlur,
+    BloomComposite,
+    Fxaa,
+    Vignette,
+    ColorGrade,
+}
+
+impl Stage {
+    const ALL: [Stage; 6] = [
+        Stage::BloomPrefilter,
+        Stage::BloomBlur,
+        Stage::BloomComposite,
+        Stage::Fxaa,
+        Stage::Vignette,
+        Stage::ColorGrade,
+    ];
+
+    fn entry_point(&self) -> &'static str {
+        match self {
+            Stage::BloomPrefilter => "fs_prefilter",
+            Stage::BloomBlur => "fs_blur",
+            Stage::BloomComposite => "fs_composite",
+            Stage::Fxaa | Stage::Vignette | Stage::ColorGrade => "fs_main",
+        }
+    }
+}
+
+/// A texture a pass can read
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Slot {
+    /// Full resolution ping-pong target
+    Ping(usize),
+    /// Half resolution bloom target
+    Bloom(usize),
+    Lut(String),
+    IdentityLut,
+}
+
+/// Where a pass writes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Ping(usize),
+    Bloom(usize),
+    Output,
+}
+
+struct Draw {
+    stage: Stage,
+    input: Slot,
+    aux: Slot,
+    target: Target,
+    values: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PassParams {
+    texel_size: [f32; 2],
+    encode_srgb: u32,
+    _padding: u32,
+    values: [f32; 4],
+}
+
+/// Full screen effects that run after tonemapping. The tonemap pass writes into
+/// `input_target()`, then every enabled pass reads the previous result and writes into the
+/// other ping-pong target, with the last one writing into the swapchain.
+pub struct PostProcessStack {
+    output_format: wgpu::TextureFormat,
+    ping_views: [wgpu::TextureView; 2],
+    bloom_views: [wgpu::TextureView; 2],
+    size: (u32, u32),
+    identity_lut: wgpu::TextureView,
+    /// Loaded LUTs by path; None while loading or after a failed load
+    luts: HashMap<String, Option<wgpu::TextureView>>,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Keyed by (input, aux); cleared whenever a texture or the params buffer is recreated
+    bind_groups: HashMap<(Slot, Slot), wgpu::BindGroup>,
+    params_buffer: wgpu::Buffer,
+    params_capacity: u64,
+    pipelines: HashMap<(Stage, wgpu::TextureFormat), wgpu::RenderPipeline>,
+}
+
+impl PostProcessStack {
+    /// Shared WGSL for the effect shaders, registered as the `post_process` import
+    pub fn shader_source() -> String {
+        POST_PROCESS_SHADER.to_string()
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        shaders: &PostProcessShaders,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post_process_bind_group_layout"),
+            entries: &[
+                texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<PassParams>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Process Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Process Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_module = |label, source: &str| {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            })
+        };
+        let bloom = create_module("Bloom Shader", &shaders.bloom);
+        let fxaa = create_module("FXAA Shader", &shaders.fxaa);
+        let vignette = create_module("Vignette Shader", &shaders.vignette);
+        let color_grade = create_module("Color Grade Shader", &shaders.color_grade);
+
+        // Any pass can be last in the chain, so every stage gets a pipeline for both formats
+        let mut pipelines = HashMap::new();
+        for stage in Stage::ALL {
+            let module = match stage {
+                Stage::BloomPrefilter | Stage::BloomBlur | Stage::BloomComposite => &bloom,
+                Stage::Fxaa => &fxaa,
+                Stage::Vignette => &vignette,
+                Stage::ColorGrade => &color_grade,
+            };
+            for format in [POST_FORMAT, config.format] {
+                pipelines.entry((stage, format)).or_insert_with(|| {
+                    Self::create_pipeline(device, &pipeline_layout, module, stage, format)
+                });
+            }
+        }
+
+        let (ping_views, bloom_views) = Self::create_targets(device, config);
+        let params_capacity = 8;
+        Self {
+            output_format: config.format,
+            ping_views,
+            bloom_views,
+            size: (config.width.max(1), config.height.max(1)),
+            identity_lut: Self::create_identity_lut(device, queue),
+            luts: HashMap::new(),
+            sampler,
+            bind_group_layout,
+            bind_groups: HashMap::new(),
+            params_buffer: Self::create_params_buffer(device, params_capacity),
+            params_capacity,
+            pipelines,
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        module: &wgpu::ShaderModule,
+        stage: Stage,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Post Process Pipeline {:?} {:?}", stage, format)),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point: Some(stage.entry_point()),
+                targets: &[Some(format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> ([wgpu::TextureView; 2], [wgpu::TextureView; 2]) {
+        let create = |label, width: u32, height: u32| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: POST_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            texture.create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let (width, height) = (config.width, config.height);
+        (
+            [
+                create("Post Process Target 0", width, height),
+                create("Post Process Target 1", width, height),
+            ],
+            [
+                create("Bloom Target 0", width / 2, height / 2),
+                create("Bloom Target 1", width / 2, height / 2),
+            ],
+        )
+    }
+
+    fn create_params_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Post Process Params Buffer"),
+            size: PARAMS_STRIDE * capacity,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Table mapping every color to itself, so grading is a no-op until a LUT loads
+    fn create_identity_lut(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::TextureView {
+        let size = IDENTITY_LUT_SIZE;
+        let level = |i: u32| (i * 255 / (size - 1)) as u8;
+        let mut pixels = Vec::with_capacity((size * size * size * 4) as usize);
+        for green in 0..size {
+            for blue in 0..size {
+                for red in 0..size {
+                    pixels.extend_from_slice(&[level(red), level(green), level(blue), 255]);
+                }
+            }
+        }
+        Self::create_lut(device, queue, "Identity LUT", size * size, size, &pixels)
+    }
+
+    fn create_lut(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> wgpu::TextureView {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        // Plain unorm: LUT entries are sRGB-encoded values the shader decodes itself
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        (self.ping_views, self.bloom_views) = Self::create_targets(device, config);
+        self.size = (config.width.max(1), config.height.max(1));
+        self.bind_groups.clear();
+    }
+
+    /// LUT paths used by `passes` that haven't been requested yet. They're marked as
+    /// requested, so each is only returned once.
+    pub fn luts_to_load(&mut self, passes: &[PostProcessPass]) -> Vec<String> {
+        let mut paths = Vec::new();
+        for pass in passes {
+            if let PostEffect::ColorGrade(ColorGradeParams {
+                lut: Some(path), ..
+            }) = &pass.effect
+                && !self.luts.contains_key(path)
+            {
+                self.luts.insert(path.clone(), None);
+                paths.push(path.clone());
+            }
+        }
+        paths
+    }
+
+    /// Upload a loaded LUT strip, which must be N*N pixels wide and N high
+    pub fn add_lut(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &str,
+        image: &image::RgbaImage,
+    ) -> anyhow::Result<()> {
+        let (width, height) = image.dimensions();
+        if height < 2 || width != height * height {
+            anyhow::bail!(
+                "LUT '{}' is {}x{}, expected a strip N*N wide and N high",
+                path,
+                width,
+                height
+            );
+        }
+        let view = Self::create_lut(device, queue, path, width, height, image.as_raw());
+        self.luts.insert(path.to_string(), Some(view));
+        // Passes using it were bound to the identity LUT until now
+        let slot = Slot::Lut(path.to_string());
+        self.bind_groups.retain(|(_, aux), _| *aux != slot);
+        Ok(())
+    }
+
+    /// True if any pass is enabled, in which case the tonemap pass should write into
+    /// `input_target()` and `render()` produces the final image
+    pub fn is_active(passes: &[PostProcessPass]) -> bool {
+        passes.iter().any(|pass| pass.enabled)
+    }
+
+    /// Where the tonemap pass writes when the chain is active; its format is `POST_FORMAT`
+    pub fn input_target(&self) -> &wgpu::TextureView {
+        &self.ping_views[0]
+    }
+
+    fn slot_view(&self, slot: &Slot) -> &wgpu::TextureView {
+        match slot {
+            Slot::Ping(index) => &self.ping_views[*index],
+            Slot::Bloom(index) => &self.bloom_views[*index],
+            Slot::Lut(path) => self
+                .luts
+                .get(path)
+                .and_then(Option::as_ref)
+                .unwrap_or(&self.identity_lut),
+            Slot::IdentityLut => &self.identity_lut,
+        }
+    }
+
+    fn slot_size(&self, slot: &Slot) -> (u32, u32) {
+        match slot {
+            Slot::Bloom(_) => ((self.size.0 / 2).max(1), (self.size.1 / 2).max(1)),
+            _ => self.size,
+        }
+    }
+
+    /// Expand the enabled passes into individual draws
+    fn plan(&self, passes: &[PostProcessPass]) -> Vec<Draw> {
+        let enabled: Vec<&PostEffect> = passes
+            .iter()
+            .filter(|pass| pass.enabled)
+            .map(|pass| &pass.effect)
+            .collect();
+        let mut draws = Vec::new();
+        let mut current = 0;
+        for (i, effect) in enabled.iter().enumerate() {
+            let input = Slot::Ping(current);
+            let target = if i + 1 == enabled.len() {
+                Target::Output
+            } else {
+                Target::Ping(1 - current)
+            };
+            let mut draw = |stage, input, aux, target, values| {
+                draws.push(Draw {
+                    stage,
+                    input,
+                    aux,
+                    target,
+                    values,
+                })
+            };
+            match effect {
+                PostEffect::Bloom(params) => {
+                    draw(
+                        Stage::BloomPrefilter,
+                        input.clone(),
+                        Slot::IdentityLut,
+                        Target::Bloom(0),
+                        [params.threshold, params.soft_knee, 0.0, 0.0],
+                    );
+                    draw(
+                        Stage::BloomBlur,
+                        Slot::Bloom(0),
+                        Slot::IdentityLut,
+                        Target::Bloom(1),
+                        [1.0, 0.0, params.radius, 0.0],
+                    );
+                    draw(
+                        Stage::BloomBlur,
+                        Slot::Bloom(1),
+                        Slot::IdentityLut,
+                        Target::Bloom(0),
+                        [0.0, 1.0, params.radius, 0.0],
+                    );
+                    draw(
+                        Stage::BloomComposite,
+                        input,
+                        Slot::Bloom(0),
+                        target,
+                        [params.intensity, 0.0, 0.0, 0.0],
+                    );
+                }
+                PostEffect::Fxaa(params) => draw(
+                    Stage::Fxaa,
+                    input,
+                    Slot::IdentityLut,
+                    target,
+                    [params.span_max, params.reduce_mul, params.reduce_min, 0.0],
+                ),
+                PostEffect::Vignette(params) => draw(
+                    Stage::Vignette,
+                    input,
+                    Slot::IdentityLut,
+                    target,
+                    [params.intensity, params.radius, params.smoothness, 0.0],
+                ),
+                PostEffect::ColorGrade(params) => {
+                    let lut = params
+                        .lut
+                        .as_ref()
+                        .map_or(Slot::IdentityLut, |path| Slot::Lut(path.clone()));
+                    draw(
+                        Stage::ColorGrade,
+                        input,
+                        lut,
+                        target,
+                        [params.strength, 0.0, 0.0, 0.0],
+                    );
+                }
+            }
+            current = 1 - current;
+        }
+        draws
+    }
+
+    /// Run the enabled passes over `input_target()`, writing the result into `output`
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        passes: &[PostProcessPass],
+    ) {
+        let draws = self.plan(passes);
+        if draws.is_empty() {
+            return;
+        }
+
+        if draws.len() as u64 > self.params_capacity {
+            self.params_capacity = (draws.len() as u64).next_power_of_two();
+            self.params_buffer = Self::create_params_buffer(device, self.params_capacity);
+            self.bind_groups.clear();
+        }
+        let mut params_data = vec![0u8; draws.len() * PARAMS_STRIDE as usize];
+        for (i, draw) in draws.iter().enumerate() {
+            let (width, height) = self.slot_size(&draw.input);
+            let params = PassParams {
+                texel_size: [1.0 / width as f32, 1.0 / height as f32],
+                encode_srgb: (draw.target == Target::Output && !self.output_format.is_srgb())
+                    as u32,
+                _padding: 0,
+                values: draw.values,
+            };
+            let offset = i * PARAMS_STRIDE as usize;
+            params_data[offset..offset + std::mem::size_of::<PassParams>()]
+                .copy_from_slice(bytemuck::bytes_of(&params));
+        }
+        queue.write_buffer(&self.params_buffer, 0, &params_data);
+
+        for draw in &draws {
+            let key = (draw.input.clone(), draw.aux.clone());
+            if !self.bind_groups.contains_key(&key) {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("post_process_bind_group"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(
+                                self.slot_view(&draw.input),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(self.slot_view(&draw.aux)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                                buffer: &self.params_buffer,
+                                offset: 0,
+                                size: wgpu::BufferSize::new(
+                                    std::mem::size_of::<PassParams>() as u64
+                                ),
+                            }),
+                        },
+                    ],
+                });
+                self.bind_groups.insert(key, bind_group);
+            }
+        }
+
+        for (i, draw) in draws.iter().enumerate() {
+            let (view, format) = match draw.target {
+                Target::Ping(index) => (&self.ping_views[index], POST_FORMAT),
+                Target::Bloom(index) => (&self.bloom_views[index], POST_FORMAT),
+                Target::Output => (output, self.output_format),
+            };
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Post Process Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipelines[&(draw.stage, format)]);
+            pass.set_bind_group(
+                0,
+                &self.bind_groups[&(draw.input.clone(), draw.aux.clone())],
+                &[(i as u64 * PARAMS_STRIDE) as u32],
+            );
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::post_process::PostProcessPass;
+use crate::tonemapping::ToneMapSettings;
+
+/// MSAA sample count used when the adapter supports it
+const DEFAULT_MSAA_SAMPLES: u32 = 4;
+
+/// Renderer options edited from the UI
+pub struct RenderSettings {
+    pub tone_map: ToneMapSettings,
+    /// Full screen effects applied in order after tonemapping
+    pub post_process: Vec<PostProcessPass>,
+    /// Requested MSAA sample count: 1, 2, 4 or 8
+    pub requested_msaa_samples: u32,
+    supported_msaa_samples: Vec<u32>,
+    /// Skip drawing particle instances outside the view frustum
+    pub frustum_culling: bool,
+}
+
+impl RenderSettings {
+    /// `supported_msaa_samples` comes from `pipeline::supported_sample_counts`
+    pub fn new(supported_msaa_samples: Vec<u32>) -> Self {
+        Self {
+            tone_map: ToneMapSettings::default(),
+            post_process: Vec::new(),
+            requested_msaa_samples: DEFAULT_MSAA_SAMPLES,
+            supported_msaa_samples,
+            frustum_culling: true,
+        }
+    }
+
+    pub fn supported_msaa_samples(&self) -> &[u32] {
+        &self.supported_msaa_samples
+    }
+
+    /// The requested sample count, clamped to the highest supported count not above it
+    pub fn msaa_samples(&self) -> u32 {
+        self.supported_msaa_samples
+            .iter()
+            .copied()
+            .filter(|&count| count <= self.requested_msaa_samples)
+            .max()
+            .unwrap_or(1)
+    }
+}
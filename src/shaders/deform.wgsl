@@ -0,0 +1,99 @@
+// Mesh deformation: poses a mesh's undeformed vertices once per animation time offset.
+// Each vertex first gets its morph target offsets scaled by their weights, then is
+// moved by its joints' matrices blended by weight. Mirrors deform_vertices in
+// deform.rs; keep the two in step.
+
+struct DeformParams {
+    vertex_count: u32,
+    joint_count: u32,
+    offset_count: u32,
+    target_count: u32,
+}
+
+// Floats per ModelVertex: position, tex coords, normal, tangent and bitangent
+const VERTEX_FLOATS: u32 = 14u;
+// Each source vertex is a ModelVertex followed by four joint indices (as bits) and
+// their four weights
+const SOURCE_FLOATS: u32 = 22u;
+// Each morph target offset is a position offset followed by a normal offset
+const TARGET_FLOATS: u32 = 6u;
+// Floats per joint matrix, column by column
+const MATRIX_FLOATS: u32 = 16u;
+
+// Packed so the pass needs no more than WebGL's three storage buffers
+@group(0) @binding(0) var<uniform> params: DeformParams;
+// `vertex_count` source vertices, then `vertex_count` offsets per morph target
+@group(0) @binding(1) var<storage, read> source: array<f32>;
+// `joint_count` matrices per time offset, then `target_count` weights per time offset
+@group(0) @binding(2) var<storage, read> pose: array<f32>;
+// `vertex_count` vertices per time offset
+@group(0) @binding(3) var<storage, read_write> deformed: array<f32>;
+
+fn read_vec3(base: u32) -> vec3<f32> {
+    return vec3<f32>(source[base], source[base + 1u], source[base + 2u]);
+}
+
+fn read_vec4(base: u32) -> vec4<f32> {
+    return vec4<f32>(pose[base], pose[base + 1u], pose[base + 2u], pose[base + 3u]);
+}
+
+fn joint_matrix(index: u32) -> mat4x4<f32> {
+    let base = index * MATRIX_FLOATS;
+    return mat4x4<f32>(read_vec4(base), read_vec4(base + 4u), read_vec4(base + 8u), read_vec4(base + 12u));
+}
+
+fn write_vec3(base: u32, value: vec3<f32>) {
+    deformed[base] = value.x;
+    deformed[base + 1u] = value.y;
+    deformed[base + 2u] = value.z;
+}
+
+// Direction moved by `linear`, left at zero if it started there
+fn transform_direction(linear: mat3x3<f32>, direction: vec3<f32>) -> vec3<f32> {
+    let moved = linear * direction;
+    if (dot(moved, moved) == 0.0) {
+        return moved;
+    }
+    return normalize(moved);
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    let offset = id.y;
+    if (index >= params.vertex_count || offset >= params.offset_count) {
+        return;
+    }
+
+    let base = index * SOURCE_FLOATS;
+    var position = read_vec3(base);
+    var normal = read_vec3(base + 5u);
+    let first_target = params.vertex_count * SOURCE_FLOATS;
+    let first_weight = params.offset_count * params.joint_count * MATRIX_FLOATS
+        + offset * params.target_count;
+    for (var t = 0u; t < params.target_count; t++) {
+        let weight = pose[first_weight + t];
+        if (weight != 0.0) {
+            let target_base = first_target + (t * params.vertex_count + index) * TARGET_FLOATS;
+            position += weight * read_vec3(target_base);
+            normal += weight * read_vec3(target_base + 3u);
+        }
+    }
+
+    let first_joint = offset * params.joint_count;
+    var skin = mat4x4<f32>(vec4<f32>(0.0), vec4<f32>(0.0), vec4<f32>(0.0), vec4<f32>(0.0));
+    for (var i = 0u; i < 4u; i++) {
+        let joint = min(bitcast<u32>(source[base + VERTEX_FLOATS + i]), params.joint_count - 1u);
+        let weight = source[base + VERTEX_FLOATS + 4u + i];
+        skin += joint_matrix(first_joint + joint) * weight;
+    }
+    let linear = mat3x3<f32>(skin[0].xyz, skin[1].xyz, skin[2].xyz);
+
+    let out = (offset * params.vertex_count + index) * VERTEX_FLOATS;
+    write_vec3(out, (skin * vec4<f32>(position, 1.0)).xyz);
+    deformed[out + 3u] = source[base + 3u];
+    deformed[out + 4u] = source[base + 4u];
+    write_vec3(out + 5u, transform_direction(linear, normal));
+    write_vec3(out + 8u, transform_direction(linear, read_vec3(base + 8u)));
+    write_vec3(out + 11u, transform_direction(linear, read_vec3(base + 11u)));
+}
@@ -0,0 +1,61 @@
+// Image based lighting environment (group 0, bindings 8-12)
+
+struct EnvironmentUniform {
+    // Clip space to world direction, with the camera's translation removed
+    inv_view_proj: mat4x4<f32>,
+    intensity: f32,
+    // Mip levels in prefiltered_map; roughness 1 reads the last one
+    prefiltered_mips: f32,
+    // 0 when no environment is loaded and shaders use a flat ambient term instead
+    enabled: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(8)
+var environment_map: texture_cube<f32>;
+// Cosine-weighted hemisphere average of the environment around each normal
+@group(0) @binding(9)
+var irradiance_map: texture_cube<f32>;
+// The environment blurred by the GGX lobe, one roughness step per mip
+@group(0) @binding(10)
+var prefiltered_map: texture_cube<f32>;
+@group(0) @binding(11)
+var environment_sampler: sampler;
+@group(0) @binding(12)
+var<uniform> environment: EnvironmentUniform;
+
+fn environment_enabled() -> bool {
+    return environment.enabled != 0u;
+}
+
+// Analytic fit of the split-sum specular BRDF integral (Karis, "Physically Based Shading on Mobile")
+fn environment_brdf(f0: vec3<f32>, roughness: f32, n_dot_v: f32) -> vec3<f32> {
+    let c0 = vec4<f32>(-1.0, -0.0275, -0.572, 0.022);
+    let c1 = vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let r = roughness * c0 + c1;
+    let a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    let ab = vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+    return f0 * ab.x + ab.y;
+}
+
+// Diffuse and specular light the environment contributes to a metallic-roughness surface
+fn environment_lighting(
+    normal: vec3<f32>,
+    view_dir: vec3<f32>,
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    f0: vec3<f32>,
+) -> vec3<f32> {
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    // Rough surfaces reflect less at grazing angles
+    let fresnel = f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - n_dot_v, 0.0, 1.0), 5.0);
+    let k_diffuse = (vec3<f32>(1.0) - fresnel) * (1.0 - metallic);
+
+    let irradiance = textureSampleLevel(irradiance_map, environment_sampler, normal, 0.0).rgb;
+    let reflected = reflect(-view_dir, normal);
+    let lod = roughness * (environment.prefiltered_mips - 1.0);
+    let prefiltered = textureSampleLevel(prefiltered_map, environment_sampler, reflected, lod).rgb;
+    let specular = prefiltered * environment_brdf(f0, roughness, n_dot_v);
+    return (k_diffuse * irradiance * base_color + specular) * environment.intensity;
+}
@@ -0,0 +1,70 @@
+// Clustered light list, spliced into shaders by `#import lights`.
+// Occupies group 0 bindings 1-4; the storage flavour of bindings 2-4 is
+// appended on backends with storage buffers, the data-texture one otherwise.
+
+const LIGHT_POINT: u32 = 0u;
+const LIGHT_DIRECTIONAL: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    color: vec4<f32>,
+    // Direction light travels in, for directional and spot lights
+    direction: vec3<f32>,
+    // Distance where point and spot lights fade out; 0 = unlimited
+    range: f32,
+    intensity: f32,
+    inner_cone_cos: f32,
+    outer_cone_cos: f32,
+    // First layer in the shadow map array, -1 without shadows
+    shadow_layer: i32,
+    shadow_bias: f32,
+    shadow_normal_bias: f32,
+    _padding: vec2<f32>,
+}
+
+struct LightClusterParams {
+    // Cluster counts along x, y and depth; w is the number of global lights
+    grid: vec4<u32>,
+    screen_size: vec2<f32>,
+    // Depth slice = log(view_depth) * z_scale + z_bias
+    z_scale: f32,
+    z_bias: f32,
+    num_lights: u32,
+}
+
+@group(0) @binding(1)
+var<uniform> light_params: LightClusterParams;
+
+// Offset into the light index list and light count of the cluster holding a fragment.
+// Global lights (directional or unlimited range) come first in the index list and
+// apply to every cluster.
+fn light_cluster_at(frag_coord: vec2<f32>, view_depth: f32) -> vec2<u32> {
+    let grid = light_params.grid;
+    let tile = vec2<u32>(clamp(
+        frag_coord / light_params.screen_size * vec2<f32>(grid.xy),
+        vec2<f32>(0.0),
+        vec2<f32>(grid.xy) - 1.0,
+    ));
+    let slice = u32(clamp(
+        log(max(view_depth, 0.0001)) * light_params.z_scale + light_params.z_bias,
+        0.0,
+        f32(grid.z - 1u),
+    ));
+    return light_cluster(tile.x + tile.y * grid.x + slice * grid.x * grid.y);
+}
+
+fn num_cluster_lights(cluster: vec2<u32>) -> u32 {
+    return light_params.grid.w + cluster.y;
+}
+
+// The i-th light affecting a cluster, for i < num_cluster_lights(cluster)
+fn cluster_light(cluster: vec2<u32>, i: u32) -> Light {
+    let num_global = light_params.grid.w;
+    var list_index = i;
+    if (i >= num_global) {
+        list_index = cluster.x + i - num_global;
+    }
+    return get_light(light_index(list_index));
+}
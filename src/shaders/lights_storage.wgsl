@@ -0,0 +1,21 @@
+// Light list bindings for backends with storage buffers
+
+@group(0) @binding(2)
+var<storage, read> lights: array<Light>;
+// (offset into light_indices, count) per cluster
+@group(0) @binding(3)
+var<storage, read> light_clusters: array<vec2<u32>>;
+@group(0) @binding(4)
+var<storage, read> light_indices: array<u32>;
+
+fn get_light(index: u32) -> Light {
+    return lights[index];
+}
+
+fn light_cluster(cluster_index: u32) -> vec2<u32> {
+    return light_clusters[cluster_index];
+}
+
+fn light_index(list_index: u32) -> u32 {
+    return light_indices[list_index];
+}
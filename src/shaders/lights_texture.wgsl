@@ -0,0 +1,47 @@
+// Light list bindings for WebGL2, which has no storage buffers: the same data
+// packed into integer textures, LIGHT_DATA_WIDTH texels per row
+
+const LIGHT_DATA_WIDTH: u32 = 1024u;
+
+// Five texels per light, floats stored as their bit patterns
+@group(0) @binding(2)
+var light_texture: texture_2d<u32>;
+@group(0) @binding(3)
+var light_cluster_texture: texture_2d<u32>;
+@group(0) @binding(4)
+var light_index_texture: texture_2d<u32>;
+
+fn light_data_coord(index: u32) -> vec2<i32> {
+    return vec2<i32>(i32(index % LIGHT_DATA_WIDTH), i32(index / LIGHT_DATA_WIDTH));
+}
+
+fn get_light(index: u32) -> Light {
+    let base = index * 5u;
+    let a = textureLoad(light_texture, light_data_coord(base), 0);
+    let b = textureLoad(light_texture, light_data_coord(base + 1u), 0);
+    let c = textureLoad(light_texture, light_data_coord(base + 2u), 0);
+    let d = textureLoad(light_texture, light_data_coord(base + 3u), 0);
+    let e = textureLoad(light_texture, light_data_coord(base + 4u), 0);
+
+    var light: Light;
+    light.position = bitcast<vec3<f32>>(a.xyz);
+    light.kind = a.w;
+    light.color = bitcast<vec4<f32>>(b);
+    light.direction = bitcast<vec3<f32>>(c.xyz);
+    light.range = bitcast<f32>(c.w);
+    light.intensity = bitcast<f32>(d.x);
+    light.inner_cone_cos = bitcast<f32>(d.y);
+    light.outer_cone_cos = bitcast<f32>(d.z);
+    light.shadow_layer = bitcast<i32>(d.w);
+    light.shadow_bias = bitcast<f32>(e.x);
+    light.shadow_normal_bias = bitcast<f32>(e.y);
+    return light;
+}
+
+fn light_cluster(cluster_index: u32) -> vec2<u32> {
+    return textureLoad(light_cluster_texture, light_data_coord(cluster_index), 0).xy;
+}
+
+fn light_index(list_index: u32) -> u32 {
+    return textureLoad(light_index_texture, light_data_coord(list_index), 0).x;
+}
@@ -0,0 +1,76 @@
+// Level-of-detail bucketing: copies each instance into the region of the bucket its
+// distance from the eye and its animation time offset select, counting them into
+// that bucket's indirect draw for every part. Buckets are level-major. Mirrors lod_for and
+// animation_offset_for in lod.rs; keep them in step.
+
+struct LodParams {
+    eye: vec3<f32>,
+    instance_count: u32,
+    // Instances per bucket region
+    bucket_capacity: u32,
+    level_count: u32,
+    offset_count: u32,
+    // Meshes drawn with the same instances, each with its own run of draws
+    part_count: u32,
+    // Squared switch distance of level i + 1, packed four to a vector
+    distances: array<vec4<f32>, 2>,
+}
+
+// Matches wgpu's DrawIndexedIndirectArgs
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+// Floats per InstanceRaw: a model matrix, a normal matrix, a color and custom data
+const INSTANCE_FLOATS: u32 = 33u;
+
+@group(0) @binding(0) var<uniform> params: LodParams;
+@group(0) @binding(1) var<storage, read> instances: array<f32>;
+@group(0) @binding(2) var<storage, read_write> buckets: array<f32>;
+@group(0) @binding(3) var<storage, read_write> draws: array<DrawArgs>;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= params.instance_count) {
+        return;
+    }
+
+    // Dead particles are collapsed to nothing, so aren't worth drawing
+    let instance_base = index * INSTANCE_FLOATS;
+    let x_axis = vec3<f32>(instances[instance_base], instances[instance_base + 1u], instances[instance_base + 2u]);
+    if (dot(x_axis, x_axis) == 0.0) {
+        return;
+    }
+
+    let position = vec3<f32>(
+        instances[instance_base + 12u],
+        instances[instance_base + 13u],
+        instances[instance_base + 14u],
+    );
+    let offset = position - params.eye;
+    let distance2 = dot(offset, offset);
+    var level = 0u;
+    for (var i = 1u; i < params.level_count; i++) {
+        if (distance2 >= params.distances[(i - 1u) / 4u][(i - 1u) % 4u]) {
+            level = i;
+        }
+    }
+
+    // Instances get time offsets in equal runs, so each run fits one region
+    let time_offset = index * params.offset_count / params.instance_count;
+    let bucket = level * params.offset_count + time_offset;
+    let slot = atomicAdd(&draws[bucket].instance_count, 1u);
+    let bucket_count = params.level_count * params.offset_count;
+    for (var part = 1u; part < params.part_count; part++) {
+        atomicAdd(&draws[part * bucket_count + bucket].instance_count, 1u);
+    }
+    let bucket_base = (bucket * params.bucket_capacity + slot) * INSTANCE_FLOATS;
+    for (var i = 0u; i < INSTANCE_FLOATS; i++) {
+        buckets[bucket_base + i] = instances[instance_base + i];
+    }
+}
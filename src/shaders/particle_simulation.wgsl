@@ -0,0 +1,161 @@
+// Particle simulation: advances each particle one step and writes its instance data.
+// Mirrors the CPU fallback in particle_simulation.rs; keep the two in step.
+
+struct SimulationParams {
+    gravity: vec3<f32>,
+    drag: f32,
+    vortex_center: vec3<f32>,
+    vortex_strength: f32,
+    // Normalized on the CPU
+    vortex_axis: vec3<f32>,
+    noise_strength: f32,
+    noise_frequency: f32,
+    spawn_rate: f32,
+    lifetime: f32,
+    initial_speed: f32,
+    cos_spread: f32,
+    end_scale: f32,
+    time: f32,
+    dt: f32,
+    capacity: u32,
+    emitter_count: u32,
+}
+
+struct Particle {
+    position: vec3<f32>,
+    // Negative until first spawned
+    age: f32,
+    velocity: vec3<f32>,
+    emitter: u32,
+    seed: u32,
+}
+
+// Floats per InstanceRaw: a model matrix, a normal matrix, a color and custom data
+const INSTANCE_FLOATS: u32 = 33u;
+const TAU: f32 = 6.28318530718;
+
+@group(0) @binding(0) var<uniform> params: SimulationParams;
+@group(0) @binding(1) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(2) var<storage, read> emitters: array<f32>;
+@group(0) @binding(3) var<storage, read_write> instances: array<f32>;
+
+fn pcg_hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(seed: ptr<function, u32>) -> f32 {
+    *seed = pcg_hash(*seed);
+    return f32(*seed) / 4294967296.0;
+}
+
+fn lattice(cell: vec3<i32>) -> f32 {
+    let hash = pcg_hash(bitcast<u32>(cell.x) ^ pcg_hash(bitcast<u32>(cell.y) ^ pcg_hash(bitcast<u32>(cell.z))));
+    return f32(hash) / 4294967296.0;
+}
+
+// Smoothly interpolated random values on the integer lattice, in 0 to 1
+fn value_noise(p: vec3<f32>) -> f32 {
+    let cell = floor(p);
+    let f = p - cell;
+    let u = f * f * (3.0 - 2.0 * f);
+    let c = vec3<i32>(cell);
+    let x00 = mix(lattice(c), lattice(c + vec3<i32>(1, 0, 0)), u.x);
+    let x10 = mix(lattice(c + vec3<i32>(0, 1, 0)), lattice(c + vec3<i32>(1, 1, 0)), u.x);
+    let x01 = mix(lattice(c + vec3<i32>(0, 0, 1)), lattice(c + vec3<i32>(1, 0, 1)), u.x);
+    let x11 = mix(lattice(c + vec3<i32>(0, 1, 1)), lattice(c + vec3<i32>(1, 1, 1)), u.x);
+    return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z);
+}
+
+// Three decorrelated noise channels, each in -1 to 1
+fn noise_vector(p: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(
+        value_noise(p),
+        value_noise(p + vec3<f32>(31.4, 17.1, 5.9)),
+        value_noise(p + vec3<f32>(-47.2, 9.3, 23.7)),
+    ) * 2.0 - 1.0;
+}
+
+fn emitter_column(emitter: u32, column: u32) -> vec3<f32> {
+    let base = emitter * INSTANCE_FLOATS + column * 4u;
+    return vec3<f32>(emitters[base], emitters[base + 1u], emitters[base + 2u]);
+}
+
+// Restart at a random emitter, moving within the spread cone around its local +Y
+fn spawn(particle: Particle) -> Particle {
+    var seed = particle.seed;
+    var spawned: Particle;
+    spawned.emitter = min(u32(random(&seed) * f32(params.emitter_count)), params.emitter_count - 1u);
+    spawned.position = emitter_column(spawned.emitter, 3u);
+
+    let cos_theta = 1.0 - random(&seed) * (1.0 - params.cos_spread);
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = random(&seed) * TAU;
+    let local = vec3<f32>(sin_theta * cos(phi), cos_theta, sin_theta * sin(phi));
+    let direction = emitter_column(spawned.emitter, 0u) * local.x
+        + emitter_column(spawned.emitter, 1u) * local.y
+        + emitter_column(spawned.emitter, 2u) * local.z;
+    if (dot(direction, direction) > 0.0) {
+        spawned.velocity = normalize(direction) * params.initial_speed;
+    }
+    spawned.seed = seed;
+    return spawned;
+}
+
+fn integrate(particle: Particle, dt: f32) -> Particle {
+    var acceleration = params.gravity;
+    let offset = particle.position - params.vortex_center;
+    let radial = offset - params.vortex_axis * dot(offset, params.vortex_axis);
+    if (dot(radial, radial) > 1e-8) {
+        acceleration += normalize(cross(params.vortex_axis, radial)) * params.vortex_strength;
+    }
+    acceleration += noise_vector(particle.position * params.noise_frequency) * params.noise_strength;
+
+    var moved = particle;
+    moved.velocity = (particle.velocity + acceleration * dt) * max(1.0 - params.drag * dt, 0.0);
+    moved.position = particle.position + moved.velocity * dt;
+    return moved;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= params.capacity) {
+        return;
+    }
+
+    // Slot i is first born at i / spawn_rate, then every capacity / spawn_rate seconds
+    var particle = particles[index];
+    let period = f32(params.capacity) / params.spawn_rate;
+    let local_time = params.time - f32(index) / params.spawn_rate;
+    var scale = 0.0;
+    if (local_time >= 0.0) {
+        let age = local_time - floor(local_time / period) * period;
+        if (particle.age < 0.0 || age < particle.age) {
+            particle = spawn(particle);
+        } else {
+            particle = integrate(particle, params.dt);
+        }
+        particle.age = age;
+        if (age < params.lifetime) {
+            scale = mix(1.0, params.end_scale, age / params.lifetime);
+        }
+    }
+    particles[index] = particle;
+
+    // The emitter's instance, moved to the particle and scaled by its age; dead
+    // particles collapse to nothing. Color and custom data carry over as they are.
+    let emitter_base = particle.emitter * INSTANCE_FLOATS;
+    let instance_base = index * INSTANCE_FLOATS;
+    for (var i = 0u; i < 12u; i++) {
+        instances[instance_base + i] = emitters[emitter_base + i] * scale;
+    }
+    instances[instance_base + 12u] = particle.position.x;
+    instances[instance_base + 13u] = particle.position.y;
+    instances[instance_base + 14u] = particle.position.z;
+    instances[instance_base + 15u] = 1.0;
+    for (var i = 16u; i < INSTANCE_FLOATS; i++) {
+        instances[instance_base + i] = emitters[emitter_base + i];
+    }
+}
@@ -0,0 +1,61 @@
+// Shared by the post-process shaders: pass inputs, a fullscreen triangle and color helpers
+
+struct PassParams {
+    // Size of one t_input texel in UV units
+    texel_size: vec2<f32>,
+    // Set when the pass writes into a swapchain that isn't sRGB
+    encode_srgb: u32,
+    _padding: u32,
+    // Meaning depends on the effect, see each shader
+    values: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_linear: sampler;
+// Second input: the blurred glow for bloom, the LUT for color grading
+@group(0) @binding(2)
+var t_aux: texture_2d<f32>;
+@group(0) @binding(3)
+var<uniform> params: PassParams;
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> FullscreenOutput {
+    // (-1, -1), (3, -1), (-1, 3): a triangle whose inside covers the whole viewport
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: FullscreenOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    // Texture V runs down while clip space Y runs up
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn linear_to_srgb(x: vec3<f32>) -> vec3<f32> {
+    let low = x * 12.92;
+    let high = 1.055 * pow(x, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, x <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(x: vec3<f32>) -> vec3<f32> {
+    let low = x / 12.92;
+    let high = pow((x + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, x <= vec3<f32>(0.04045));
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Every fragment entry point returns its linear color through this
+fn finish(color: vec3<f32>) -> vec4<f32> {
+    if (params.encode_srgb != 0u) {
+        return vec4<f32>(linear_to_srgb(color), 1.0);
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,86 @@
+// Shadow maps for the clustered lights (group 0, bindings 5-7). Needs the lights import.
+
+const NUM_CASCADES: u32 = 3u;
+
+struct ShadowUniform {
+    // Light-space view-projection for every layer of the shadow map
+    matrices: array<mat4x4<f32>, 16>,
+    // View depth where each directional cascade ends
+    cascade_splits: vec4<f32>,
+}
+
+@group(0) @binding(5)
+var shadow_map: texture_depth_2d_array;
+@group(0) @binding(6)
+var shadow_sampler: sampler_comparison;
+@group(0) @binding(7)
+var<uniform> shadows: ShadowUniform;
+
+// Which of a light's layers covers this fragment, or -1 past the last cascade.
+// Directional lights pick a cascade by view depth; point lights pick the cube
+// face of the major axis, laid out +X, -X, +Y, -Y, +Z, -Z.
+fn shadow_layer_offset(light: Light, world_position: vec3<f32>, view_depth: f32) -> i32 {
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        for (var i = 0u; i < NUM_CASCADES; i = i + 1u) {
+            if (view_depth < shadows.cascade_splits[i]) {
+                return i32(i);
+            }
+        }
+        return -1;
+    }
+    if (light.kind == LIGHT_POINT) {
+        let d = world_position - light.position;
+        let a = abs(d);
+        if (a.x >= a.y && a.x >= a.z) {
+            return select(1, 0, d.x > 0.0);
+        }
+        if (a.y >= a.z) {
+            return select(3, 2, d.y > 0.0);
+        }
+        return select(5, 4, d.z > 0.0);
+    }
+    return 0;
+}
+
+// Fraction of the light reaching `world_position`, with 3x3 percentage-closer filtering.
+// `light_dir` points from the surface towards the light.
+fn shadow_visibility(
+    light: Light,
+    world_position: vec3<f32>,
+    normal: vec3<f32>,
+    light_dir: vec3<f32>,
+    view_depth: f32,
+) -> f32 {
+    if (light.shadow_layer < 0) {
+        return 1.0;
+    }
+    let offset = shadow_layer_offset(light, world_position, view_depth);
+    if (offset < 0) {
+        return 1.0;
+    }
+    let layer = light.shadow_layer + offset;
+
+    // Push the lookup off the surface so it doesn't shadow itself
+    let biased_position = world_position
+        + normal * light.shadow_normal_bias
+        + light_dir * light.shadow_bias;
+    let light_clip = shadows.matrices[layer] * vec4<f32>(biased_position, 1.0);
+    if (light_clip.w <= 0.0) {
+        return 1.0;
+    }
+    let ndc = light_clip.xyz / light_clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let sample_uv = uv + vec2<f32>(f32(x), f32(y)) * texel;
+            lit = lit + textureSampleCompareLevel(shadow_map, shadow_sampler, sample_uv, layer, ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
@@ -0,0 +1,468 @@
+use crate::camera::{Camera, OPENGL_TO_WGPU_MATRIX, Projection};
+use crate::light::{Light, LightKind};
+use crate::model::{Mesh, ModelVertex, Vertex};
+use crate::particle_system::InstanceRaw;
+use crate::texture::GpuTexture;
+use cgmath::{
+    Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3, Vector4,
+};
+
+const SHADOW_SHADER: &str = include_str!("shaders/shadows.wgsl");
+
+/// Resolution of every layer in the shadow map array
+pub const SHADOW_MAP_SIZE: u32 = 1024;
+/// Layers shared by all shadow casting lights; lights past the budget render unshadowed
+pub const MAX_SHADOW_LAYERS: u32 = 16;
+/// Cascades per directional light
+pub const NUM_CASCADES: usize = 3;
+/// How far from the camera directional shadows reach
+const CASCADE_DISTANCE: f32 = 50.0;
+/// Blend between uniform (0) and logarithmic (1) cascade splits
+const CASCADE_SPLIT_LAMBDA: f32 = 0.6;
+/// Extra depth behind a cascade so casters outside the view still throw shadows into it
+const CASCADE_CASTER_MARGIN: f32 = 50.0;
+/// Far plane for point and spot shadows when the light has unlimited range
+const UNLIMITED_RANGE_SHADOW_FAR: f32 = 50.0;
+const SHADOW_NEAR: f32 = 0.05;
+/// Uniform buffer offsets must be aligned to this for dynamic offsets
+const LAYER_UNIFORM_STRIDE: u64 = 256;
+
+/// Light-space matrices for every layer, read by the lighting shader
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    matrices: [[[f32; 4]; 4]; MAX_SHADOW_LAYERS as usize],
+    /// View depth where each cascade ends; the last one is the shadow distance
+    cascade_splits: [f32; 4],
+}
+
+/// Depth-only rendering of instanced meshes into a layered shadow map.
+///
+/// Each frame `update` hands out layers to shadow casting lights: three cascades
+/// for a directional light, six cube faces for a point light and one for a spot.
+/// The map, a comparison sampler and the matrices are bound in the per-frame group.
+pub struct ShadowMaps {
+    view: wgpu::TextureView,
+    layer_views: Vec<wgpu::TextureView>,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    layer_buffer: wgpu::Buffer,
+    layer_bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    num_layers: u32,
+}
+
+impl ShadowMaps {
+    /// WGSL for `#import shadows`; expects the `lights` import before it
+    pub fn shader_source() -> String {
+        SHADOW_SHADER.to_string()
+    }
+
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    sample_type: wgpu::TextureSampleType::Depth,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    /// `depth_shader` is the WGSL for the depth pass (`shadow.wgsl`)
+    pub fn new(device: &wgpu::Device, depth_shader: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Map"),
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: MAX_SHADOW_LAYERS,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: GpuTexture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Shadow Map View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..MAX_SHADOW_LAYERS)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow Map Layer View"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Uniform Buffer"),
+            size: std::mem::size_of::<ShadowUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // One view-projection per layer, selected with a dynamic offset in the depth pass
+        let layer_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Layer Buffer"),
+            size: LAYER_UNIFORM_STRIDE * MAX_SHADOW_LAYERS as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let matrix_size = wgpu::BufferSize::new(std::mem::size_of::<[[f32; 4]; 4]>() as u64);
+        let layer_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("shadow_layer_bind_group_layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: matrix_size,
+                    },
+                    count: None,
+                }],
+            });
+        let layer_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("shadow_layer_bind_group"),
+            layout: &layer_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &layer_buffer,
+                    offset: 0,
+                    size: matrix_size,
+                }),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&layer_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(depth_shader.into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[ModelVertex::desc(), InstanceRaw::desc()],
+                compilation_options: Default::default(),
+            },
+            // Depth only
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: GpuTexture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                // Slope-scaled bias keeps surfaces at grazing angles from shadowing themselves
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            view,
+            layer_views,
+            sampler,
+            uniform_buffer,
+            layer_buffer,
+            layer_bind_group,
+            pipeline,
+            num_layers: 0,
+        }
+    }
+
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::TextureView(&self.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: self.uniform_buffer.as_entire_binding(),
+            },
+        ]
+    }
+
+    /// Hand out layers to shadow casting lights and upload their matrices.
+    /// Returns each light's first layer, or None if it has no shadow map this frame.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        lights: &[Light],
+        camera: &Camera,
+        projection: &Projection,
+    ) -> Vec<Option<u32>> {
+        let splits = cascade_splits(projection);
+        let mut matrices: Vec<Matrix4<f32>> = Vec::new();
+        let mut layers = Vec::with_capacity(lights.len());
+
+        for light in lights {
+            let light_matrices = if light.cast_shadows {
+                match light.kind {
+                    LightKind::Directional => cascade_matrices(light, camera, projection, &splits),
+                    LightKind::Point => point_matrices(light),
+                    LightKind::Spot => vec![spot_matrix(light)],
+                }
+            } else {
+                Vec::new()
+            };
+
+            if light_matrices.is_empty()
+                || matrices.len() + light_matrices.len() > MAX_SHADOW_LAYERS as usize
+            {
+                layers.push(None);
+                continue;
+            }
+            layers.push(Some(matrices.len() as u32));
+            matrices.extend(light_matrices);
+        }
+
+        let mut uniform = ShadowUniform {
+            matrices: [Matrix4::identity().into(); MAX_SHADOW_LAYERS as usize],
+            cascade_splits: [splits[1], splits[2], splits[3], CASCADE_DISTANCE],
+        };
+        let mut layer_data = vec![0u8; (LAYER_UNIFORM_STRIDE * MAX_SHADOW_LAYERS as u64) as usize];
+        for (i, matrix) in matrices.iter().enumerate() {
+            let matrix: [[f32; 4]; 4] = (*matrix).into();
+            uniform.matrices[i] = matrix;
+            let offset = i * LAYER_UNIFORM_STRIDE as usize;
+            layer_data[offset..offset + 64].copy_from_slice(bytemuck::cast_slice(&matrix));
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        if !matrices.is_empty() {
+            queue.write_buffer(&self.layer_buffer, 0, &layer_data);
+        }
+        self.num_layers = matrices.len() as u32;
+
+        layers
+    }
+
+    /// Render the depth of every `(mesh, vertices, instances, instance count)` draw into
+    /// each used layer. The vertices are the mesh's own or a posed skinned copy.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        draws: &[(&Mesh, wgpu::BufferSlice, wgpu::BufferSlice, u32)],
+    ) {
+        for layer in 0..self.num_layers {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.layer_views[layer as usize],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            shadow_pass.set_pipeline(&self.pipeline);
+            shadow_pass.set_bind_group(
+                0,
+                &self.layer_bind_group,
+                &[(layer as u64 * LAYER_UNIFORM_STRIDE) as u32],
+            );
+            for (mesh, vertices, instances, num_instances) in draws {
+                shadow_pass.set_vertex_buffer(0, *vertices);
+                shadow_pass.set_vertex_buffer(1, *instances);
+                shadow_pass
+                    .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                shadow_pass.draw_indexed(0..mesh.num_elements, 0, 0..*num_instances);
+            }
+        }
+    }
+}
+
+/// Cascade boundaries as view depths, from the near plane to `CASCADE_DISTANCE`
+fn cascade_splits(projection: &Projection) -> [f32; NUM_CASCADES + 1] {
+    let near = projection.znear;
+    let far = CASCADE_DISTANCE.min(projection.zfar);
+    let mut splits = [near; NUM_CASCADES + 1];
+    for (i, split) in splits.iter_mut().enumerate().skip(1) {
+        let t = i as f32 / NUM_CASCADES as f32;
+        let log = near * (far / near).powf(t);
+        let uniform = near + (far - near) * t;
+        *split = CASCADE_SPLIT_LAMBDA * log + (1.0 - CASCADE_SPLIT_LAMBDA) * uniform;
+    }
+    splits
+}
+
+/// Orthographic light matrices, each fit around the bounding sphere of one slice of the view frustum.
+/// Fitting a sphere keeps the projection size constant as the camera turns, and snapping its
+/// center to whole texels keeps shadow edges from shimmering as the camera moves.
+fn cascade_matrices(
+    light: &Light,
+    camera: &Camera,
+    projection: &Projection,
+    splits: &[f32; NUM_CASCADES + 1],
+) -> Vec<Matrix4<f32>> {
+    let direction = light.unit_direction();
+    let inverse_view = camera
+        .calc_matrix()
+        .invert()
+        .unwrap_or_else(Matrix4::identity);
+    let tan_half_y = (projection.fovy.0 * 0.5).tan();
+    let tan_half_x = tan_half_y * projection.aspect;
+
+    (0..NUM_CASCADES)
+        .map(|cascade| {
+            let (d0, d1) = (splits[cascade], splits[cascade + 1]);
+            let corners: Vec<Point3<f32>> = [d0, d1]
+                .iter()
+                .flat_map(|&d| {
+                    [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+                        let corner = Vector4::new(x * tan_half_x * d, y * tan_half_y * d, -d, 1.0);
+                        Point3::from_homogeneous(inverse_view * corner)
+                    })
+                })
+                .collect();
+            let center = Point3::centroid(&corners);
+            let radius = corners
+                .iter()
+                .map(|corner| (corner - center).magnitude())
+                .fold(0.0, f32::max)
+                .max(0.01);
+
+            // Snap the center in light space so the map moves in whole texels
+            let up = stable_up(direction);
+            let light_view = Matrix4::look_to_rh(Point3::origin(), direction, up);
+            let texel = 2.0 * radius / SHADOW_MAP_SIZE as f32;
+            let mut snapped = light_view.transform_point(center);
+            snapped.x = (snapped.x / texel).floor() * texel;
+            snapped.y = (snapped.y / texel).floor() * texel;
+            let inverse_light_view = light_view.invert().unwrap_or_else(Matrix4::identity);
+            let center = inverse_light_view.transform_point(snapped);
+
+            let eye = center - direction * (radius + CASCADE_CASTER_MARGIN);
+            let view = Matrix4::look_to_rh(eye, direction, up);
+            let ortho = cgmath::ortho(
+                -radius,
+                radius,
+                -radius,
+                radius,
+                0.0,
+                2.0 * radius + CASCADE_CASTER_MARGIN,
+            );
+            OPENGL_TO_WGPU_MATRIX * ortho * view
+        })
+        .collect()
+}
+
+/// One 90 degree perspective per cube face, in +X, -X, +Y, -Y, +Z, -Z order.
+/// The lighting shader picks a face by the major axis of the light-to-fragment vector.
+fn point_matrices(light: &Light) -> Vec<Matrix4<f32>> {
+    let far = shadow_far(light);
+    let projection = OPENGL_TO_WGPU_MATRIX * cgmath::perspective(Deg(90.0), 1.0, SHADOW_NEAR, far);
+    let eye = Point3::from(light.position);
+    [
+        (Vector3::unit_x(), -Vector3::unit_y()),
+        (-Vector3::unit_x(), -Vector3::unit_y()),
+        (Vector3::unit_y(), Vector3::unit_z()),
+        (-Vector3::unit_y(), -Vector3::unit_z()),
+        (Vector3::unit_z(), -Vector3::unit_y()),
+        (-Vector3::unit_z(), -Vector3::unit_y()),
+    ]
+    .iter()
+    .map(|&(forward, up)| projection * Matrix4::look_to_rh(eye, forward, up))
+    .collect()
+}
+
+fn spot_matrix(light: &Light) -> Matrix4<f32> {
+    let direction = light.unit_direction();
+    let fov = (light.outer_cone_deg.clamp(1.0, 85.0) * 2.0).min(170.0);
+    let projection = cgmath::perspective(Deg(fov), 1.0, SHADOW_NEAR, shadow_far(light));
+    OPENGL_TO_WGPU_MATRIX
+        * projection
+        * Matrix4::look_to_rh(
+            Point3::from(light.position),
+            direction,
+            stable_up(direction),
+        )
+}
+
+fn shadow_far(light: &Light) -> f32 {
+    if light.range > 0.0 {
+        light.range
+    } else {
+        UNLIMITED_RANGE_SHADOW_FAR
+    }
+}
+
+/// An up vector that isn't parallel to `direction`
+fn stable_up(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    }
+}
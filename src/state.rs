@@ -1,17 +1,32 @@
+use crate::culling::{Aabb, Frustum};
+use crate::deform::Deformer;
 use crate::egui::EguiRenderer;
-use crate::light::LightManager;
-use crate::model::{self, DrawLight, ModelVertex, Vertex};
+use crate::environment::{Environment, EnvironmentImage};
+use crate::light::{Light, LightManager};
+use crate::light_clusters::{ClusterView, LightClusters, LightStorage};
+use crate::lod::LodBucketer;
+use crate::material_schema::{MaterialLayout, UniformValues};
+use crate::model::{self, AlphaMode, DrawLight, ModelVertex, Vertex};
+use crate::particle_simulation::{ParticleSimulator, SimulationBackend};
 use crate::particle_system::{
-    GeneratorType, InstanceRaw, ParticleSystem, ParticleSystemDesc, ParticleSystemManager,
+    GeneratorType, Heightmap, InstanceCount, ParticleSystem, ParticleSystemDesc,
+    ParticleSystemManager,
 };
+use crate::pipeline::{PipelineCache, PipelineKey, VertexLayout};
+use crate::post_process::{POST_FORMAT, PostProcessShaders, PostProcessStack};
+use crate::render_settings::RenderSettings;
 use crate::scripting::ScriptEngine;
-use crate::texture::GpuTexture;
-use crate::world::{CameraData, LightParams, ParticleSystemData, WorldData};
+use crate::shadows::ShadowMaps;
+use crate::texture::{GpuTexture, SamplerSettings};
+use crate::tonemapping::{ToneMapSettings, ToneMapping};
+use crate::world::{
+    CameraData, CustomMaterialData, EnvironmentData, LightParams, ParticleSystemData, WorldData,
+};
 use crate::{camera, resources};
-use cgmath::{Deg, Matrix4, Point3, Rad};
+use cgmath::{Deg, Matrix4, MetricSpace, Point3, Rad};
 use egui_wgpu::ScreenDescriptor;
 use std::sync::{Mutex, mpsc};
-use std::{iter, sync::Arc};
+use std::{iter, rc::Rc, sync::Arc};
 use wgpu::util::DeviceExt;
 use winit::event::{ElementState, KeyEvent, MouseButton, WindowEvent};
 use winit::event_loop::ActiveEventLoop;
@@ -50,67 +65,6 @@ impl CameraUniform {
     }
 }
 
-fn create_render_pipeline(
-    device: &wgpu::Device,
-    layout: &wgpu::PipelineLayout,
-    color_format: wgpu::TextureFormat,
-    depth_format: Option<wgpu::TextureFormat>,
-    vertex_layouts: &[wgpu::VertexBufferLayout],
-    shader: wgpu::ShaderModuleDescriptor,
-) -> wgpu::RenderPipeline {
-    let shader = device.create_shader_module(shader);
-
-    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: Some("vs_main"),
-            buffers: vertex_layouts,
-            compilation_options: Default::default(),
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: Some("fs_main"),
-            targets: &[Some(wgpu::ColorTargetState {
-                format: color_format,
-                blend: Some(wgpu::BlendState {
-                    alpha: wgpu::BlendComponent::REPLACE,
-                    color: wgpu::BlendComponent::REPLACE,
-                }),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-            compilation_options: Default::default(),
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-            polygon_mode: wgpu::PolygonMode::Fill,
-            // Requires Features::DEPTH_CLIP_CONTROL
-            unclipped_depth: false,
-            // Requires Features::CONSERVATIVE_RASTERIZATION
-            conservative: false,
-        },
-        depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
-            format,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
-        }),
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
-        cache: None,
-    })
-}
-
 pub struct State {
     // Put egui_renderer first so it gets dropped before GPU resources
     egui_renderer: EguiRenderer,
@@ -119,7 +73,12 @@ pub struct State {
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     is_surface_configured: bool,
-    render_pipeline: wgpu::RenderPipeline,
+    pipeline_cache: PipelineCache,
+    tone_mapping: ToneMapping,
+    post_process: PostProcessStack,
+    render_settings: RenderSettings,
+    light_pipeline_layout: wgpu::PipelineLayout,
+    light_shader: wgpu::ShaderModule,
     light_render_pipeline: wgpu::RenderPipeline,
     #[allow(dead_code)]
     camera: camera::Camera,
@@ -128,17 +87,25 @@ pub struct State {
     mouse_pressed: bool,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
+    per_frame_bind_group_layout: wgpu::BindGroupLayout,
     per_frame_bind_group: wgpu::BindGroup,
     light_manager: LightManager,
-    light_buffer: wgpu::Buffer,
+    light_clusters: LightClusters,
+    shadow_maps: ShadowMaps,
+    environment: Environment,
+    /// Environment in use or loading; None keeps the solid background
+    environment_data: Option<EnvironmentData>,
     particle_system_manager: ParticleSystemManager,
+    particle_simulator: ParticleSimulator,
+    lod_bucketer: LodBucketer,
+    deformer: Deformer,
     depth_texture: GpuTexture,
     window: Arc<Window>,
     clear_color: wgpu::Color,
     models: std::collections::HashMap<String, Arc<model::Model>>,
-    materials: std::collections::HashMap<model::MaterialSource, Arc<model::GpuMaterial>>,
-    textures: Arc<Mutex<std::collections::HashMap<String, Arc<GpuTexture>>>>,
-    texture_bind_group_layout: wgpu::BindGroupLayout,
+    materials: model::MaterialRegistry,
+    textures: model::TextureRegistry,
+    fallback_textures: model::FallbackTextures,
     #[cfg(not(target_arch = "wasm32"))]
     script_engine: ScriptEngineDesktop,
     #[cfg(target_arch = "wasm32")]
@@ -146,6 +113,19 @@ pub struct State {
     elapsed_time: f32,
     pending_model_loads: std::collections::HashSet<String>,
     in_flight_model_loads: std::collections::HashSet<String>,
+    pending_shader_loads: std::collections::HashSet<String>,
+    in_flight_shader_loads: std::collections::HashSet<String>,
+    /// Custom materials from a loaded world waiting on their shader
+    pending_custom_materials: Vec<CustomMaterialData>,
+    loaded_shader_receiver: mpsc::Receiver<(String, Result<String, String>)>,
+    loaded_shader_sender: mpsc::Sender<(String, Result<String, String>)>,
+    loaded_environment_receiver:
+        mpsc::Receiver<Result<(EnvironmentData, EnvironmentImage), String>>,
+    loaded_environment_sender: mpsc::Sender<Result<(EnvironmentData, EnvironmentImage), String>>,
+    loaded_lut_receiver: mpsc::Receiver<Result<(String, image::RgbaImage), String>>,
+    loaded_lut_sender: mpsc::Sender<Result<(String, image::RgbaImage), String>>,
+    loaded_heightmap_receiver: mpsc::Receiver<(String, Result<Heightmap, String>)>,
+    loaded_heightmap_sender: mpsc::Sender<(String, Result<Heightmap, String>)>,
     ui_state: crate::app_ui::UiState,
     loaded_model_receiver: mpsc::Receiver<
         Result<
@@ -175,6 +155,10 @@ impl State {
 
         // Create channel for async model loading (used on web)
         let (loaded_model_sender, loaded_model_receiver) = mpsc::channel();
+        let (loaded_shader_sender, loaded_shader_receiver) = mpsc::channel();
+        let (loaded_environment_sender, loaded_environment_receiver) = mpsc::channel();
+        let (loaded_lut_sender, loaded_lut_receiver) = mpsc::channel();
+        let (loaded_heightmap_sender, loaded_heightmap_receiver) = mpsc::channel();
 
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
             #[cfg(not(target_arch = "wasm32"))]
@@ -197,15 +181,29 @@ impl State {
 
         let backend = adapter.get_info().backend;
         log::info!("Render backend: {}", backend);
+        let light_storage = LightStorage::for_adapter(&adapter);
+        log::info!("Light list storage: {:?}", light_storage);
+        let simulation_backend = SimulationBackend::for_adapter(&adapter);
+        log::info!("Particle simulation: {:?}", simulation_backend);
+        let hdr_format = ToneMapping::format_for(&adapter);
+        log::info!("Scene color format: {:?}", hdr_format);
 
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
-                required_features: wgpu::Features::empty(),
+                // Lets MSAA use every sample count the adapter supports, not just 4,
+                // and compressed textures upload as-is in whichever formats it can sample
+                required_features: adapter.features()
+                    & (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                        | wgpu::Features::TEXTURE_COMPRESSION_BC
+                        | wgpu::Features::TEXTURE_COMPRESSION_ETC2
+                        | wgpu::Features::TEXTURE_COMPRESSION_ASTC),
                 required_limits: {
                     let mut limits = wgpu::Limits::downlevel_webgl2_defaults();
                     limits.max_texture_dimension_2d =
                         wgpu::Limits::default().max_texture_dimension_2d;
+                    light_storage.apply_limits(&mut limits, &adapter);
+                    simulation_backend.apply_limits(&mut limits, &adapter);
                     limits
                 },
                 memory_hints: Default::default(),
@@ -214,6 +212,16 @@ impl State {
             .await
             .unwrap();
 
+        let render_settings = RenderSettings::new(crate::pipeline::supported_sample_counts(
+            &adapter, &device, hdr_format,
+        ));
+        let sample_count = render_settings.msaa_samples();
+        log::info!(
+            "MSAA sample counts: {:?}, using {}",
+            render_settings.supported_msaa_samples(),
+            sample_count
+        );
+
         let surface_caps = surface.get_capabilities(&adapter);
 
         let surface_format = surface_caps
@@ -246,40 +254,8 @@ impl State {
             log::warn!("Demo functions failed: {}", e);
         }
 
-        let depth_texture = GpuTexture::create_depth_texture(&device, &config, "Depth Texture");
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
+        let depth_texture =
+            GpuTexture::create_depth_texture(&device, &config, sample_count, "Depth Texture");
 
         let camera = camera::Camera::new((0.0, 5.0, 10.0), cgmath::Deg(-90.0), cgmath::Deg(-20.0));
         let projection =
@@ -295,104 +271,109 @@ impl State {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        // Combined per-frame bind group layout (camera + lights)
+        // Combined per-frame bind group layout (camera + clustered lights + shadow maps +
+        // environment)
+        let mut per_frame_layout_entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        per_frame_layout_entries.extend(light_storage.layout_entries());
+        per_frame_layout_entries.extend(ShadowMaps::layout_entries());
+        per_frame_layout_entries.extend(Environment::layout_entries());
         let per_frame_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
+                entries: &per_frame_layout_entries,
                 label: Some("per_frame_bind_group_layout"),
             });
 
-        // Note: Light manager and initial light buffer will be created after loading models
-
-        // Placeholder - will be replaced after model loading
-        let lights =
-            crate::light::LightManager::new(model::MaterialSource::System("default".to_string()))
-                .sync_to_gpu();
-        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("light_buffer"),
-            contents: bytemuck::cast_slice(&[lights]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        // Create combined per-frame bind group (camera + lights)
-        let per_frame_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &per_frame_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: camera_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: light_buffer.as_entire_binding(),
-                },
-            ],
-            label: Some("per_frame_bind_group"),
-        });
-
-        let shader_source = resources::load_string("shader.wgsl").await.unwrap();
-        let shader = wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
-        };
-
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&per_frame_bind_group_layout, &texture_bind_group_layout],
-                push_constant_ranges: &[],
-            });
+        // Light data is filled in by the first update()
+        let light_clusters = LightClusters::new(&device, light_storage);
+        let shadow_maps = ShadowMaps::new(&device, &resources::load_string("shadow.wgsl").await?);
+        let particle_simulator = ParticleSimulator::new(&device, simulation_backend);
+        let lod_bucketer = LodBucketer::new(&device, simulation_backend);
+        let deformer = Deformer::new(&device, simulation_backend);
+
+        // Compile built-in shaders up front; pipelines are created on first use
+        let mut pipeline_cache = PipelineCache::new(
+            per_frame_bind_group_layout.clone(),
+            hdr_format,
+            sample_count,
+        );
+        pipeline_cache.add_import("lights", light_storage.shader_source());
+        pipeline_cache.add_import("shadows", ShadowMaps::shader_source());
+        pipeline_cache.add_import("environment", Environment::shader_source());
+        pipeline_cache.add_import("post_process", PostProcessStack::shader_source());
+        for shader_path in crate::defaults::BUILTIN_SHADERS {
+            let shader_source = resources::load_string(shader_path).await?;
+            pipeline_cache.insert_shader(&device, shader_path, &shader_source)?;
+        }
+        let default_layout = pipeline_cache
+            .material_layout(crate::defaults::DEFAULT_SHADER_PATH)
+            .cloned()
+            .expect("default shader is built in");
 
-        let render_pipeline = create_render_pipeline(
+        // Starts without an environment; a world can load one
+        let environment = Environment::new(
             &device,
-            &render_pipeline_layout,
-            config.format,
-            Some(GpuTexture::DEPTH_FORMAT),
-            &[ModelVertex::desc(), InstanceRaw::desc()],
-            shader,
+            hdr_format,
+            sample_count,
+            &per_frame_bind_group_layout,
+            &resources::load_string("environment_maps.wgsl").await?,
+            &pipeline_cache.preprocess(&resources::load_string("skybox.wgsl").await?)?,
+        );
+        let per_frame_bind_group = Self::create_per_frame_bind_group(
+            &device,
+            &per_frame_bind_group_layout,
+            &camera_buffer,
+            &light_clusters,
+            &shadow_maps,
+            &environment,
         );
 
-        let light_render_pipeline = {
-            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        let light_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Light Pipeline Layout"),
                 bind_group_layouts: &[&per_frame_bind_group_layout],
                 push_constant_ranges: &[],
             });
-            let shader_source = resources::load_string("light.wgsl").await.unwrap();
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("Light Shader"),
-                source: wgpu::ShaderSource::Wgsl(shader_source.into()),
-            };
-            create_render_pipeline(
-                &device,
-                &layout,
-                config.format,
-                Some(GpuTexture::DEPTH_FORMAT),
-                &[ModelVertex::desc()],
-                shader,
-            )
+        let light_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Light Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                pipeline_cache
+                    .preprocess(&resources::load_string("light.wgsl").await?)?
+                    .into(),
+            ),
+        });
+        let light_render_pipeline = Self::create_light_pipeline(
+            &device,
+            &light_pipeline_layout,
+            &light_shader,
+            hdr_format,
+            sample_count,
+        );
+
+        // The scene renders into an HDR target that's tonemapped into the swapchain
+        let tone_mapping = ToneMapping::new(
+            &device,
+            hdr_format,
+            &config,
+            sample_count,
+            &resources::load_string("tonemap.wgsl").await?,
+        );
+        let post_process_shaders = PostProcessShaders {
+            bloom: pipeline_cache.preprocess(&resources::load_string("bloom.wgsl").await?)?,
+            fxaa: pipeline_cache.preprocess(&resources::load_string("fxaa.wgsl").await?)?,
+            vignette: pipeline_cache.preprocess(&resources::load_string("vignette.wgsl").await?)?,
+            color_grade: pipeline_cache
+                .preprocess(&resources::load_string("color_grade.wgsl").await?)?,
         };
+        let post_process = PostProcessStack::new(&device, &queue, &config, &post_process_shaders);
 
         // Get particle system parameters from JS (will create system after loading model)
         let system_desc: ParticleSystemDesc = script_engine
@@ -409,67 +390,27 @@ impl State {
 
         // Create default material
         let mut materials = std::collections::HashMap::new();
+        let fallback_textures = model::FallbackTextures::load(&device, &queue, &textures).await?;
         let default_material = {
-            let texture_name = "white.png";
-
-            // Load texture into registry
-            let diffuse_texture = {
-                let mut registry = textures.lock().unwrap();
-                if let Some(existing) = registry.get(texture_name) {
-                    Arc::clone(existing)
-                } else {
-                    let diffuse_texture_bytes = resources::load_binary(texture_name).await?;
-                    let texture = Arc::new(GpuTexture::from_bytes(
-                        &device,
-                        &queue,
-                        &diffuse_texture_bytes,
-                        texture_name,
-                    )?);
-                    registry.insert(texture_name.to_string(), Arc::clone(&texture));
-                    texture
-                }
-            };
-
             let desc = model::MaterialDesc {
                 name: "default".to_string(),
-                texture_path: texture_name.to_string(),
-                properties: std::cell::RefCell::new(model::MaterialProperties::default()),
+                shader: crate::defaults::DEFAULT_SHADER_PATH.to_string(),
+                textures: std::collections::HashMap::new(),
+                uniforms: std::cell::RefCell::new(UniformValues::new()),
+                alpha_mode: AlphaMode::Opaque,
+                samplers: std::collections::HashMap::new(),
             };
 
-            let properties_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("default_material_properties"),
-                contents: bytemuck::cast_slice(&[*desc.properties.borrow()]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
-
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("default_material_bind_group"),
-                layout: &texture_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: properties_buffer.as_entire_binding(),
-                    },
-                ],
-            });
-
-            model::GpuMaterial {
+            model::GpuMaterial::new(
+                &device,
                 desc,
-                diffuse_texture,
-                properties_buffer,
-                bind_group,
-            }
+                &default_layout,
+                std::collections::HashMap::new(),
+                &fallback_textures,
+            )
         };
         let default_source = model::MaterialSource::System("default".to_string());
-        materials.insert(default_source, Arc::new(default_material));
+        materials.insert(default_source, Rc::new(default_material));
 
         // Load initial model into HashMap
         let mut models = std::collections::HashMap::new();
@@ -478,7 +419,8 @@ impl State {
             crate::defaults::INITIAL_MODEL_PATH,
             &device,
             &queue,
-            &texture_bind_group_layout,
+            &default_layout,
+            &fallback_textures,
             &textures,
         )
         .await
@@ -486,7 +428,7 @@ impl State {
 
         // Move materials directly into registry (no cloning needed)
         for (key, material) in initial_materials {
-            materials.insert(key, Arc::new(material));
+            materials.insert(key, Rc::new(material));
         }
 
         // Extract material sources before moving initial_model
@@ -506,6 +448,7 @@ impl State {
             0, // mesh_index: use first mesh
             initial_material_source.clone(),
             GeneratorType::Grid(initial_grid_params),
+            None,
         );
         particle_system_manager.add("main".to_string(), grid_system);
 
@@ -519,14 +462,15 @@ impl State {
                     crate::defaults::LIGHT_MODEL_PATH,
                     &device,
                     &queue,
-                    &texture_bind_group_layout,
+                    &default_layout,
+                    &fallback_textures,
                     &textures,
                 )
                 .await
                 .unwrap();
 
                 for (key, material) in light_materials {
-                    materials.insert(key, Arc::new(material));
+                    materials.insert(key, Rc::new(material));
                 }
 
                 let light_mat_source = light_model.meshes[0].material_source.clone();
@@ -543,16 +487,12 @@ impl State {
         let mut light_manager = LightManager::with_lights(
             light_material_source,
             &[
-                ([2.0, 2.0, 2.0], [1.0, 1.0, 1.0, 1.0]),
-                ([-2.0, 2.0, 2.0], [1.0, 0.0, 0.0, 1.0]),
+                Light::point([2.0, 2.0, 2.0], [1.0, 1.0, 1.0, 1.0]),
+                Light::point([-2.0, 2.0, 2.0], [1.0, 0.0, 0.0, 1.0]),
             ],
         );
         light_manager.set_model_path(crate::defaults::LIGHT_MODEL_PATH.to_string());
 
-        // Update light buffer with actual lights
-        let updated_lights = light_manager.sync_to_gpu();
-        queue.write_buffer(&light_buffer, 0, bytemuck::cast_slice(&[updated_lights]));
-
         let egui_renderer = EguiRenderer::new(
             &device,
             config.format,
@@ -570,17 +510,29 @@ impl State {
             queue,
             config,
             is_surface_configured: false,
-            render_pipeline,
+            pipeline_cache,
+            tone_mapping,
+            post_process,
+            render_settings,
+            light_pipeline_layout,
+            light_shader,
             light_render_pipeline,
             camera,
             projection,
             camera_controller,
             camera_buffer,
+            per_frame_bind_group_layout,
             per_frame_bind_group,
             camera_uniform,
             light_manager,
-            light_buffer,
+            light_clusters,
+            shadow_maps,
+            environment,
+            environment_data: None,
             particle_system_manager,
+            particle_simulator,
+            lod_bucketer,
+            deformer,
             depth_texture,
             window,
             mouse_pressed: false,
@@ -594,10 +546,21 @@ impl State {
             models,
             materials,
             textures,
-            texture_bind_group_layout,
+            fallback_textures,
             elapsed_time: 0.0,
             pending_model_loads: std::collections::HashSet::new(),
             in_flight_model_loads: std::collections::HashSet::new(),
+            pending_shader_loads: std::collections::HashSet::new(),
+            in_flight_shader_loads: std::collections::HashSet::new(),
+            pending_custom_materials: Vec::new(),
+            loaded_shader_receiver,
+            loaded_shader_sender,
+            loaded_environment_receiver,
+            loaded_environment_sender,
+            loaded_lut_receiver,
+            loaded_lut_sender,
+            loaded_heightmap_receiver,
+            loaded_heightmap_sender,
             ui_state: crate::app_ui::UiState::default(),
             loaded_model_receiver,
             loaded_model_sender,
@@ -608,6 +571,84 @@ impl State {
         &self.window
     }
 
+    fn create_light_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        crate::pipeline::create_render_pipeline(
+            device,
+            &crate::pipeline::RenderPipelineDesc {
+                label: "Light Pipeline",
+                layout,
+                shader,
+                vertex_layouts: &[ModelVertex::desc()],
+                color_format,
+                depth_format: Some(GpuTexture::DEPTH_FORMAT),
+                sample_count,
+            },
+        )
+    }
+
+    /// Rebuild pipelines and attachments if the MSAA setting changed
+    fn apply_sample_count(&mut self) {
+        let sample_count = self.render_settings.msaa_samples();
+        if sample_count == self.pipeline_cache.sample_count() {
+            return;
+        }
+        log::info!("Switching to {}x MSAA", sample_count);
+        self.pipeline_cache.set_sample_count(sample_count);
+        self.light_render_pipeline = Self::create_light_pipeline(
+            &self.device,
+            &self.light_pipeline_layout,
+            &self.light_shader,
+            self.tone_mapping.format(),
+            sample_count,
+        );
+        self.depth_texture = GpuTexture::create_depth_texture(
+            &self.device,
+            &self.config,
+            sample_count,
+            "Depth Texture",
+        );
+        self.tone_mapping
+            .set_sample_count(&self.device, &self.config, sample_count);
+        self.environment
+            .set_sample_count(&self.device, sample_count);
+    }
+
+    fn create_per_frame_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        camera_buffer: &wgpu::Buffer,
+        light_clusters: &LightClusters,
+        shadow_maps: &ShadowMaps,
+        environment: &Environment,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: camera_buffer.as_entire_binding(),
+        }];
+        entries.extend(light_clusters.bind_group_entries());
+        entries.extend(shadow_maps.bind_group_entries());
+        entries.extend(environment.bind_group_entries());
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some("per_frame_bind_group"),
+        })
+    }
+
+    /// Schema and layout that model materials are created with
+    fn default_material_layout(&self) -> MaterialLayout {
+        self.pipeline_cache
+            .material_layout(crate::defaults::DEFAULT_SHADER_PATH)
+            .cloned()
+            .expect("default shader is built in")
+    }
+
     /// Get or load a model by path. Returns Arc for cheap cloning.
     pub async fn get_or_load_model(&mut self, path: &str) -> anyhow::Result<Arc<model::Model>> {
         if let Some(model) = self.models.get(path) {
@@ -617,14 +658,15 @@ impl State {
                 path,
                 &self.device,
                 &self.queue,
-                &self.texture_bind_group_layout,
+                &self.default_material_layout(),
+                &self.fallback_textures,
                 &self.textures,
             )
             .await?;
 
             // Register materials into the materials registry
             for (key, material) in materials {
-                self.materials.insert(key, Arc::new(material));
+                self.materials.insert(key, Rc::new(material));
             }
 
             let model = Arc::new(model);
@@ -639,8 +681,14 @@ impl State {
             self.config.width = width;
             self.config.height = height;
             self.surface.configure(&self.device, &self.config);
-            self.depth_texture =
-                GpuTexture::create_depth_texture(&self.device, &self.config, "Depth Texture");
+            self.depth_texture = GpuTexture::create_depth_texture(
+                &self.device,
+                &self.config,
+                self.pipeline_cache.sample_count(),
+                "Depth Texture",
+            );
+            self.tone_mapping.resize(&self.device, &self.config);
+            self.post_process.resize(&self.device, &self.config);
             self.projection.resize(width, height);
         }
     }
@@ -702,12 +750,123 @@ impl State {
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
 
-        // Sync light manager to GPU only if dirty
-        if self.light_manager.is_dirty() {
-            let lights = self.light_manager.sync_to_gpu();
-            self.queue
-                .write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[lights]));
-            self.light_manager.clear_dirty();
+        // Fit shadow maps and re-cluster lights for the new view; growing past capacity
+        // reallocates GPU data
+        let lights = self.light_manager.active_lights();
+        let shadow_layers =
+            self.shadow_maps
+                .update(&self.queue, &lights, &self.camera, &self.projection);
+        if self.light_clusters.update(
+            &self.device,
+            &self.queue,
+            &lights,
+            &shadow_layers,
+            &ClusterView {
+                camera: &self.camera,
+                projection: &self.projection,
+                screen_size: (self.config.width, self.config.height),
+            },
+        ) {
+            self.rebuild_per_frame_bind_group();
+        }
+
+        let environment_intensity = self
+            .environment_data
+            .as_ref()
+            .map_or(1.0, |data| data.intensity);
+        self.environment.update(
+            &self.queue,
+            &self.camera,
+            &self.projection,
+            environment_intensity,
+        );
+
+        // Color grading LUTs load in the background; passes use the identity LUT meanwhile
+        for path in self
+            .post_process
+            .luts_to_load(&self.render_settings.post_process)
+        {
+            log::info!("Starting load for LUT: {}", path);
+            let sender = self.loaded_lut_sender.clone();
+            let load = async move {
+                let result = async {
+                    let bytes = resources::load_binary(&path).await?;
+                    anyhow::Ok(image::load_from_memory(&bytes)?.to_rgba8())
+                }
+                .await
+                .map(|image| (path.clone(), image))
+                .map_err(|e| format!("Failed to load LUT {}: {:#}", path, e));
+                let _ = sender.send(result);
+            };
+
+            #[cfg(not(target_arch = "wasm32"))]
+            std::thread::spawn(move || pollster::block_on(load));
+
+            #[cfg(target_arch = "wasm32")]
+            wasm_bindgen_futures::spawn_local(load);
+        }
+        while let Ok(result) = self.loaded_lut_receiver.try_recv() {
+            let added = result.and_then(|(path, image)| {
+                self.post_process
+                    .add_lut(&self.device, &self.queue, &path, &image)
+                    .map_err(|e| e.to_string())
+            });
+            if let Err(error_msg) = added {
+                log::error!("{}", error_msg);
+            }
+        }
+
+        // Animation clocks; skinned and morphed meshes are posed when rendering
+        for (_name, system) in self.particle_system_manager.systems_mut() {
+            system.advance_animation(dt_secs);
+        }
+
+        // Heightmaps load in the background; their systems stay empty meanwhile
+        for path in self.particle_system_manager.heightmaps_to_load() {
+            log::info!("Starting load for heightmap: {}", path);
+            let sender = self.loaded_heightmap_sender.clone();
+            let load = async move {
+                let result = async {
+                    let bytes = resources::load_binary(&path).await?;
+                    let image = image::load_from_memory(&bytes)?;
+                    Heightmap::new(path.clone(), &image)
+                }
+                .await
+                .map_err(|e| format!("Failed to load heightmap {}: {:#}", path, e));
+                let _ = sender.send((path, result));
+            };
+
+            #[cfg(not(target_arch = "wasm32"))]
+            std::thread::spawn(move || pollster::block_on(load));
+
+            #[cfg(target_arch = "wasm32")]
+            wasm_bindgen_futures::spawn_local(load);
+        }
+        while let Ok((path, result)) = self.loaded_heightmap_receiver.try_recv() {
+            match result {
+                Ok(heightmap) => self.particle_system_manager.add_heightmap(heightmap),
+                Err(error_msg) => {
+                    log::error!("{}", error_msg);
+                    self.particle_system_manager.heightmap_failed(&path);
+                }
+            }
+        }
+
+        // Build maps for an environment that finished loading, unless another replaced it meanwhile
+        while let Ok(result) = self.loaded_environment_receiver.try_recv() {
+            match result {
+                Ok((data, image)) => {
+                    let current_source = self.environment_data.as_ref().map(|d| &d.source);
+                    if current_source != Some(&data.source) {
+                        continue;
+                    }
+                    log::info!("Building environment maps for {:?}", data.source);
+                    self.environment
+                        .set_image(&self.device, &self.queue, &image);
+                    self.rebuild_per_frame_bind_group();
+                }
+                Err(error_msg) => log::error!("Environment load failed: {}", error_msg),
+            }
         }
 
         // Poll channel for loaded models (from async tasks)
@@ -718,7 +877,7 @@ impl State {
 
                     // Register materials
                     for (key, material) in materials {
-                        self.materials.insert(key, Arc::new(material));
+                        self.materials.insert(key, Rc::new(material));
                     }
 
                     // Register model
@@ -736,6 +895,13 @@ impl State {
             }
         }
 
+        // Surface scatter systems regenerate once the model they cover has arrived
+        for path in self.particle_system_manager.attach_surfaces(&self.models) {
+            if !self.in_flight_model_loads.contains(&path) {
+                self.pending_model_loads.insert(path);
+            }
+        }
+
         // Process pending model loads
         if !self.pending_model_loads.is_empty() {
             let paths_to_load: Vec<String> = self.pending_model_loads.drain().collect();
@@ -760,7 +926,8 @@ impl State {
                     // Desktop: spawn thread and send result through channel
                     let device = self.device.clone();
                     let queue = self.queue.clone();
-                    let texture_bind_group_layout = self.texture_bind_group_layout.clone();
+                    let material_layout = self.default_material_layout();
+                    let fallback_textures = self.fallback_textures.clone();
                     let textures = Arc::clone(&self.textures);
                     let sender = self.loaded_model_sender.clone();
                     let path_clone = path.clone();
@@ -770,7 +937,8 @@ impl State {
                             &path_clone,
                             &device,
                             &queue,
-                            &texture_bind_group_layout,
+                            &material_layout,
+                            &fallback_textures,
                             &textures,
                         ));
 
@@ -801,7 +969,8 @@ impl State {
                     // Web: spawn async task and send result through channel
                     let device = self.device.clone();
                     let queue = self.queue.clone();
-                    let texture_bind_group_layout = self.texture_bind_group_layout.clone();
+                    let material_layout = self.default_material_layout();
+                    let fallback_textures = self.fallback_textures.clone();
                     let textures = Arc::clone(&self.textures);
                     let sender = self.loaded_model_sender.clone();
 
@@ -810,7 +979,8 @@ impl State {
                             &path,
                             &device,
                             &queue,
-                            &texture_bind_group_layout,
+                            &material_layout,
+                            &fallback_textures,
                             &textures,
                         )
                         .await
@@ -833,6 +1003,70 @@ impl State {
             }
         }
 
+        // Poll channel for loaded shader sources and compile them on this thread
+        while let Ok((path, result)) = self.loaded_shader_receiver.try_recv() {
+            self.in_flight_shader_loads.remove(&path);
+            match result {
+                Ok(source) => {
+                    log::info!("Registering loaded shader: {}", path);
+                    if let Err(e) = self
+                        .pipeline_cache
+                        .insert_shader(&self.device, &path, &source)
+                    {
+                        log::error!("Shader '{}' failed to compile: {:#}", path, e);
+                        self.pending_custom_materials
+                            .retain(|mat_data| mat_data.shader != path);
+                        continue;
+                    }
+
+                    // Materials from a loaded world that were waiting on this shader
+                    let (ready, waiting): (Vec<_>, Vec<_>) =
+                        std::mem::take(&mut self.pending_custom_materials)
+                            .into_iter()
+                            .partition(|mat_data| mat_data.shader == path);
+                    self.pending_custom_materials = waiting;
+                    for mat_data in &ready {
+                        self.recreate_custom_material(mat_data);
+                    }
+                }
+                Err(error_msg) => {
+                    log::error!("Shader load failed: {}", error_msg);
+                    self.pending_custom_materials
+                        .retain(|mat_data| mat_data.shader != path);
+                }
+            }
+        }
+
+        // Process pending shader loads
+        if !self.pending_shader_loads.is_empty() {
+            let paths_to_load: Vec<String> = self.pending_shader_loads.drain().collect();
+
+            for path in paths_to_load {
+                if self.pipeline_cache.has_shader(&path)
+                    || self.in_flight_shader_loads.contains(&path)
+                {
+                    continue;
+                }
+
+                log::info!("Starting load for shader: {}", path);
+                self.in_flight_shader_loads.insert(path.clone());
+                let sender = self.loaded_shader_sender.clone();
+
+                let load = async move {
+                    let result = resources::load_string(&path)
+                        .await
+                        .map_err(|e| format!("Failed to load shader '{}': {}", path, e));
+                    let _ = sender.send((path, result));
+                };
+
+                #[cfg(not(target_arch = "wasm32"))]
+                std::thread::spawn(move || pollster::block_on(load));
+
+                #[cfg(target_arch = "wasm32")]
+                wasm_bindgen_futures::spawn_local(load);
+            }
+        }
+
         // Call JS update function every frame and capture clear color
         match self.script_engine.call_js("update".into(), &()) {
             Ok(_color) => {
@@ -889,11 +1123,101 @@ impl State {
         }
 
         // Rebuild particle systems if needed (before render pass)
+        let script_engine = &mut self.script_engine;
+        let mut scripts = |function: &str, count: usize| {
+            script_engine.call_js_float32array(function.to_string(), &count)
+        };
         for (_name, system) in self.particle_system_manager.systems_mut() {
             if system.needs_rebuild() {
-                system.rebuild(&self.device, &self.queue);
+                system.rebuild(&self.device, &self.queue, &mut scripts);
+            }
+        }
+
+        // Step dynamic systems ahead of sorting, culling and LOD bucketing, which see their
+        // new instances
+        let mut simulation_encoder =
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Particle Simulation Encoder"),
+                });
+        for (_name, system) in self.particle_system_manager.systems_mut() {
+            system.simulate(
+                &self.device,
+                &self.queue,
+                &mut simulation_encoder,
+                &self.particle_simulator,
+                dt.as_secs_f32(),
+            );
+            let Some(model) = self.models.get(system.model_path()) else {
+                continue;
+            };
+            let mut mesh_indices: Vec<usize> = system_parts(&self.models, system)
+                .iter()
+                .flat_map(|part| part.mesh_indices.iter().flatten().copied())
+                .collect();
+            mesh_indices.sort_unstable();
+            mesh_indices.dedup();
+            system.evaluate_morph_weights(model, &mesh_indices, &mut scripts);
+            system.animate(
+                &self.device,
+                &self.queue,
+                &mut simulation_encoder,
+                &self.deformer,
+                model,
+                &mesh_indices,
+            );
+        }
+
+        self.apply_sample_count();
+
+        // Create pipelines for any (shader, layout, alpha mode) used this frame, order
+        // blended systems' instances back to front for the current view, then cull them
+        let eye = self.camera.position;
+        let frustum = self.render_settings.frustum_culling.then(|| {
+            Frustum::from_view_proj(&(self.projection.calc_matrix() * self.camera.calc_matrix()))
+        });
+        for (_name, system) in self.particle_system_manager.systems_mut() {
+            let parts = system_parts(&self.models, system);
+            let mut blended = false;
+            for part in &parts {
+                if let Some(material) = self.materials.get(&part.material) {
+                    let key = PipelineKey::new(
+                        &material.desc.shader,
+                        VertexLayout::ModelInstanced,
+                        material.desc.alpha_mode,
+                    );
+                    self.pipeline_cache.prepare(&self.device, &key);
+                    blended |= material.desc.alpha_mode == AlphaMode::Blend;
+                }
+            }
+            if blended {
+                system.sort_back_to_front(&self.queue, eye);
+            }
+            if let Some(bounds) = parts
+                .iter()
+                .map(|part| {
+                    part.mesh_indices[0]
+                        .and_then(|index| system.deformed_bounds(index))
+                        .unwrap_or(part.meshes[0].bounds)
+                })
+                .reduce(Aabb::union)
+            {
+                system.cull(&self.queue, &bounds, frustum.as_ref(), eye);
+                let index_counts: Vec<Vec<u32>> = parts
+                    .iter()
+                    .map(|part| part.meshes.iter().map(|mesh| mesh.num_elements).collect())
+                    .collect();
+                system.bucket_lods(
+                    &self.device,
+                    &self.queue,
+                    &mut simulation_encoder,
+                    &self.lod_bucketer,
+                    eye,
+                    &index_counts,
+                );
             }
         }
+        self.queue.submit(iter::once(simulation_encoder.finish()));
 
         let output = self.surface.get_current_texture()?;
         if output.suboptimal {
@@ -910,12 +1234,63 @@ impl State {
                 label: Some("Render Encoder"),
             });
 
+        // Shadow casters are every particle system's instances, whatever their material,
+        // including those culled from the camera's view. Deformed meshes cast one draw per
+        // animation time offset. Shadows are always cast at full detail by each part's own
+        // mesh and its posed vertices, whatever LOD level the colour pass draws, since the
+        // LOD buckets only hold the instances the camera sees.
+        let shadow_draws: Vec<_> = self
+            .particle_system_manager
+            .systems()
+            .filter(|(_name, system)| system.num_instances() > 0)
+            .flat_map(|(_name, system)| {
+                system_parts(&self.models, system)
+                    .into_iter()
+                    .flat_map(move |part| {
+                        (0..system.offset_count()).filter_map(move |offset| {
+                            let (instances, count) = system.offset_instances(offset)?;
+                            let vertices = part.mesh_indices[0]
+                                .and_then(|index| system.deformed_vertices(index, offset))
+                                .unwrap_or(part.meshes[0].vertex_buffer.slice(..));
+                            Some((part.meshes[0], vertices, instances, count))
+                        })
+                    })
+            })
+            .collect();
+        self.shadow_maps.render(&mut encoder, &shadow_draws);
+
+        // Each mesh of each system is a draw. Opaque and masked ones are sorted so
+        // pipelines only switch when the shader changes; blended ones draw afterwards,
+        // farthest system first.
+        let materials = &self.materials;
+        let (mut opaque_draws, mut blended_draws): (Vec<_>, Vec<_>) = self
+            .particle_system_manager
+            .systems()
+            .flat_map(|(_name, system)| {
+                system_parts(&self.models, system)
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(move |(index, part)| {
+                        let material = materials.get(&part.material)?;
+                        Some((system, index, part, material.as_ref()))
+                    })
+            })
+            .partition(|(_, _, _, material)| material.desc.alpha_mode != AlphaMode::Blend);
+        opaque_draws.sort_by(|a, b| a.3.desc.shader.cmp(&b.3.desc.shader));
+        blended_draws.sort_by(|a, b| {
+            let distance_a = a.0.center().distance2(eye);
+            let distance_b = b.0.center().distance2(eye);
+            distance_b.total_cmp(&distance_a)
+        });
+
+        let (scene_view, resolve_target) = self.tone_mapping.scene_target();
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
+                    view: scene_view,
+                    // With blended draws the transparent pass resolves instead
+                    resolve_target: resolve_target.filter(|_| blended_draws.is_empty()),
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(self.clear_color),
                         store: wgpu::StoreOp::Store,
@@ -933,15 +1308,19 @@ impl State {
                 timestamp_writes: None,
             });
 
-            use model::DrawModel;
-
             // Render lights
             render_pass.set_pipeline(&self.light_render_pipeline);
             if let Some(light_model) = self.models.get(self.light_manager.model_path()) {
                 let material_source = self.light_manager.material_source();
                 if self.materials.contains_key(material_source) {
-                    // Draw mesh at specified index
-                    if let Some(mesh) = light_model.meshes.get(self.light_manager.mesh_index()) {
+                    // Every mesh, or just the one at the specified index
+                    let meshes = if self.light_manager.entire_model() {
+                        &light_model.meshes[..]
+                    } else {
+                        let index = self.light_manager.mesh_index();
+                        light_model.meshes.get(index..=index).unwrap_or_default()
+                    };
+                    for mesh in meshes {
                         render_pass.draw_light_mesh_instanced(
                             mesh,
                             0..self.light_manager.num_lights(),
@@ -951,26 +1330,59 @@ impl State {
                 }
             }
 
-            // Render particle systems
-            render_pass.set_pipeline(&self.render_pipeline);
-
-            for (_name, system) in self.particle_system_manager.systems() {
-                if let Some(model) = self.models.get(system.model_path()) {
-                    let material_source = system.material_source();
-                    if let Some(material) = self.materials.get(material_source) {
-                        render_pass.set_vertex_buffer(1, system.instance_buffer().slice(..));
-                        // Draw mesh at specified index
-                        if let Some(mesh) = model.meshes.get(system.mesh_index()) {
-                            render_pass.draw_mesh_instanced(
-                                mesh,
-                                material,
-                                0..system.num_instances(),
-                                &self.per_frame_bind_group,
-                            );
-                        }
-                    }
-                }
-            }
+            self.draw_particle_systems(&mut render_pass, &opaque_draws);
+
+            // Last, so it only shades pixels no geometry covered
+            self.environment
+                .draw_skybox(&mut render_pass, &self.per_frame_bind_group);
+        }
+
+        // Blended geometry goes over the finished opaque scene, depth tested but not written
+        if !blended_draws.is_empty() {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Transparent Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: scene_view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            self.draw_particle_systems(&mut render_pass, &blended_draws);
+        }
+
+        // With post-processing the tonemapped image goes through the chain on its way out
+        let post_passes = &self.render_settings.post_process;
+        if PostProcessStack::is_active(post_passes) {
+            self.tone_mapping.render(
+                &self.queue,
+                &mut encoder,
+                self.post_process.input_target(),
+                POST_FORMAT,
+                &self.render_settings.tone_map,
+            );
+            self.post_process
+                .render(&self.device, &self.queue, &mut encoder, &view, post_passes);
+        } else {
+            self.tone_mapping.render(
+                &self.queue,
+                &mut encoder,
+                &view,
+                self.config.format,
+                &self.render_settings.tone_map,
+            );
         }
 
         let screen_descriptor = ScreenDescriptor {
@@ -979,10 +1391,10 @@ impl State {
         };
 
         let clear_color = &mut self.clear_color;
+        let render_settings = &mut self.render_settings;
+        let environment_data = self.environment_data.as_ref();
         let particle_system_manager = &mut self.particle_system_manager;
         let light_manager = &mut self.light_manager;
-        let light_buffer = &self.light_buffer;
-        let queue = &self.queue;
         let loading_models_count =
             self.pending_model_loads.len() + self.in_flight_model_loads.len();
         let ui_actions = self.egui_renderer.draw(
@@ -996,17 +1408,20 @@ impl State {
                 crate::app_ui::app_ui(
                     ctx,
                     clear_color,
+                    render_settings,
                     particle_system_manager,
                     light_manager,
-                    light_buffer,
-                    dt.as_millis() as f32,
-                    queue,
-                    &self.device,
-                    &self.models,
-                    &self.materials,
-                    &self.textures,
+                    crate::app_ui::UiInputs {
+                        environment: environment_data,
+                        delta_time_ms: dt.as_millis() as f32,
+                        device: &self.device,
+                        models: &self.models,
+                        materials: &self.materials,
+                        textures: &self.textures,
+                        pipeline_cache: &self.pipeline_cache,
+                        loading_models_count,
+                    },
                     &mut self.ui_state,
-                    loading_models_count,
                 )
             },
         );
@@ -1025,18 +1440,20 @@ impl State {
         if let Some(model_path) = ui_actions.model_to_load {
             self.pending_model_loads.insert(model_path);
         }
-        if let Some((material_key, color)) = ui_actions.material_color_changed {
-            if let Some(material) = self.materials.get(&material_key) {
-                material.desc.properties.borrow_mut().color = color;
-                self.queue.write_buffer(
-                    &material.properties_buffer,
-                    0,
-                    bytemuck::cast_slice(&[*material.desc.properties.borrow()]),
-                );
-            }
+        if let Some((material_key, field, value)) = ui_actions.material_uniform_changed
+            && let Err(e) = self.set_material_uniform(&material_key, &field, value)
+        {
+            log::error!("Failed to set material uniform: {}", e);
         }
-        if let Some((name, texture_path, color)) = ui_actions.material_to_create {
-            match self.create_material(name, texture_path, color) {
+        if let Some((name, shader, textures, uniforms)) = ui_actions.material_to_create {
+            match self.create_material(
+                name,
+                shader,
+                textures,
+                uniforms,
+                AlphaMode::default(),
+                std::collections::HashMap::new(),
+            ) {
                 Ok(material_key) => {
                     log::info!("Successfully created material: {}", material_key);
                 }
@@ -1045,10 +1462,31 @@ impl State {
                 }
             }
         }
-        if let Some((material_key, new_texture_path)) = ui_actions.material_texture_changed {
-            if let Err(e) = self.change_material_texture(&material_key, &new_texture_path) {
-                log::error!("Failed to change material texture: {}", e);
-            }
+        if let Some((material_key, slot, new_texture_path)) = ui_actions.material_texture_changed
+            && let Err(e) = self.change_material_texture(&material_key, &slot, &new_texture_path)
+        {
+            log::error!("Failed to change material texture: {}", e);
+        }
+        if let Some((material_key, new_shader)) = ui_actions.material_shader_changed
+            && let Err(e) = self.change_material_shader(&material_key, &new_shader)
+        {
+            log::error!("Failed to change material shader: {}", e);
+        }
+        if let Some((material_key, alpha_mode)) = ui_actions.material_alpha_mode_changed
+            && let Err(e) = self.change_material_alpha_mode(&material_key, alpha_mode)
+        {
+            log::error!("Failed to change material alpha mode: {}", e);
+        }
+        if let Some((material_key, slot, sampler)) = ui_actions.material_sampler_changed
+            && let Err(e) = self.change_material_sampler(&material_key, &slot, sampler)
+        {
+            log::error!("Failed to change material sampler: {}", e);
+        }
+        if let Some(environment) = ui_actions.environment_changed {
+            self.set_environment(environment);
+        }
+        if let Some(shader_path) = ui_actions.shader_to_load {
+            self.request_shader(&shader_path);
         }
 
         self.queue.submit(iter::once(encoder.finish()));
@@ -1075,13 +1513,23 @@ impl State {
 
         // Export lights
         let mut lights = Vec::new();
-        for i in 0..self.light_manager.max_lights() {
+        for i in 0..self.light_manager.slot_count() {
             if let Some(light) = self.light_manager.get_light(i) {
                 lights.push(LightParams {
-                    position: [light.position[0], light.position[1], light.position[2]],
+                    kind: light.kind,
+                    position: light.position,
+                    direction: light.direction,
                     color: light.color,
+                    intensity: light.intensity,
+                    range: light.range,
+                    inner_cone_deg: light.inner_cone_deg,
+                    outer_cone_deg: light.outer_cone_deg,
+                    cast_shadows: light.cast_shadows,
+                    shadow_bias: light.shadow_bias,
+                    shadow_normal_bias: light.shadow_normal_bias,
                     model: self.light_manager.model_path().to_string(),
                     mesh_index: self.light_manager.mesh_index(),
+                    entire_model: self.light_manager.entire_model(),
                     material_source: self.light_manager.material_source().clone(),
                 });
             }
@@ -1095,7 +1543,14 @@ impl State {
                 model: system.model_path().to_string(),
                 mesh_index: system.mesh_index(),
                 material_source: system.material_source().clone(),
+                entire_model: system.entire_model(),
+                material_overrides: system.material_overrides().clone(),
                 generator: system.generator().clone(),
+                attributes: system.attributes().clone(),
+                simulation: system.simulation().cloned(),
+                lods: system.lods().to_vec(),
+                animation: system.animation().cloned(),
+                morph_weights: system.morph_weights().cloned(),
             });
         }
 
@@ -1111,16 +1566,31 @@ impl State {
         let mut custom_materials = Vec::new();
         for (source, material) in &self.materials {
             if let model::MaterialSource::Custom(name) = source {
-                custom_materials.push(crate::world::CustomMaterialData {
+                custom_materials.push(CustomMaterialData {
                     name: name.clone(),
-                    texture_path: material.desc.texture_path.clone(),
-                    color: material.desc.properties.borrow().color,
+                    shader: material.desc.shader.clone(),
+                    textures: material.desc.textures.clone().into_iter().collect(),
+                    uniforms: material
+                        .desc
+                        .uniforms
+                        .borrow()
+                        .clone()
+                        .into_iter()
+                        .collect(),
+                    alpha_mode: material.desc.alpha_mode,
+                    samplers: material.desc.samplers.clone().into_iter().collect(),
+                    texture_path: None,
+                    color: None,
                 });
             }
         }
 
         WorldData {
             background_color,
+            exposure: self.render_settings.tone_map.exposure,
+            tonemapper: self.render_settings.tone_map.tonemapper,
+            post_process: self.render_settings.post_process.clone(),
+            environment: self.environment_data.clone(),
             camera: camera_data,
             lights,
             particle_systems,
@@ -1130,37 +1600,14 @@ impl State {
 
     /// Load world state from serialized data
     pub fn load_world(&mut self, data: WorldData) {
-        // Recreate custom materials first (they may be needed by other entities)
-        for mat_data in &data.custom_materials {
-            // Check if texture exists in registry
-            let texture_exists = {
-                let registry = self.textures.lock().unwrap();
-                registry.contains_key(&mat_data.texture_path)
-            };
-
-            if texture_exists {
-                match self.create_material(
-                    mat_data.name.clone(),
-                    mat_data.texture_path.clone(),
-                    mat_data.color,
-                ) {
-                    Ok(source) => {
-                        log::info!("Recreated custom material: {}", source.display_key());
-                    }
-                    Err(e) => {
-                        log::warn!(
-                            "Failed to recreate custom material '{}': {}",
-                            mat_data.name,
-                            e
-                        );
-                    }
-                }
+        // Recreate custom materials first (they may be needed by other entities).
+        // Materials whose shader isn't compiled yet are created once it arrives.
+        for mat_data in data.custom_materials {
+            if self.pipeline_cache.has_shader(&mat_data.shader) {
+                self.recreate_custom_material(&mat_data);
             } else {
-                log::warn!(
-                    "Cannot recreate custom material '{}': texture '{}' not found",
-                    mat_data.name,
-                    mat_data.texture_path
-                );
+                self.request_shader(&mat_data.shader);
+                self.pending_custom_materials.push(mat_data);
             }
         }
 
@@ -1171,6 +1618,7 @@ impl State {
         }
         for ps_data in &data.particle_systems {
             required_models.insert(ps_data.model.clone());
+            required_models.extend(ps_data.lods.iter().map(|lod| lod.model.clone()));
         }
 
         for model_path in required_models {
@@ -1208,10 +1656,11 @@ impl State {
             self.light_manager = LightManager::new(first_light.material_source.clone());
             self.light_manager.set_model_path(first_light.model.clone());
             self.light_manager.set_mesh_index(first_light.mesh_index);
+            self.light_manager
+                .set_entire_model(first_light.entire_model);
 
             for light_data in data.lights {
-                self.light_manager
-                    .add_light(light_data.position, light_data.color);
+                self.light_manager.add_light(light_data.to_light());
             }
         } else {
             // No lights in saved world - create empty manager with default material
@@ -1219,26 +1668,41 @@ impl State {
                 LightManager::new(model::MaterialSource::System("default".to_string()));
         }
 
-        // Sync lights to GPU
-        let lights = self.light_manager.sync_to_gpu();
-        self.queue
-            .write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[lights]));
-        self.light_manager.clear_dirty();
-
         // Load particle systems
         self.particle_system_manager = ParticleSystemManager::new();
         for ps_data in data.particle_systems {
-            let system = ParticleSystem::new(
+            let mut system = ParticleSystem::new(
                 &self.device,
                 ps_data.name.clone(),
                 ps_data.model,
                 ps_data.mesh_index,
                 ps_data.material_source,
                 ps_data.generator,
+                ps_data.simulation,
             );
+            // Applied on the next rebuild, where scripts can be called
+            if !ps_data.attributes.is_empty() {
+                system.set_attributes(ps_data.attributes);
+            }
+            system.set_lods(ps_data.lods);
+            system.set_entire_model(ps_data.entire_model);
+            system.set_material_overrides(ps_data.material_overrides);
+            if ps_data.animation.is_some() {
+                system.set_animation(ps_data.animation);
+            }
+            if ps_data.morph_weights.is_some() {
+                system.set_morph_weights(ps_data.morph_weights);
+            }
             self.particle_system_manager.add(ps_data.name, system);
         }
 
+        self.render_settings.tone_map = ToneMapSettings {
+            tonemapper: data.tonemapper,
+            exposure: data.exposure,
+        };
+        self.render_settings.post_process = data.post_process;
+        self.set_environment(data.environment);
+
         // Load background color
         self.clear_color = wgpu::Color {
             r: data.background_color[0] as f64,
@@ -1311,8 +1775,11 @@ impl State {
     pub fn create_material(
         &mut self,
         name: String,
-        texture_path: String,
-        color: [f32; 4],
+        shader: String,
+        textures: std::collections::HashMap<String, String>,
+        uniforms: UniformValues,
+        alpha_mode: AlphaMode,
+        samplers: std::collections::HashMap<String, SamplerSettings>,
     ) -> Result<model::MaterialSource, String> {
         // Generate unique material source
         let material_source = model::MaterialSource::Custom(name.clone());
@@ -1322,70 +1789,62 @@ impl State {
             return Err(format!("Material '{}' already exists", name));
         }
 
-        // Get or load texture from registry
-        let diffuse_texture = {
-            let mut registry = self.textures.lock().unwrap();
-            if let Some(existing) = registry.get(&texture_path) {
-                Arc::clone(existing)
-            } else {
-                return Err(format!(
-                    "Texture '{}' not found in registry. Load it first.",
-                    texture_path
-                ));
-            }
-        };
-
         let desc = model::MaterialDesc {
-            name: name.clone(),
-            texture_path: texture_path.clone(),
-            properties: std::cell::RefCell::new(model::MaterialProperties { color }),
-        };
-
-        let properties_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{}_properties", name)),
-                contents: bytemuck::cast_slice(&[*desc.properties.borrow()]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
-
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some(&format!("{}_bind_group", name)),
-            layout: &self.texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: properties_buffer.as_entire_binding(),
-                },
-            ],
-        });
-
-        let gpu_material = model::GpuMaterial {
-            desc,
-            diffuse_texture,
-            properties_buffer,
-            bind_group,
+            name,
+            shader,
+            textures,
+            uniforms: std::cell::RefCell::new(uniforms),
+            alpha_mode,
+            samplers,
         };
+        let gpu_material = self.build_material(desc)?;
 
         self.materials
-            .insert(material_source.clone(), Arc::new(gpu_material));
-        log::info!(
-            "Created material '{}' with texture '{}'",
-            material_source.display_key(),
-            texture_path
-        );
+            .insert(material_source.clone(), Rc::new(gpu_material));
+        log::info!("Created material '{}'", material_source.display_key());
 
         Ok(material_source)
     }
 
+    /// Realize a material description against its shader's schema.
+    /// The shader and every referenced texture must already be loaded.
+    fn build_material(&self, desc: model::MaterialDesc) -> Result<model::GpuMaterial, String> {
+        let layout = self
+            .pipeline_cache
+            .material_layout(&desc.shader)
+            .ok_or_else(|| format!("Shader '{}' is not loaded", desc.shader))?;
+
+        // Each slot gets the texture in its own color space, whichever one it was loaded in
+        let mut textures = std::collections::HashMap::new();
+        for (slot, texture_path) in &desc.textures {
+            if !layout.schema.has_texture_slot(slot) {
+                continue;
+            }
+            let texture = model::registered_texture(
+                texture_path,
+                model::slot_color_space(slot),
+                &self.device,
+                &self.queue,
+                &self.textures,
+            )
+            .ok_or_else(|| {
+                format!(
+                    "Texture '{}' not found in registry. Load it first.",
+                    texture_path
+                )
+            })?;
+            textures.insert(slot.clone(), texture);
+        }
+
+        Ok(model::GpuMaterial::new(
+            &self.device,
+            desc,
+            layout,
+            textures,
+            &self.fallback_textures,
+        ))
+    }
+
     /// Check if a material can be edited (custom or modified model materials)
     pub fn is_material_editable(&self, material_source: &model::MaterialSource) -> bool {
         match material_source {
@@ -1400,90 +1859,339 @@ impl State {
         matches!(material_source, model::MaterialSource::Custom(_))
     }
 
-    /// Change a material's texture at runtime
+    fn recreate_custom_material(&mut self, mat_data: &CustomMaterialData) {
+        match self.create_material(
+            mat_data.name.clone(),
+            mat_data.shader.clone(),
+            mat_data.texture_slots(),
+            mat_data.uniform_values(),
+            mat_data.alpha_mode,
+            mat_data.samplers.clone().into_iter().collect(),
+        ) {
+            Ok(source) => {
+                log::info!("Recreated custom material: {}", source.display_key());
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed to recreate custom material '{}': {}",
+                    mat_data.name,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Change the texture bound to one of a material's slots at runtime
     pub fn change_material_texture(
         &mut self,
         material_source: &model::MaterialSource,
+        slot: &str,
         new_texture_path: &str,
     ) -> Result<(), String> {
-        // Get the material
         let material = self
             .materials
             .get(material_source)
             .ok_or_else(|| format!("Material '{}' not found", material_source.display_key()))?;
 
         // Check if texture is already the same
-        if material.desc.texture_path == new_texture_path {
+        if material.desc.textures.get(slot).map(String::as_str) == Some(new_texture_path) {
             return Ok(());
         }
 
-        // Get or load new texture from registry
-        let new_texture = {
-            let registry = self.textures.lock().unwrap();
-            registry.get(new_texture_path).cloned().ok_or_else(|| {
-                format!(
-                    "Texture '{}' not found in registry. Load it first.",
-                    new_texture_path
-                )
-            })?
-        };
+        let mut new_desc = material.desc.clone();
+        new_desc
+            .textures
+            .insert(slot.to_string(), new_texture_path.to_string());
+        let new_gpu_material = self.build_material(new_desc)?;
+
+        // Replace in registry
+        self.materials
+            .insert(material_source.clone(), Rc::new(new_gpu_material));
+        log::info!(
+            "Changed material '{}' slot '{}' texture to '{}'",
+            material_source.display_key(),
+            slot,
+            new_texture_path
+        );
 
-        // Clone the current properties
-        let current_properties = *material.desc.properties.borrow();
+        Ok(())
+    }
+
+    /// Switch a material between opaque, alpha-tested and blended rendering
+    pub fn change_material_alpha_mode(
+        &mut self,
+        material_source: &model::MaterialSource,
+        alpha_mode: AlphaMode,
+    ) -> Result<(), String> {
+        let material = self
+            .materials
+            .get(material_source)
+            .ok_or_else(|| format!("Material '{}' not found", material_source.display_key()))?;
+        if material.desc.alpha_mode == alpha_mode {
+            return Ok(());
+        }
+
+        let mut new_desc = material.desc.clone();
+        new_desc.alpha_mode = alpha_mode;
+        let new_gpu_material = self.build_material(new_desc)?;
+        self.materials
+            .insert(material_source.clone(), Rc::new(new_gpu_material));
+        log::info!(
+            "Changed material '{}' alpha mode to {:?}",
+            material_source.display_key(),
+            alpha_mode
+        );
+
+        Ok(())
+    }
 
-        // Create new material desc
-        let new_desc = model::MaterialDesc {
-            name: material.desc.name.clone(),
-            texture_path: new_texture_path.to_string(),
-            properties: std::cell::RefCell::new(current_properties),
+    /// Override how one of a material's textures is sampled, or with `None`
+    /// go back to the texture's own sampler
+    pub fn change_material_sampler(
+        &mut self,
+        material_source: &model::MaterialSource,
+        slot: &str,
+        sampler: Option<SamplerSettings>,
+    ) -> Result<(), String> {
+        let material = self
+            .materials
+            .get(material_source)
+            .ok_or_else(|| format!("Material '{}' not found", material_source.display_key()))?;
+        if material.desc.samplers.get(slot) == sampler.as_ref() {
+            return Ok(());
+        }
+
+        let mut new_desc = material.desc.clone();
+        match sampler {
+            Some(settings) => new_desc.samplers.insert(slot.to_string(), settings),
+            None => new_desc.samplers.remove(slot),
         };
+        let new_gpu_material = self.build_material(new_desc)?;
+        self.materials
+            .insert(material_source.clone(), Rc::new(new_gpu_material));
+        log::info!(
+            "Changed material '{}' slot '{}' sampler to {:?}",
+            material_source.display_key(),
+            slot,
+            sampler
+        );
 
-        // Create new properties buffer (reuse same data)
-        let properties_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{}_properties", material.desc.name)),
-                contents: bytemuck::cast_slice(&[current_properties]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
+        Ok(())
+    }
 
-        // Create new bind group with new texture
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some(&format!("{}_bind_group", material.desc.name)),
-            layout: &self.texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&new_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&new_texture.sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: properties_buffer.as_entire_binding(),
-                },
-            ],
-        });
+    /// Set a uniform field on a material and upload it
+    pub fn set_material_uniform(
+        &mut self,
+        material_source: &model::MaterialSource,
+        field: &str,
+        value: [f32; 4],
+    ) -> Result<(), String> {
+        let material = self
+            .materials
+            .get(material_source)
+            .ok_or_else(|| format!("Material '{}' not found", material_source.display_key()))?;
 
-        // Create new GPU material
-        let new_gpu_material = model::GpuMaterial {
-            desc: new_desc,
-            diffuse_texture: new_texture,
-            properties_buffer,
-            bind_group,
+        material
+            .desc
+            .uniforms
+            .borrow_mut()
+            .insert(field.to_string(), value);
+        material.write_uniforms(&self.queue);
+        Ok(())
+    }
+
+    /// Draw particle systems in order, switching pipelines only when the key changes.
+    /// Systems whose pipeline isn't built yet (shader still loading) are skipped.
+    fn draw_particle_systems<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        draws: &[(
+            &'a ParticleSystem,
+            usize,
+            SystemPart<'a>,
+            &'a model::GpuMaterial,
+        )],
+    ) {
+        use model::DrawModel;
+
+        let mut current_key: Option<PipelineKey> = None;
+        for (system, index, part, material) in draws {
+            if system.num_visible() == 0 {
+                continue;
+            }
+            let key = PipelineKey::new(
+                &material.desc.shader,
+                VertexLayout::ModelInstanced,
+                material.desc.alpha_mode,
+            );
+            if current_key.as_ref() != Some(&key) {
+                let Some(pipeline) = self.pipeline_cache.get(&key) else {
+                    continue;
+                };
+                render_pass.set_pipeline(pipeline);
+                current_key = Some(key);
+            }
+            for draw in system.lod_draws(*index) {
+                let level = draw.level.min(part.meshes.len() - 1);
+                let mesh = part.meshes[level];
+                let vertices = part.mesh_indices[level]
+                    .and_then(|index| system.deformed_vertices(index, draw.offset))
+                    .unwrap_or(mesh.vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(1, draw.instances);
+                match draw.count {
+                    InstanceCount::Direct(count) => render_pass.draw_mesh_instanced(
+                        mesh,
+                        vertices,
+                        material,
+                        0..count,
+                        &self.per_frame_bind_group,
+                    ),
+                    InstanceCount::Indirect(buffer, offset) => render_pass.draw_mesh_indirect(
+                        mesh,
+                        vertices,
+                        material,
+                        buffer,
+                        offset,
+                        &self.per_frame_bind_group,
+                    ),
+                }
+            }
+        }
+    }
+
+    fn rebuild_per_frame_bind_group(&mut self) {
+        self.per_frame_bind_group = Self::create_per_frame_bind_group(
+            &self.device,
+            &self.per_frame_bind_group_layout,
+            &self.camera_buffer,
+            &self.light_clusters,
+            &self.shadow_maps,
+            &self.environment,
+        );
+    }
+
+    /// Switch to a new environment, or back to the solid background with None.
+    /// Images load in the background; the old environment stays until they're ready.
+    pub fn set_environment(&mut self, data: Option<EnvironmentData>) {
+        let same_source = match (&self.environment_data, &data) {
+            (Some(current), Some(new)) => current.source == new.source,
+            (None, None) => true,
+            _ => false,
+        };
+        self.environment_data = data.clone();
+        // Intensity is applied per frame, so only a new source needs rebuilding
+        if same_source {
+            return;
+        }
+        let Some(data) = data else {
+            self.environment.clear(&self.device);
+            self.rebuild_per_frame_bind_group();
+            return;
         };
 
-        // Replace in registry
+        log::info!("Starting load for environment: {:?}", data.source);
+        let sender = self.loaded_environment_sender.clone();
+        let load = async move {
+            let result = EnvironmentImage::load(&data.source)
+                .await
+                .map(|image| (data.clone(), image))
+                .map_err(|e| format!("Failed to load environment {:?}: {:#}", data.source, e));
+            let _ = sender.send(result);
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(move || pollster::block_on(load));
+
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(load);
+    }
+
+    /// Queue a shader for loading unless it's already compiled
+    pub fn request_shader(&mut self, path: &str) {
+        if !self.pipeline_cache.has_shader(path) {
+            self.pending_shader_loads.insert(path.to_string());
+        }
+    }
+
+    /// Switch the shader a material renders with.
+    /// Textures and uniform values carry over for slots and fields the new shader shares.
+    pub fn change_material_shader(
+        &mut self,
+        material_source: &model::MaterialSource,
+        new_shader: &str,
+    ) -> Result<(), String> {
+        let material = self
+            .materials
+            .get(material_source)
+            .ok_or_else(|| format!("Material '{}' not found", material_source.display_key()))?;
+
+        if material.desc.shader == new_shader {
+            return Ok(());
+        }
+
+        let mut new_desc = material.desc.clone();
+        new_desc.shader = new_shader.to_string();
+        let new_gpu_material = self.build_material(new_desc)?;
+
         self.materials
-            .insert(material_source.clone(), Arc::new(new_gpu_material));
+            .insert(material_source.clone(), Rc::new(new_gpu_material));
         log::info!(
-            "Changed material '{}' texture to '{}'",
+            "Changed material '{}' shader to '{}'",
             material_source.display_key(),
-            new_texture_path
+            new_shader
         );
 
         Ok(())
     }
 }
+
+/// One mesh a particle system draws and the material it draws with
+struct SystemPart<'a> {
+    material: model::MaterialSource,
+    /// The mesh at each LOD level, its own first
+    meshes: Vec<&'a model::Mesh>,
+    /// Index of each level's mesh in the system's own model, when it comes from there,
+    /// for finding its posed copy
+    mesh_indices: Vec<Option<usize>>,
+}
+
+/// Each mesh `system` draws, in the order its LOD buckets expect. In entire-model mode
+/// LOD models stand in mesh for mesh. Levels whose model is still loading fall back to
+/// the next finer level's mesh. Empty until the system's own model is in.
+fn system_parts<'a>(
+    models: &'a std::collections::HashMap<String, Arc<model::Model>>,
+    system: &ParticleSystem,
+) -> Vec<SystemPart<'a>> {
+    let Some(model) = models.get(system.model_path()) else {
+        return Vec::new();
+    };
+    system
+        .mesh_materials(model)
+        .into_iter()
+        .map(|(index, material)| {
+            let mut meshes = vec![&model.meshes[index]];
+            for lod in system.lods() {
+                let lod_index = if system.entire_model() {
+                    index
+                } else {
+                    lod.mesh_index
+                };
+                let finer = meshes[meshes.len() - 1];
+                let mesh = models
+                    .get(&lod.model)
+                    .and_then(|model| model.meshes.get(lod_index))
+                    .unwrap_or(finer);
+                meshes.push(mesh);
+            }
+            let mesh_indices = meshes
+                .iter()
+                .map(|&mesh| model.meshes.iter().position(|own| std::ptr::eq(own, mesh)))
+                .collect();
+            SystemPart {
+                material,
+                meshes,
+                mesh_indices,
+            }
+        })
+        .collect()
+}
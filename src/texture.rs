@@ -1,5 +1,141 @@
+use crate::compressed_texture::CompressedImage;
 use anyhow::*;
-use image::GenericImageView;
+use serde::{Deserialize, Serialize};
+
+/// How a texture's texels are encoded. Colors are sRGB; data such as normals,
+/// metallic or roughness is linear and must not be gamma-decoded when sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddressMode {
+    #[default]
+    ClampToEdge,
+    Repeat,
+    MirrorRepeat,
+}
+
+impl AddressMode {
+    pub const ALL: [AddressMode; 3] = [
+        AddressMode::ClampToEdge,
+        AddressMode::Repeat,
+        AddressMode::MirrorRepeat,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AddressMode::ClampToEdge => "Clamp",
+            AddressMode::Repeat => "Repeat",
+            AddressMode::MirrorRepeat => "Mirror",
+        }
+    }
+
+    fn to_wgpu(self) -> wgpu::AddressMode {
+        match self {
+            AddressMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+            AddressMode::Repeat => wgpu::AddressMode::Repeat,
+            AddressMode::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterMode {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+impl FilterMode {
+    pub const ALL: [FilterMode; 2] = [FilterMode::Nearest, FilterMode::Linear];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FilterMode::Nearest => "Nearest",
+            FilterMode::Linear => "Linear",
+        }
+    }
+
+    fn to_wgpu(self) -> wgpu::FilterMode {
+        match self {
+            FilterMode::Nearest => wgpu::FilterMode::Nearest,
+            FilterMode::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+/// How a texture is sampled. Textures get the default (trilinear, clamped) when loaded;
+/// materials can override it per texture slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplerSettings {
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    pub mag_filter: FilterMode,
+    pub min_filter: FilterMode,
+    pub mipmap_filter: FilterMode,
+    /// Maximum anisotropy, 1 to 16. Only applies when every filter is linear.
+    pub anisotropy: u16,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self {
+            address_mode_u: AddressMode::default(),
+            address_mode_v: AddressMode::default(),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            anisotropy: 1,
+        }
+    }
+}
+
+impl SamplerSettings {
+    /// The same addressing with every filter nearest, for textures that can't be filtered
+    pub fn unfiltered(self) -> Self {
+        Self {
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            ..self
+        }
+    }
+
+    pub fn create_sampler(&self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Sampler {
+        // wgpu rejects anisotropy unless all filtering is linear
+        let all_linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|filter| *filter == FilterMode::Linear);
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode_u.to_wgpu(),
+            address_mode_v: self.address_mode_v.to_wgpu(),
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: self.mag_filter.to_wgpu(),
+            min_filter: self.min_filter.to_wgpu(),
+            mipmap_filter: self.mipmap_filter.to_wgpu(),
+            anisotropy_clamp: if all_linear {
+                self.anisotropy.clamp(1, 16)
+            } else {
+                1
+            },
+            ..Default::default()
+        })
+    }
+}
 
 pub struct GpuTexture {
     #[allow(unused)]
@@ -9,6 +145,10 @@ pub struct GpuTexture {
     pub label: String,
     pub width: u32,
     pub height: u32,
+    pub color_space: ColorSpace,
+    pub format: wgpu::TextureFormat,
+    /// Size of every mip level as stored on the GPU
+    pub memory_bytes: u64,
 }
 
 impl GpuTexture {
@@ -19,77 +159,234 @@ impl GpuTexture {
         queue: &wgpu::Queue,
         bytes: &[u8],
         label: &str,
+        color_space: ColorSpace,
     ) -> Result<Self> {
+        if CompressedImage::is_container(bytes) {
+            let image = CompressedImage::parse(bytes)?;
+            return Self::from_compressed(device, queue, &image, label, color_space);
+        }
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_image(device, queue, &img, Some(label), color_space)
     }
 
+    /// Upload an image with a full mip chain, generated on the CPU
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+        color_space: ColorSpace,
     ) -> Result<Self> {
         let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
+        let mips = generate_mips(&rgba, color_space);
+        let levels: Vec<&[u8]> = std::iter::once(&rgba)
+            .chain(&mips)
+            .map(|level| level.as_raw().as_slice())
+            .collect();
+        Ok(Self::from_levels(
+            device,
+            queue,
+            label,
+            color_space,
+            color_space.format(),
+            rgba.dimensions(),
+            &levels,
+        ))
+    }
+
+    /// Upload a KTX2/DDS texture with the mips it ships with. Block-compressed data goes
+    /// to the GPU as-is when the device supports its format, and is decoded to RGBA8 otherwise.
+    pub fn from_compressed(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &CompressedImage,
+        label: &str,
+        color_space: ColorSpace,
+    ) -> Result<Self> {
+        // The blocks are the same either way; the slot decides whether they're sRGB decoded
+        let format = match color_space {
+            ColorSpace::Srgb => image.format.add_srgb_suffix(),
+            ColorSpace::Linear => image.format.remove_srgb_suffix(),
+        };
+        // Level 0 must be whole blocks; smaller mips are padded by the container
+        let (block_width, block_height) = format.block_dimensions();
+        let block_aligned =
+            image.width.is_multiple_of(block_width) && image.height.is_multiple_of(block_height);
+        if block_aligned && device.features().contains(format.required_features()) {
+            let levels: Vec<&[u8]> = image.levels.iter().map(Vec::as_slice).collect();
+            return Ok(Self::from_levels(
+                device,
+                queue,
+                Some(label),
+                color_space,
+                format,
+                (image.width, image.height),
+                &levels,
+            ));
+        }
+
+        log::info!("Decoding {label} from {:?} in software", image.format);
+        let mut decoded = match image.decode_rgba8() {
+            // A magenta stand-in shows what's missing without failing the whole model
+            Err(e) => {
+                log::error!("Texture {label} can't be decoded: {e:#}");
+                let missing = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 255, 255]));
+                let missing = image::DynamicImage::ImageRgba8(missing);
+                return Self::from_image(device, queue, &missing, Some(label), color_space);
+            }
+            decoded => decoded?,
+        };
+        if decoded.len() == 1 {
+            let level = image::DynamicImage::ImageRgba8(decoded.remove(0));
+            return Self::from_image(device, queue, &level, Some(label), color_space);
+        }
+        let levels: Vec<&[u8]> = decoded
+            .iter()
+            .map(|level| level.as_raw().as_slice())
+            .collect();
+        Ok(Self::from_levels(
+            device,
+            queue,
+            Some(label),
+            color_space,
+            color_space.format(),
+            (image.width, image.height),
+            &levels,
+        ))
+    }
 
+    /// Create a texture from tightly packed mip levels, level 0 first
+    fn from_levels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: Option<&str>,
+        color_space: ColorSpace,
+        format: wgpu::TextureFormat,
+        (width, height): (u32, u32),
+        levels: &[&[u8]],
+    ) -> Self {
         let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
-
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count: levels.len() as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
+        let (block_width, block_height) = format.block_dimensions();
+        let block_size = format
+            .block_copy_size(None)
+            .expect("color formats have a block size");
+        for (mip_level, data) in levels.iter().enumerate() {
+            let mip_size = size
+                .mip_level_size(mip_level as u32, wgpu::TextureDimension::D2)
+                .physical_size(format);
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: mip_level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                data,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(mip_size.width / block_width * block_size),
+                    rows_per_image: Some(mip_size.height / block_height),
+                },
+                mip_size,
+            );
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+        let sampler = SamplerSettings::default().create_sampler(device, label);
 
-        Ok(Self {
+        Self {
             texture,
             view,
             sampler,
             label: label.unwrap_or("unknown").to_string(),
-            width: dimensions.0,
-            height: dimensions.1,
-        })
+            width,
+            height,
+            color_space,
+            format,
+            memory_bytes: levels.iter().map(|level| level.len() as u64).sum(),
+        }
+    }
+
+    /// The same texels in the sRGB or non-sRGB variant of this texture's format, copied on
+    /// the GPU. Mips keep the filtering of the color space they were generated in.
+    pub fn with_color_space(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color_space: ColorSpace,
+    ) -> Self {
+        let format = match color_space {
+            ColorSpace::Srgb => self.format.add_srgb_suffix(),
+            ColorSpace::Linear => self.format.remove_srgb_suffix(),
+        };
+        let size = self.texture.size();
+        let mip_level_count = self.texture.mip_level_count();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&self.label),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: self.texture.usage(),
+            view_formats: &[],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some(&format!("{}_color_space_copy", self.label)),
+        });
+        for mip_level in 0..mip_level_count {
+            let copy = |texture| wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            };
+            encoder.copy_texture_to_texture(
+                copy(&self.texture),
+                copy(&texture),
+                size.mip_level_size(mip_level, wgpu::TextureDimension::D2)
+                    .physical_size(format),
+            );
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = SamplerSettings::default().create_sampler(device, Some(&self.label));
+        Self {
+            texture,
+            view,
+            sampler,
+            label: self.label.clone(),
+            width: self.width,
+            height: self.height,
+            color_space,
+            format,
+            memory_bytes: self.memory_bytes,
+        }
     }
 
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
         label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
@@ -102,10 +399,15 @@ impl GpuTexture {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            // Multisampled depth can't be sampled through a regular texture binding
+            usage: if sample_count > 1 {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            },
             view_formats: &[],
         });
 
@@ -131,6 +433,161 @@ impl GpuTexture {
             label: label.to_string(),
             width: size.width,
             height: size.height,
+            color_space: ColorSpace::Linear,
+            format: Self::DEPTH_FORMAT,
+            memory_bytes: (size.width * size.height * 4 * sample_count) as u64,
         }
     }
+
+    /// A zeroed 1x1 texture viewed as `view_dimension` with texels of `sample_type`, for
+    /// binding where no image texture fits. Depth textures get a comparison sampler.
+    pub fn blank(
+        device: &wgpu::Device,
+        view_dimension: wgpu::TextureViewDimension,
+        sample_type: wgpu::TextureSampleType,
+        label: &str,
+    ) -> Self {
+        let (dimension, layers) = match view_dimension {
+            wgpu::TextureViewDimension::D1 => (wgpu::TextureDimension::D1, 1),
+            wgpu::TextureViewDimension::D3 => (wgpu::TextureDimension::D3, 1),
+            wgpu::TextureViewDimension::Cube | wgpu::TextureViewDimension::CubeArray => {
+                (wgpu::TextureDimension::D2, 6)
+            }
+            wgpu::TextureViewDimension::D2 | wgpu::TextureViewDimension::D2Array => {
+                (wgpu::TextureDimension::D2, 1)
+            }
+        };
+        let format = match sample_type {
+            wgpu::TextureSampleType::Float { .. } => wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureSampleType::Sint => wgpu::TextureFormat::Rgba8Sint,
+            wgpu::TextureSampleType::Uint => wgpu::TextureFormat::Rgba8Uint,
+            wgpu::TextureSampleType::Depth => Self::DEPTH_FORMAT,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(view_dimension),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            compare: (sample_type == wgpu::TextureSampleType::Depth)
+                .then_some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+            label: label.to_string(),
+            width: 1,
+            height: 1,
+            color_space: ColorSpace::Linear,
+            format,
+            memory_bytes: 4 * layers as u64,
+        }
+    }
+}
+
+/// Mip levels 1 and up, each a 2x2 box filter of the one above, down to 1x1.
+/// sRGB images are filtered in linear space so mips don't darken.
+fn generate_mips(image: &image::RgbaImage, color_space: ColorSpace) -> Vec<image::RgbaImage> {
+    let decode: Vec<f32> = (0..=255u8)
+        .map(|value| {
+            let value = value as f32 / 255.0;
+            match color_space {
+                ColorSpace::Srgb => srgb_to_linear(value),
+                ColorSpace::Linear => value,
+            }
+        })
+        .collect();
+    let encode = |value: f32| {
+        let value = match color_space {
+            ColorSpace::Srgb => linear_to_srgb(value),
+            ColorSpace::Linear => value,
+        };
+        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+
+    // Filtered at full precision so error doesn't accumulate down the chain; alpha is linear
+    let (mut width, mut height) = image.dimensions();
+    let mut level: Vec<[f32; 4]> = image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            [
+                decode[r as usize],
+                decode[g as usize],
+                decode[b as usize],
+                a as f32 / 255.0,
+            ]
+        })
+        .collect();
+
+    let mut mips = Vec::new();
+    while width > 1 || height > 1 {
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        let mut next = Vec::with_capacity((next_width * next_height) as usize);
+        for y in 0..next_height {
+            // Odd or single texel dimensions clamp to the last row or column
+            let rows = [(2 * y).min(height - 1), (2 * y + 1).min(height - 1)];
+            for x in 0..next_width {
+                let columns = [(2 * x).min(width - 1), (2 * x + 1).min(width - 1)];
+                let mut sum = [0.0; 4];
+                for row in rows {
+                    for column in columns {
+                        let texel = level[(row * width + column) as usize];
+                        for (total, channel) in sum.iter_mut().zip(texel) {
+                            *total += channel * 0.25;
+                        }
+                    }
+                }
+                next.push(sum);
+            }
+        }
+
+        let mut mip = image::RgbaImage::new(next_width, next_height);
+        for (pixel, [r, g, b, a]) in mip.pixels_mut().zip(&next) {
+            *pixel = image::Rgba([
+                encode(*r),
+                encode(*g),
+                encode(*b),
+                (a.clamp(0.0, 1.0) * 255.0).round() as u8,
+            ]);
+        }
+        mips.push(mip);
+        level = next;
+        (width, height) = (next_width, next_height);
+    }
+    mips
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
 }
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Format of the scene color target when the adapter can render to it
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Curve that maps HDR scene color into displayable range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tonemapper {
+    /// Clamp to [0, 1]
+    None,
+    Reinhard,
+    #[default]
+    Aces,
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 3] = [Tonemapper::None, Tonemapper::Reinhard, Tonemapper::Aces];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Tonemapper::None => "None",
+            Tonemapper::Reinhard => "Reinhard",
+            Tonemapper::Aces => "ACES",
+        }
+    }
+
+    /// Matches the TONEMAP_* constants in tonemap.wgsl
+    fn gpu_id(&self) -> u32 {
+        match self {
+            Tonemapper::None => 0,
+            Tonemapper::Reinhard => 1,
+            Tonemapper::Aces => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapSettings {
+    pub tonemapper: Tonemapper,
+    /// Linear multiplier applied before the curve
+    pub exposure: f32,
+}
+
+impl Default for ToneMapSettings {
+    fn default() -> Self {
+        Self {
+            tonemapper: Tonemapper::default(),
+            exposure: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ToneMapParams {
+    exposure: f32,
+    tonemapper: u32,
+    /// Non-zero if the output isn't an sRGB format and the shader must encode itself
+    encode_srgb: u32,
+    _padding: u32,
+}
+
+/// The scene's HDR color target and the fullscreen pass that tonemaps it into the swapchain.
+/// With MSAA the scene renders into a multisampled target that resolves into the HDR one.
+pub struct ToneMapping {
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    hdr_view: wgpu::TextureView,
+    msaa_view: Option<wgpu::TextureView>,
+    params_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    /// By output format: the swapchain's, and the post-process chain's
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl ToneMapping {
+    /// `HDR_FORMAT`, or the plain 8-bit format on adapters that can't render to half floats
+    pub fn format_for(adapter: &wgpu::Adapter) -> wgpu::TextureFormat {
+        let features = adapter.get_texture_format_features(HDR_FORMAT);
+        if features
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+        {
+            HDR_FORMAT
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        }
+    }
+
+    /// `shader_source` is the WGSL for the fullscreen pass (`tonemap.wgsl`)
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        shader_source: &str,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tonemap_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tonemap Params Buffer"),
+            size: std::mem::size_of::<ToneMapParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tonemap Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Tonemap Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+        let pipelines = [config.format, crate::post_process::POST_FORMAT]
+            .into_iter()
+            .map(|output_format| {
+                let pipeline = crate::pipeline::create_render_pipeline(
+                    device,
+                    &crate::pipeline::RenderPipelineDesc {
+                        label: "Tonemap Pipeline",
+                        layout: &pipeline_layout,
+                        shader: &shader,
+                        vertex_layouts: &[],
+                        color_format: output_format,
+                        depth_format: None,
+                        sample_count: 1,
+                    },
+                );
+                (output_format, pipeline)
+            })
+            .collect();
+
+        let (hdr_view, bind_group) =
+            Self::create_target(device, format, config, &bind_group_layout, &params_buffer);
+
+        Self {
+            format,
+            sample_count,
+            hdr_view,
+            msaa_view: Self::create_msaa_target(device, format, config, sample_count),
+            params_buffer,
+            bind_group_layout,
+            bind_group,
+            pipelines,
+        }
+    }
+
+    fn create_target(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        config: &wgpu::SurfaceConfiguration,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        params_buffer: &wgpu::Buffer,
+    ) -> (wgpu::TextureView, wgpu::BindGroup) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("HDR Color Target"),
+            size: wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tonemap_bind_group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        (view, bind_group)
+    }
+
+    fn create_msaa_target(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Option<wgpu::TextureView> {
+        if sample_count <= 1 {
+            return None;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Multisampled Color Target"),
+            size: wgpu::Extent3d {
+                width: config.width.max(1),
+                height: config.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        (self.hdr_view, self.bind_group) = Self::create_target(
+            device,
+            self.format,
+            config,
+            &self.bind_group_layout,
+            &self.params_buffer,
+        );
+        self.msaa_view = Self::create_msaa_target(device, self.format, config, self.sample_count);
+    }
+
+    pub fn set_sample_count(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) {
+        self.sample_count = sample_count;
+        self.msaa_view = Self::create_msaa_target(device, self.format, config, sample_count);
+    }
+
+    /// Color format the scene is rendered in
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// Where the scene pass renders, and the view it resolves into when multisampled
+    pub fn scene_target(&self) -> (&wgpu::TextureView, Option<&wgpu::TextureView>) {
+        match &self.msaa_view {
+            Some(msaa_view) => (msaa_view, Some(&self.hdr_view)),
+            None => (&self.hdr_view, None),
+        }
+    }
+
+    /// Tonemap the scene target into `output`, which is either the swapchain or the
+    /// post-process input
+    pub fn render(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        output_format: wgpu::TextureFormat,
+        settings: &ToneMapSettings,
+    ) {
+        let params = ToneMapParams {
+            exposure: settings.exposure,
+            tonemapper: settings.tonemapper.gpu_id(),
+            encode_srgb: (!output_format.is_srgb()) as u32,
+            _padding: 0,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tonemap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipelines[&output_format]);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        // One triangle covering the screen, generated from the vertex index
+        pass.draw(0..3, 0..1);
+    }
+}
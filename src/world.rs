@@ -1,18 +1,76 @@
-use crate::particle_system::GeneratorType;
+use crate::animation::{AnimationPlayer, MorphWeights};
+use crate::environment::EnvironmentSource;
+use crate::light::{Light, LightKind};
+use crate::lod::LodLevel;
+use crate::material_schema::UniformValues;
+use crate::model::AlphaMode;
+use crate::particle_simulation::SimulationParams;
+use crate::particle_system::{GeneratorType, InstanceAttributes};
+use crate::post_process::PostProcessPass;
+use crate::texture::SamplerSettings;
+use crate::tonemapping::Tonemapper;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
 /// Serializable custom material data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CustomMaterialData {
     pub name: String,
-    pub texture_path: String,
-    pub color: [f32; 4],
+    #[serde(default = "default_shader")]
+    pub shader: String,
+    /// Texture slot -> texture path
+    #[serde(default)]
+    pub textures: BTreeMap<String, String>,
+    /// Uniform field -> value
+    #[serde(default)]
+    pub uniforms: BTreeMap<String, [f32; 4]>,
+    #[serde(default)]
+    pub alpha_mode: AlphaMode,
+    /// Texture slot -> sampler overriding the texture's own
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub samplers: BTreeMap<String, SamplerSettings>,
+    /// Legacy single-texture format, read as the "diffuse" slot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture_path: Option<String>,
+    /// Legacy tint, read as the "base_color" field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<[f32; 4]>,
+}
+
+impl CustomMaterialData {
+    pub fn texture_slots(&self) -> HashMap<String, String> {
+        let mut textures: HashMap<String, String> = self.textures.clone().into_iter().collect();
+        if let Some(path) = &self.texture_path {
+            textures
+                .entry("diffuse".to_string())
+                .or_insert_with(|| path.clone());
+        }
+        textures
+    }
+
+    pub fn uniform_values(&self) -> UniformValues {
+        let mut uniforms: UniformValues = self.uniforms.clone().into_iter().collect();
+        if let Some(color) = self.color {
+            uniforms.entry("base_color".to_string()).or_insert(color);
+        }
+        uniforms
+    }
 }
 
 /// Serializable representation of the entire game world state
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorldData {
     pub background_color: [f32; 4],
+    #[serde(default = "default_exposure")]
+    pub exposure: f32,
+    #[serde(default)]
+    pub tonemapper: Tonemapper,
+    /// Post-process chain, applied in order
+    #[serde(default)]
+    pub post_process: Vec<PostProcessPass>,
+    /// Skybox and image based lighting; without one the background is `background_color`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<EnvironmentData>,
     pub camera: CameraData,
     pub lights: Vec<LightParams>,
     pub particle_systems: Vec<ParticleSystemData>,
@@ -24,6 +82,10 @@ impl Default for WorldData {
     fn default() -> Self {
         Self {
             background_color: [0.1, 0.2, 0.3, 1.0],
+            exposure: default_exposure(),
+            tonemapper: Tonemapper::default(),
+            post_process: vec![],
+            environment: None,
             camera: CameraData::default(),
             lights: vec![],
             particle_systems: vec![],
@@ -32,6 +94,14 @@ impl Default for WorldData {
     }
 }
 
+/// Environment map source and how strongly it lights the scene
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentData {
+    pub source: EnvironmentSource,
+    #[serde(default = "default_environment_intensity")]
+    pub intensity: f32,
+}
+
 /// Camera position and view parameters
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CameraData {
@@ -59,15 +129,55 @@ impl Default for CameraData {
 /// Light source parameters
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LightParams {
+    #[serde(default)]
+    pub kind: LightKind,
     pub position: [f32; 3],
+    #[serde(default = "default_light_direction")]
+    pub direction: [f32; 3],
     pub color: [f32; 4],
+    #[serde(default = "default_light_intensity")]
+    pub intensity: f32,
+    /// 0 means unlimited
+    #[serde(default)]
+    pub range: f32,
+    #[serde(default = "default_inner_cone_deg")]
+    pub inner_cone_deg: f32,
+    #[serde(default = "default_outer_cone_deg")]
+    pub outer_cone_deg: f32,
+    #[serde(default)]
+    pub cast_shadows: bool,
+    #[serde(default = "default_shadow_bias")]
+    pub shadow_bias: f32,
+    #[serde(default = "default_shadow_normal_bias")]
+    pub shadow_normal_bias: f32,
     #[serde(default = "default_model")]
     pub model: String,
     #[serde(default = "default_mesh_index")]
     pub mesh_index: usize,
+    /// Draw every mesh of the model instead of just `mesh_index`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub entire_model: bool,
     pub material_source: crate::model::MaterialSource,
 }
 
+impl LightParams {
+    pub fn to_light(&self) -> Light {
+        Light {
+            kind: self.kind,
+            position: self.position,
+            direction: self.direction,
+            color: self.color,
+            intensity: self.intensity,
+            range: self.range,
+            inner_cone_deg: self.inner_cone_deg,
+            outer_cone_deg: self.outer_cone_deg,
+            cast_shadows: self.cast_shadows,
+            shadow_bias: self.shadow_bias,
+            shadow_normal_bias: self.shadow_normal_bias,
+        }
+    }
+}
+
 /// Particle system configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParticleSystemData {
@@ -77,17 +187,75 @@ pub struct ParticleSystemData {
     #[serde(default = "default_mesh_index")]
     pub mesh_index: usize,
     pub material_source: crate::model::MaterialSource,
+    /// Draw every mesh of the model, each with its own material, instead of just
+    /// `mesh_index` with `material_source`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub entire_model: bool,
+    /// Materials used in place of a mesh's own in entire-model mode, by mesh index
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub material_overrides: BTreeMap<usize, crate::model::MaterialSource>,
     pub generator: GeneratorType,
+    /// Per-instance color, scale and custom data
+    #[serde(default, skip_serializing_if = "InstanceAttributes::is_empty")]
+    pub attributes: InstanceAttributes,
+    /// Dynamic systems spawn particles from the generated instances
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simulation: Option<SimulationParams>,
+    /// Coarser meshes for distant instances, nearest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lods: Vec<LodLevel>,
+    /// Skeletal animation of the model's skinned meshes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<AnimationPlayer>,
+    /// Weights for the model's morph targets in place of its own or the animation's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub morph_weights: Option<MorphWeights>,
 }
 
 fn default_model() -> String {
     crate::defaults::PARTICLE_SYSTEM_MODEL_PATH.to_string()
 }
 
+fn default_shader() -> String {
+    crate::defaults::DEFAULT_SHADER_PATH.to_string()
+}
+
+fn default_exposure() -> f32 {
+    crate::tonemapping::ToneMapSettings::default().exposure
+}
+
+fn default_environment_intensity() -> f32 {
+    1.0
+}
+
 fn default_mesh_index() -> usize {
     0
 }
 
+fn default_light_direction() -> [f32; 3] {
+    Light::default().direction
+}
+
+fn default_light_intensity() -> f32 {
+    Light::default().intensity
+}
+
+fn default_inner_cone_deg() -> f32 {
+    Light::default().inner_cone_deg
+}
+
+fn default_outer_cone_deg() -> f32 {
+    Light::default().outer_cone_deg
+}
+
+fn default_shadow_bias() -> f32 {
+    Light::default().shadow_bias
+}
+
+fn default_shadow_normal_bias() -> f32 {
+    Light::default().shadow_normal_bias
+}
+
 impl ParticleSystemData {
     pub fn name(&self) -> &str {
         &self.name